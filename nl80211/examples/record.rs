@@ -0,0 +1,27 @@
+//! Records the netlink traffic of a scan into a fixture file for offline tests:
+//!
+//! ```sh
+//! sudo cargo run -p nl80211 --example record -- wlan0 nl80211/tests/fixtures/scan.nlrec
+//! ```
+
+use anyhow::{Context, Result};
+
+use nl80211::scan::scan;
+use nl80211::Nl80211;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let mut args = std::env::args().skip(1);
+    let interface = args.next().context("Missing interface argument")?;
+    let path = args.next().context("Missing fixture path argument")?;
+
+    let (nl80211, recording) = Nl80211::record()?;
+
+    let results = scan(&nl80211, &interface).await?;
+    println!("{} access points", results.len());
+
+    recording.save(&path)?;
+    println!("Recorded to {path}");
+
+    Ok(())
+}
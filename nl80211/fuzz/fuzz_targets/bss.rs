@@ -0,0 +1,37 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::Buffer;
+use neli::{FromBytesWithInput, ToBytes};
+
+use nl80211::enums::{Nl80211Attr, Nl80211Cmd};
+use nl80211::scan::parse_bss;
+use nl80211::Nl80211Payload;
+
+fuzz_target!(|data: &[u8]| {
+    // Most inputs fail to decode as a message, so they are also tried as the nested BSS
+    // attributes of a well-formed one
+    let Ok(bss_attr) = Nlattr::new(true, false, Nl80211Attr::Bss, Buffer::from(data)) else {
+        return;
+    };
+    let payload = Genlmsghdr::new(
+        Nl80211Cmd::NewScanResults,
+        1,
+        std::iter::once(bss_attr).collect(),
+    );
+    let mut cursor = Cursor::new(Vec::new());
+    payload.to_bytes(&mut cursor).unwrap();
+    let wrapped = cursor.into_inner();
+
+    for bytes in [data, wrapped.as_slice()] {
+        if let Ok(payload) =
+            Nl80211Payload::from_bytes_with_input(&mut Cursor::new(bytes), bytes.len())
+        {
+            let _bss = parse_bss(&payload);
+        }
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use nl80211::scan::information_elements;
+
+fuzz_target!(|data: &[u8]| {
+    let walked: usize = information_elements(data)
+        .map(|(_, element)| element.len() + 2)
+        .sum();
+
+    assert!(walked <= data.len());
+});
@@ -0,0 +1,17 @@
+#![no_main]
+
+use core::convert::TryFrom;
+use std::io::Cursor;
+
+use libfuzzer_sys::fuzz_target;
+
+use neli::FromBytesWithInput;
+
+use nl80211::interface::Interface;
+use nl80211::Nl80211Payload;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(payload) = Nl80211Payload::from_bytes_with_input(&mut Cursor::new(data), data.len()) {
+        Interface::try_from(&payload).ok();
+    }
+});
@@ -0,0 +1,1293 @@
+//! Open or WPA2 access point hosted on a wireless interface without an access point daemon.
+//!
+//! mac80211 based drivers transmit the beacons, but leave answering probe requests and
+//! authenticating and associating clients to userspace, which is otherwise the job of hostapd.
+//! [`AccessPoint`] handles these management frames from a background task and adds associated
+//! clients as stations. Networks with a passphrase use WPA2-PSK with CCMP: the 4-way handshake
+//! runs over the nl80211 control port, and clients pass traffic once their keys are installed.
+//!
+//! The frames are registered for on a dedicated socket, which conflicts with a
+//! [`crate::probe::ProbeListener`] on the same interface.
+
+use core::convert::{TryFrom, TryInto};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Context, Result};
+
+use macaddr::MacAddr6;
+
+use neli::consts::MAX_NL_LENGTH;
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::Buffer;
+
+use tokio::task::JoinHandle;
+use tokio::time::interval;
+
+use crate::client::Nl80211;
+use crate::consts::{
+    NL80211_AUTHTYPE_OPEN_SYSTEM, NL80211_CHAN_NO_HT, NL80211_HIDDEN_SSID_NOT_IN_USE,
+    NL80211_STA_FLAG_ASSOCIATED, NL80211_STA_FLAG_AUTHENTICATED, NL80211_STA_FLAG_AUTHORIZED,
+    NL80211_WPA_VERSION_2,
+};
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::handshake::{
+    rsn_element, Authenticator, Handshake, Step, AKM_SUITE_PSK, CIPHER_SUITE_CCMP, ETH_P_PAE,
+    GTK_KEY_INDEX, KEY_LEN, PTK_KEY_INDEX, RETRANSMIT_TIMEOUT, WLAN_EID_RSN,
+};
+use crate::interface::{find_interface, set_interface_type, Iftype};
+use crate::link::{add_ipv4_address, delete_ipv4_address, set_link_up};
+use crate::message::{recv_frames, Message, Nl80211Payload};
+use crate::probe::{register_frames, request_on};
+use crate::scan::{extract_ssid, information_elements};
+use crate::ssid::Ssid;
+use crate::transport::Transport;
+
+/// Channel used unless configured otherwise.
+pub const DEFAULT_CHANNEL: u8 = 6;
+
+// Frame control field values of the management frame subtypes
+const ASSOC_REQUEST_FRAME_TYPE: u16 = 0x0000;
+const ASSOC_RESPONSE_FRAME_TYPE: u16 = 0x0010;
+const REASSOC_REQUEST_FRAME_TYPE: u16 = 0x0020;
+const REASSOC_RESPONSE_FRAME_TYPE: u16 = 0x0030;
+const PROBE_REQUEST_FRAME_TYPE: u16 = 0x0040;
+const PROBE_RESPONSE_FRAME_TYPE: u16 = 0x0050;
+const BEACON_FRAME_TYPE: u16 = 0x0080;
+const DISASSOC_FRAME_TYPE: u16 = 0x00a0;
+const AUTH_FRAME_TYPE: u16 = 0x00b0;
+const DEAUTH_FRAME_TYPE: u16 = 0x00c0;
+const FRAME_SUBTYPE_MASK: u16 = 0x00fc;
+
+const REGISTERED_FRAME_TYPES: [u16; 6] = [
+    PROBE_REQUEST_FRAME_TYPE,
+    AUTH_FRAME_TYPE,
+    ASSOC_REQUEST_FRAME_TYPE,
+    REASSOC_REQUEST_FRAME_TYPE,
+    DISASSOC_FRAME_TYPE,
+    DEAUTH_FRAME_TYPE,
+];
+
+const MGMT_HEADER_LEN: usize = 24;
+const DESTINATION_OFFSET: usize = 4;
+const SOURCE_OFFSET: usize = 10;
+const BSSID_OFFSET: usize = 16;
+
+// Capability, listen interval and for reassociations the current access point precede the
+// elements of association requests
+const ASSOC_REQUEST_FIXED_LEN: usize = 4;
+const REASSOC_REQUEST_FIXED_LEN: usize = 10;
+
+const WLAN_EID_SSID: u8 = 0;
+const WLAN_EID_SUPP_RATES: u8 = 1;
+const WLAN_EID_DS_PARAMS: u8 = 3;
+const WLAN_EID_EXT_SUPP_RATES: u8 = 50;
+
+// 1, 2, 5.5 and 11 Mbps as basic rates followed by 6 to 18 Mbps, in units of 500 kbps
+const SUPPORTED_RATES: [u8; 8] = [0x82, 0x84, 0x8b, 0x96, 0x0c, 0x12, 0x18, 0x24];
+// 24 to 54 Mbps
+const EXTENDED_SUPPORTED_RATES: [u8; 4] = [0x30, 0x48, 0x60, 0x6c];
+const MAX_STATION_RATES: usize = 32;
+
+const CAPABILITY_ESS: u16 = 0x0001;
+const CAPABILITY_PRIVACY: u16 = 0x0010;
+const BEACON_INTERVAL_TU: u16 = 100;
+const DTIM_PERIOD: u32 = 2;
+
+const AUTH_ALGORITHM_OPEN: u16 = 0;
+const AUTH_TRANSACTION_REQUEST: u16 = 1;
+const AUTH_TRANSACTION_RESPONSE: u16 = 2;
+
+const WLAN_STATUS_SUCCESS: u16 = 0;
+const WLAN_STATUS_UNSPECIFIED_FAILURE: u16 = 1;
+const WLAN_STATUS_NOT_SUPPORTED_AUTH_ALG: u16 = 13;
+const WLAN_STATUS_AP_UNABLE_TO_HANDLE_NEW_STA: u16 = 17;
+const WLAN_STATUS_ASSOC_DENIED_RATES: u16 = 18;
+const WLAN_STATUS_INVALID_IE: u16 = 40;
+const WLAN_STATUS_GROUP_CIPHER_NOT_VALID: u16 = 41;
+const WLAN_STATUS_PAIRWISE_CIPHER_NOT_VALID: u16 = 42;
+const WLAN_STATUS_AKMP_NOT_VALID: u16 = 43;
+const WLAN_STATUS_UNSUPPORTED_RSN_IE_VERSION: u16 = 44;
+
+const WLAN_REASON_4WAY_HANDSHAKE_TIMEOUT: u16 = 15;
+
+const MAX_AID: u16 = 2007;
+// The two most significant bits of the AID field are set in association responses
+const AID_FIELD_BITS: u16 = 0xc000;
+
+/// Settings of the access point.
+#[derive(Debug, Clone)]
+pub struct AccessPointConfig {
+    /// Network name
+    pub ssid: Ssid,
+    /// 2.4 GHz channel between 1 and 13
+    pub channel: u8,
+    /// Address assigned to the interface, for clients to use as their gateway
+    pub address: Ipv4Addr,
+    /// Prefix length of the network of the address
+    pub prefix_len: u8,
+    /// WPA2 passphrase of 8 to 63 printable ASCII characters. The network is open without one.
+    pub passphrase: Option<String>,
+}
+
+/// Beacon template. The kernel builds the beacons from the head, the traffic indication map
+/// element it maintains itself and the tail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Beacon {
+    /// Management header, fixed fields and the elements before the traffic indication map
+    pub head: Vec<u8>,
+    /// Elements after the traffic indication map
+    pub tail: Vec<u8>,
+}
+
+impl Beacon {
+    /// Beacon of a network on a 2.4 GHz channel, supporting the 802.11b and 802.11g rates.
+    /// Protected networks set the privacy capability and advertise WPA2-PSK with CCMP in the
+    /// RSN element.
+    pub fn new(ssid: &Ssid, bssid: MacAddr6, channel: u8, protected: bool) -> Result<Self> {
+        let mut head = management_header(BEACON_FRAME_TYPE, MacAddr6::broadcast(), bssid);
+        // The timestamp is filled in on transmission
+        head.extend([0; 8]);
+        head.extend(BEACON_INTERVAL_TU.to_le_bytes());
+        head.extend(capability(protected).to_le_bytes());
+        push_element(&mut head, WLAN_EID_SSID, ssid.as_bytes())?;
+        push_element(&mut head, WLAN_EID_SUPP_RATES, &SUPPORTED_RATES)?;
+        push_element(&mut head, WLAN_EID_DS_PARAMS, &[channel])?;
+
+        let mut tail = Vec::new();
+        if protected {
+            push_element(&mut tail, WLAN_EID_RSN, &rsn_element())?;
+        }
+        push_element(
+            &mut tail,
+            WLAN_EID_EXT_SUPP_RATES,
+            &EXTENDED_SUPPORTED_RATES,
+        )?;
+
+        Ok(Self { head, tail })
+    }
+
+    /// Probe responses carry the same fields and elements as beacons, apart from the traffic
+    /// indication map.
+    fn probe_response(&self, destination: MacAddr6, bssid: MacAddr6) -> Vec<u8> {
+        let mut frame = management_header(PROBE_RESPONSE_FRAME_TYPE, destination, bssid);
+        frame.extend(self.head.get(MGMT_HEADER_LEN..).unwrap_or_default());
+        frame.extend(&self.tail);
+        frame
+    }
+}
+
+const fn capability(protected: bool) -> u16 {
+    if protected {
+        CAPABILITY_ESS | CAPABILITY_PRIVACY
+    } else {
+        CAPABILITY_ESS
+    }
+}
+
+/// Center frequency in MHz of a 2.4 GHz channel. Channel 14 is not supported, as it is
+/// restricted to 802.11b.
+#[must_use]
+pub fn channel_frequency(channel: u8) -> Option<u32> {
+    if !(1..=13).contains(&channel) {
+        return None;
+    }
+
+    u32::from(channel).checked_mul(5)?.checked_add(2407)
+}
+
+/// Running access point. Dropping it stops answering clients, and the kernel stops protected
+/// networks along with it, but the interface stays in access point mode, see
+/// [`AccessPoint::stop`].
+pub struct AccessPoint {
+    nl80211: Nl80211,
+    iface_index: u32,
+    address: Ipv4Addr,
+    prefix_len: u8,
+    mlme: JoinHandle<()>,
+}
+
+impl core::fmt::Debug for AccessPoint {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AccessPoint")
+            .field("iface_index", &self.iface_index)
+            .field("address", &self.address)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for AccessPoint {
+    fn drop(&mut self) {
+        self.mlme.abort();
+    }
+}
+
+impl AccessPoint {
+    /// Switches the interface to access point mode, assigns the address and starts beaconing.
+    /// Any connection of the interface is dropped. Requires `CAP_NET_ADMIN`.
+    pub async fn start(
+        nl80211: &Nl80211,
+        interface: &str,
+        config: &AccessPointConfig,
+    ) -> Result<Self> {
+        let frequency = channel_frequency(config.channel)
+            .with_context(|| format!("Unsupported channel {}", config.channel))?;
+
+        let iface = find_interface(nl80211, interface).await?;
+
+        let authenticator = config
+            .passphrase
+            .as_deref()
+            .map(|passphrase| Authenticator::new(iface.mac_address, &config.ssid, passphrase))
+            .transpose()?;
+
+        let beacon = Beacon::new(
+            &config.ssid,
+            iface.mac_address,
+            config.channel,
+            authenticator.is_some(),
+        )?;
+
+        let configured = configure(
+            nl80211,
+            iface.index,
+            config,
+            frequency,
+            &beacon,
+            authenticator.as_ref(),
+        )
+        .await;
+
+        let transport = match configured {
+            Ok(transport) => transport,
+            Err(err) => {
+                // Best effort, as the failed step may have left the interface in any state
+                nl80211
+                    .request(create_stop_ap_message(iface.index)?)
+                    .await
+                    .ok();
+                delete_ipv4_address(iface.index, config.address, config.prefix_len).ok();
+                restore_station_mode(nl80211, iface.index).await.ok();
+                return Err(err);
+            }
+        };
+
+        let mlme = Mlme {
+            nl80211: nl80211.clone(),
+            iface_index: iface.index,
+            bssid: iface.mac_address,
+            ssid: config.ssid.clone(),
+            beacon,
+            stations: HashMap::new(),
+            authenticator,
+            handshakes: HashMap::new(),
+        };
+
+        Ok(Self {
+            nl80211: nl80211.clone(),
+            iface_index: iface.index,
+            address: config.address,
+            prefix_len: config.prefix_len,
+            mlme: tokio::spawn(mlme.run(transport)),
+        })
+    }
+
+    /// Stops beaconing, which disconnects all clients, removes the address and returns the
+    /// interface to station mode.
+    pub async fn stop(self) -> Result<()> {
+        // Before the management frame socket closes, which stops protected networks on its own
+        self.nl80211
+            .request(create_stop_ap_message(self.iface_index)?)
+            .await
+            .context("Failed to stop access point")?;
+
+        self.mlme.abort();
+
+        delete_ipv4_address(self.iface_index, self.address, self.prefix_len)?;
+
+        restore_station_mode(&self.nl80211, self.iface_index).await
+    }
+}
+
+async fn configure(
+    nl80211: &Nl80211,
+    iface_index: u32,
+    config: &AccessPointConfig,
+    frequency: u32,
+    beacon: &Beacon,
+    authenticator: Option<&Authenticator>,
+) -> Result<Box<dyn Transport>> {
+    set_link_up(iface_index, false)?;
+    set_interface_type(nl80211, iface_index, Iftype::AP).await?;
+    add_ipv4_address(iface_index, config.address, config.prefix_len)?;
+    set_link_up(iface_index, true)?;
+
+    // Registrations are dropped on interface type changes, so they follow the change
+    let transport = register_frames(nl80211, iface_index, &REGISTERED_FRAME_TYPES)
+        .await
+        .context("Failed to register for management frames")?;
+
+    // The control port passes the EAPOL frames of protected networks to the socket that
+    // started the network, which is the one receiving the management frames
+    let mut buf = vec![0; MAX_NL_LENGTH];
+    request_on(
+        nl80211,
+        &*transport,
+        &mut buf,
+        create_start_ap_message(
+            iface_index,
+            &config.ssid,
+            frequency,
+            beacon,
+            authenticator.is_some(),
+        )?,
+    )
+    .await
+    .context("Failed to start access point")?;
+
+    if let Some(authenticator) = authenticator {
+        install_group_key(nl80211, iface_index, authenticator.gtk()).await?;
+    }
+
+    Ok(transport)
+}
+
+/// Installs the key protecting broadcast traffic as the default for transmission.
+async fn install_group_key(nl80211: &Nl80211, iface_index: u32, gtk: &[u8]) -> Result<()> {
+    nl80211
+        .request(create_new_key_message(
+            iface_index,
+            None,
+            GTK_KEY_INDEX,
+            gtk,
+        )?)
+        .await
+        .context("Failed to install group key")?;
+
+    nl80211
+        .request(create_set_default_key_message(iface_index, GTK_KEY_INDEX)?)
+        .await
+        .context("Failed to set default group key")?;
+
+    Ok(())
+}
+
+async fn restore_station_mode(nl80211: &Nl80211, iface_index: u32) -> Result<()> {
+    set_link_up(iface_index, false)?;
+    set_interface_type(nl80211, iface_index, Iftype::Station).await?;
+    set_link_up(iface_index, true)
+}
+
+/// Management frame received from a client.
+struct ManagementFrame<'a> {
+    frame_type: u16,
+    destination: MacAddr6,
+    source: MacAddr6,
+    bssid: MacAddr6,
+    body: &'a [u8],
+}
+
+impl<'a> ManagementFrame<'a> {
+    fn parse(frame: &'a [u8]) -> Option<Self> {
+        let frame_control = u16::from_le_bytes(frame.get(..2)?.try_into().ok()?);
+
+        Some(Self {
+            frame_type: frame_control & FRAME_SUBTYPE_MASK,
+            destination: parse_address(frame, DESTINATION_OFFSET)?,
+            source: parse_address(frame, SOURCE_OFFSET)?,
+            bssid: parse_address(frame, BSSID_OFFSET)?,
+            body: frame.get(MGMT_HEADER_LEN..)?,
+        })
+    }
+
+    fn field(&self, offset: usize) -> Option<u16> {
+        let end = offset.checked_add(2)?;
+        Some(u16::from_le_bytes(
+            self.body.get(offset..end)?.try_into().ok()?,
+        ))
+    }
+}
+
+fn parse_address(frame: &[u8], offset: usize) -> Option<MacAddr6> {
+    let end = offset.checked_add(6)?;
+    let bytes: [u8; 6] = frame.get(offset..end)?.try_into().ok()?;
+    Some(bytes.into())
+}
+
+/// Answers the management frames of clients, in place of an access point daemon.
+struct Mlme {
+    nl80211: Nl80211,
+    iface_index: u32,
+    bssid: MacAddr6,
+    ssid: Ssid,
+    beacon: Beacon,
+    /// Association IDs of the associated clients
+    stations: HashMap<MacAddr6, u16>,
+    /// Present for protected networks
+    authenticator: Option<Authenticator>,
+    /// Handshakes of the associated clients not authorized yet
+    handshakes: HashMap<MacAddr6, Handshake>,
+}
+
+impl Mlme {
+    async fn run(mut self, transport: Box<dyn Transport>) {
+        let mut buf = vec![0; MAX_NL_LENGTH];
+        let mut retransmissions = interval(RETRANSMIT_TIMEOUT);
+
+        loop {
+            let received = tokio::select! {
+                received = recv_frames(&*transport, &mut buf) => received,
+                _ = retransmissions.tick(), if !self.handshakes.is_empty() => {
+                    if let Err(err) = self.retransmit().await {
+                        println!("Failed to retransmit handshake message: {err:#}");
+                    }
+                    continue;
+                }
+            };
+
+            let frames = match received {
+                Ok(frames) => frames,
+                Err(err) => {
+                    println!("Access point stopped receiving management frames: {err:#}");
+                    return;
+                }
+            };
+
+            for frame in frames {
+                if let Message::Payload(ref payload) = frame.message {
+                    if let Err(err) = self.handle(payload).await {
+                        println!("Failed to handle management frame: {err:#}");
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle(&mut self, payload: &Nl80211Payload) -> Result<()> {
+        if payload.cmd == Nl80211Cmd::ControlPortFrame {
+            return self.handle_eapol(payload).await;
+        }
+
+        if payload.cmd != Nl80211Cmd::Frame {
+            return Ok(());
+        }
+
+        let attrs = payload.get_attr_handle();
+        let frame = attrs
+            .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Frame)
+            .context("Missing frame attribute")?;
+
+        let Some(request) = ManagementFrame::parse(frame) else {
+            return Ok(());
+        };
+
+        if request.frame_type == PROBE_REQUEST_FRAME_TYPE {
+            return self.answer_probe(&request).await;
+        }
+
+        // Addressed to this access point and within its network
+        if [request.destination, request.bssid] != [self.bssid; 2] {
+            return Ok(());
+        }
+
+        match request.frame_type {
+            AUTH_FRAME_TYPE => self.authenticate(&request).await,
+            ASSOC_REQUEST_FRAME_TYPE => {
+                self.associate(&request, ASSOC_REQUEST_FIXED_LEN, ASSOC_RESPONSE_FRAME_TYPE)
+                    .await
+            }
+            REASSOC_REQUEST_FRAME_TYPE => {
+                self.associate(
+                    &request,
+                    REASSOC_REQUEST_FIXED_LEN,
+                    REASSOC_RESPONSE_FRAME_TYPE,
+                )
+                .await
+            }
+            DISASSOC_FRAME_TYPE | DEAUTH_FRAME_TYPE => self.remove_station(request.source).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Answers wildcard probes and probes for the network name.
+    async fn answer_probe(&self, request: &ManagementFrame<'_>) -> Result<()> {
+        if request.bssid != MacAddr6::broadcast() && request.bssid != self.bssid {
+            return Ok(());
+        }
+
+        let ssid = extract_ssid(request.body);
+        if !ssid.is_empty() && ssid != self.ssid.as_bytes() {
+            return Ok(());
+        }
+
+        let response = self.beacon.probe_response(request.source, self.bssid);
+
+        // Probing clients often move on to the next channel before acknowledging
+        self.send_frame(response, true).await
+    }
+
+    /// Open system authentication, which accepts any client.
+    async fn authenticate(&mut self, request: &ManagementFrame<'_>) -> Result<()> {
+        let algorithm = request.field(0).context("Truncated authentication frame")?;
+        let transaction = request.field(2).context("Truncated authentication frame")?;
+
+        if transaction != AUTH_TRANSACTION_REQUEST {
+            return Ok(());
+        }
+
+        // A client authenticating again starts over
+        self.remove_station(request.source).await?;
+
+        let status = if algorithm == AUTH_ALGORITHM_OPEN {
+            WLAN_STATUS_SUCCESS
+        } else {
+            WLAN_STATUS_NOT_SUPPORTED_AUTH_ALG
+        };
+
+        let mut response = management_header(AUTH_FRAME_TYPE, request.source, self.bssid);
+        response.extend(algorithm.to_le_bytes());
+        response.extend(AUTH_TRANSACTION_RESPONSE.to_le_bytes());
+        response.extend(status.to_le_bytes());
+
+        self.send_frame(response, false).await
+    }
+
+    /// Adds the client as a station and confirms the association. Clients of open networks are
+    /// authorized right away, those of protected ones once the handshake installed their keys.
+    async fn associate(
+        &mut self,
+        request: &ManagementFrame<'_>,
+        fixed_len: usize,
+        response_type: u16,
+    ) -> Result<()> {
+        let listen_interval = request.field(2).context("Truncated association request")?;
+        let elements = request.body.get(fixed_len..).unwrap_or_default();
+
+        if extract_ssid(elements) != self.ssid.as_bytes() {
+            return Ok(());
+        }
+
+        let rates = information_elements(elements)
+            .filter(|&(eid, _)| eid == WLAN_EID_SUPP_RATES || eid == WLAN_EID_EXT_SUPP_RATES)
+            .flat_map(|(_, rates)| rates.iter().copied())
+            .take(MAX_STATION_RATES)
+            .collect::<Vec<_>>();
+
+        let source = request.source;
+
+        if rates.is_empty() {
+            return self
+                .respond_association(response_type, source, WLAN_STATUS_ASSOC_DENIED_RATES, 0)
+                .await;
+        }
+
+        let client_rsn = information_elements(elements)
+            .find(|&(eid, _)| eid == WLAN_EID_RSN)
+            .map(|(_, data)| data);
+
+        if self.authenticator.is_some() {
+            let status = client_rsn.map_or(WLAN_STATUS_INVALID_IE, check_rsn_element);
+            if status != WLAN_STATUS_SUCCESS {
+                return self
+                    .respond_association(response_type, source, status, 0)
+                    .await;
+            }
+        }
+
+        let Some(aid) = self.allocate_aid(source) else {
+            return self
+                .respond_association(
+                    response_type,
+                    source,
+                    WLAN_STATUS_AP_UNABLE_TO_HANDLE_NEW_STA,
+                    0,
+                )
+                .await;
+        };
+
+        // Reassociating clients are added again with the new parameters and keys
+        self.handshakes.remove(&source);
+        if self.stations.insert(source, aid).is_some() {
+            self.nl80211
+                .request(create_del_station_message(self.iface_index, source)?)
+                .await
+                .ok();
+        }
+
+        let added = self
+            .nl80211
+            .request(create_new_station_message(
+                self.iface_index,
+                source,
+                &rates,
+                aid,
+                listen_interval,
+                self.authenticator.is_none(),
+            )?)
+            .await
+            .context("Failed to add station");
+
+        if let Err(err) = added {
+            self.stations.remove(&source);
+            self.respond_association(response_type, source, WLAN_STATUS_UNSPECIFIED_FAILURE, 0)
+                .await?;
+            return Err(err);
+        }
+
+        println!("Client {source} associated");
+
+        self.respond_association(response_type, source, WLAN_STATUS_SUCCESS, aid)
+            .await?;
+
+        if let (Some(authenticator), Some(rsn)) = (self.authenticator.as_ref(), client_rsn) {
+            let (handshake, message_1) = authenticator.start(source, rsn)?;
+            self.handshakes.insert(source, handshake);
+            self.send_eapol(source, message_1).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Continues the handshake with the client sending the EAPOL frame.
+    async fn handle_eapol(&mut self, payload: &Nl80211Payload) -> Result<()> {
+        let attrs = payload.get_attr_handle();
+
+        if attrs
+            .get_attr_payload_as::<u16>(Nl80211Attr::ControlPortEthertype)
+            .ok()
+            != Some(ETH_P_PAE)
+        {
+            return Ok(());
+        }
+
+        let frame = attrs
+            .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Frame)
+            .context("Missing frame attribute")?;
+        let source = attrs
+            .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Mac)
+            .ok()
+            .and_then(|mac| <[u8; 6]>::try_from(mac).ok())
+            .map(MacAddr6::from)
+            .context("Missing MAC address attribute")?;
+
+        let (Some(authenticator), Some(handshake)) = (
+            self.authenticator.as_ref(),
+            self.handshakes.get_mut(&source),
+        ) else {
+            return Ok(());
+        };
+
+        match authenticator.receive(handshake, frame)? {
+            Step::Reply(reply) => self.send_eapol(source, reply).await,
+            Step::Complete(tk) => {
+                self.handshakes.remove(&source);
+                self.authorize(source, &tk).await
+            }
+            Step::Ignore => Ok(()),
+        }
+    }
+
+    /// Installs the traffic key of the client and lets it pass traffic.
+    async fn authorize(&self, client: MacAddr6, tk: &[u8; KEY_LEN]) -> Result<()> {
+        self.nl80211
+            .request(create_new_key_message(
+                self.iface_index,
+                Some(client),
+                PTK_KEY_INDEX,
+                tk,
+            )?)
+            .await
+            .context("Failed to install pairwise key")?;
+
+        self.nl80211
+            .request(create_authorize_station_message(self.iface_index, client)?)
+            .await
+            .context("Failed to authorize station")?;
+
+        println!("Client {client} completed the handshake");
+
+        Ok(())
+    }
+
+    /// Sends unanswered handshake messages again and disconnects clients that stopped
+    /// answering.
+    async fn retransmit(&mut self) -> Result<()> {
+        let Some(ref authenticator) = self.authenticator else {
+            return Ok(());
+        };
+
+        let mut messages = Vec::new();
+        let mut expired = Vec::new();
+
+        for (&client, handshake) in &mut self.handshakes {
+            if !handshake.timed_out() {
+                continue;
+            }
+
+            match authenticator.retransmit(handshake)? {
+                Some(message) => messages.push((client, message)),
+                None => expired.push(client),
+            }
+        }
+
+        for (client, message) in messages {
+            self.send_eapol(client, message).await?;
+        }
+
+        for client in expired {
+            println!("Client {client} did not complete the handshake");
+            self.deauthenticate(client, WLAN_REASON_4WAY_HANDSHAKE_TIMEOUT)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn deauthenticate(&mut self, client: MacAddr6, reason: u16) -> Result<()> {
+        let mut frame = management_header(DEAUTH_FRAME_TYPE, client, self.bssid);
+        frame.extend(reason.to_le_bytes());
+        self.send_frame(frame, false).await?;
+
+        self.remove_station(client).await
+    }
+
+    async fn send_eapol(&self, client: MacAddr6, frame: Vec<u8>) -> Result<()> {
+        self.nl80211
+            .request(create_control_port_frame_message(
+                self.iface_index,
+                client,
+                frame,
+            )?)
+            .await
+            .context("Failed to send EAPOL frame")?;
+
+        Ok(())
+    }
+
+    async fn respond_association(
+        &self,
+        response_type: u16,
+        destination: MacAddr6,
+        status: u16,
+        aid: u16,
+    ) -> Result<()> {
+        let mut response = management_header(response_type, destination, self.bssid);
+        response.extend(capability(self.authenticator.is_some()).to_le_bytes());
+        response.extend(status.to_le_bytes());
+        response.extend((aid | AID_FIELD_BITS).to_le_bytes());
+        push_element(&mut response, WLAN_EID_SUPP_RATES, &SUPPORTED_RATES)?;
+        push_element(
+            &mut response,
+            WLAN_EID_EXT_SUPP_RATES,
+            &EXTENDED_SUPPORTED_RATES,
+        )?;
+
+        self.send_frame(response, false).await
+    }
+
+    /// Keeps the association ID of a reassociating client, otherwise picks the lowest free one.
+    fn allocate_aid(&self, client: MacAddr6) -> Option<u16> {
+        if let Some(&aid) = self.stations.get(&client) {
+            return Some(aid);
+        }
+
+        (1..=MAX_AID).find(|aid| !self.stations.values().any(|used| used == aid))
+    }
+
+    async fn remove_station(&mut self, client: MacAddr6) -> Result<()> {
+        self.handshakes.remove(&client);
+
+        if self.stations.remove(&client).is_none() {
+            return Ok(());
+        }
+
+        self.nl80211
+            .request(create_del_station_message(self.iface_index, client)?)
+            .await
+            .context("Failed to remove station")?;
+
+        println!("Client {client} disassociated");
+
+        Ok(())
+    }
+
+    async fn send_frame(&self, frame: Vec<u8>, dont_wait_for_ack: bool) -> Result<()> {
+        self.nl80211
+            .request(create_frame_message(
+                self.iface_index,
+                frame,
+                dont_wait_for_ack,
+            )?)
+            .await
+            .context("Failed to send management frame")?;
+
+        Ok(())
+    }
+}
+
+/// Header with the duration and sequence number left for the driver to fill in.
+fn management_header(frame_type: u16, destination: MacAddr6, bssid: MacAddr6) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(MGMT_HEADER_LEN);
+    frame.extend(frame_type.to_le_bytes());
+    frame.extend([0; 2]);
+    frame.extend(destination.as_bytes());
+    frame.extend(bssid.as_bytes());
+    frame.extend(bssid.as_bytes());
+    frame.extend([0; 2]);
+    frame
+}
+
+fn push_element(frame: &mut Vec<u8>, eid: u8, data: &[u8]) -> Result<()> {
+    let Ok(len) = u8::try_from(data.len()) else {
+        bail!("Element {eid} exceeds 255 bytes");
+    };
+
+    frame.push(eid);
+    frame.push(len);
+    frame.extend(data);
+
+    Ok(())
+}
+
+/// Status of an association request with the RSN element of the client. The client has to
+/// select the suites the network advertises.
+fn check_rsn_element(data: &[u8]) -> u16 {
+    let count = |offset: usize| -> Option<u16> {
+        Some(u16::from_le_bytes(
+            data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+        ))
+    };
+    let suite = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+        ))
+    };
+
+    if count(0) != Some(1) {
+        WLAN_STATUS_UNSUPPORTED_RSN_IE_VERSION
+    } else if suite(2) != Some(CIPHER_SUITE_CCMP) {
+        WLAN_STATUS_GROUP_CIPHER_NOT_VALID
+    } else if count(6) != Some(1) || suite(8) != Some(CIPHER_SUITE_CCMP) {
+        WLAN_STATUS_PAIRWISE_CIPHER_NOT_VALID
+    } else if count(12) != Some(1) || suite(14) != Some(AKM_SUITE_PSK) {
+        WLAN_STATUS_AKMP_NOT_VALID
+    } else {
+        WLAN_STATUS_SUCCESS
+    }
+}
+
+fn create_start_ap_message(
+    iface_index: u32,
+    ssid: &Ssid,
+    frequency: u32,
+    beacon: &Beacon,
+    protected: bool,
+) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let head_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::BeaconHead,
+        Buffer::from(beacon.head.clone()),
+    )
+    .context("Failed to create beacon head attribute")?;
+    let tail_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::BeaconTail,
+        Buffer::from(beacon.tail.clone()),
+    )
+    .context("Failed to create beacon tail attribute")?;
+    let interval_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::BeaconInterval,
+        u32::from(BEACON_INTERVAL_TU),
+    )
+    .context("Failed to create beacon interval attribute")?;
+    let dtim_attr = Nlattr::new(false, false, Nl80211Attr::DtimPeriod, DTIM_PERIOD)
+        .context("Failed to create DTIM period attribute")?;
+    let ssid_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::Ssid,
+        Buffer::from(ssid.as_bytes().to_vec()),
+    )
+    .context("Failed to create SSID attribute")?;
+    let hidden_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::HiddenSsid,
+        NL80211_HIDDEN_SSID_NOT_IN_USE,
+    )
+    .context("Failed to create hidden SSID attribute")?;
+    let auth_type_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::AuthType,
+        NL80211_AUTHTYPE_OPEN_SYSTEM,
+    )
+    .context("Failed to create authentication type attribute")?;
+    let frequency_attr = Nlattr::new(false, false, Nl80211Attr::WiphyFreq, frequency)
+        .context("Failed to create frequency attribute")?;
+    let channel_type_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::WiphyChannelType,
+        NL80211_CHAN_NO_HT,
+    )
+    .context("Failed to create channel type attribute")?;
+
+    let mut attrs = vec![
+        iface_attr,
+        head_attr,
+        tail_attr,
+        interval_attr,
+        dtim_attr,
+        ssid_attr,
+        hidden_attr,
+        auth_type_attr,
+        frequency_attr,
+        channel_type_attr,
+    ];
+
+    if protected {
+        attrs.extend(create_rsn_attrs()?);
+    }
+
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::StartAp,
+        1,
+        attrs.into_iter().collect(),
+    ))
+}
+
+/// WPA2-PSK with CCMP, with the EAPOL frames of the handshake passed over nl80211 to the
+/// socket owning the network rather than the network interface.
+fn create_rsn_attrs() -> Result<Vec<Nlattr<Nl80211Attr, Buffer>>> {
+    let flag = |attr: Nl80211Attr, name: &str| {
+        Nlattr::new(false, false, attr, Buffer::new())
+            .with_context(|| format!("Failed to create {name} attribute"))
+    };
+
+    Ok(vec![
+        flag(Nl80211Attr::Privacy, "privacy")?,
+        Nlattr::new(
+            false,
+            false,
+            Nl80211Attr::WpaVersions,
+            NL80211_WPA_VERSION_2,
+        )
+        .context("Failed to create WPA versions attribute")?,
+        Nlattr::new(
+            false,
+            false,
+            Nl80211Attr::CipherSuitesPairwise,
+            Buffer::from(CIPHER_SUITE_CCMP.to_ne_bytes().to_vec()),
+        )
+        .context("Failed to create pairwise cipher suites attribute")?,
+        Nlattr::new(
+            false,
+            false,
+            Nl80211Attr::CipherSuiteGroup,
+            CIPHER_SUITE_CCMP,
+        )
+        .context("Failed to create group cipher suite attribute")?,
+        Nlattr::new(
+            false,
+            false,
+            Nl80211Attr::AkmSuites,
+            Buffer::from(AKM_SUITE_PSK.to_ne_bytes().to_vec()),
+        )
+        .context("Failed to create AKM suites attribute")?,
+        flag(Nl80211Attr::ControlPort, "control port")?,
+        flag(
+            Nl80211Attr::ControlPortOverNl80211,
+            "control port over nl80211",
+        )?,
+        flag(Nl80211Attr::SocketOwner, "socket owner")?,
+    ])
+}
+
+fn create_stop_ap_message(iface_index: u32) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::StopAp,
+        1,
+        core::iter::once(iface_attr).collect(),
+    ))
+}
+
+fn create_new_station_message(
+    iface_index: u32,
+    client: MacAddr6,
+    rates: &[u8],
+    aid: u16,
+    listen_interval: u16,
+    authorized: bool,
+) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let mac_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::Mac,
+        Buffer::from(client.as_bytes().to_vec()),
+    )
+    .context("Failed to create MAC address attribute")?;
+    let rates_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::StaSupportedRates,
+        Buffer::from(rates.to_vec()),
+    )
+    .context("Failed to create supported rates attribute")?;
+    let aid_attr = Nlattr::new(false, false, Nl80211Attr::StaAid, aid)
+        .context("Failed to create association ID attribute")?;
+    let listen_interval_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::StaListenInterval,
+        listen_interval,
+    )
+    .context("Failed to create listen interval attribute")?;
+    let flags = [
+        NL80211_STA_FLAG_AUTHENTICATED,
+        NL80211_STA_FLAG_ASSOCIATED,
+        NL80211_STA_FLAG_AUTHORIZED,
+    ];
+    let set = if authorized { &flags[..] } else { &flags[..2] };
+    let flags_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::StaFlags2,
+        Buffer::from(station_flag_update(&flags, set)),
+    )
+    .context("Failed to create station flags attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::NewStation,
+        1,
+        [
+            iface_attr,
+            mac_attr,
+            rates_attr,
+            aid_attr,
+            listen_interval_attr,
+            flags_attr,
+        ]
+        .into_iter()
+        .collect(),
+    ))
+}
+
+/// `struct nl80211_sta_flag_update` changing the flags of the mask to the set ones.
+fn station_flag_update(mask: &[u32], set: &[u32]) -> Vec<u8> {
+    let bits = |flags: &[u32]| {
+        flags.iter().fold(0_u32, |bits, &flag| {
+            bits | 1_u32.checked_shl(flag).unwrap_or_default()
+        })
+    };
+
+    [bits(mask), bits(set)]
+        .into_iter()
+        .flat_map(u32::to_ne_bytes)
+        .collect()
+}
+
+/// Lets a client of a protected network pass traffic once its keys are installed.
+fn create_authorize_station_message(iface_index: u32, client: MacAddr6) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let mac_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::Mac,
+        Buffer::from(client.as_bytes().to_vec()),
+    )
+    .context("Failed to create MAC address attribute")?;
+    let authorized = [NL80211_STA_FLAG_AUTHORIZED];
+    let flags_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::StaFlags2,
+        Buffer::from(station_flag_update(&authorized, &authorized)),
+    )
+    .context("Failed to create station flags attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::SetStation,
+        1,
+        [iface_attr, mac_attr, flags_attr].into_iter().collect(),
+    ))
+}
+
+/// Pairwise keys are installed for a client, the group key for the whole network.
+fn create_new_key_message(
+    iface_index: u32,
+    client: Option<MacAddr6>,
+    key_index: u8,
+    key: &[u8],
+) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let data_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::KeyData,
+        Buffer::from(key.to_vec()),
+    )
+    .context("Failed to create key data attribute")?;
+    let index_attr = Nlattr::new(false, false, Nl80211Attr::KeyIdx, key_index)
+        .context("Failed to create key index attribute")?;
+    let cipher_attr = Nlattr::new(false, false, Nl80211Attr::KeyCipher, CIPHER_SUITE_CCMP)
+        .context("Failed to create key cipher attribute")?;
+
+    let mut attrs = vec![iface_attr, data_attr, index_attr, cipher_attr];
+
+    if let Some(client) = client {
+        attrs.push(
+            Nlattr::new(
+                false,
+                false,
+                Nl80211Attr::Mac,
+                Buffer::from(client.as_bytes().to_vec()),
+            )
+            .context("Failed to create MAC address attribute")?,
+        );
+    }
+
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::NewKey,
+        1,
+        attrs.into_iter().collect(),
+    ))
+}
+
+fn create_set_default_key_message(iface_index: u32, key_index: u8) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let index_attr = Nlattr::new(false, false, Nl80211Attr::KeyIdx, key_index)
+        .context("Failed to create key index attribute")?;
+    // Flag attribute without payload
+    let default_attr = Nlattr::new(false, false, Nl80211Attr::KeyDefault, Buffer::new())
+        .context("Failed to create default key attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::SetKey,
+        1,
+        [iface_attr, index_attr, default_attr].into_iter().collect(),
+    ))
+}
+
+/// EAPOL frame for a client, sent unencrypted until its pairwise key is installed.
+fn create_control_port_frame_message(
+    iface_index: u32,
+    client: MacAddr6,
+    frame: Vec<u8>,
+) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let mac_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::Mac,
+        Buffer::from(client.as_bytes().to_vec()),
+    )
+    .context("Failed to create MAC address attribute")?;
+    let frame_attr = Nlattr::new(false, false, Nl80211Attr::Frame, Buffer::from(frame))
+        .context("Failed to create frame attribute")?;
+    let ethertype_attr = Nlattr::new(false, false, Nl80211Attr::ControlPortEthertype, ETH_P_PAE)
+        .context("Failed to create EtherType attribute")?;
+    // Flag attribute without payload
+    let no_ack_attr = Nlattr::new(false, false, Nl80211Attr::DontWaitForAck, Buffer::new())
+        .context("Failed to create no ACK attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::ControlPortFrame,
+        1,
+        [
+            iface_attr,
+            mac_attr,
+            frame_attr,
+            ethertype_attr,
+            no_ack_attr,
+        ]
+        .into_iter()
+        .collect(),
+    ))
+}
+
+fn create_del_station_message(iface_index: u32, client: MacAddr6) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let mac_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::Mac,
+        Buffer::from(client.as_bytes().to_vec()),
+    )
+    .context("Failed to create MAC address attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::DelStation,
+        1,
+        [iface_attr, mac_attr].into_iter().collect(),
+    ))
+}
+
+fn create_frame_message(
+    iface_index: u32,
+    frame: Vec<u8>,
+    dont_wait_for_ack: bool,
+) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let frame_attr = Nlattr::new(false, false, Nl80211Attr::Frame, Buffer::from(frame))
+        .context("Failed to create frame attribute")?;
+
+    let mut attrs = vec![iface_attr, frame_attr];
+
+    if dont_wait_for_ack {
+        // Flag attribute without payload
+        attrs.push(
+            Nlattr::new(false, false, Nl80211Attr::DontWaitForAck, Buffer::new())
+                .context("Failed to create no ACK attribute")?,
+        );
+    }
+
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::Frame,
+        1,
+        attrs.into_iter().collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_rsn_element_of_clients() {
+        let mut element = rsn_element();
+        assert_eq!(check_rsn_element(&element), WLAN_STATUS_SUCCESS);
+
+        // TKIP as the pairwise cipher
+        element[11] = 0x02;
+        assert_eq!(
+            check_rsn_element(&element),
+            WLAN_STATUS_PAIRWISE_CIPHER_NOT_VALID
+        );
+
+        // 802.1X instead of pre-shared keys
+        let mut element = rsn_element();
+        element[17] = 0x01;
+        assert_eq!(check_rsn_element(&element), WLAN_STATUS_AKMP_NOT_VALID);
+
+        assert_eq!(
+            check_rsn_element(&element[..4]),
+            WLAN_STATUS_GROUP_CIPHER_NOT_VALID
+        );
+        assert_eq!(
+            check_rsn_element(&[]),
+            WLAN_STATUS_UNSUPPORTED_RSN_IE_VERSION
+        );
+    }
+}
@@ -0,0 +1,317 @@
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::time::Duration;
+use std::collections::HashMap;
+use std::io::{self, Cursor};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::{bail, Context, Result};
+
+use neli::consts::nl::{NlmF, NlmFFlags};
+use neli::consts::socket::NlFamily;
+use neli::consts::MAX_NL_LENGTH;
+use neli::nl::{NlPayload, Nlmsghdr};
+use neli::socket::NlSocketHandle;
+use neli::ToBytes;
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use crate::events::{EventMonitor, EVENT_MULTICAST_NAMES};
+use crate::message::{parse_messages, Frame, Message, Nl80211Payload};
+use crate::replay::{Fixture, Recording, ReplayTransport};
+use crate::transport::{SocketTransport, Transport};
+
+const NL80211_FAMILY_NAME: &str = "nl80211";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Pending = Mutex<HashMap<u32, UnboundedSender<Message>>>;
+
+/// Long-lived nl80211 connection. Replies are read by a background task and routed to the
+/// waiting request by sequence number, so a single client can serve concurrent requests.
+#[derive(Clone)]
+pub struct Nl80211 {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    transport: Arc<dyn Transport>,
+    nl_id: u16,
+    mcast_ids: Vec<u32>,
+    seq: AtomicU32,
+    pending: Arc<Pending>,
+    // The kernel allows a single dump in progress per netlink socket
+    dump_lock: tokio::sync::Mutex<()>,
+    reader: JoinHandle<()>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+impl core::fmt::Debug for Nl80211 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Nl80211")
+            .field("nl_id", &self.inner.nl_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Removes the reply route when the request completes or its future is dropped.
+struct Registration<'a> {
+    pending: &'a Pending,
+    seq: u32,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        lock(self.pending).remove(&self.seq);
+    }
+}
+
+impl Nl80211 {
+    /// Must be called from within a tokio runtime, which then drives the reply reader.
+    pub fn new() -> Result<Self> {
+        let (transport, nl_id, mcast_ids) = connect()?;
+
+        Ok(Self::with_transport(Box::new(transport), nl_id, mcast_ids))
+    }
+
+    /// Like [`Nl80211::new`], additionally capturing all traffic into the returned recording.
+    pub fn record() -> Result<(Self, Recording)> {
+        let (transport, nl_id, mcast_ids) = connect()?;
+
+        let recording = Recording::new(nl_id, mcast_ids.clone());
+        let nl80211 = Self::with_transport(recording.wrap(Box::new(transport)), nl_id, mcast_ids);
+
+        Ok((nl80211, recording))
+    }
+
+    /// Serves replies and events from a fixture file instead of the kernel. Requests that
+    /// differ from the recorded ones fail.
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self> {
+        let fixture = Fixture::load(path)?;
+        let nl_id = fixture.family_id;
+        let mcast_ids = fixture.mcast_ids.clone();
+
+        Ok(Self::with_transport(
+            Box::new(ReplayTransport::new(fixture)),
+            nl_id,
+            mcast_ids,
+        ))
+    }
+
+    /// Client over a custom transport. `nl_id` is the generic netlink family ID of nl80211
+    /// and `mcast_ids` are the IDs of its scan, mlme, regulatory and config multicast groups.
+    #[must_use]
+    pub fn with_transport(transport: Box<dyn Transport>, nl_id: u16, mcast_ids: Vec<u32>) -> Self {
+        let transport: Arc<dyn Transport> = Arc::from(transport);
+        let pending = Arc::new(Mutex::new(HashMap::new()));
+
+        let reader = tokio::spawn(run_reader(Arc::clone(&transport), Arc::clone(&pending)));
+
+        Self {
+            inner: Arc::new(Inner {
+                transport,
+                nl_id,
+                mcast_ids,
+                seq: AtomicU32::new(1),
+                pending,
+                dump_lock: tokio::sync::Mutex::new(()),
+                reader,
+            }),
+        }
+    }
+
+    /// Sends a request and collects its replies until the kernel acknowledges it.
+    pub async fn request(&self, msg: Nl80211Payload) -> Result<Vec<Nl80211Payload>> {
+        self.execute(msg, &[NlmF::Request, NlmF::Ack]).await
+    }
+
+    /// Sends a dump request and collects all of its replies.
+    pub async fn dump(&self, msg: Nl80211Payload) -> Result<Vec<Nl80211Payload>> {
+        let _dump_guard = self.inner.dump_lock.lock().await;
+
+        self.execute(msg, &[NlmF::Request, NlmF::Dump]).await
+    }
+
+    /// Sends a request without waiting for the kernel to process it. Usable from `Drop`.
+    pub fn send_nowait(&self, msg: Nl80211Payload) -> Result<()> {
+        let (_, buf) = self.serialize(msg, &[NlmF::Request])?;
+
+        self.inner
+            .transport
+            .send_nowait(&buf)
+            .context("Failed to send nl80211 message")?;
+
+        Ok(())
+    }
+
+    /// Generic netlink family ID, for requests sent on a dedicated socket.
+    #[must_use]
+    pub fn family_id(&self) -> u16 {
+        self.inner.nl_id
+    }
+
+    /// Subscribes to the scan, MLME, regulatory and config multicast groups. Each monitor has
+    /// its own socket, so events are not missed between calls.
+    pub fn event_monitor(&self) -> Result<EventMonitor> {
+        let transport = self
+            .inner
+            .transport
+            .open(&self.inner.mcast_ids)
+            .context("Failed to connect event monitor socket")?;
+
+        Ok(EventMonitor::new(transport))
+    }
+
+    /// Opens a socket without multicast groups, e.g. for frame registrations.
+    pub(crate) fn open_socket(&self) -> Result<Box<dyn Transport>> {
+        self.inner
+            .transport
+            .open(&[])
+            .context("Failed to connect netlink socket")
+    }
+
+    async fn execute(&self, msg: Nl80211Payload, flags: &[NlmF]) -> Result<Vec<Nl80211Payload>> {
+        let (seq, buf) = self.serialize(msg, flags)?;
+
+        let (sender, receiver) = unbounded_channel();
+        lock(&self.inner.pending).insert(seq, sender);
+        let _registration = Registration {
+            pending: &self.inner.pending,
+            seq,
+        };
+
+        self.inner
+            .transport
+            .send(&buf)
+            .await
+            .context("Failed to send nl80211 message")?;
+
+        timeout(REQUEST_TIMEOUT, collect_replies(receiver))
+            .await
+            .context("Timed out waiting for nl80211 reply")?
+    }
+
+    pub(crate) fn serialize(&self, msg: Nl80211Payload, flags: &[NlmF]) -> Result<(u32, Vec<u8>)> {
+        let seq = self.inner.seq.fetch_add(1, Ordering::Relaxed);
+        let nl_msghdr = Nlmsghdr::new(
+            None,
+            self.inner.nl_id,
+            NlmFFlags::new(flags),
+            Some(seq),
+            None,
+            NlPayload::Payload(msg),
+        );
+
+        let mut cursor = Cursor::new(Vec::new());
+        nl_msghdr
+            .to_bytes(&mut cursor)
+            .context("Failed to serialize nl80211 message")?;
+
+        Ok((seq, cursor.into_inner()))
+    }
+}
+
+async fn collect_replies(mut receiver: UnboundedReceiver<Message>) -> Result<Vec<Nl80211Payload>> {
+    let mut payloads = Vec::new();
+
+    while let Some(message) = receiver.recv().await {
+        match message {
+            Message::Payload(payload) => payloads.push(payload),
+            Message::Ack | Message::Done => return Ok(payloads),
+            Message::Error(err) => return Err(err.into()),
+        }
+    }
+
+    bail!("nl80211 reply reader stopped")
+}
+
+async fn run_reader(transport: Arc<dyn Transport>, pending: Arc<Pending>) {
+    let mut buf = vec![0; MAX_NL_LENGTH];
+
+    loop {
+        let size = match transport.recv(&mut buf).await {
+            Ok(size) => size,
+            Err(err) => {
+                println!("Failed to read from nl80211 socket: {err}");
+                break;
+            }
+        };
+
+        match parse_messages(buf.get(..size).unwrap_or_default()) {
+            Ok(frames) => dispatch(&pending, frames),
+            Err(err) => println!("Failed to parse nl80211 reply: {err:#}"),
+        }
+    }
+
+    // Dropping the reply routes fails all waiting requests
+    lock(&pending).clear();
+}
+
+fn dispatch(pending: &Pending, frames: Vec<Frame>) {
+    let routes = lock(pending);
+
+    for Frame { seq, message } in frames {
+        if let Some(sender) = routes.get(&seq) {
+            sender.send(message).ok();
+        }
+    }
+}
+
+fn lock(pending: &Pending) -> std::sync::MutexGuard<'_, HashMap<u32, UnboundedSender<Message>>> {
+    pending.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Resolves the nl80211 family and event multicast groups and sets up the request socket.
+fn connect() -> Result<(SocketTransport, u16, Vec<u32>)> {
+    let mut socket_handle = NlSocketHandle::connect(NlFamily::Generic, None, &[])
+        .context("Failed to establish netlink socket")?;
+
+    let nl_id = socket_handle
+        .resolve_genl_family(NL80211_FAMILY_NAME)
+        .context("Failed to resolve nl80211 family")?;
+
+    let mut mcast_ids = Vec::with_capacity(EVENT_MULTICAST_NAMES.len());
+    for mcast_name in EVENT_MULTICAST_NAMES {
+        let mcast_id = socket_handle
+            .resolve_nl_mcast_group(NL80211_FAMILY_NAME, mcast_name)
+            .with_context(|| format!("Failed to resolve '{mcast_name}' multicast group"))?;
+        mcast_ids.push(mcast_id);
+    }
+
+    enable_extended_ack(&socket_handle).ok();
+
+    let transport =
+        SocketTransport::from_handle(socket_handle).context("Failed to register netlink socket")?;
+
+    Ok((transport, nl_id, mcast_ids))
+}
+
+fn enable_extended_ack(socket_handle: &NlSocketHandle) -> Result<()> {
+    let enable: libc::c_int = 1;
+    let len = core::mem::size_of::<libc::c_int>().try_into()?;
+
+    // SAFETY: the option value points to a live integer of the passed length
+    let res = unsafe {
+        libc::setsockopt(
+            socket_handle.as_raw_fd(),
+            libc::SOL_NETLINK,
+            libc::NETLINK_EXT_ACK,
+            core::ptr::addr_of!(enable).cast(),
+            len,
+        )
+    };
+
+    if res < 0 {
+        return Err(io::Error::last_os_error()).context("Failed to enable extended ACK");
+    }
+
+    Ok(())
+}
@@ -1,4 +1,8 @@
+//! Command and attribute identifiers, named after the `linux/nl80211.h` constants they mirror,
+//! e.g. [`Nl80211Attr::Ifindex`] for `NL80211_ATTR_IFINDEX`.
+
 #![allow(
+    missing_docs,
     clippy::use_self,
     clippy::wildcard_imports,
     clippy::cast_possible_truncation,
@@ -8,7 +12,7 @@
 
 use neli::neli_enum;
 
-use crate::nl80211::consts::*;
+use crate::consts::*;
 
 #[neli_enum(serialized_type = "u16")]
 pub enum Nl80211Attr {
@@ -495,3 +499,126 @@ pub enum Nl80211Bss {
 }
 
 impl neli::consts::genl::NlAttrType for Nl80211Bss {}
+
+#[neli_enum(serialized_type = "u16")]
+pub enum Nl80211SchedScanMatchAttr {
+    Ssid = NL80211_SCHED_SCAN_MATCH_ATTR_SSID as u16,
+    Rssi = NL80211_SCHED_SCAN_MATCH_ATTR_RSSI as u16,
+    RelativeRssi = NL80211_SCHED_SCAN_MATCH_ATTR_RELATIVE_RSSI as u16,
+    RssiAdjust = NL80211_SCHED_SCAN_MATCH_ATTR_RSSI_ADJUST as u16,
+    Bssid = NL80211_SCHED_SCAN_MATCH_ATTR_BSSID as u16,
+    PerBandRssi = NL80211_SCHED_SCAN_MATCH_PER_BAND_RSSI as u16,
+}
+
+impl neli::consts::genl::NlAttrType for Nl80211SchedScanMatchAttr {}
+
+#[neli_enum(serialized_type = "u16")]
+pub enum Nl80211SchedScanPlan {
+    Interval = NL80211_SCHED_SCAN_PLAN_INTERVAL as u16,
+    Iterations = NL80211_SCHED_SCAN_PLAN_ITERATIONS as u16,
+}
+
+impl neli::consts::genl::NlAttrType for Nl80211SchedScanPlan {}
+
+#[neli_enum(serialized_type = "u16")]
+pub enum Nl80211StaInfo {
+    InactiveTime = NL80211_STA_INFO_INACTIVE_TIME as u16,
+    RxBytes = NL80211_STA_INFO_RX_BYTES as u16,
+    TxBytes = NL80211_STA_INFO_TX_BYTES as u16,
+    Llid = NL80211_STA_INFO_LLID as u16,
+    Plid = NL80211_STA_INFO_PLID as u16,
+    PlinkState = NL80211_STA_INFO_PLINK_STATE as u16,
+    Signal = NL80211_STA_INFO_SIGNAL as u16,
+    TxBitrate = NL80211_STA_INFO_TX_BITRATE as u16,
+    RxPackets = NL80211_STA_INFO_RX_PACKETS as u16,
+    TxPackets = NL80211_STA_INFO_TX_PACKETS as u16,
+    TxRetries = NL80211_STA_INFO_TX_RETRIES as u16,
+    TxFailed = NL80211_STA_INFO_TX_FAILED as u16,
+    SignalAvg = NL80211_STA_INFO_SIGNAL_AVG as u16,
+    RxBitrate = NL80211_STA_INFO_RX_BITRATE as u16,
+    BssParam = NL80211_STA_INFO_BSS_PARAM as u16,
+    ConnectedTime = NL80211_STA_INFO_CONNECTED_TIME as u16,
+    StaFlags = NL80211_STA_INFO_STA_FLAGS as u16,
+    BeaconLoss = NL80211_STA_INFO_BEACON_LOSS as u16,
+    TOffset = NL80211_STA_INFO_T_OFFSET as u16,
+    LocalPm = NL80211_STA_INFO_LOCAL_PM as u16,
+    PeerPm = NL80211_STA_INFO_PEER_PM as u16,
+    NonpeerPm = NL80211_STA_INFO_NONPEER_PM as u16,
+    RxBytes64 = NL80211_STA_INFO_RX_BYTES64 as u16,
+    TxBytes64 = NL80211_STA_INFO_TX_BYTES64 as u16,
+    ChainSignal = NL80211_STA_INFO_CHAIN_SIGNAL as u16,
+    ChainSignalAvg = NL80211_STA_INFO_CHAIN_SIGNAL_AVG as u16,
+    ExpectedThroughput = NL80211_STA_INFO_EXPECTED_THROUGHPUT as u16,
+    RxDropMisc = NL80211_STA_INFO_RX_DROP_MISC as u16,
+    BeaconRx = NL80211_STA_INFO_BEACON_RX as u16,
+    BeaconSignalAvg = NL80211_STA_INFO_BEACON_SIGNAL_AVG as u16,
+    TidStats = NL80211_STA_INFO_TID_STATS as u16,
+    RxDuration = NL80211_STA_INFO_RX_DURATION as u16,
+    Pad = NL80211_STA_INFO_PAD as u16,
+    AckSignal = NL80211_STA_INFO_ACK_SIGNAL as u16,
+    AckSignalAvg = NL80211_STA_INFO_ACK_SIGNAL_AVG as u16,
+    RxMpdus = NL80211_STA_INFO_RX_MPDUS as u16,
+    FcsErrorCount = NL80211_STA_INFO_FCS_ERROR_COUNT as u16,
+    ConnectedToGate = NL80211_STA_INFO_CONNECTED_TO_GATE as u16,
+    TxDuration = NL80211_STA_INFO_TX_DURATION as u16,
+    AirtimeWeight = NL80211_STA_INFO_AIRTIME_WEIGHT as u16,
+    AirtimeLinkMetric = NL80211_STA_INFO_AIRTIME_LINK_METRIC as u16,
+    AssocAtBoottime = NL80211_STA_INFO_ASSOC_AT_BOOTTIME as u16,
+    ConnectedToAs = NL80211_STA_INFO_CONNECTED_TO_AS as u16,
+}
+
+impl neli::consts::genl::NlAttrType for Nl80211StaInfo {}
+
+#[neli_enum(serialized_type = "u16")]
+pub enum Nl80211RateInfo {
+    Bitrate = NL80211_RATE_INFO_BITRATE as u16,
+    Mcs = NL80211_RATE_INFO_MCS as u16,
+    Width40Mhz = NL80211_RATE_INFO_40_MHZ_WIDTH as u16,
+    ShortGi = NL80211_RATE_INFO_SHORT_GI as u16,
+    Bitrate32 = NL80211_RATE_INFO_BITRATE32 as u16,
+    VhtMcs = NL80211_RATE_INFO_VHT_MCS as u16,
+    VhtNss = NL80211_RATE_INFO_VHT_NSS as u16,
+    Width80Mhz = NL80211_RATE_INFO_80_MHZ_WIDTH as u16,
+    Width80p80Mhz = NL80211_RATE_INFO_80P80_MHZ_WIDTH as u16,
+    Width160Mhz = NL80211_RATE_INFO_160_MHZ_WIDTH as u16,
+    Width10Mhz = NL80211_RATE_INFO_10_MHZ_WIDTH as u16,
+    Width5Mhz = NL80211_RATE_INFO_5_MHZ_WIDTH as u16,
+    HeMcs = NL80211_RATE_INFO_HE_MCS as u16,
+    HeNss = NL80211_RATE_INFO_HE_NSS as u16,
+    HeGi = NL80211_RATE_INFO_HE_GI as u16,
+    HeDcm = NL80211_RATE_INFO_HE_DCM as u16,
+    HeRuAlloc = NL80211_RATE_INFO_HE_RU_ALLOC as u16,
+}
+
+impl neli::consts::genl::NlAttrType for Nl80211RateInfo {}
+
+#[neli_enum(serialized_type = "u16")]
+pub enum Nl80211SurveyInfo {
+    Frequency = NL80211_SURVEY_INFO_FREQUENCY as u16,
+    Noise = NL80211_SURVEY_INFO_NOISE as u16,
+    InUse = NL80211_SURVEY_INFO_IN_USE as u16,
+    Time = NL80211_SURVEY_INFO_TIME as u16,
+    TimeBusy = NL80211_SURVEY_INFO_TIME_BUSY as u16,
+    TimeExtBusy = NL80211_SURVEY_INFO_TIME_EXT_BUSY as u16,
+    TimeRx = NL80211_SURVEY_INFO_TIME_RX as u16,
+    TimeTx = NL80211_SURVEY_INFO_TIME_TX as u16,
+    TimeScan = NL80211_SURVEY_INFO_TIME_SCAN as u16,
+    Pad = NL80211_SURVEY_INFO_PAD as u16,
+    TimeBssRx = NL80211_SURVEY_INFO_TIME_BSS_RX as u16,
+    FrequencyOffset = NL80211_SURVEY_INFO_FREQUENCY_OFFSET as u16,
+}
+
+impl neli::consts::genl::NlAttrType for Nl80211SurveyInfo {}
+
+#[neli_enum(serialized_type = "u16")]
+pub enum Nl80211RegRuleAttr {
+    Flags = NL80211_ATTR_REG_RULE_FLAGS as u16,
+    FreqRangeStart = NL80211_ATTR_FREQ_RANGE_START as u16,
+    FreqRangeEnd = NL80211_ATTR_FREQ_RANGE_END as u16,
+    FreqRangeMaxBw = NL80211_ATTR_FREQ_RANGE_MAX_BW as u16,
+    PowerRuleMaxAntGain = NL80211_ATTR_POWER_RULE_MAX_ANT_GAIN as u16,
+    PowerRuleMaxEirp = NL80211_ATTR_POWER_RULE_MAX_EIRP as u16,
+    DfsCacTime = NL80211_ATTR_DFS_CAC_TIME as u16,
+}
+
+impl neli::consts::genl::NlAttrType for Nl80211RegRuleAttr {}
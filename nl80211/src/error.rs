@@ -0,0 +1,147 @@
+//! Errors reported by the kernel.
+
+use core::fmt;
+use std::io;
+
+/// Classification of the errno values callers commonly handle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The device is busy, e.g. with another scan, and the request can be retried
+    Busy,
+    /// The process lacks `CAP_NET_ADMIN`
+    PermissionDenied,
+    /// The interface or wiphy does not exist
+    NoDevice,
+    /// The driver or the current interface state does not support the request
+    NotSupported,
+    /// The request is in effect already, e.g. another socket registered for the frame type
+    AlreadyActive,
+    /// Any other error
+    Other,
+}
+
+impl From<i32> for ErrorKind {
+    fn from(errno: i32) -> Self {
+        match errno {
+            libc::EBUSY => Self::Busy,
+            libc::EPERM | libc::EACCES => Self::PermissionDenied,
+            libc::ENODEV => Self::NoDevice,
+            libc::EOPNOTSUPP => Self::NotSupported,
+            libc::EALREADY => Self::AlreadyActive,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Error reported by the kernel in a `NLMSG_ERROR` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nl80211Error {
+    errno: i32,
+    message: Option<String>,
+}
+
+impl Nl80211Error {
+    /// The error code is the positive errno value, as opposed to the negated one on the wire.
+    #[must_use]
+    pub const fn new(errno: i32, message: Option<String>) -> Self {
+        Self { errno, message }
+    }
+
+    /// Classification of the error code.
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        self.errno.into()
+    }
+
+    /// Positive errno value.
+    #[must_use]
+    pub const fn errno(&self) -> i32 {
+        self.errno
+    }
+
+    /// Extended ACK text message, if the kernel supplied one.
+    #[must_use]
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
+
+impl fmt::Display for Nl80211Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = io::Error::from_raw_os_error(self.errno);
+
+        match self.message {
+            Some(ref message) => write!(f, "{description}: {message}"),
+            None => write!(f, "{description}"),
+        }
+    }
+}
+
+impl std::error::Error for Nl80211Error {}
+
+/// Whether the error is an [`Nl80211Error`] of the [`ErrorKind::Busy`] kind.
+pub fn is_busy(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<Nl80211Error>().map(Nl80211Error::kind),
+        Some(ErrorKind::Busy)
+    )
+}
+
+/// Whether the error is an [`Nl80211Error`] of the [`ErrorKind::NotSupported`] kind.
+pub fn is_not_supported(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<Nl80211Error>().map(Nl80211Error::kind),
+        Some(ErrorKind::NotSupported)
+    )
+}
+
+/// Whether the error is an [`Nl80211Error`] of the [`ErrorKind::AlreadyActive`] kind.
+pub fn is_already_active(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<Nl80211Error>().map(Nl80211Error::kind),
+        Some(ErrorKind::AlreadyActive)
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    fn error(errno: i32) -> anyhow::Error {
+        let result: Result<(), _> = Err(Nl80211Error::new(errno, None));
+        result.context("Failed to trigger scan").unwrap_err()
+    }
+
+    #[test]
+    fn classifies_errors_through_context() {
+        assert!(is_busy(&error(libc::EBUSY)));
+        assert!(!is_busy(&error(libc::EOPNOTSUPP)));
+        assert!(is_not_supported(&error(libc::EOPNOTSUPP)));
+        assert!(!is_not_supported(&error(libc::EBUSY)));
+        assert!(is_already_active(&error(libc::EALREADY)));
+        assert!(!is_busy(&anyhow!("Device or resource busy")));
+    }
+
+    #[test]
+    fn classifies_errno_values() {
+        assert_eq!(ErrorKind::from(libc::EPERM), ErrorKind::PermissionDenied);
+        assert_eq!(ErrorKind::from(libc::EACCES), ErrorKind::PermissionDenied);
+        assert_eq!(ErrorKind::from(libc::ENODEV), ErrorKind::NoDevice);
+        assert_eq!(ErrorKind::from(libc::EALREADY), ErrorKind::AlreadyActive);
+        assert_eq!(ErrorKind::from(libc::EINVAL), ErrorKind::Other);
+    }
+
+    #[test]
+    fn displays_ext_ack_message() {
+        let err = Nl80211Error::new(libc::EINVAL, Some("Invalid channel".to_owned()));
+
+        assert!(err.to_string().ends_with(": Invalid channel"));
+        assert_eq!(
+            Nl80211Error::new(libc::EINVAL, None).to_string(),
+            std::io::Error::from_raw_os_error(libc::EINVAL).to_string()
+        );
+    }
+}
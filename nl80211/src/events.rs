@@ -0,0 +1,202 @@
+//! Notifications multicast by the kernel.
+
+use core::convert::{TryFrom, TryInto};
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result};
+
+use futures_util::future::ready;
+use futures_util::stream::{unfold, Stream, StreamExt};
+
+use macaddr::MacAddr6;
+
+use neli::consts::MAX_NL_LENGTH;
+
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::interface::Interface;
+use crate::message::{parse_string, recv_frames, Message, Nl80211Payload};
+use crate::transport::Transport;
+
+pub(crate) const EVENT_MULTICAST_NAMES: [&str; 4] = ["scan", "mlme", "regulatory", "config"];
+
+/// Event received by an [`EventMonitor`]. Fields named `ifindex` hold the index of the
+/// interface the event happened on.
+#[allow(missing_docs)]
+#[derive(Debug, Clone)]
+pub enum Nl80211Event {
+    /// A scan was triggered, by this or another process
+    ScanStarted { ifindex: u32 },
+    /// A scan was aborted and has no results
+    ScanAborted { ifindex: u32 },
+    /// A scan completed and its results are in the BSS table
+    ScanDone { ifindex: u32 },
+    /// A scheduled scan has results available
+    SchedScanResults { ifindex: u32 },
+    /// A scheduled scan was stopped, e.g. by the driver
+    SchedScanStopped { ifindex: u32 },
+    /// A connection attempt finished with the IEEE 802.11 status code
+    Connect {
+        ifindex: u32,
+        bssid: Option<MacAddr6>,
+        status: Option<u16>,
+    },
+    /// The connection was lost, with the IEEE 802.11 reason code
+    Disconnect {
+        ifindex: u32,
+        reason: Option<u16>,
+        by_ap: bool,
+    },
+    /// A station associated with the access point
+    NewStation { ifindex: u32, mac_address: MacAddr6 },
+    /// A station left the access point
+    DelStation { ifindex: u32, mac_address: MacAddr6 },
+    /// The regulatory domain changed
+    RegChange { alpha2: Option<String> },
+    /// An interface was created
+    InterfaceAdded(Interface),
+    /// An interface was removed
+    InterfaceRemoved(Interface),
+}
+
+impl Nl80211Event {
+    /// Interface the event relates to. Regulatory changes are global and have none.
+    #[must_use]
+    pub const fn ifindex(&self) -> Option<u32> {
+        match *self {
+            Self::ScanStarted { ifindex }
+            | Self::ScanAborted { ifindex }
+            | Self::ScanDone { ifindex }
+            | Self::SchedScanResults { ifindex }
+            | Self::SchedScanStopped { ifindex }
+            | Self::Connect { ifindex, .. }
+            | Self::Disconnect { ifindex, .. }
+            | Self::NewStation { ifindex, .. }
+            | Self::DelStation { ifindex, .. } => Some(ifindex),
+            Self::InterfaceAdded(ref iface) | Self::InterfaceRemoved(ref iface) => {
+                Some(iface.index)
+            }
+            Self::RegChange { .. } => None,
+        }
+    }
+
+    fn from_payload(payload: &Nl80211Payload) -> Option<Self> {
+        let attrs = payload.get_attr_handle();
+        let ifindex = || attrs.get_attr_payload_as::<u32>(Nl80211Attr::Ifindex).ok();
+        let mac_address = || {
+            let mac_bytes: [u8; 6] = attrs
+                .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Mac)
+                .ok()?
+                .try_into()
+                .ok()?;
+            Some(MacAddr6::from(mac_bytes))
+        };
+
+        let event = match payload.cmd {
+            Nl80211Cmd::TriggerScan => Self::ScanStarted {
+                ifindex: ifindex()?,
+            },
+            Nl80211Cmd::ScanAborted => Self::ScanAborted {
+                ifindex: ifindex()?,
+            },
+            Nl80211Cmd::NewScanResults => Self::ScanDone {
+                ifindex: ifindex()?,
+            },
+            Nl80211Cmd::SchedScanResults => Self::SchedScanResults {
+                ifindex: ifindex()?,
+            },
+            Nl80211Cmd::SchedScanStopped => Self::SchedScanStopped {
+                ifindex: ifindex()?,
+            },
+            Nl80211Cmd::Connect => Self::Connect {
+                ifindex: ifindex()?,
+                bssid: mac_address(),
+                status: attrs.get_attr_payload_as(Nl80211Attr::StatusCode).ok(),
+            },
+            Nl80211Cmd::Disconnect => Self::Disconnect {
+                ifindex: ifindex()?,
+                reason: attrs.get_attr_payload_as(Nl80211Attr::ReasonCode).ok(),
+                by_ap: attrs.get_attribute(Nl80211Attr::DisconnectedByAp).is_some(),
+            },
+            Nl80211Cmd::NewStation => Self::NewStation {
+                ifindex: ifindex()?,
+                mac_address: mac_address()?,
+            },
+            Nl80211Cmd::DelStation => Self::DelStation {
+                ifindex: ifindex()?,
+                mac_address: mac_address()?,
+            },
+            Nl80211Cmd::RegChange => Self::RegChange {
+                alpha2: attrs
+                    .get_attr_payload_as_with_len(Nl80211Attr::RegAlpha2)
+                    .ok()
+                    .and_then(|alpha2| parse_string(alpha2).ok()),
+            },
+            Nl80211Cmd::NewInterface => Self::InterfaceAdded(Interface::try_from(payload).ok()?),
+            Nl80211Cmd::DelInterface => Self::InterfaceRemoved(Interface::try_from(payload).ok()?),
+            _ => return None,
+        };
+
+        Some(event)
+    }
+}
+
+/// Listens on the nl80211 scan, mlme, regulatory and config multicast groups.
+pub struct EventMonitor {
+    transport: Box<dyn Transport>,
+    buf: Vec<u8>,
+    pending: VecDeque<Nl80211Event>,
+}
+
+impl core::fmt::Debug for EventMonitor {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EventMonitor")
+            .field("pending", &self.pending)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventMonitor {
+    /// Takes a socket joined to the `EVENT_MULTICAST_NAMES` groups.
+    pub(crate) fn new(transport: Box<dyn Transport>) -> Self {
+        Self {
+            transport,
+            buf: vec![0; MAX_NL_LENGTH],
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Waits for the next event. Notifications without a corresponding event are skipped.
+    pub async fn next_event(&mut self) -> Result<Nl80211Event> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+
+            let frames = recv_frames(&*self.transport, &mut self.buf)
+                .await
+                .context("Failed to receive nl80211 event")?;
+
+            self.pending
+                .extend(frames.iter().filter_map(|frame| match frame.message {
+                    Message::Payload(ref payload) => Nl80211Event::from_payload(payload),
+                    _ => None,
+                }));
+        }
+    }
+
+    /// Stream of events for the given interface index. Global events are always passed through.
+    pub fn into_stream(self, ifindex: Option<u32>) -> impl Stream<Item = Result<Nl80211Event>> {
+        let events = unfold(self, |mut monitor| async move {
+            let event = monitor.next_event().await;
+            Some((event, monitor))
+        });
+
+        events.filter(move |event| {
+            let matching = match (ifindex, event) {
+                (Some(index), Ok(event)) => !matches!(event.ifindex(), Some(i) if i != index),
+                _ => true,
+            };
+            ready(matching)
+        })
+    }
+}
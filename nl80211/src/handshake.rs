@@ -0,0 +1,719 @@
+//! Authenticator side of the WPA2 4-way handshake with pre-shared keys, see IEEE 802.11-2020
+//! 12.7.6. The pairwise key of a client is derived from the passphrase and both nonces, and
+//! the group key is handed to the client in the third message. Only CCMP is supported.
+
+use core::array;
+use core::convert::{TryFrom, TryInto};
+use core::time::Duration;
+use std::fs::File;
+use std::io::Read;
+use std::time::Instant;
+
+use aes_kw::KekAes128;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+
+use hmac::{Hmac, Mac};
+
+use macaddr::MacAddr6;
+
+use sha1::Sha1;
+
+use crate::scan::information_elements;
+use crate::ssid::Ssid;
+
+type HmacSha1 = Hmac<Sha1>;
+
+/// Ethernet protocol of EAPOL frames, which the kernel passes over the control port.
+pub const ETH_P_PAE: u16 = 0x888e;
+
+/// Suite selectors with the 00-0F-AC OUI, as nl80211 expects them. The elements carry the
+/// big endian bytes.
+pub const CIPHER_SUITE_CCMP: u32 = 0x000f_ac04;
+pub const AKM_SUITE_PSK: u32 = 0x000f_ac02;
+
+pub const WLAN_EID_RSN: u8 = 48;
+const RSN_VERSION: u16 = 1;
+
+pub const PTK_KEY_INDEX: u8 = 0;
+pub const GTK_KEY_INDEX: u8 = 1;
+pub const KEY_LEN: usize = 16;
+
+const PMK_LEN: usize = 32;
+// Key confirmation, key encryption and temporal keys
+const PTK_LEN: usize = 48;
+const NONCE_LEN: usize = 32;
+const MIC_LEN: usize = 16;
+const PSK_ITERATIONS: u32 = 4096;
+const MIN_PASSPHRASE_LEN: usize = 8;
+const MAX_PASSPHRASE_LEN: usize = 63;
+
+const EAPOL_VERSION: u8 = 2;
+const EAPOL_TYPE_KEY: u8 = 3;
+const KEY_DESCRIPTOR_RSN: u8 = 2;
+
+const KEY_INFO_VERSION_AES_HMAC_SHA1: u16 = 0x0002;
+const KEY_INFO_PAIRWISE: u16 = 0x0008;
+const KEY_INFO_INSTALL: u16 = 0x0040;
+const KEY_INFO_ACK: u16 = 0x0080;
+const KEY_INFO_MIC: u16 = 0x0100;
+const KEY_INFO_SECURE: u16 = 0x0200;
+const KEY_INFO_ENCRYPTED_KEY_DATA: u16 = 0x1000;
+
+// Offsets within the EAPOL frame, which starts with a 4 byte header
+const EAPOL_HEADER_LEN: usize = 4;
+const DESCRIPTOR_TYPE_OFFSET: usize = 4;
+const KEY_INFO_OFFSET: usize = 5;
+const REPLAY_COUNTER_OFFSET: usize = 9;
+const NONCE_OFFSET: usize = 17;
+const MIC_OFFSET: usize = 81;
+const KEY_DATA_LEN_OFFSET: usize = 97;
+const KEY_FRAME_LEN: usize = 99;
+
+// Key data elements are padded to the 8 byte blocks of the key wrap, with at least two blocks
+const KEY_WRAP_BLOCK_LEN: usize = 8;
+const MIN_WRAPPED_LEN: usize = 16;
+const WLAN_EID_VENDOR_SPECIFIC: u8 = 0xdd;
+const GTK_KDE_SELECTOR: [u8; 4] = [0x00, 0x0f, 0xac, 0x01];
+
+/// Messages 1 and 3 are sent again after this long without an answer.
+pub const RETRANSMIT_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_ATTEMPTS: u32 = 4;
+
+/// RSN element advertising CCMP for both pairwise and group traffic and pre-shared keys.
+pub fn rsn_element() -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(RSN_VERSION.to_le_bytes());
+    data.extend(CIPHER_SUITE_CCMP.to_be_bytes());
+    data.extend(1_u16.to_le_bytes());
+    data.extend(CIPHER_SUITE_CCMP.to_be_bytes());
+    data.extend(1_u16.to_le_bytes());
+    data.extend(AKM_SUITE_PSK.to_be_bytes());
+    // No capabilities, which leaves management frame protection disabled
+    data.extend([0; 2]);
+    data
+}
+
+/// Pairwise master key of a passphrase of 8 to 63 printable ASCII characters, salted with the
+/// network name.
+pub fn derive_pmk(passphrase: &str, ssid: &Ssid) -> Result<[u8; PMK_LEN]> {
+    if !(MIN_PASSPHRASE_LEN..=MAX_PASSPHRASE_LEN).contains(&passphrase.len())
+        || !passphrase
+            .bytes()
+            .all(|byte| byte == b' ' || byte.is_ascii_graphic())
+    {
+        bail!("WPA2 passphrases are 8 to 63 printable ASCII characters");
+    }
+
+    Ok(pbkdf2::pbkdf2_hmac_array::<Sha1, PMK_LEN>(
+        passphrase.as_bytes(),
+        ssid.as_bytes(),
+        PSK_ITERATIONS,
+    ))
+}
+
+fn hmac_sha1(key: &[u8]) -> HmacSha1 {
+    HmacSha1::new_from_slice(key).expect("HMAC accepts keys of any length")
+}
+
+/// Pseudo-random function of IEEE 802.11-2020 12.7.1.2, concatenating HMAC-SHA1 digests.
+fn prf(key: &[u8], label: &[u8], data: &[u8], len: usize) -> Vec<u8> {
+    let mut output = Vec::with_capacity(len);
+
+    for counter in 0..=u8::MAX {
+        if output.len() >= len {
+            break;
+        }
+
+        let mut mac = hmac_sha1(key);
+        mac.update(label);
+        mac.update(&[0]);
+        mac.update(data);
+        mac.update(&[counter]);
+        output.extend(mac.finalize().into_bytes());
+    }
+
+    output.truncate(len);
+    output
+}
+
+/// Pairwise transient key, split into the keys protecting the handshake and the traffic key.
+struct Ptk {
+    kck: [u8; KEY_LEN],
+    kek: [u8; KEY_LEN],
+    tk: [u8; KEY_LEN],
+}
+
+impl Ptk {
+    fn derive(
+        pmk: &[u8],
+        authenticator: MacAddr6,
+        supplicant: MacAddr6,
+        anonce: &[u8; NONCE_LEN],
+        snonce: &[u8; NONCE_LEN],
+    ) -> Self {
+        let mut addresses = [authenticator.as_bytes(), supplicant.as_bytes()];
+        addresses.sort_unstable();
+        let mut nonces = [anonce, snonce];
+        nonces.sort_unstable();
+
+        let data = addresses
+            .into_iter()
+            .flatten()
+            .chain(nonces.into_iter().flatten())
+            .copied()
+            .collect::<Vec<_>>();
+
+        let mut keys = prf(pmk, b"Pairwise key expansion", &data, PTK_LEN).into_iter();
+        let mut next_key = || array::from_fn(|_| keys.next().unwrap_or_default());
+
+        Self {
+            kck: next_key(),
+            kek: next_key(),
+            tk: next_key(),
+        }
+    }
+}
+
+/// EAPOL-Key frame received from a client.
+struct KeyMessage<'a> {
+    frame: &'a [u8],
+    key_info: u16,
+    replay_counter: u64,
+    nonce: [u8; NONCE_LEN],
+    key_data: &'a [u8],
+}
+
+impl<'a> KeyMessage<'a> {
+    /// Other EAPOL frames, e.g. EAPOL-Start, are not key messages.
+    fn parse(frame: &'a [u8]) -> Option<Self> {
+        let header = frame.get(..KEY_FRAME_LEN)?;
+        if header.get(1) != Some(&EAPOL_TYPE_KEY)
+            || header.get(DESCRIPTOR_TYPE_OFFSET) != Some(&KEY_DESCRIPTOR_RSN)
+        {
+            return None;
+        }
+
+        // Trailing padding of short Ethernet frames is not part of the body
+        let body_len = usize::from(read_u16(header, 2)?);
+        let frame = frame.get(..EAPOL_HEADER_LEN.checked_add(body_len)?)?;
+
+        let key_data_len = usize::from(read_u16(header, KEY_DATA_LEN_OFFSET)?);
+        let key_data_end = KEY_FRAME_LEN.checked_add(key_data_len)?;
+
+        Some(Self {
+            frame,
+            key_info: read_u16(header, KEY_INFO_OFFSET)?,
+            replay_counter: u64::from_be_bytes(
+                header
+                    .get(REPLAY_COUNTER_OFFSET..NONCE_OFFSET)?
+                    .try_into()
+                    .ok()?,
+            ),
+            nonce: header
+                .get(NONCE_OFFSET..NONCE_OFFSET.checked_add(NONCE_LEN)?)?
+                .try_into()
+                .ok()?,
+            key_data: frame.get(KEY_FRAME_LEN..key_data_end)?,
+        })
+    }
+
+    fn has_valid_mic(&self, kck: &[u8; KEY_LEN]) -> bool {
+        let mic_end = MIC_OFFSET.saturating_add(MIC_LEN);
+        let Some(mic) = self.frame.get(MIC_OFFSET..mic_end) else {
+            return false;
+        };
+
+        let mut zeroed = self.frame.to_vec();
+        zeroed
+            .get_mut(MIC_OFFSET..mic_end)
+            .into_iter()
+            .flatten()
+            .for_each(|byte| *byte = 0);
+
+        let mut mac = hmac_sha1(kck);
+        mac.update(&zeroed);
+        mac.verify_truncated_left(mic).is_ok()
+    }
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        bytes.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+/// Keys shared by all clients of the network.
+pub struct Authenticator {
+    address: MacAddr6,
+    pmk: [u8; PMK_LEN],
+    gtk: [u8; KEY_LEN],
+}
+
+impl Authenticator {
+    /// The group key is generated once and kept for the lifetime of the network.
+    pub fn new(address: MacAddr6, ssid: &Ssid, passphrase: &str) -> Result<Self> {
+        Ok(Self {
+            address,
+            pmk: derive_pmk(passphrase, ssid)?,
+            gtk: random_bytes()?,
+        })
+    }
+
+    pub const fn gtk(&self) -> &[u8; KEY_LEN] {
+        &self.gtk
+    }
+
+    /// Starts the handshake with a freshly associated client by building message 1. The RSN
+    /// element of the association request is checked against message 2.
+    pub fn start(
+        &self,
+        client: MacAddr6,
+        client_rsn_element: &[u8],
+    ) -> Result<(Handshake, Vec<u8>)> {
+        let handshake = Handshake {
+            client,
+            client_rsn_element: client_rsn_element.to_vec(),
+            anonce: random_bytes()?,
+            replay_counter: 1,
+            ptk: None,
+            completed: false,
+            sent_at: Instant::now(),
+            attempts: 1,
+        };
+
+        let message = self.build_message(&handshake)?;
+
+        Ok((handshake, message))
+    }
+
+    /// Handles an EAPOL frame of the client. Message 2 is answered with message 3, message 4
+    /// completes the handshake with the traffic key to install for the client.
+    pub fn receive(&self, handshake: &mut Handshake, frame: &[u8]) -> Result<Step> {
+        let Some(message) = KeyMessage::parse(frame) else {
+            return Ok(Step::Ignore);
+        };
+
+        // Retransmissions of superseded messages carry earlier replay counters
+        if handshake.completed
+            || message.key_info & (KEY_INFO_PAIRWISE | KEY_INFO_ACK | KEY_INFO_MIC)
+                != KEY_INFO_PAIRWISE | KEY_INFO_MIC
+            || message.replay_counter != handshake.replay_counter
+        {
+            return Ok(Step::Ignore);
+        }
+
+        let client = handshake.client;
+
+        if let Some(ref ptk) = handshake.ptk {
+            if message.key_info & KEY_INFO_SECURE == 0 {
+                return Ok(Step::Ignore);
+            }
+
+            ensure!(
+                message.has_valid_mic(&ptk.kck),
+                "Message 4 of the handshake with {client} has an invalid MIC"
+            );
+
+            let tk = ptk.tk;
+            handshake.completed = true;
+
+            return Ok(Step::Complete(tk));
+        }
+
+        let ptk = Ptk::derive(
+            &self.pmk,
+            self.address,
+            client,
+            &handshake.anonce,
+            &message.nonce,
+        );
+
+        ensure!(
+            message.has_valid_mic(&ptk.kck),
+            "Message 2 of the handshake with {client} has an invalid MIC, the client likely \
+             uses a wrong passphrase"
+        );
+
+        let rsn_element = information_elements(message.key_data)
+            .find(|&(eid, _)| eid == WLAN_EID_RSN)
+            .map(|(_, data)| data);
+        ensure!(
+            rsn_element == Some(handshake.client_rsn_element.as_slice()),
+            "RSN element of {client} differs from its association request"
+        );
+
+        handshake.ptk = Some(ptk);
+        handshake.advance()?;
+        handshake.attempts = 1;
+
+        self.build_message(handshake).map(Step::Reply)
+    }
+
+    /// Sends message 1 or 3 again with a new replay counter. Returns `None` once the client
+    /// failed to answer all attempts.
+    pub fn retransmit(&self, handshake: &mut Handshake) -> Result<Option<Vec<u8>>> {
+        if handshake.attempts >= MAX_ATTEMPTS {
+            return Ok(None);
+        }
+
+        handshake.advance()?;
+        handshake.attempts = handshake.attempts.saturating_add(1);
+
+        self.build_message(handshake).map(Some)
+    }
+
+    fn build_message(&self, handshake: &Handshake) -> Result<Vec<u8>> {
+        let Some(ref ptk) = handshake.ptk else {
+            return key_frame(
+                KEY_INFO_VERSION_AES_HMAC_SHA1 | KEY_INFO_PAIRWISE | KEY_INFO_ACK,
+                handshake.replay_counter,
+                &handshake.anonce,
+                &[],
+            );
+        };
+
+        let mut frame = key_frame(
+            KEY_INFO_VERSION_AES_HMAC_SHA1
+                | KEY_INFO_PAIRWISE
+                | KEY_INFO_INSTALL
+                | KEY_INFO_ACK
+                | KEY_INFO_MIC
+                | KEY_INFO_SECURE
+                | KEY_INFO_ENCRYPTED_KEY_DATA,
+            handshake.replay_counter,
+            &handshake.anonce,
+            &self.wrapped_key_data(&ptk.kek)?,
+        )?;
+
+        write_mic(&ptk.kck, &mut frame)?;
+
+        Ok(frame)
+    }
+
+    /// Key data of message 3: the RSN element of the network and the group key, encrypted
+    /// with the key encryption key.
+    fn wrapped_key_data(&self, kek: &[u8; KEY_LEN]) -> Result<Vec<u8>> {
+        let rsn = rsn_element();
+
+        let mut data = vec![WLAN_EID_RSN];
+        data.push(u8::try_from(rsn.len())?);
+        data.extend(rsn);
+
+        // Key ID without the Tx bit and a reserved octet precede the key
+        let gtk_kde = GTK_KDE_SELECTOR
+            .into_iter()
+            .chain([GTK_KEY_INDEX, 0])
+            .chain(self.gtk)
+            .collect::<Vec<_>>();
+        data.push(WLAN_EID_VENDOR_SPECIFIC);
+        data.push(u8::try_from(gtk_kde.len())?);
+        data.extend(gtk_kde);
+
+        let unaligned = |bytes: &[u8]| {
+            bytes.len().checked_rem(KEY_WRAP_BLOCK_LEN) != Some(0) || bytes.len() < MIN_WRAPPED_LEN
+        };
+        if unaligned(&data) {
+            data.push(WLAN_EID_VENDOR_SPECIFIC);
+            while unaligned(&data) {
+                data.push(0);
+            }
+        }
+
+        KekAes128::from(*kek)
+            .wrap_vec(&data)
+            .map_err(|err| anyhow!("Failed to wrap key data: {err}"))
+    }
+}
+
+/// Progress of the handshake with a client.
+pub struct Handshake {
+    client: MacAddr6,
+    client_rsn_element: Vec<u8>,
+    anonce: [u8; NONCE_LEN],
+    replay_counter: u64,
+    /// Derived once message 2 is verified
+    ptk: Option<Ptk>,
+    completed: bool,
+    sent_at: Instant,
+    attempts: u32,
+}
+
+impl Handshake {
+    /// Whether the last message is unanswered past the retransmission timeout.
+    pub fn timed_out(&self) -> bool {
+        !self.completed && self.sent_at.elapsed() >= RETRANSMIT_TIMEOUT
+    }
+
+    /// Every message sent takes a new replay counter.
+    fn advance(&mut self) -> Result<()> {
+        self.replay_counter = self
+            .replay_counter
+            .checked_add(1)
+            .context("Replay counter overflow")?;
+        self.sent_at = Instant::now();
+        Ok(())
+    }
+}
+
+/// Outcome of a frame received during the handshake.
+pub enum Step {
+    /// Frame to send to the client
+    Reply(Vec<u8>),
+    /// Traffic key of the client, once it confirmed the keys
+    Complete([u8; KEY_LEN]),
+    Ignore,
+}
+
+/// EAPOL-Key frame with an empty IV, receive sequence counter and MIC.
+fn key_frame(
+    key_info: u16,
+    replay_counter: u64,
+    nonce: &[u8; NONCE_LEN],
+    key_data: &[u8],
+) -> Result<Vec<u8>> {
+    let key_data_len = u16::try_from(key_data.len()).context("Key data too long")?;
+    let body_len = KEY_FRAME_LEN
+        .checked_sub(EAPOL_HEADER_LEN)
+        .and_then(|len| len.checked_add(key_data.len()))
+        .and_then(|len| u16::try_from(len).ok())
+        .context("Key data too long")?;
+
+    let mut frame = Vec::with_capacity(KEY_FRAME_LEN.saturating_add(key_data.len()));
+    frame.extend([EAPOL_VERSION, EAPOL_TYPE_KEY]);
+    frame.extend(body_len.to_be_bytes());
+    frame.push(KEY_DESCRIPTOR_RSN);
+    frame.extend(key_info.to_be_bytes());
+    frame.extend(u16::try_from(KEY_LEN)?.to_be_bytes());
+    frame.extend(replay_counter.to_be_bytes());
+    frame.extend(nonce);
+    // IV, receive sequence counter, reserved key ID and MIC
+    frame.resize(KEY_DATA_LEN_OFFSET, 0);
+    frame.extend(key_data_len.to_be_bytes());
+    frame.extend(key_data);
+
+    Ok(frame)
+}
+
+/// Fills in the MIC of a frame built with an empty one.
+fn write_mic(kck: &[u8; KEY_LEN], frame: &mut [u8]) -> Result<()> {
+    let mut mac = hmac_sha1(kck);
+    mac.update(frame);
+    let mic = mac.finalize().into_bytes();
+
+    frame
+        .get_mut(MIC_OFFSET..MIC_OFFSET.saturating_add(MIC_LEN))
+        .context("Truncated EAPOL-Key frame")?
+        .copy_from_slice(mic.get(..MIC_LEN).context("Truncated MIC")?);
+
+    Ok(())
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N]> {
+    let mut bytes = [0; N];
+    File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .context("Failed to read random bytes")?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::assertions_on_result_states)]
+mod tests {
+    use super::*;
+
+    const AP: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x01, 0x00];
+    const CLIENT: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x02, 0x00];
+    const SNONCE: [u8; NONCE_LEN] = [0x5a; NONCE_LEN];
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn authenticator(passphrase: &str) -> Authenticator {
+        Authenticator::new(MacAddr6::from(AP), &Ssid::from("portal"), passphrase).unwrap()
+    }
+
+    fn client_rsn_element() -> Vec<u8> {
+        [WLAN_EID_RSN, 20]
+            .into_iter()
+            .chain(rsn_element())
+            .collect()
+    }
+
+    /// Answer of the client to message 1 or 3, signed with the key it derived.
+    fn answer(request: &[u8], key_info: u16, kck: &[u8; KEY_LEN], key_data: &[u8]) -> Vec<u8> {
+        let request = KeyMessage::parse(request).unwrap();
+        let mut frame = key_frame(
+            KEY_INFO_VERSION_AES_HMAC_SHA1 | KEY_INFO_PAIRWISE | KEY_INFO_MIC | key_info,
+            request.replay_counter,
+            &SNONCE,
+            key_data,
+        )
+        .unwrap();
+        write_mic(kck, &mut frame).unwrap();
+        frame
+    }
+
+    fn client_ptk(passphrase: &str, message_1: &[u8]) -> Ptk {
+        let pmk = derive_pmk(passphrase, &Ssid::from("portal")).unwrap();
+        let anonce = KeyMessage::parse(message_1).unwrap().nonce;
+        Ptk::derive(
+            &pmk,
+            MacAddr6::from(AP),
+            MacAddr6::from(CLIENT),
+            &anonce,
+            &SNONCE,
+        )
+    }
+
+    #[test]
+    fn derives_pmk_from_passphrase() {
+        // IEEE 802.11-2020 J.4.2
+        let pmk = derive_pmk("password", &Ssid::from("IEEE")).unwrap();
+
+        assert_eq!(
+            hex(&pmk),
+            "f42c6fc52df0ebef9ebb4b90b38a5f902e83fe1b135a70e23aed762e9710a12e"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_passphrases() {
+        let too_long = "a".repeat(64);
+
+        for passphrase in ["short", too_long.as_str(), "pass\tword", "pässword"] {
+            assert!(derive_pmk(passphrase, &Ssid::from("IEEE")).is_err());
+        }
+    }
+
+    #[test]
+    fn expands_keys_with_prf() {
+        // IEEE 802.11-2020 J.3.2
+        let output = prf(&[0x0b; 20], b"prefix", b"Hi There", 64);
+
+        assert_eq!(
+            hex(&output),
+            "bcd4c650b30b9684951829e0d75f9d54b862175ed9f00606e17d8da35402ffee\
+             75df78c3d31e0f889f012120c0862beb67753e7439ae242edb8373698356cf5a"
+        );
+    }
+
+    #[test]
+    fn completes_handshake() {
+        let authenticator = authenticator("portal123");
+        let (mut handshake, message_1) = authenticator
+            .start(MacAddr6::from(CLIENT), &rsn_element())
+            .unwrap();
+
+        let parsed = KeyMessage::parse(&message_1).unwrap();
+        assert_eq!(
+            parsed.key_info,
+            KEY_INFO_VERSION_AES_HMAC_SHA1 | KEY_INFO_PAIRWISE | KEY_INFO_ACK
+        );
+        assert!(parsed.key_data.is_empty());
+
+        let ptk = client_ptk("portal123", &message_1);
+        let message_2 = answer(&message_1, 0, &ptk.kck, &client_rsn_element());
+
+        let Step::Reply(message_3) = authenticator.receive(&mut handshake, &message_2).unwrap()
+        else {
+            panic!("Message 3 expected");
+        };
+
+        let parsed = KeyMessage::parse(&message_3).unwrap();
+        assert!(parsed.has_valid_mic(&ptk.kck));
+        assert_eq!(parsed.replay_counter, 2);
+        assert_eq!(parsed.key_info & KEY_INFO_INSTALL, KEY_INFO_INSTALL);
+
+        let key_data = KekAes128::from(ptk.kek)
+            .unwrap_vec(parsed.key_data)
+            .unwrap();
+        let elements = information_elements(&key_data).collect::<Vec<_>>();
+        assert_eq!(elements[0], (WLAN_EID_RSN, rsn_element().as_slice()));
+        let (eid, gtk_kde) = elements[1];
+        assert_eq!(eid, WLAN_EID_VENDOR_SPECIFIC);
+        assert_eq!(gtk_kde[..6], [0x00, 0x0f, 0xac, 0x01, GTK_KEY_INDEX, 0]);
+        assert_eq!(gtk_kde[6..], *authenticator.gtk());
+
+        let message_4 = answer(&message_3, KEY_INFO_SECURE, &ptk.kck, &[]);
+
+        let Step::Complete(tk) = authenticator.receive(&mut handshake, &message_4).unwrap() else {
+            panic!("Completion expected");
+        };
+        assert_eq!(tk, ptk.tk);
+        assert!(!handshake.timed_out());
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase() {
+        let authenticator = authenticator("portal123");
+        let (mut handshake, message_1) = authenticator
+            .start(MacAddr6::from(CLIENT), &rsn_element())
+            .unwrap();
+
+        let ptk = client_ptk("wrong-passphrase", &message_1);
+        let message_2 = answer(&message_1, 0, &ptk.kck, &client_rsn_element());
+
+        let err = authenticator
+            .receive(&mut handshake, &message_2)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("invalid MIC"));
+    }
+
+    #[test]
+    fn rejects_downgraded_rsn_element() {
+        let authenticator = authenticator("portal123");
+        let (mut handshake, message_1) = authenticator
+            .start(MacAddr6::from(CLIENT), &rsn_element())
+            .unwrap();
+
+        let ptk = client_ptk("portal123", &message_1);
+        let mut downgraded = client_rsn_element();
+        // TKIP as the pairwise cipher
+        downgraded[13] = 0x02;
+        let message_2 = answer(&message_1, 0, &ptk.kck, &downgraded);
+
+        assert!(authenticator.receive(&mut handshake, &message_2).is_err());
+    }
+
+    #[test]
+    fn ignores_superseded_messages() {
+        let authenticator = authenticator("portal123");
+        let (mut handshake, message_1) = authenticator
+            .start(MacAddr6::from(CLIENT), &rsn_element())
+            .unwrap();
+
+        let retransmitted = authenticator.retransmit(&mut handshake).unwrap().unwrap();
+        assert_eq!(KeyMessage::parse(&retransmitted).unwrap().replay_counter, 2);
+
+        let ptk = client_ptk("portal123", &message_1);
+        let stale = answer(&message_1, 0, &ptk.kck, &client_rsn_element());
+
+        assert!(matches!(
+            authenticator.receive(&mut handshake, &stale).unwrap(),
+            Step::Ignore
+        ));
+        assert!(matches!(
+            authenticator
+                .receive(&mut handshake, b"\x02\x01\x00\x00")
+                .unwrap(),
+            Step::Ignore
+        ));
+    }
+
+    #[test]
+    fn gives_up_after_retransmissions() {
+        let authenticator = authenticator("portal123");
+        let (mut handshake, _) = authenticator
+            .start(MacAddr6::from(CLIENT), &rsn_element())
+            .unwrap();
+
+        for _ in 1..MAX_ATTEMPTS {
+            assert!(authenticator.retransmit(&mut handshake).unwrap().is_some());
+        }
+
+        assert!(authenticator.retransmit(&mut handshake).unwrap().is_none());
+    }
+}
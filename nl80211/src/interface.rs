@@ -0,0 +1,196 @@
+//! Wireless network interfaces.
+
+#![allow(clippy::upper_case_acronyms)]
+
+use core::convert::{TryFrom, TryInto};
+
+use anyhow::{Context, Result};
+
+use macaddr::MacAddr6;
+
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::{Buffer, GenlBuffer};
+
+use crate::client::Nl80211;
+use crate::consts;
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::message::{parse_string, Nl80211Payload};
+
+/// Operating mode of an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Iftype {
+    /// Unknown or unset mode
+    Unspecified = 0,
+    /// Independent BSS member
+    Adhoc,
+    /// Managed BSS member, i.e. a client
+    Station,
+    /// Access point
+    AP,
+    /// VLAN interface of an access point
+    APVlan,
+    /// Wireless distribution system
+    WDS,
+    /// Monitor interface receiving all frames
+    Monitor,
+    /// Mesh point
+    MeshPoint,
+    /// P2P client
+    P2PClient,
+    /// P2P group owner
+    P2PGo,
+    /// P2P device without a netdev
+    P2PDevice,
+    /// Outside the context of a BSS
+    Ocb,
+    /// Neighbor awareness networking
+    Nan,
+}
+
+impl From<::std::os::raw::c_uint> for Iftype {
+    fn from(orig: ::std::os::raw::c_uint) -> Self {
+        match orig {
+            consts::NL80211_IFTYPE_ADHOC => Self::Adhoc,
+            consts::NL80211_IFTYPE_STATION => Self::Station,
+            consts::NL80211_IFTYPE_AP => Self::AP,
+            consts::NL80211_IFTYPE_AP_VLAN => Self::APVlan,
+            consts::NL80211_IFTYPE_WDS => Self::WDS,
+            consts::NL80211_IFTYPE_MONITOR => Self::Monitor,
+            consts::NL80211_IFTYPE_MESH_POINT => Self::MeshPoint,
+            consts::NL80211_IFTYPE_P2P_CLIENT => Self::P2PClient,
+            consts::NL80211_IFTYPE_P2P_GO => Self::P2PGo,
+            consts::NL80211_IFTYPE_P2P_DEVICE => Self::P2PDevice,
+            consts::NL80211_IFTYPE_OCB => Self::Ocb,
+            consts::NL80211_IFTYPE_NAN => Self::Nan,
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+impl From<Iftype> for ::std::os::raw::c_uint {
+    fn from(iftype: Iftype) -> Self {
+        match iftype {
+            Iftype::Unspecified => consts::NL80211_IFTYPE_UNSPECIFIED,
+            Iftype::Adhoc => consts::NL80211_IFTYPE_ADHOC,
+            Iftype::Station => consts::NL80211_IFTYPE_STATION,
+            Iftype::AP => consts::NL80211_IFTYPE_AP,
+            Iftype::APVlan => consts::NL80211_IFTYPE_AP_VLAN,
+            Iftype::WDS => consts::NL80211_IFTYPE_WDS,
+            Iftype::Monitor => consts::NL80211_IFTYPE_MONITOR,
+            Iftype::MeshPoint => consts::NL80211_IFTYPE_MESH_POINT,
+            Iftype::P2PClient => consts::NL80211_IFTYPE_P2P_CLIENT,
+            Iftype::P2PGo => consts::NL80211_IFTYPE_P2P_GO,
+            Iftype::P2PDevice => consts::NL80211_IFTYPE_P2P_DEVICE,
+            Iftype::Ocb => consts::NL80211_IFTYPE_OCB,
+            Iftype::Nan => consts::NL80211_IFTYPE_NAN,
+        }
+    }
+}
+
+/// Wireless interface and the wiphy it belongs to.
+#[derive(Debug, Clone)]
+pub struct Interface {
+    /// Network interface name, e.g. `wlan0`
+    pub name: String,
+    /// Network interface index
+    pub index: u32,
+    /// Operating mode
+    pub iftype: Iftype,
+    /// Index of the physical device, see [`crate::wiphy`]
+    pub wiphy: u32,
+    /// Wireless device identifier, unique across wiphys
+    pub wdev: u64,
+    /// Current MAC address
+    pub mac_address: MacAddr6,
+    /// Current TX power in units of 0.01 dBm, if the driver reports it
+    pub tx_power_mbm: Option<i32>,
+}
+
+/// Looks up a wireless interface by name.
+pub async fn find_interface(nl80211: &Nl80211, interface: &str) -> Result<Interface> {
+    let ifaces = get_interfaces(nl80211)
+        .await
+        .context("Failed to get interfaces")?;
+
+    ifaces
+        .into_iter()
+        .find(|iface| iface.name == interface)
+        .context("Interface not found")
+}
+
+/// Lists the wireless interfaces of all wiphys.
+pub async fn get_interfaces(nl80211: &Nl80211) -> Result<Vec<Interface>> {
+    let payloads = nl80211
+        .dump(create_get_interface_message())
+        .await
+        .context("Failed to receive get interface response")?;
+
+    Ok(payloads
+        .iter()
+        .filter_map(|payload| Interface::try_from(payload).ok())
+        .collect())
+}
+
+/// Changes the operating mode. Most drivers require the interface to be down, see
+/// [`crate::link::set_link_up`]. Requires `CAP_NET_ADMIN`.
+pub async fn set_interface_type(nl80211: &Nl80211, iface_index: u32, iftype: Iftype) -> Result<()> {
+    nl80211
+        .request(create_set_interface_message(iface_index, iftype)?)
+        .await
+        .context("Failed to set interface type")?;
+
+    Ok(())
+}
+
+fn create_get_interface_message() -> Nl80211Payload {
+    let attrs = GenlBuffer::<Nl80211Attr, Buffer>::new();
+    Genlmsghdr::new(Nl80211Cmd::GetInterface, 1, attrs)
+}
+
+fn create_set_interface_message(iface_index: u32, iftype: Iftype) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let iftype_attr = Nlattr::new(
+        false,
+        false,
+        Nl80211Attr::Iftype,
+        ::std::os::raw::c_uint::from(iftype),
+    )
+    .context("Failed to create interface type attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::SetInterface,
+        1,
+        [iface_attr, iftype_attr].into_iter().collect(),
+    ))
+}
+
+impl TryFrom<&Genlmsghdr<Nl80211Cmd, Nl80211Attr>> for Interface {
+    type Error = anyhow::Error;
+
+    fn try_from(payload: &Genlmsghdr<Nl80211Cmd, Nl80211Attr>) -> Result<Self, Self::Error> {
+        let attrs = payload.get_attr_handle();
+        let name = parse_string(attrs.get_attr_payload_as_with_len(Nl80211Attr::Ifname)?)?;
+        let index = attrs.get_attr_payload_as(Nl80211Attr::Ifindex)?;
+        let iftype = attrs
+            .get_attr_payload_as::<u32>(Nl80211Attr::Iftype)?
+            .into();
+        let wiphy = attrs.get_attr_payload_as(Nl80211Attr::Wiphy)?;
+        let wdev = attrs.get_attr_payload_as(Nl80211Attr::Wdev)?;
+        let mac_bytes: [u8; 6] = attrs
+            .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Mac)?
+            .try_into()?;
+        let mac_address = mac_bytes.into();
+        let tx_power_mbm = attrs
+            .get_attr_payload_as(Nl80211Attr::WiphyTxPowerLevel)
+            .ok();
+        Ok(Self {
+            name,
+            index,
+            iftype,
+            wiphy,
+            wdev,
+            mac_address,
+            tx_power_mbm,
+        })
+    }
+}
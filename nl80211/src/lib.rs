@@ -0,0 +1,88 @@
+//! Async nl80211 client for querying and configuring Linux wireless devices.
+//!
+//! A single [`Nl80211`] connection serves concurrent requests from a tokio runtime. The modules
+//! wrap the nl80211 commands by area, with every operation taking the client and an interface
+//! name or index:
+//!
+//! - [`interface`] lists wireless interfaces
+//! - [`wiphy`] describes the physical devices behind them
+//! - [`scan`] and [`sched_scan`] trigger scans and read the BSS table
+//! - [`station`] and [`survey`] report link and channel statistics
+//! - [`regulatory`] reads and requests the regulatory domain
+//! - [`power`] controls power save and TX power
+//! - [`events`] streams multicast notifications
+//! - [`probe`] receives probe request frames
+//! - [`ap`] hosts an open or WPA2 access point, with [`link`] configuring its address over
+//!   rtnetlink
+//! - [`transport`] and [`replay`] decouple the client from the kernel for offline tests
+//!
+//! Messages for commands not covered yet can be built from [`enums`] and sent with
+//! [`Nl80211::request`] or [`Nl80211::dump`].
+//!
+//! All fallible operations fail alike, with an [`anyhow::Error`] naming the failed step. Errors
+//! the kernel reports are attached as [`error::Nl80211Error`], which the helpers of [`error`]
+//! classify.
+
+#![warn(
+    clippy::all,
+    clippy::restriction,
+    clippy::pedantic,
+    clippy::nursery,
+    clippy::cargo,
+    rust_2018_idioms,
+    rust_2018_compatibility,
+    rust_2021_compatibility,
+    future_incompatible,
+    nonstandard_style,
+    missing_copy_implementations,
+    missing_debug_implementations,
+    missing_docs,
+    unused
+)]
+#![allow(
+    clippy::missing_docs_in_private_items,
+    clippy::implicit_return,
+    clippy::mod_module_files,
+    clippy::expect_used,
+    clippy::future_not_send,
+    clippy::option_if_let_else,
+    clippy::wildcard_enum_match_arm,
+    clippy::float_arithmetic,
+    clippy::separated_literal_suffix,
+    clippy::blanket_clippy_restriction_lints,
+    clippy::print_stdout,
+    clippy::use_debug,
+    // Failures are documented once in the crate documentation rather than per operation
+    clippy::missing_errors_doc
+)]
+
+extern crate alloc;
+
+pub mod ap;
+mod client;
+pub mod enums;
+pub mod error;
+pub mod events;
+mod handshake;
+pub mod interface;
+pub mod link;
+mod message;
+
+/// Constants generated from `linux/nl80211.h`.
+#[allow(dead_code, missing_docs, non_upper_case_globals, non_camel_case_types)]
+pub mod consts;
+pub mod power;
+pub mod probe;
+pub mod regulatory;
+pub mod replay;
+pub mod scan;
+pub mod sched_scan;
+mod ssid;
+pub mod station;
+pub mod survey;
+pub mod transport;
+pub mod wiphy;
+
+pub use client::Nl80211;
+pub use message::Nl80211Payload;
+pub use ssid::Ssid;
@@ -0,0 +1,112 @@
+//! Link state and IPv4 addresses of network interfaces over rtnetlink.
+//!
+//! The kernel processes these requests synchronously, so they are sent on a short-lived
+//! blocking socket instead of going through the nl80211 client.
+
+use core::convert::TryFrom;
+use std::net::Ipv4Addr;
+
+use anyhow::{Context, Result};
+
+use neli::consts::nl::{NlmF, NlmFFlags};
+use neli::consts::rtnl::{Arphrd, Ifa, IfaFFlags, Iff, IffFlags, RtAddrFamily, RtScope, Rtm};
+use neli::consts::socket::NlFamily;
+use neli::nl::{NlPayload, Nlmsghdr};
+use neli::rtnl::{Ifaddrmsg, Ifinfomsg, Rtattr};
+use neli::socket::NlSocketHandle;
+use neli::types::{Buffer, RtBuffer};
+use neli::{Size, ToBytes};
+
+/// Brings the interface up or down. Requires `CAP_NET_ADMIN`.
+pub fn set_link_up(iface_index: u32, up: bool) -> Result<()> {
+    let flags = if up {
+        IffFlags::new(&[Iff::Up])
+    } else {
+        IffFlags::empty()
+    };
+
+    let ifinfomsg = Ifinfomsg::new(
+        RtAddrFamily::Unspecified,
+        Arphrd::None,
+        interface_index(iface_index)?,
+        flags,
+        IffFlags::new(&[Iff::Up]),
+        RtBuffer::new(),
+    );
+
+    request(Rtm::Setlink, &[], ifinfomsg).context("Failed to change link state")
+}
+
+/// Assigns an address with the given prefix length. Requires `CAP_NET_ADMIN`.
+pub fn add_ipv4_address(iface_index: u32, address: Ipv4Addr, prefix_len: u8) -> Result<()> {
+    let ifaddrmsg = create_ipv4_address_message(iface_index, address, prefix_len)?;
+
+    request(Rtm::Newaddr, &[NlmF::Create, NlmF::Replace], ifaddrmsg)
+        .context("Failed to add interface address")
+}
+
+/// Removes an address assigned with [`add_ipv4_address`]. Requires `CAP_NET_ADMIN`.
+pub fn delete_ipv4_address(iface_index: u32, address: Ipv4Addr, prefix_len: u8) -> Result<()> {
+    let ifaddrmsg = create_ipv4_address_message(iface_index, address, prefix_len)?;
+
+    request(Rtm::Deladdr, &[], ifaddrmsg).context("Failed to delete interface address")
+}
+
+fn create_ipv4_address_message(
+    iface_index: u32,
+    address: Ipv4Addr,
+    prefix_len: u8,
+) -> Result<Ifaddrmsg> {
+    let rtattrs = [Ifa::Local, Ifa::Address]
+        .into_iter()
+        .map(|attr_type| Rtattr::new(None, attr_type, Buffer::from(address.octets().to_vec())))
+        .collect::<Result<RtBuffer<_, _>, _>>()
+        .context("Failed to create address attribute")?;
+
+    Ok(Ifaddrmsg {
+        ifa_family: RtAddrFamily::Inet,
+        ifa_prefixlen: prefix_len,
+        ifa_flags: IfaFFlags::empty(),
+        ifa_scope: RtScope::Universe.into(),
+        ifa_index: interface_index(iface_index)?,
+        rtattrs,
+    })
+}
+
+fn interface_index(iface_index: u32) -> Result<libc::c_int> {
+    libc::c_int::try_from(iface_index).context("Interface index out of range")
+}
+
+/// Sends the message and waits for the kernel to acknowledge it.
+fn request<P: Size + ToBytes + core::fmt::Debug>(
+    msg_type: Rtm,
+    flags: &[NlmF],
+    payload: P,
+) -> Result<()> {
+    let mut socket_handle = NlSocketHandle::connect(NlFamily::Route, None, &[])
+        .context("Failed to establish rtnetlink socket")?;
+
+    let flags = [NlmF::Request, NlmF::Ack]
+        .into_iter()
+        .chain(flags.iter().copied())
+        .collect::<Vec<_>>();
+    let nl_msghdr = Nlmsghdr::new(
+        None,
+        msg_type,
+        NlmFFlags::new(&flags),
+        None,
+        None,
+        NlPayload::Payload(payload),
+    );
+
+    socket_handle
+        .send(nl_msghdr)
+        .context("Failed to send rtnetlink message")?;
+
+    // Errors are returned by `recv`, anything else received before is the acknowledgement
+    socket_handle
+        .recv::<Rtm, Buffer>()
+        .context("Failed to receive rtnetlink acknowledgement")?;
+
+    Ok(())
+}
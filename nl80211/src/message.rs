@@ -0,0 +1,315 @@
+use core::convert::TryInto;
+use std::io::Cursor;
+
+use anyhow::{bail, Context, Result};
+
+use byteorder::{NativeEndian, ReadBytesExt};
+
+use neli::genl::Genlmsghdr;
+use neli::FromBytesWithInput;
+
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::error::Nl80211Error;
+use crate::transport::Transport;
+
+/// Generic netlink message of the nl80211 family.
+pub type Nl80211Payload = Genlmsghdr<Nl80211Cmd, Nl80211Attr>;
+
+const NLMSG_HDRLEN: usize = 16;
+const NLMSG_ALIGNTO: usize = 4;
+const NLA_HDRLEN: usize = 4;
+
+const NLM_F_CAPPED: u16 = 0x100;
+const NLM_F_ACK_TLVS: u16 = 0x200;
+const NLMSGERR_ATTR_MSG: u16 = 1;
+
+#[derive(Debug)]
+pub struct Frame {
+    pub seq: u32,
+    pub message: Message,
+}
+
+#[derive(Debug)]
+pub enum Message {
+    Payload(Nl80211Payload),
+    Ack,
+    Error(Nl80211Error),
+    Done,
+}
+
+pub async fn recv_frames(transport: &dyn Transport, buf: &mut [u8]) -> Result<Vec<Frame>> {
+    let size = transport
+        .recv(buf)
+        .await
+        .context("Failed to read from netlink socket")?;
+
+    parse_messages(buf.get(..size).unwrap_or_default())
+}
+
+/// Decodes a NUL terminated string attribute payload. Unlike neli's `String` decoding this
+/// does not panic on empty payloads.
+pub fn parse_string(payload: &[u8]) -> Result<String> {
+    let text = payload.split(|&b| b == 0).next().unwrap_or_default();
+
+    String::from_utf8(text.to_vec()).context("Invalid UTF-8 in string attribute")
+}
+
+/// Splits a netlink datagram into messages. Unlike neli's own decoding this keeps error
+/// responses apart from regular payloads and extracts the extended ACK text message.
+pub fn parse_messages(buf: &[u8]) -> Result<Vec<Frame>> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+
+    while let Some(frame) = buf.get(offset..).filter(|frame| !frame.is_empty()) {
+        let mut cursor = Cursor::new(frame);
+        let len: usize = cursor.read_u32::<NativeEndian>()?.try_into()?;
+        let nl_type = cursor.read_u16::<NativeEndian>()?;
+        let flags = cursor.read_u16::<NativeEndian>()?;
+        let seq = cursor.read_u32::<NativeEndian>()?;
+
+        let body = frame
+            .get(NLMSG_HDRLEN..len)
+            .context("Truncated netlink message")?;
+
+        if let Some(message) = parse_message(nl_type, flags, body)? {
+            frames.push(Frame { seq, message });
+        }
+
+        offset = len
+            .checked_next_multiple_of(NLMSG_ALIGNTO)
+            .and_then(|aligned| offset.checked_add(aligned))
+            .context("Netlink message length overflow")?;
+    }
+
+    Ok(frames)
+}
+
+fn parse_message(nl_type: u16, flags: u16, body: &[u8]) -> Result<Option<Message>> {
+    let message = match i32::from(nl_type) {
+        libc::NLMSG_NOOP => return Ok(None),
+        libc::NLMSG_OVERRUN => bail!("Netlink message overrun"),
+        libc::NLMSG_ERROR => parse_error(flags, body)?,
+        libc::NLMSG_DONE => parse_done(body),
+        _ => Message::Payload(
+            Nl80211Payload::from_bytes_with_input(&mut Cursor::new(body), body.len())
+                .context("Failed to deserialize nl80211 message")?,
+        ),
+    };
+
+    Ok(Some(message))
+}
+
+fn parse_done(body: &[u8]) -> Message {
+    // Dumps that fail midway report the error code in the done message
+    let code = Cursor::new(body).read_i32::<NativeEndian>().unwrap_or(0);
+
+    match code.checked_neg() {
+        Some(errno) if errno > 0 => Message::Error(Nl80211Error::new(errno, None)),
+        _ => Message::Done,
+    }
+}
+
+fn parse_error(flags: u16, body: &[u8]) -> Result<Message> {
+    let mut cursor = Cursor::new(body);
+    let code = cursor.read_i32::<NativeEndian>()?;
+
+    if code == 0 {
+        return Ok(Message::Ack);
+    }
+
+    let errno = code.checked_neg().context("Invalid netlink error code")?;
+
+    let message = if flags & NLM_F_ACK_TLVS == 0 {
+        None
+    } else {
+        let echoed_len = if flags & NLM_F_CAPPED == 0 {
+            cursor.read_u32::<NativeEndian>()?.try_into()?
+        } else {
+            NLMSG_HDRLEN
+        };
+
+        echoed_len
+            .checked_next_multiple_of(NLMSG_ALIGNTO)
+            .and_then(|len| len.checked_add(core::mem::size_of::<i32>()))
+            .and_then(|start| body.get(start..))
+            .and_then(extract_ext_ack_message)
+    };
+
+    Ok(Message::Error(Nl80211Error::new(errno, message)))
+}
+
+fn extract_ext_ack_message(mut tlvs: &[u8]) -> Option<String> {
+    while !tlvs.is_empty() {
+        let mut cursor = Cursor::new(tlvs);
+        let len: usize = cursor.read_u16::<NativeEndian>().ok()?.into();
+        let tlv_type = cursor.read_u16::<NativeEndian>().ok()?;
+        let payload = tlvs.get(NLA_HDRLEN..len)?;
+
+        if tlv_type == NLMSGERR_ATTR_MSG {
+            let text = payload.split(|&b| b == 0).next().unwrap_or_default();
+            return Some(String::from_utf8_lossy(text).into_owned());
+        }
+
+        tlvs = tlvs.get(len.checked_next_multiple_of(NLMSG_ALIGNTO)?..)?;
+    }
+
+    None
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::indexing_slicing,
+    clippy::assertions_on_result_states
+)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    const SEQ: u32 = 7;
+
+    fn frame(nl_type: i32, flags: u16, body: &[u8]) -> Vec<u8> {
+        let len = u32::try_from(NLMSG_HDRLEN.saturating_add(body.len())).unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&len.to_ne_bytes());
+        bytes.extend_from_slice(&u16::try_from(nl_type).unwrap().to_ne_bytes());
+        bytes.extend_from_slice(&flags.to_ne_bytes());
+        bytes.extend_from_slice(&SEQ.to_ne_bytes());
+        bytes.extend_from_slice(&0_u32.to_ne_bytes());
+        bytes.extend_from_slice(body);
+        bytes.resize(bytes.len().next_multiple_of(NLMSG_ALIGNTO), 0);
+        bytes
+    }
+
+    /// Error body echoing the header of a request with `payload_len` payload bytes.
+    fn error_body(errno: i32, echoed_payload_len: usize) -> Vec<u8> {
+        let request = frame(libc::NLMSG_MIN_TYPE, 0, &vec![0; echoed_payload_len]);
+
+        let mut body = errno.wrapping_neg().to_ne_bytes().to_vec();
+        body.extend_from_slice(&request);
+        body
+    }
+
+    fn tlv(tlv_type: u16, payload: &[u8]) -> Vec<u8> {
+        let len = u16::try_from(NLA_HDRLEN.saturating_add(payload.len())).unwrap();
+
+        let mut bytes = len.to_ne_bytes().to_vec();
+        bytes.extend_from_slice(&tlv_type.to_ne_bytes());
+        bytes.extend_from_slice(payload);
+        bytes.resize(bytes.len().next_multiple_of(NLMSG_ALIGNTO), 0);
+        bytes
+    }
+
+    fn parse_error_frame(flags: u16, body: &[u8]) -> Option<Nl80211Error> {
+        let frames = parse_messages(&frame(libc::NLMSG_ERROR, flags, body)).unwrap();
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].seq, SEQ);
+        match frames.into_iter().next()?.message {
+            Message::Error(err) => Some(err),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_ack() {
+        let frames = parse_messages(&frame(libc::NLMSG_ERROR, 0, &error_body(0, 0))).unwrap();
+
+        assert!(matches!(frames[0].message, Message::Ack));
+    }
+
+    #[test]
+    fn parses_error_without_ext_ack() {
+        let err = parse_error_frame(0, &error_body(libc::EBUSY, 8)).unwrap();
+
+        assert_eq!(err, Nl80211Error::new(libc::EBUSY, None));
+    }
+
+    #[test]
+    fn parses_ext_ack_message_of_capped_error() {
+        let mut body = error_body(libc::EINVAL, 0);
+        body.extend(tlv(NLMSGERR_ATTR_MSG, b"Invalid channel\0"));
+
+        let err = parse_error_frame(NLM_F_ACK_TLVS | NLM_F_CAPPED, &body).unwrap();
+
+        assert_eq!(err.errno(), libc::EINVAL);
+        assert_eq!(err.message(), Some("Invalid channel"));
+    }
+
+    #[test]
+    fn parses_ext_ack_message_after_echoed_request() {
+        let mut body = error_body(libc::EOPNOTSUPP, 6);
+        body.extend(tlv(NLMSGERR_ATTR_MSG + 1, &[0; 4]));
+        body.extend(tlv(NLMSGERR_ATTR_MSG, b"Not supported\0"));
+
+        let err = parse_error_frame(NLM_F_ACK_TLVS, &body).unwrap();
+
+        assert_eq!(err.errno(), libc::EOPNOTSUPP);
+        assert_eq!(err.message(), Some("Not supported"));
+    }
+
+    #[test]
+    fn ignores_malformed_ext_ack() {
+        let mut body = error_body(libc::EINVAL, 0);
+        body.extend_from_slice(&[2, 0, 1, 0]);
+
+        let err = parse_error_frame(NLM_F_ACK_TLVS | NLM_F_CAPPED, &body).unwrap();
+        assert_eq!(err, Nl80211Error::new(libc::EINVAL, None));
+
+        // Echoed request length beyond the end of the message
+        let mut body = error_body(libc::EINVAL, 0);
+        body.truncate(8);
+        body[4..8].copy_from_slice(&u32::MAX.to_ne_bytes());
+
+        let err = parse_error_frame(NLM_F_ACK_TLVS, &body).unwrap();
+        assert_eq!(err, Nl80211Error::new(libc::EINVAL, None));
+    }
+
+    #[test]
+    fn parses_failed_dump() {
+        let body = libc::ENOBUFS.wrapping_neg().to_ne_bytes();
+        let frames = parse_messages(&frame(libc::NLMSG_DONE, 0, &body)).unwrap();
+
+        assert!(matches!(
+            frames[0].message,
+            Message::Error(ref err) if err.errno() == libc::ENOBUFS
+        ));
+
+        let frames = parse_messages(&frame(libc::NLMSG_DONE, 0, &[0; 4])).unwrap();
+        assert!(matches!(frames[0].message, Message::Done));
+    }
+
+    #[test]
+    fn rejects_truncated_messages() {
+        let bytes = frame(libc::NLMSG_ERROR, 0, &error_body(libc::EBUSY, 0));
+
+        assert!(parse_messages(&bytes[..3]).is_err());
+        assert!(parse_messages(&bytes[..NLMSG_HDRLEN]).is_err());
+        assert!(parse_messages(&bytes[..bytes.len().saturating_sub(4)]).is_err());
+
+        // Error message without an error code
+        assert!(parse_messages(&frame(libc::NLMSG_ERROR, 0, &[])).is_err());
+
+        // Length shorter than the header
+        let mut bytes = bytes;
+        bytes[..4].copy_from_slice(&4_u32.to_ne_bytes());
+        assert!(parse_messages(&bytes).is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn arbitrary_bytes_do_not_panic(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+            let _result = parse_messages(&bytes);
+        }
+
+        #[test]
+        fn arbitrary_error_bodies_do_not_panic(
+            flags in any::<u16>(),
+            body in prop::collection::vec(any::<u8>(), 0..128),
+        ) {
+            let _result = parse_messages(&frame(libc::NLMSG_ERROR, flags, &body));
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Power save and transmit power.
+
+use core::iter::once;
+
+use anyhow::{Context, Result};
+
+use neli::genl::{Genlmsghdr, Nlattr};
+
+use crate::client::Nl80211;
+use crate::consts::{
+    NL80211_PS_DISABLED, NL80211_PS_ENABLED, NL80211_TX_POWER_AUTOMATIC, NL80211_TX_POWER_FIXED,
+    NL80211_TX_POWER_LIMITED,
+};
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::interface::{find_interface, get_interfaces};
+use crate::message::Nl80211Payload;
+
+// TX power is configured in units of 0.01 dBm
+const MBM_PER_DBM: i32 = 100;
+
+/// Settings to apply to the managed interface. Unset values are left as they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PowerSettings {
+    /// Power save mode of the interface
+    pub power_save: Option<bool>,
+    /// Fixed TX power of the wiphy
+    pub tx_power_dbm: Option<i32>,
+}
+
+/// Values changed by `apply_power_settings`, to be put back with `restore_power_settings`.
+#[derive(Debug, Clone, Copy)]
+pub struct PreviousPowerSettings {
+    iface_index: u32,
+    wiphy: u32,
+    power_save: Option<bool>,
+    tx_power_changed: bool,
+    tx_power_mbm: Option<i32>,
+}
+
+/// Applies the settings and returns the replaced values. Requires `CAP_NET_ADMIN`. Settings
+/// applied before a failing one are restored before returning the error.
+pub async fn apply_power_settings(
+    nl80211: &Nl80211,
+    interface: &str,
+    settings: PowerSettings,
+) -> Result<PreviousPowerSettings> {
+    let iface = find_interface(nl80211, interface).await?;
+
+    let mut previous = PreviousPowerSettings {
+        iface_index: iface.index,
+        wiphy: iface.wiphy,
+        power_save: None,
+        tx_power_changed: false,
+        tx_power_mbm: iface.tx_power_mbm,
+    };
+
+    if let Some(enabled) = settings.power_save {
+        let current = get_power_save(nl80211, iface.index).await?;
+        set_power_save(nl80211, iface.index, enabled).await?;
+        previous.power_save = Some(current);
+    }
+
+    if let Some(tx_power_dbm) = settings.tx_power_dbm {
+        if let Err(err) = set_tx_power(nl80211, iface.wiphy, Some(tx_power_dbm)).await {
+            return match restore_power_settings(nl80211, previous).await {
+                Ok(()) => Err(err),
+                Err(restore_err) => {
+                    Err(err.context(format!("Failed to restore power settings: {restore_err:#}")))
+                }
+            };
+        }
+        previous.tx_power_changed = true;
+    }
+
+    Ok(previous)
+}
+
+/// Puts back the values replaced by `apply_power_settings`.
+///
+/// The kernel reports the TX power level, but not whether it was automatic, limited or fixed.
+/// A changed TX power is returned to automatic control, which is the driver default, and the
+/// previous level is set as a limit if automatic control does not restore it.
+pub async fn restore_power_settings(
+    nl80211: &Nl80211,
+    previous: PreviousPowerSettings,
+) -> Result<()> {
+    if let Some(enabled) = previous.power_save {
+        set_power_save(nl80211, previous.iface_index, enabled).await?;
+    }
+
+    if previous.tx_power_changed {
+        set_tx_power(nl80211, previous.wiphy, None).await?;
+
+        if let Some(previous_mbm) = previous.tx_power_mbm {
+            let current_mbm = get_interfaces(nl80211)
+                .await
+                .context("Failed to get interfaces")?
+                .into_iter()
+                .find(|iface| iface.index == previous.iface_index)
+                .and_then(|iface| iface.tx_power_mbm);
+
+            if current_mbm != Some(previous_mbm) {
+                limit_tx_power(nl80211, previous.wiphy, previous_mbm).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether power save is enabled on the interface.
+pub async fn get_power_save(nl80211: &Nl80211, iface_index: u32) -> Result<bool> {
+    let payloads = nl80211
+        .request(create_get_power_save_message(iface_index)?)
+        .await
+        .context("Failed to receive get power save response")?;
+
+    let state = payloads
+        .first()
+        .context("Empty get power save response")?
+        .get_attr_handle()
+        .get_attr_payload_as::<u32>(Nl80211Attr::PsState)
+        .context("Missing power save state")?;
+
+    Ok(state == NL80211_PS_ENABLED)
+}
+
+/// Enables or disables power save on the interface.
+pub async fn set_power_save(nl80211: &Nl80211, iface_index: u32, enabled: bool) -> Result<()> {
+    nl80211
+        .request(create_set_power_save_message(iface_index, enabled)?)
+        .await
+        .context("Failed to set power save")?;
+
+    Ok(())
+}
+
+/// Fixes the TX power of the wiphy to `tx_power_dbm`, or returns it to automatic control.
+pub async fn set_tx_power(nl80211: &Nl80211, wiphy: u32, tx_power_dbm: Option<i32>) -> Result<()> {
+    let message = match tx_power_dbm {
+        Some(tx_power_dbm) => {
+            let level = tx_power_dbm
+                .checked_mul(MBM_PER_DBM)
+                .context("TX power out of range")?;
+            create_set_tx_power_message(wiphy, NL80211_TX_POWER_FIXED, Some(level))?
+        }
+        None => create_set_tx_power_message(wiphy, NL80211_TX_POWER_AUTOMATIC, None)?,
+    };
+
+    nl80211
+        .request(message)
+        .await
+        .context("Failed to set TX power")?;
+
+    Ok(())
+}
+
+/// Lets the driver control the TX power of the wiphy up to `level_mbm`, in units of 0.01 dBm.
+pub async fn limit_tx_power(nl80211: &Nl80211, wiphy: u32, level_mbm: i32) -> Result<()> {
+    nl80211
+        .request(create_set_tx_power_message(
+            wiphy,
+            NL80211_TX_POWER_LIMITED,
+            Some(level_mbm),
+        )?)
+        .await
+        .context("Failed to limit TX power")?;
+
+    Ok(())
+}
+
+fn create_get_power_save_message(iface_index: u32) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::GetPowerSave,
+        1,
+        once(iface_attr).collect(),
+    ))
+}
+
+fn create_set_power_save_message(iface_index: u32, enabled: bool) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let state = if enabled {
+        NL80211_PS_ENABLED
+    } else {
+        NL80211_PS_DISABLED
+    };
+    let state_attr = Nlattr::new(false, false, Nl80211Attr::PsState, state)
+        .context("Failed to create power save state attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::SetPowerSave,
+        1,
+        [iface_attr, state_attr].into_iter().collect(),
+    ))
+}
+
+fn create_set_tx_power_message(
+    wiphy: u32,
+    setting: u32,
+    level_mbm: Option<i32>,
+) -> Result<Nl80211Payload> {
+    let wiphy_attr = Nlattr::new(false, false, Nl80211Attr::Wiphy, wiphy)
+        .context("Failed to create wiphy attribute")?;
+    let setting_attr = Nlattr::new(false, false, Nl80211Attr::WiphyTxPowerSetting, setting)
+        .context("Failed to create TX power setting attribute")?;
+
+    let Some(level_mbm) = level_mbm else {
+        return Ok(Genlmsghdr::new(
+            Nl80211Cmd::SetWiphy,
+            1,
+            [wiphy_attr, setting_attr].into_iter().collect(),
+        ));
+    };
+
+    let level_attr = Nlattr::new(false, false, Nl80211Attr::WiphyTxPowerLevel, level_mbm)
+        .context("Failed to create TX power level attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::SetWiphy,
+        1,
+        [wiphy_attr, setting_attr, level_attr].into_iter().collect(),
+    ))
+}
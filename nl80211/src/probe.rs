@@ -0,0 +1,215 @@
+//! Probe requests sent by nearby devices looking for networks.
+
+use core::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use macaddr::MacAddr6;
+
+use neli::consts::nl::NlmF;
+use neli::consts::MAX_NL_LENGTH;
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::Buffer;
+
+use serde::Serialize;
+
+use crate::client::Nl80211;
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::error::is_already_active;
+use crate::interface::find_interface;
+use crate::message::{recv_frames, Message, Nl80211Payload};
+use crate::scan::extract_ssid;
+use crate::ssid::Ssid;
+use crate::transport::Transport;
+
+// Management frame of probe request subtype
+const PROBE_REQUEST_FRAME_TYPE: u16 = 0x0040;
+
+const MGMT_HEADER_LEN: usize = 24;
+const SOURCE_ADDRESS_OFFSET: usize = 10;
+const LOCALLY_ADMINISTERED_BIT: u8 = 0x02;
+
+/// Probe request received by the interface.
+#[derive(Serialize, Debug, Clone)]
+pub struct ProbeRequest {
+    /// Address of the sending device
+    pub mac_address: String,
+    /// Locally administered addresses are randomized by the device for privacy and change
+    /// between scans, so they do not identify a device.
+    pub randomized: bool,
+    /// Signal level of the frame
+    pub signal_dbm: Option<i32>,
+    /// Channel center frequency in MHz
+    pub frequency: Option<u32>,
+    /// Absent for wildcard probes, which ask for any network
+    #[serde(flatten)]
+    pub ssid: Option<Ssid>,
+    /// Seconds since the Unix epoch
+    pub received_at: u64,
+}
+
+/// Receives probe requests on a dedicated socket, as registered frames are delivered only
+/// to the socket that registered for them. The registration lasts until the socket is closed.
+pub struct ProbeListener {
+    transport: Box<dyn Transport>,
+    buf: Vec<u8>,
+}
+
+impl core::fmt::Debug for ProbeListener {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ProbeListener").finish_non_exhaustive()
+    }
+}
+
+impl ProbeListener {
+    /// Fails if another process, e.g. the access point daemon, or a [`crate::ap::AccessPoint`]
+    /// has registered for probe requests on the interface already. Registering ahead of them
+    /// does not help, as the kernel drops registrations when the interface becomes an access
+    /// point.
+    pub async fn new(nl80211: &Nl80211, interface: &str) -> Result<Self> {
+        let iface = find_interface(nl80211, interface).await?;
+
+        let transport = register_frames(nl80211, iface.index, &[PROBE_REQUEST_FRAME_TYPE])
+            .await
+            .map_err(|err| {
+                if is_already_active(&err) {
+                    err.context("Probe requests are received by another process on the interface")
+                } else {
+                    err
+                }
+            })
+            .context("Failed to register for probe requests")?;
+
+        Ok(Self {
+            transport,
+            buf: vec![0; MAX_NL_LENGTH],
+        })
+    }
+
+    /// Waits for the next probe request. Other frames are skipped.
+    pub async fn next_probe(&mut self) -> Result<ProbeRequest> {
+        loop {
+            let frames = recv_frames(&*self.transport, &mut self.buf)
+                .await
+                .context("Failed to receive probe request")?;
+
+            let probe = frames.iter().find_map(|frame| match frame.message {
+                Message::Payload(ref payload) => parse_probe_request(payload),
+                _ => None,
+            });
+
+            if let Some(probe) = probe {
+                return Ok(probe);
+            }
+        }
+    }
+}
+
+fn parse_probe_request(payload: &Nl80211Payload) -> Option<ProbeRequest> {
+    if payload.cmd != Nl80211Cmd::Frame {
+        return None;
+    }
+
+    let attrs = payload.get_attr_handle();
+
+    let frame = attrs
+        .get_attr_payload_as_with_len::<&[u8]>(Nl80211Attr::Frame)
+        .ok()?;
+
+    let source_end = SOURCE_ADDRESS_OFFSET.checked_add(6)?;
+    let source: [u8; 6] = frame
+        .get(SOURCE_ADDRESS_OFFSET..source_end)?
+        .try_into()
+        .ok()?;
+    let mac_address = MacAddr6::from(source);
+
+    let ssid = Some(Ssid::from(extract_ssid(frame.get(MGMT_HEADER_LEN..)?)))
+        .filter(|ssid| !ssid.is_empty());
+
+    let received_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    Some(ProbeRequest {
+        mac_address: mac_address.to_string(),
+        randomized: mac_address.as_bytes().first()? & LOCALLY_ADMINISTERED_BIT != 0,
+        signal_dbm: attrs.get_attr_payload_as(Nl80211Attr::RxSignalDbm).ok(),
+        frequency: attrs.get_attr_payload_as(Nl80211Attr::WiphyFreq).ok(),
+        ssid,
+        received_at,
+    })
+}
+
+/// Opens a socket receiving the management frames of the given types sent to the interface.
+/// Registrations last until the socket is closed or the interface type changes.
+pub(crate) async fn register_frames(
+    nl80211: &Nl80211,
+    iface_index: u32,
+    frame_types: &[u16],
+) -> Result<Box<dyn Transport>> {
+    let transport = nl80211.open_socket()?;
+
+    let mut buf = vec![0; MAX_NL_LENGTH];
+
+    for &frame_type in frame_types {
+        request_on(
+            nl80211,
+            &*transport,
+            &mut buf,
+            create_register_frame_message(iface_index, frame_type)?,
+        )
+        .await
+        .with_context(|| format!("Failed to register for frame type {frame_type:#06x}"))?;
+    }
+
+    Ok(transport)
+}
+
+/// Sends a request on a socket other than the shared one of the client, as the kernel ties
+/// some state to the requesting socket, and waits for its acknowledgement.
+pub(crate) async fn request_on(
+    nl80211: &Nl80211,
+    transport: &dyn Transport,
+    buf: &mut [u8],
+    payload: Nl80211Payload,
+) -> Result<()> {
+    let (seq, msg) = nl80211.serialize(payload, &[NlmF::Request, NlmF::Ack])?;
+    transport
+        .send(&msg)
+        .await
+        .context("Failed to send message")?;
+
+    wait_for_ack(transport, buf, seq).await
+}
+
+async fn wait_for_ack(transport: &dyn Transport, buf: &mut [u8], seq: u32) -> Result<()> {
+    loop {
+        for frame in recv_frames(transport, buf).await? {
+            match frame.message {
+                _ if frame.seq != seq => {}
+                Message::Ack => return Ok(()),
+                Message::Error(err) => return Err(err.into()),
+                Message::Payload(_) | Message::Done => {}
+            }
+        }
+    }
+}
+
+fn create_register_frame_message(iface_index: u32, frame_type: u16) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let frame_type_attr = Nlattr::new(false, false, Nl80211Attr::FrameType, frame_type)
+        .context("Failed to create frame type attribute")?;
+    // An empty match registers for all frames of the type
+    let frame_match_attr = Nlattr::new(false, false, Nl80211Attr::FrameMatch, Buffer::new())
+        .context("Failed to create frame match attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::RegisterFrame,
+        1,
+        [iface_attr, frame_type_attr, frame_match_attr]
+            .into_iter()
+            .collect(),
+    ))
+}
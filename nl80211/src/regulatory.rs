@@ -0,0 +1,125 @@
+//! Regulatory domain, which limits the usable channels and transmit power.
+
+use core::iter::once;
+
+use anyhow::{Context, Result};
+
+use neli::consts::genl::Index;
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::Buffer;
+
+use serde::Serialize;
+
+use crate::client::Nl80211;
+use crate::enums::{Nl80211Attr, Nl80211Cmd, Nl80211RegRuleAttr};
+use crate::message::{parse_string, Nl80211Payload};
+
+/// Regulatory domain in effect.
+#[derive(Serialize, Debug, Clone)]
+pub struct RegulatoryDomain {
+    /// ISO 3166 country code, or `00` for the world domain
+    pub alpha2: String,
+    /// `NL80211_DFS_*` region for radar detection requirements
+    pub dfs_region: Option<u8>,
+    /// Frequency ranges where transmitting is allowed
+    pub rules: Vec<RegulatoryRule>,
+}
+
+/// Frequency range and the limits that apply to it.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct RegulatoryRule {
+    /// Start of the range in kHz
+    pub start_khz: u32,
+    /// End of the range in kHz
+    pub end_khz: u32,
+    /// Maximum channel bandwidth in kHz
+    pub max_bandwidth_khz: u32,
+    /// Maximum antenna gain in mBi
+    pub max_antenna_gain_mbi: u32,
+    /// Maximum EIRP in mBm
+    pub max_eirp_mbm: u32,
+    /// `NL80211_RRF_*` flags, e.g. DFS or no initiating radiation
+    pub flags: u32,
+}
+
+/// Global regulatory domain, or the one of a wiphy with its own self-managed domain.
+pub async fn get_regulatory(nl80211: &Nl80211, wiphy: Option<u32>) -> Result<RegulatoryDomain> {
+    let payloads = nl80211
+        .request(create_get_reg_message(wiphy)?)
+        .await
+        .context("Failed to receive get regulatory response")?;
+
+    payloads
+        .first()
+        .and_then(parse_regulatory_domain)
+        .context("Invalid get regulatory response")
+}
+
+/// Hints the country the device operates in. The kernel applies the domain asynchronously and
+/// announces it with [`crate::events::Nl80211Event::RegChange`]. Requires `CAP_NET_ADMIN`.
+pub async fn set_regulatory(nl80211: &Nl80211, alpha2: &str) -> Result<()> {
+    nl80211
+        .request(create_req_set_reg_message(alpha2)?)
+        .await
+        .context("Failed to request regulatory domain")?;
+
+    Ok(())
+}
+
+fn parse_regulatory_domain(payload: &Nl80211Payload) -> Option<RegulatoryDomain> {
+    let mut attrs = payload.get_attr_handle();
+
+    let alpha2 = parse_string(
+        attrs
+            .get_attr_payload_as_with_len(Nl80211Attr::RegAlpha2)
+            .ok()?,
+    )
+    .ok()?;
+    let dfs_region = attrs.get_attr_payload_as(Nl80211Attr::DfsRegion).ok();
+
+    let rules = attrs
+        .get_nested_attributes::<Index>(Nl80211Attr::RegRules)
+        .map(|rules| rules.iter().filter_map(parse_rule).collect())
+        .unwrap_or_default();
+
+    Some(RegulatoryDomain {
+        alpha2,
+        dfs_region,
+        rules,
+    })
+}
+
+fn parse_rule(rule: &Nlattr<Index, Buffer>) -> Option<RegulatoryRule> {
+    let rule_attrs = rule.get_attr_handle::<Nl80211RegRuleAttr>().ok()?;
+    let value = |attr| rule_attrs.get_attr_payload_as::<u32>(attr).ok();
+
+    Some(RegulatoryRule {
+        start_khz: value(Nl80211RegRuleAttr::FreqRangeStart)?,
+        end_khz: value(Nl80211RegRuleAttr::FreqRangeEnd)?,
+        max_bandwidth_khz: value(Nl80211RegRuleAttr::FreqRangeMaxBw)?,
+        max_antenna_gain_mbi: value(Nl80211RegRuleAttr::PowerRuleMaxAntGain).unwrap_or(0),
+        max_eirp_mbm: value(Nl80211RegRuleAttr::PowerRuleMaxEirp).unwrap_or(0),
+        flags: value(Nl80211RegRuleAttr::Flags).unwrap_or(0),
+    })
+}
+
+fn create_get_reg_message(wiphy: Option<u32>) -> Result<Nl80211Payload> {
+    let attrs = wiphy
+        .map(|wiphy| {
+            Nlattr::new(false, false, Nl80211Attr::Wiphy, wiphy)
+                .context("Failed to create wiphy attribute")
+        })
+        .into_iter()
+        .collect::<Result<_>>()?;
+    Ok(Genlmsghdr::new(Nl80211Cmd::GetReg, 1, attrs))
+}
+
+fn create_req_set_reg_message(alpha2: &str) -> Result<Nl80211Payload> {
+    let alpha2_attr = Nlattr::new(false, false, Nl80211Attr::RegAlpha2, alpha2)
+        .context("Failed to create regulatory alpha2 attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::ReqSetReg,
+        1,
+        once(alpha2_attr).collect(),
+    ))
+}
@@ -0,0 +1,415 @@
+//! Recording of netlink traffic to fixture files and offline replay of them.
+//!
+//! A fixture lists the datagrams exchanged on each socket in the order they occurred:
+//!
+//! ```text
+//! family 28
+//! mcast 5 6 7 8
+//! send 0 <hex>
+//! recv 0 <hex>
+//! open 1
+//! recv 1 <hex>
+//! ```
+//!
+//! Socket 0 is the request socket of the client, further sockets are numbered in the order
+//! they are opened, e.g. by [`crate::Nl80211::event_monitor`]. On replay, requests must match
+//! the recorded ones byte for byte, and every received datagram is delivered once the request
+//! preceding it in the fixture has been sent.
+//!
+//! With the `fixtures` feature, `fixture_path` locates the fixtures shipped with the crate, so
+//! that dependent crates can replay them in their tests.
+
+use alloc::sync::Arc;
+use core::fmt::{self, Write as _};
+use core::str::FromStr;
+use std::fs;
+use std::io;
+use std::path::Path;
+#[cfg(feature = "fixtures")]
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use anyhow::{bail, Context, Result};
+
+use futures_util::future::{pending, BoxFuture};
+
+use tokio::sync::watch;
+
+use crate::transport::Transport;
+
+/// Path of a fixture in the `tests/fixtures` directory of this crate.
+#[cfg(feature = "fixtures")]
+#[must_use]
+pub fn fixture_path(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    Open(usize),
+    Send(usize, Vec<u8>),
+    Recv(usize, Vec<u8>),
+}
+
+/// Recorded netlink traffic of a client session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Fixture {
+    /// Generic netlink family ID of nl80211 at recording time
+    pub family_id: u16,
+    /// Multicast group IDs of the event groups at recording time
+    pub mcast_ids: Vec<u32>,
+    entries: Vec<Entry>,
+}
+
+impl Fixture {
+    /// Reads a fixture file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read fixture {}", path.display()))?;
+        text.parse()
+            .with_context(|| format!("Failed to parse fixture {}", path.display()))
+    }
+
+    /// Writes the fixture file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_string())
+            .with_context(|| format!("Failed to write fixture {}", path.display()))
+    }
+}
+
+impl FromStr for Fixture {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut fixture = Self::default();
+
+        let lines = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let keyword = fields.next().unwrap_or_default();
+            let mut field = || fields.next().context("Missing fixture field");
+
+            match keyword {
+                "family" => fixture.family_id = field()?.parse()?,
+                "mcast" => {
+                    fixture.mcast_ids = fields.map(str::parse).collect::<Result<_, _>>()?;
+                }
+                "open" => fixture.entries.push(Entry::Open(field()?.parse()?)),
+                "send" => {
+                    let socket = field()?.parse()?;
+                    fixture
+                        .entries
+                        .push(Entry::Send(socket, decode_hex(field()?)?));
+                }
+                "recv" => {
+                    let socket = field()?.parse()?;
+                    fixture
+                        .entries
+                        .push(Entry::Recv(socket, decode_hex(field()?)?));
+                }
+                _ => bail!("Unknown fixture line: {line}"),
+            }
+        }
+
+        Ok(fixture)
+    }
+}
+
+impl fmt::Display for Fixture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "family {}", self.family_id)?;
+
+        write!(f, "mcast")?;
+        for mcast_id in &self.mcast_ids {
+            write!(f, " {mcast_id}")?;
+        }
+        writeln!(f)?;
+
+        for entry in &self.entries {
+            match *entry {
+                Entry::Open(socket) => writeln!(f, "open {socket}")?,
+                Entry::Send(socket, ref data) => writeln!(f, "send {socket} {}", encode_hex(data))?,
+                Entry::Recv(socket, ref data) => writeln!(f, "recv {socket} {}", encode_hex(data))?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Captures the datagrams passing through wrapped transports.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    fixture: Arc<Mutex<Fixture>>,
+}
+
+impl Recording {
+    /// Starts an empty recording for a client with the given family and multicast group IDs.
+    #[must_use]
+    pub fn new(family_id: u16, mcast_ids: Vec<u32>) -> Self {
+        Self {
+            fixture: Arc::new(Mutex::new(Fixture {
+                family_id,
+                mcast_ids,
+                entries: Vec::new(),
+            })),
+        }
+    }
+
+    /// Records the traffic of the request socket and of every socket opened from it.
+    #[must_use]
+    pub fn wrap(&self, transport: Box<dyn Transport>) -> Box<dyn Transport> {
+        Box::new(RecordingTransport {
+            inner: transport,
+            socket: 0,
+            recording: self.clone(),
+        })
+    }
+
+    /// Traffic recorded so far.
+    #[must_use]
+    pub fn fixture(&self) -> Fixture {
+        self.lock().clone()
+    }
+
+    /// Writes the traffic recorded so far to a fixture file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.fixture().save(path)
+    }
+
+    fn push(&self, entry: Entry) {
+        self.lock().entries.push(entry);
+    }
+
+    fn next_socket(&self) -> usize {
+        self.lock()
+            .entries
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Open(_)))
+            .count()
+            .saturating_add(1)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Fixture> {
+        self.fixture.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+struct RecordingTransport {
+    inner: Box<dyn Transport>,
+    socket: usize,
+    recording: Recording,
+}
+
+impl Transport for RecordingTransport {
+    fn send<'a>(&'a self, buf: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        // Recorded before sending, so that replies are never logged ahead of their request
+        self.recording.push(Entry::Send(self.socket, buf.to_vec()));
+        self.inner.send(buf)
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>> {
+        Box::pin(async move {
+            let size = self.inner.recv(buf).await?;
+            let datagram = buf.get(..size).unwrap_or_default().to_vec();
+            self.recording.push(Entry::Recv(self.socket, datagram));
+            Ok(size)
+        })
+    }
+
+    fn send_nowait(&self, buf: &[u8]) -> io::Result<()> {
+        self.recording.push(Entry::Send(self.socket, buf.to_vec()));
+        self.inner.send_nowait(buf)
+    }
+
+    fn open(&self, mcast_ids: &[u32]) -> io::Result<Box<dyn Transport>> {
+        let inner = self.inner.open(mcast_ids)?;
+        let socket = self.recording.next_socket();
+        self.recording.push(Entry::Open(socket));
+
+        Ok(Box::new(Self {
+            inner,
+            socket,
+            recording: self.recording.clone(),
+        }))
+    }
+}
+
+struct ReplayState {
+    entries: Vec<Entry>,
+    /// Index of the next expected request, or the entry count once all were sent
+    next_send: usize,
+    /// Per socket index of the entry to look for the next datagram from
+    recv_cursors: Vec<usize>,
+}
+
+impl ReplayState {
+    fn find_send(&self, start: usize) -> usize {
+        self.entries
+            .iter()
+            .enumerate()
+            .skip(start)
+            .find(|&(_, entry)| matches!(entry, Entry::Send(..)))
+            .map_or(self.entries.len(), |(index, _)| index)
+    }
+}
+
+struct ReplayShared {
+    state: Mutex<ReplayState>,
+    progress: watch::Sender<usize>,
+}
+
+impl ReplayShared {
+    fn lock(&self) -> MutexGuard<'_, ReplayState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Serves the datagrams of a fixture in place of the kernel.
+pub(crate) struct ReplayTransport {
+    socket: usize,
+    shared: Arc<ReplayShared>,
+}
+
+impl ReplayTransport {
+    pub(crate) fn new(fixture: Fixture) -> Self {
+        let mut state = ReplayState {
+            entries: fixture.entries,
+            next_send: 0,
+            recv_cursors: vec![0],
+        };
+        state.next_send = state.find_send(0);
+
+        let (progress, _) = watch::channel(0);
+
+        Self {
+            socket: 0,
+            shared: Arc::new(ReplayShared {
+                state: Mutex::new(state),
+                progress,
+            }),
+        }
+    }
+
+    fn expect_send(&self, buf: &[u8]) -> io::Result<()> {
+        let mut state = self.shared.lock();
+        let index = state.next_send;
+
+        match state.entries.get(index) {
+            Some(&Entry::Send(socket, ref data)) if socket == self.socket && data == buf => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Unexpected request on socket {} at fixture entry {index}",
+                        self.socket
+                    ),
+                ))
+            }
+        }
+
+        state.next_send = state.find_send(index.saturating_add(1));
+        drop(state);
+
+        self.shared
+            .progress
+            .send_modify(|sent| *sent = sent.saturating_add(1));
+
+        Ok(())
+    }
+
+    fn take_recv(&self) -> Option<Vec<u8>> {
+        let mut state = self.shared.lock();
+        let cursor = state.recv_cursors.get(self.socket).copied()?;
+
+        let (index, data) = state.entries.iter().enumerate().skip(cursor).find_map(
+            |(index, entry)| match *entry {
+                Entry::Recv(socket, ref data) if socket == self.socket => Some((index, data)),
+                _ => None,
+            },
+        )?;
+
+        if index > state.next_send {
+            return None;
+        }
+
+        let data = data.clone();
+        *state.recv_cursors.get_mut(self.socket)? = index.saturating_add(1);
+        drop(state);
+
+        Some(data)
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send<'a>(&'a self, buf: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move { self.expect_send(buf) })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>> {
+        Box::pin(async move {
+            // Subscribed before checking, so that no progress is missed in between
+            let mut progress = self.shared.progress.subscribe();
+
+            loop {
+                if let Some(data) = self.take_recv() {
+                    let target = buf.get_mut(..data.len()).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "Datagram exceeds buffer")
+                    })?;
+                    target.copy_from_slice(&data);
+                    return Ok(data.len());
+                }
+
+                if progress.changed().await.is_err() {
+                    // Like an idle socket, an exhausted fixture never delivers anything
+                    return pending().await;
+                }
+            }
+        })
+    }
+
+    fn send_nowait(&self, buf: &[u8]) -> io::Result<()> {
+        self.expect_send(buf)
+    }
+
+    fn open(&self, _mcast_ids: &[u32]) -> io::Result<Box<dyn Transport>> {
+        let mut state = self.shared.lock();
+        let socket = state.recv_cursors.len();
+        state.recv_cursors.push(0);
+        drop(state);
+
+        Ok(Box::new(Self {
+            socket,
+            shared: Arc::clone(&self.shared),
+        }))
+    }
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").ok();
+        hex
+    })
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len().checked_rem(2) != Some(0) {
+        bail!("Odd number of hex digits");
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digits = core::str::from_utf8(pair).context("Invalid hex")?;
+            u8::from_str_radix(digits, 16).context("Invalid hex")
+        })
+        .collect()
+}
@@ -0,0 +1,432 @@
+//! Scans and the kernel BSS table.
+
+use core::convert::TryInto;
+use core::iter::{from_fn, once};
+use core::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use futures_util::stream::{Stream, StreamExt};
+
+use neli::attr::Attribute;
+use neli::consts::genl::Index;
+use neli::genl::{Genlmsghdr, Nlattr};
+
+use macaddr::MacAddr6;
+
+use tokio::time::{sleep, timeout};
+
+use crate::client::Nl80211;
+use crate::consts::{
+    NL80211_BSS_STATUS_ASSOCIATED, NL80211_SCAN_FLAG_AP, NL80211_SCAN_FLAG_RANDOM_ADDR,
+};
+use crate::enums::{Nl80211Attr, Nl80211Bss, Nl80211Cmd};
+use crate::error::{is_busy, is_not_supported};
+use crate::events::Nl80211Event;
+use crate::interface::find_interface;
+use crate::message::Nl80211Payload;
+use crate::ssid::Ssid;
+use crate::wiphy::get_wiphy;
+
+const WLAN_EID_SSID: u8 = 0;
+
+const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+const SCAN_BUSY_RETRIES: usize = 5;
+const SCAN_BUSY_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    Triggered,
+    Started,
+    Done,
+    Aborted,
+}
+
+impl ScanState {
+    /// Completion events received before our own scan start notification belong to an
+    /// earlier scan on the same interface and are ignored.
+    const fn next(self, event: &Nl80211Event) -> Self {
+        match (self, event) {
+            (Self::Triggered, &Nl80211Event::ScanStarted { .. }) => Self::Started,
+            (Self::Started, &Nl80211Event::ScanDone { .. }) => Self::Done,
+            (Self::Started, &Nl80211Event::ScanAborted { .. }) => Self::Aborted,
+            _ => self,
+        }
+    }
+
+    const fn is_final(self) -> bool {
+        matches!(self, Self::Done | Self::Aborted)
+    }
+}
+
+/// Aborts the scan on drop, so that timeouts and dropped callers do not leave it running.
+/// Armed from the moment a trigger request is sent, as the kernel may start the scan before
+/// the caller receives its acknowledgement.
+struct AbortScanGuard<'a> {
+    nl80211: &'a Nl80211,
+    iface_index: u32,
+    armed: bool,
+}
+
+impl<'a> AbortScanGuard<'a> {
+    const fn new(nl80211: &'a Nl80211, iface_index: u32) -> Self {
+        Self {
+            nl80211,
+            iface_index,
+            armed: false,
+        }
+    }
+
+    const fn arm(&mut self) {
+        self.armed = true;
+    }
+
+    const fn disarm(&mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for AbortScanGuard<'_> {
+    fn drop(&mut self) {
+        if self.armed {
+            println!("Aborting scan...");
+            abort_scan(self.nl80211, self.iface_index).ok();
+        }
+    }
+}
+
+/// Access point found by a scan.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Bss {
+    /// Empty for hidden networks
+    pub ssid: Ssid,
+    /// MAC address of the access point
+    pub bssid: MacAddr6,
+    /// Channel center frequency in MHz
+    pub frequency: u32,
+    /// Signal level of the strongest receive chain in dBm
+    pub signal_dbm: i32,
+    /// Whether the interface is associated with the access point
+    pub associated: bool,
+}
+
+/// Entries of the kernel BSS table, without triggering a new scan.
+#[derive(Debug)]
+pub struct CachedScanResults {
+    /// Entries seen within the requested maximum age
+    pub results: Vec<Bss>,
+    /// Time since the stalest included entry was last seen
+    pub age: Duration,
+}
+
+/// Triggers a scan and returns its results once complete. The scan is aborted if the returned
+/// future is dropped before then.
+pub async fn scan(nl80211: &Nl80211, interface: &str) -> Result<Vec<Bss>> {
+    let iface = find_interface(nl80211, interface).await?;
+
+    // Subscribe before triggering, otherwise the scan may complete before we listen
+    let events = nl80211.event_monitor()?.into_stream(Some(iface.index));
+    tokio::pin!(events);
+
+    let random_addr = supports_random_addr(nl80211, iface.wiphy).await;
+
+    let mut guard = AbortScanGuard::new(nl80211, iface.index);
+
+    trigger_scan_with_retries(nl80211, &mut guard, random_addr)
+        .await
+        .context("Failed to trigger scan")?;
+
+    let state = timeout(SCAN_TIMEOUT, complete_scan(&mut events))
+        .await
+        .context("Timed out waiting for scan results")??;
+
+    guard.disarm();
+
+    if state == ScanState::Aborted {
+        bail!("Scan aborted");
+    }
+
+    get_scan_results(nl80211, iface.index).await
+}
+
+/// Dumps the BSS table without triggering a scan, which would take the radio off channel and
+/// disrupt clients connected to the access point. Entries not seen within `max_age` are skipped.
+pub async fn cached_scan(
+    nl80211: &Nl80211,
+    interface: &str,
+    max_age: Duration,
+) -> Result<CachedScanResults> {
+    let iface = find_interface(nl80211, interface).await?;
+
+    let payloads = nl80211
+        .dump(create_get_scan_message(iface.index)?)
+        .await
+        .context("Failed to receive get scan results response")?;
+
+    let now = boottime_now()?;
+
+    let mut results = Vec::new();
+    let mut age = Duration::ZERO;
+
+    for payload in &payloads {
+        let Some(bss) = parse_bss(payload) else {
+            continue;
+        };
+
+        let bss_age = get_bss_age(payload, now).unwrap_or_default();
+        if bss_age > max_age {
+            continue;
+        }
+
+        age = age.max(bss_age);
+        results.push(bss);
+    }
+
+    Ok(CachedScanResults { results, age })
+}
+
+/// Scanning from a random address hides the permanent one from nearby access points. Failing
+/// to query the wiphy features is not fatal, the scan then uses the permanent address.
+async fn supports_random_addr(nl80211: &Nl80211, wiphy: u32) -> bool {
+    match get_wiphy(nl80211, wiphy).await {
+        Ok(wiphy) => wiphy.supports_scan_random_addr(),
+        Err(err) => {
+            println!("Failed to get wiphy features: {err:#}");
+            false
+        }
+    }
+}
+
+async fn trigger_scan_with_retries(
+    nl80211: &Nl80211,
+    guard: &mut AbortScanGuard<'_>,
+    mut random_addr: bool,
+) -> Result<()> {
+    for _ in 0..SCAN_BUSY_RETRIES {
+        match trigger_scan(nl80211, guard, random_addr).await {
+            Err(err) if is_busy(&err) => {
+                println!("Device busy, retrying scan...");
+                sleep(SCAN_BUSY_RETRY_DELAY).await;
+            }
+            // The kernel refuses random addresses while the interface is connected
+            Err(err) if random_addr && is_not_supported(&err) => {
+                println!("Random scan address rejected, retrying scan...");
+                random_addr = false;
+            }
+            result => return result,
+        }
+    }
+
+    trigger_scan(nl80211, guard, random_addr).await
+}
+
+/// Disarms the guard again on failure, where no scan of ours is running and aborting would
+/// cancel the one another process started.
+async fn trigger_scan(
+    nl80211: &Nl80211,
+    guard: &mut AbortScanGuard<'_>,
+    random_addr: bool,
+) -> Result<()> {
+    let msg = create_trigger_scan_message(guard.iface_index, random_addr)?;
+
+    guard.arm();
+
+    let result = nl80211
+        .request(msg)
+        .await
+        .context("Failed to receive trigger scan acknowledgement");
+
+    if result.is_err() {
+        guard.disarm();
+    }
+
+    result.map(drop)
+}
+
+async fn complete_scan<S>(events: &mut S) -> Result<ScanState>
+where
+    S: Stream<Item = Result<Nl80211Event>> + Unpin,
+{
+    let mut state = ScanState::Triggered;
+
+    while let Some(event) = events.next().await {
+        let event = event.context("Failed to receive scan notification")?;
+
+        state = state.next(&event);
+
+        if state.is_final() {
+            return Ok(state);
+        }
+    }
+
+    bail!("Event stream ended before scan completion")
+}
+
+fn abort_scan(nl80211: &Nl80211, iface_index: u32) -> Result<()> {
+    nl80211
+        .send_nowait(create_abort_scan_message(iface_index)?)
+        .context("Failed to send abort scan message")
+}
+
+/// Dumps the kernel BSS table of the interface, which holds the results of recent scans.
+pub async fn get_scan_results(nl80211: &Nl80211, iface_index: u32) -> Result<Vec<Bss>> {
+    let payloads = nl80211
+        .dump(create_get_scan_message(iface_index)?)
+        .await
+        .context("Failed to receive get scan results response")?;
+
+    Ok(payloads.iter().filter_map(parse_bss).collect())
+}
+
+/// Decodes an entry of a get scan dump, or returns `None` if it lacks the BSSID, frequency or
+/// signal level.
+#[must_use]
+pub fn parse_bss(payload: &Nl80211Payload) -> Option<Bss> {
+    let mut attrs = payload.get_attr_handle();
+    let mut bss_attrs = attrs
+        .get_nested_attributes::<Nl80211Bss>(Nl80211Attr::Bss)
+        .ok()?;
+
+    // Prefer the strongest receive chain, as the combined level may be reported lower
+    let chain_signal_dbm = bss_attrs
+        .get_nested_attributes::<Index>(Nl80211Bss::ChainSignal)
+        .ok()
+        .and_then(|chains| {
+            chains
+                .iter()
+                .filter_map(|chain| chain.get_payload_as::<i8>().ok())
+                .max()
+        });
+
+    let signal_dbm = match chain_signal_dbm {
+        Some(chain_signal_dbm) => i32::from(chain_signal_dbm),
+        None => bss_attrs
+            .get_attribute(Nl80211Bss::SignalMbm)?
+            .get_payload_as::<i32>()
+            .ok()?
+            .checked_div(100)?,
+    };
+
+    let bssid: [u8; 6] = bss_attrs
+        .get_attr_payload_as_with_len::<&[u8]>(Nl80211Bss::Bssid)
+        .ok()?
+        .try_into()
+        .ok()?;
+
+    let frequency = bss_attrs
+        .get_attr_payload_as::<u32>(Nl80211Bss::Frequency)
+        .ok()?;
+
+    let associated = bss_attrs
+        .get_attr_payload_as::<u32>(Nl80211Bss::Status)
+        .ok()
+        == Some(NL80211_BSS_STATUS_ASSOCIATED);
+
+    // Hidden networks do not advertise their SSID
+    let ssid = bss_attrs
+        .get_attribute(Nl80211Bss::InformationElements)
+        .map(|ie_attrs| Ssid::from(extract_ssid(ie_attrs.payload().as_ref())))
+        .unwrap_or_default();
+
+    Some(Bss {
+        ssid,
+        bssid: MacAddr6::from(bssid),
+        frequency,
+        signal_dbm,
+        associated,
+    })
+}
+
+/// Prefers the absolute boottime timestamp, as the relative age is only as fresh as the dump.
+fn get_bss_age(payload: &Nl80211Payload, now: Duration) -> Option<Duration> {
+    let mut attrs = payload.get_attr_handle();
+    let bss_attrs = attrs
+        .get_nested_attributes::<Nl80211Bss>(Nl80211Attr::Bss)
+        .ok()?;
+
+    if let Some(attr) = bss_attrs.get_attribute(Nl80211Bss::LastSeenBoottime) {
+        let last_seen = Duration::from_nanos(attr.get_payload_as::<u64>().ok()?);
+        return Some(now.saturating_sub(last_seen));
+    }
+
+    let seen_ms_ago = bss_attrs
+        .get_attribute(Nl80211Bss::SeenMsAgo)?
+        .get_payload_as::<u32>()
+        .ok()?;
+
+    Some(Duration::from_millis(seen_ms_ago.into()))
+}
+
+fn boottime_now() -> Result<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    // SAFETY: the timespec is a live, writable struct for the duration of the call
+    let res = unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, core::ptr::addr_of_mut!(ts)) };
+
+    if res < 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to read boottime clock");
+    }
+
+    Ok(Duration::new(ts.tv_sec.try_into()?, ts.tv_nsec.try_into()?))
+}
+
+fn create_trigger_scan_message(iface_index: u32, random_addr: bool) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Faled to create interface index attribute")?;
+    let scan_flags = if random_addr {
+        NL80211_SCAN_FLAG_AP | NL80211_SCAN_FLAG_RANDOM_ADDR
+    } else {
+        NL80211_SCAN_FLAG_AP
+    };
+    let scan_attr = Nlattr::new(false, true, Nl80211Attr::ScanFlags, scan_flags)
+        .context("Failed to create scan flags attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::TriggerScan,
+        1,
+        [iface_attr, scan_attr].into_iter().collect(),
+    ))
+}
+
+fn create_abort_scan_message(iface_index: u32) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::AbortScan,
+        1,
+        once(iface_attr).collect(),
+    ))
+}
+
+pub(crate) fn create_get_scan_message(iface_index: u32) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::GetScan,
+        1,
+        once(iface_attr).collect(),
+    ))
+}
+
+pub(crate) fn extract_ssid(ies: &[u8]) -> Vec<u8> {
+    information_elements(ies)
+        .find(|&(eid, _)| eid == WLAN_EID_SSID)
+        .map(|(_, ssid)| ssid.to_vec())
+        .unwrap_or_default()
+}
+
+/// Walks the information elements of a beacon, probe response or probe request body, yielding
+/// the ID and data of each. Stops at the first truncated element.
+pub fn information_elements(ies: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    let mut rest = ies;
+
+    from_fn(move || {
+        let (&eid, tail) = rest.split_first()?;
+        let (&len, tail) = tail.split_first()?;
+        let (data, tail) = tail.split_at_checked(len.into())?;
+        rest = tail;
+        Some((eid, data))
+    })
+}
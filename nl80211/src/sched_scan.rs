@@ -0,0 +1,303 @@
+//! Scheduled scans, which the firmware runs periodically in low power mode.
+
+use core::iter::once;
+use core::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use futures_util::stream::StreamExt;
+
+use neli::consts::genl::{Index, NlAttrType};
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::Buffer;
+
+use crate::client::Nl80211;
+use crate::enums::{Nl80211Attr, Nl80211Cmd, Nl80211SchedScanMatchAttr, Nl80211SchedScanPlan};
+use crate::events::Nl80211Event;
+use crate::interface::find_interface;
+use crate::message::Nl80211Payload;
+use crate::scan::{get_scan_results, Bss};
+use crate::ssid::Ssid;
+
+/// Sent without plans, as the kernel refuses to start a scheduled scan with neither
+const DEFAULT_SCHED_SCAN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Networks the firmware reports on. Both criteria must hold when set.
+#[derive(Debug, Clone, Default)]
+pub struct MatchSet {
+    /// SSID of the network
+    pub ssid: Option<Ssid>,
+    /// Minimum signal strength in dBm
+    pub rssi_threshold: Option<i32>,
+}
+
+/// Scan every `interval` seconds, `iterations` times. The last plan must run indefinitely.
+#[derive(Debug, Clone, Copy)]
+pub struct ScanPlan {
+    /// Seconds between scans
+    pub interval: u32,
+    /// Number of scans, `None` for the last plan
+    pub iterations: Option<u32>,
+}
+
+/// Networks to look for and how often. Without plans, scans run every 30 seconds.
+#[derive(Debug, Clone, Default)]
+pub struct SchedScanConfig {
+    /// Results are reported if any of the sets matches, or for every scan if empty
+    pub match_sets: Vec<MatchSet>,
+    /// Plans run in order
+    pub plans: Vec<ScanPlan>,
+}
+
+/// Stops the scheduled scan on drop, so that it does not keep running in the firmware.
+struct StopSchedScanGuard<'a> {
+    nl80211: &'a Nl80211,
+    iface_index: u32,
+}
+
+impl Drop for StopSchedScanGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(msg) = create_stop_sched_scan_message(self.iface_index) {
+            self.nl80211.send_nowait(msg).ok();
+        }
+    }
+}
+
+/// Runs a scheduled scan until one of the matched networks shows up in the scan results and
+/// returns the matching access points. Wrap in a timeout to bound the wait.
+pub async fn wait_for_match(
+    nl80211: &Nl80211,
+    interface: &str,
+    config: &SchedScanConfig,
+) -> Result<Vec<Bss>> {
+    let iface = find_interface(nl80211, interface).await?;
+
+    let events = nl80211.event_monitor()?.into_stream(Some(iface.index));
+    tokio::pin!(events);
+
+    start_sched_scan(nl80211, iface.index, config)
+        .await
+        .context("Failed to start scheduled scan")?;
+
+    let _guard = StopSchedScanGuard {
+        nl80211,
+        iface_index: iface.index,
+    };
+
+    while let Some(event) = events.next().await {
+        match event.context("Failed to receive scheduled scan notification")? {
+            Nl80211Event::SchedScanResults { .. } => {
+                let results = get_scan_results(nl80211, iface.index).await?;
+
+                let matching = results
+                    .into_iter()
+                    .filter(|bss| is_matching(bss, &config.match_sets))
+                    .collect::<Vec<_>>();
+
+                if !matching.is_empty() {
+                    return Ok(matching);
+                }
+            }
+            Nl80211Event::SchedScanStopped { .. } => bail!("Scheduled scan stopped"),
+            _ => {}
+        }
+    }
+
+    bail!("Event stream ended before scheduled scan match")
+}
+
+/// Starts a scheduled scan, which keeps running until stopped. Results are announced with
+/// [`Nl80211Event::SchedScanResults`].
+pub async fn start_sched_scan(
+    nl80211: &Nl80211,
+    iface_index: u32,
+    config: &SchedScanConfig,
+) -> Result<()> {
+    nl80211
+        .request(create_start_sched_scan_message(iface_index, config)?)
+        .await
+        .context("Failed to receive start scheduled scan acknowledgement")?;
+
+    Ok(())
+}
+
+/// Stops the scheduled scan running on the interface.
+pub async fn stop_sched_scan(nl80211: &Nl80211, iface_index: u32) -> Result<()> {
+    nl80211
+        .request(create_stop_sched_scan_message(iface_index)?)
+        .await
+        .context("Failed to receive stop scheduled scan acknowledgement")?;
+
+    Ok(())
+}
+
+/// Results of a matching scan include all networks, so the match sets are rechecked. The RSSI
+/// threshold is checked as well, as not all drivers enforce it.
+fn is_matching(bss: &Bss, match_sets: &[MatchSet]) -> bool {
+    match_sets.is_empty() || match_sets.iter().any(|match_set| {
+        !matches!(match_set.ssid, Some(ref ssid) if *ssid != bss.ssid)
+            && !matches!(match_set.rssi_threshold, Some(threshold) if bss.signal_dbm < threshold)
+    })
+}
+
+fn create_start_sched_scan_message(
+    iface_index: u32,
+    config: &SchedScanConfig,
+) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+
+    let mut attrs = vec![iface_attr];
+
+    if !config.match_sets.is_empty() {
+        let mut match_attr = Nlattr::new(true, false, Nl80211Attr::SchedScanMatch, Buffer::new())
+            .context("Failed to create scheduled scan match attribute")?;
+        for (index, match_set) in (1..).zip(&config.match_sets) {
+            match_attr
+                .add_nested_attribute(&create_match_set_attribute(index, match_set)?)
+                .context("Failed to add scheduled scan match set")?;
+        }
+        attrs.push(match_attr);
+    }
+
+    if config.plans.is_empty() {
+        let interval_ms = u32::try_from(DEFAULT_SCHED_SCAN_INTERVAL.as_millis())
+            .context("Scheduled scan interval out of range")?;
+        let interval_attr = Nlattr::new(false, false, Nl80211Attr::SchedScanInterval, interval_ms)
+            .context("Failed to create scheduled scan interval attribute")?;
+        attrs.push(interval_attr);
+    } else {
+        let mut plans_attr = Nlattr::new(true, false, Nl80211Attr::SchedScanPlans, Buffer::new())
+            .context("Failed to create scheduled scan plans attribute")?;
+        for (index, plan) in (1..).zip(&config.plans) {
+            plans_attr
+                .add_nested_attribute(&create_plan_attribute(index, plan)?)
+                .context("Failed to add scheduled scan plan")?;
+        }
+        attrs.push(plans_attr);
+    }
+
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::StartSchedScan,
+        1,
+        attrs.into_iter().collect(),
+    ))
+}
+
+fn create_stop_sched_scan_message(iface_index: u32) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::StopSchedScan,
+        1,
+        once(iface_attr).collect(),
+    ))
+}
+
+fn create_match_set_attribute(index: u16, match_set: &MatchSet) -> Result<Nlattr<Index, Buffer>> {
+    let mut attr = create_index_attribute(index)?;
+
+    if let Some(ref ssid) = match_set.ssid {
+        add_nested(&mut attr, Nl80211SchedScanMatchAttr::Ssid, ssid.as_bytes())?;
+    }
+
+    if let Some(rssi_threshold) = match_set.rssi_threshold {
+        add_nested(&mut attr, Nl80211SchedScanMatchAttr::Rssi, rssi_threshold)?;
+    }
+
+    Ok(attr)
+}
+
+fn create_plan_attribute(index: u16, plan: &ScanPlan) -> Result<Nlattr<Index, Buffer>> {
+    let mut attr = create_index_attribute(index)?;
+
+    add_nested(&mut attr, Nl80211SchedScanPlan::Interval, plan.interval)?;
+
+    if let Some(iterations) = plan.iterations {
+        add_nested(&mut attr, Nl80211SchedScanPlan::Iterations, iterations)?;
+    }
+
+    Ok(attr)
+}
+
+/// Nested arrays are encoded as attributes whose types are the 1-based element indices.
+fn create_index_attribute(index: u16) -> Result<Nlattr<Index, Buffer>> {
+    Nlattr::new(true, false, Index::from(index), Buffer::new())
+        .context("Failed to create nested array attribute")
+}
+
+fn add_nested<T, P>(attr: &mut Nlattr<Index, Buffer>, nla_type: T, payload: P) -> Result<()>
+where
+    T: NlAttrType,
+    P: neli::Size + neli::ToBytes,
+{
+    let nested = Nlattr::new(false, false, nla_type, payload)
+        .context("Failed to create nested attribute")?;
+    attr.add_nested_attribute(&nested)
+        .context("Failed to add nested attribute")
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use macaddr::MacAddr6;
+
+    use super::*;
+
+    #[test]
+    fn sends_default_interval_without_plans() {
+        let msg = create_start_sched_scan_message(3, &SchedScanConfig::default()).unwrap();
+        let attrs = msg.get_attr_handle();
+
+        assert_eq!(
+            attrs
+                .get_attr_payload_as::<u32>(Nl80211Attr::SchedScanInterval)
+                .unwrap(),
+            30_000
+        );
+        assert!(attrs.get_attribute(Nl80211Attr::SchedScanPlans).is_none());
+    }
+
+    #[test]
+    fn sends_plans_without_interval() {
+        let config = SchedScanConfig {
+            match_sets: Vec::new(),
+            plans: vec![ScanPlan {
+                interval: 10,
+                iterations: None,
+            }],
+        };
+
+        let msg = create_start_sched_scan_message(3, &config).unwrap();
+        let attrs = msg.get_attr_handle();
+
+        assert!(attrs
+            .get_attribute(Nl80211Attr::SchedScanInterval)
+            .is_none());
+        assert!(attrs.get_attribute(Nl80211Attr::SchedScanPlans).is_some());
+    }
+
+    #[test]
+    fn matches_ssid_and_signal_strength() {
+        let bss = Bss {
+            ssid: Ssid::from("Home"),
+            bssid: MacAddr6::new(0x0a, 0x11, 0x22, 0x33, 0x44, 0x55),
+            frequency: 2412,
+            signal_dbm: -75,
+            associated: false,
+        };
+        let match_set = |ssid: Option<&str>, rssi_threshold| MatchSet {
+            ssid: ssid.map(Ssid::from),
+            rssi_threshold,
+        };
+
+        assert!(is_matching(&bss, &[]));
+        assert!(is_matching(&bss, &[match_set(Some("Home"), Some(-80))]));
+        assert!(!is_matching(&bss, &[match_set(Some("Home"), Some(-70))]));
+        assert!(!is_matching(&bss, &[match_set(Some("Cafe"), None)]));
+        assert!(is_matching(
+            &bss,
+            &[match_set(Some("Cafe"), None), match_set(None, Some(-75))]
+        ));
+    }
+}
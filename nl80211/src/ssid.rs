@@ -0,0 +1,104 @@
+use core::fmt;
+use core::fmt::Write;
+
+use anyhow::{bail, Context, Result};
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+/// Raw SSID bytes. SSIDs are not required to be UTF-8 and legacy access points often use
+/// Latin-1 or GBK names, so they are only decoded for display.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Ssid(Vec<u8>);
+
+impl Ssid {
+    /// Longest SSID in bytes that 802.11 allows.
+    pub const MAX_LEN: usize = 32;
+
+    /// Raw SSID bytes.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Hidden networks have an empty SSID.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Parses the SSID from hex digits, as produced by [`Ssid::to_hex`]. Either case is
+    /// accepted, but nothing besides ASCII hex digits.
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        if hex.len().checked_rem(2) != Some(0) {
+            bail!("Odd number of digits in hex SSID");
+        }
+
+        let bytes = hex
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| match *pair {
+                [high, low] => hex_digit(high)?
+                    .checked_mul(16)?
+                    .checked_add(hex_digit(low)?),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .context("Invalid hex SSID")?;
+
+        Ok(Self(bytes))
+    }
+
+    /// Lossless lowercase hex encoding.
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        self.0.iter().fold(String::new(), |mut hex, byte| {
+            write!(hex, "{byte:02x}").ok();
+            hex
+        })
+    }
+}
+
+fn hex_digit(digit: u8) -> Option<u8> {
+    char::from(digit)
+        .to_digit(16)
+        .and_then(|value| u8::try_from(value).ok())
+}
+
+impl From<Vec<u8>> for Ssid {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<&[u8]> for Ssid {
+    fn from(bytes: &[u8]) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl From<&str> for Ssid {
+    fn from(ssid: &str) -> Self {
+        Self(ssid.as_bytes().to_vec())
+    }
+}
+
+/// Invalid UTF-8 sequences are shown as replacement characters.
+impl fmt::Display for Ssid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
+}
+
+/// Serializes to the display form and the lossless hex form, as `ssid` and `ssid_hex`.
+impl Serialize for Ssid {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("Ssid", 2)?;
+        state.serialize_field("ssid", &self.to_string())?;
+        state.serialize_field("ssid_hex", &self.to_hex())?;
+        state.end()
+    }
+}
@@ -0,0 +1,129 @@
+//! Statistics of the associated access point.
+
+use anyhow::{Context, Result};
+
+use macaddr::MacAddr6;
+
+use neli::attr::AttrHandle;
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::{Buffer, GenlBuffer};
+
+use serde::Serialize;
+
+use crate::client::Nl80211;
+use crate::enums::{Nl80211Attr, Nl80211Cmd, Nl80211RateInfo, Nl80211StaInfo};
+use crate::interface::find_interface;
+use crate::message::Nl80211Payload;
+use crate::scan::{create_get_scan_message, parse_bss};
+use crate::ssid::Ssid;
+
+type RateInfoAttrs<'a> =
+    AttrHandle<'a, GenlBuffer<Nl80211RateInfo, Buffer>, Nlattr<Nl80211RateInfo, Buffer>>;
+
+/// Link quality of the access point the interface is associated with.
+#[derive(Serialize, Debug)]
+pub struct LinkStatus {
+    /// Empty for hidden networks
+    #[serde(flatten)]
+    pub ssid: Ssid,
+    /// MAC address of the access point
+    pub bssid: String,
+    /// Channel center frequency in MHz
+    pub frequency: u32,
+    /// Signal level of the last received frame
+    pub signal_dbm: Option<i8>,
+    /// Bitrate of the last transmitted frame
+    pub tx_bitrate_kbps: Option<u32>,
+    /// Bitrate of the last received frame
+    pub rx_bitrate_kbps: Option<u32>,
+    /// Seconds since the association
+    pub connected_time_secs: Option<u32>,
+}
+
+/// Returns `None` if the interface is not associated.
+pub async fn get_link_status(nl80211: &Nl80211, interface: &str) -> Result<Option<LinkStatus>> {
+    let iface = find_interface(nl80211, interface).await?;
+
+    let payloads = nl80211
+        .dump(create_get_scan_message(iface.index)?)
+        .await
+        .context("Failed to receive get scan results response")?;
+
+    let Some(bss) = payloads
+        .iter()
+        .filter_map(parse_bss)
+        .find(|bss| bss.associated)
+    else {
+        return Ok(None);
+    };
+
+    let payloads = nl80211
+        .request(create_get_station_message(iface.index, bss.bssid)?)
+        .await
+        .context("Failed to receive get station response")?;
+
+    let mut status = LinkStatus {
+        ssid: bss.ssid,
+        bssid: bss.bssid.to_string(),
+        frequency: bss.frequency,
+        signal_dbm: None,
+        tx_bitrate_kbps: None,
+        rx_bitrate_kbps: None,
+        connected_time_secs: None,
+    };
+
+    if let Some(payload) = payloads.first() {
+        parse_station_info(payload, &mut status);
+    }
+
+    Ok(Some(status))
+}
+
+fn parse_station_info(payload: &Nl80211Payload, status: &mut LinkStatus) {
+    let mut attrs = payload.get_attr_handle();
+    let Ok(mut sta_attrs) = attrs.get_nested_attributes::<Nl80211StaInfo>(Nl80211Attr::StaInfo)
+    else {
+        return;
+    };
+
+    status.signal_dbm = sta_attrs.get_attr_payload_as(Nl80211StaInfo::Signal).ok();
+    status.connected_time_secs = sta_attrs
+        .get_attr_payload_as(Nl80211StaInfo::ConnectedTime)
+        .ok();
+    status.tx_bitrate_kbps = sta_attrs
+        .get_nested_attributes::<Nl80211RateInfo>(Nl80211StaInfo::TxBitrate)
+        .ok()
+        .and_then(|rate_attrs| parse_bitrate(&rate_attrs));
+    status.rx_bitrate_kbps = sta_attrs
+        .get_nested_attributes::<Nl80211RateInfo>(Nl80211StaInfo::RxBitrate)
+        .ok()
+        .and_then(|rate_attrs| parse_bitrate(&rate_attrs));
+}
+
+/// Bitrates are reported in units of 100 kbit/s. The 32-bit value is preferred, as the 16-bit
+/// one is capped for fast links.
+fn parse_bitrate(rate_attrs: &RateInfoAttrs<'_>) -> Option<u32> {
+    let bitrate = rate_attrs
+        .get_attr_payload_as::<u32>(Nl80211RateInfo::Bitrate32)
+        .ok()
+        .or_else(|| {
+            rate_attrs
+                .get_attr_payload_as::<u16>(Nl80211RateInfo::Bitrate)
+                .ok()
+                .map(u32::from)
+        })?;
+
+    bitrate.checked_mul(100)
+}
+
+fn create_get_station_message(iface_index: u32, mac_address: MacAddr6) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    let mac_attr = Nlattr::new(false, false, Nl80211Attr::Mac, mac_address.as_bytes())
+        .context("Failed to create MAC address attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::GetStation,
+        1,
+        [iface_attr, mac_attr].into_iter().collect(),
+    ))
+}
@@ -0,0 +1,127 @@
+//! Channel survey, i.e. how busy each channel is.
+
+use core::iter::once;
+
+use anyhow::{Context, Result};
+
+use neli::genl::{Genlmsghdr, Nlattr};
+
+use serde::Serialize;
+
+use crate::client::Nl80211;
+use crate::enums::{Nl80211Attr, Nl80211Cmd, Nl80211SurveyInfo};
+use crate::interface::find_interface;
+use crate::message::Nl80211Payload;
+
+/// Channel usage counters accumulated by the driver. Times are in milliseconds and the
+/// percentages are relative to the time spent on the channel.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct ChannelSurvey {
+    /// Channel center frequency in MHz
+    pub frequency: u32,
+    /// Noise floor
+    pub noise_dbm: Option<i8>,
+    /// Whether the interface currently operates on the channel
+    pub in_use: bool,
+    /// Time spent on the channel
+    pub time_ms: Option<u64>,
+    /// Time the channel was sensed busy
+    pub busy_ms: Option<u64>,
+    /// Time spent receiving
+    pub rx_ms: Option<u64>,
+    /// Time spent transmitting
+    pub tx_ms: Option<u64>,
+    /// Busy time relative to the time on the channel
+    pub busy_percent: Option<u8>,
+    /// Receive time relative to the time on the channel
+    pub rx_percent: Option<u8>,
+    /// Transmit time relative to the time on the channel
+    pub tx_percent: Option<u8>,
+}
+
+/// Survey of every channel the driver has data for.
+pub async fn get_survey(nl80211: &Nl80211, interface: &str) -> Result<Vec<ChannelSurvey>> {
+    let iface = find_interface(nl80211, interface).await?;
+
+    let payloads = nl80211
+        .dump(create_get_survey_message(iface.index)?)
+        .await
+        .context("Failed to receive get survey response")?;
+
+    Ok(payloads.iter().filter_map(parse_survey).collect())
+}
+
+fn parse_survey(payload: &Nl80211Payload) -> Option<ChannelSurvey> {
+    let mut attrs = payload.get_attr_handle();
+    let survey_attrs = attrs
+        .get_nested_attributes::<Nl80211SurveyInfo>(Nl80211Attr::SurveyInfo)
+        .ok()?;
+
+    let frequency = survey_attrs
+        .get_attr_payload_as::<u32>(Nl80211SurveyInfo::Frequency)
+        .ok()?;
+
+    let time = |attr| survey_attrs.get_attr_payload_as::<u64>(attr).ok();
+
+    let time_ms = time(Nl80211SurveyInfo::Time);
+    let busy_ms = time(Nl80211SurveyInfo::TimeBusy);
+    let rx_ms = time(Nl80211SurveyInfo::TimeRx);
+    let tx_ms = time(Nl80211SurveyInfo::TimeTx);
+
+    Some(ChannelSurvey {
+        frequency,
+        noise_dbm: survey_attrs
+            .get_attr_payload_as(Nl80211SurveyInfo::Noise)
+            .ok(),
+        in_use: survey_attrs
+            .get_attribute(Nl80211SurveyInfo::InUse)
+            .is_some(),
+        time_ms,
+        busy_ms,
+        rx_ms,
+        tx_ms,
+        busy_percent: percent(busy_ms, time_ms),
+        rx_percent: percent(rx_ms, time_ms),
+        tx_percent: percent(tx_ms, time_ms),
+    })
+}
+
+fn percent(part: Option<u64>, total: Option<u64>) -> Option<u8> {
+    let percent = part?.checked_mul(100)?.checked_div(total?)?;
+    u8::try_from(percent.min(100)).ok()
+}
+
+fn create_get_survey_message(iface_index: u32) -> Result<Nl80211Payload> {
+    let iface_attr = Nlattr::new(false, true, Nl80211Attr::Ifindex, iface_index)
+        .context("Failed to create interface index attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::GetSurvey,
+        1,
+        once(iface_attr).collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_percent_of_channel_time() {
+        assert_eq!(percent(Some(250), Some(1000)), Some(25));
+        assert_eq!(percent(Some(1), Some(3)), Some(33));
+        assert_eq!(percent(Some(1000), Some(1000)), Some(100));
+    }
+
+    #[test]
+    fn caps_busy_time_above_channel_time() {
+        // Counters are sampled separately and may race each other
+        assert_eq!(percent(Some(1200), Some(1000)), Some(100));
+    }
+
+    #[test]
+    fn skips_percent_without_channel_time() {
+        assert_eq!(percent(Some(0), Some(0)), None);
+        assert_eq!(percent(Some(250), None), None);
+        assert_eq!(percent(None, Some(1000)), None);
+    }
+}
@@ -0,0 +1,87 @@
+//! Datagram transport between the client and the kernel, replaceable for offline testing.
+
+use std::io;
+
+use futures_util::future::BoxFuture;
+
+use neli::consts::socket::NlFamily;
+use neli::socket::{NlSocket, NlSocketHandle};
+
+use tokio::io::unix::AsyncFd;
+
+/// Generic netlink socket. Each call of [`Transport::recv`] returns a whole datagram, which
+/// may hold several netlink messages.
+pub trait Transport: Send + Sync {
+    /// Sends a datagram.
+    fn send<'a>(&'a self, buf: &'a [u8]) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Receives the next datagram into `buf` and returns its length.
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>>;
+
+    /// Sends a datagram without waiting for the socket to become writable. Usable from `Drop`.
+    fn send_nowait(&self, buf: &[u8]) -> io::Result<()>;
+
+    /// Opens another socket of the same kind, joined to the given multicast groups. Frames
+    /// registered for on a socket are delivered to that socket only.
+    fn open(&self, mcast_ids: &[u32]) -> io::Result<Box<dyn Transport>>;
+}
+
+/// Non-blocking generic netlink socket driven by the tokio reactor.
+pub(crate) struct SocketTransport {
+    socket: AsyncFd<NlSocket>,
+}
+
+impl SocketTransport {
+    pub(crate) fn connect(mcast_ids: &[u32]) -> io::Result<Self> {
+        let socket_handle = NlSocketHandle::connect(NlFamily::Generic, None, &[])?;
+
+        if !mcast_ids.is_empty() {
+            socket_handle.add_mcast_membership(mcast_ids)?;
+        }
+
+        Self::from_handle(socket_handle)
+    }
+
+    pub(crate) fn from_handle(socket_handle: NlSocketHandle) -> io::Result<Self> {
+        let socket = NlSocket::from(socket_handle);
+        socket.nonblock()?;
+
+        Ok(Self {
+            socket: AsyncFd::new(socket)?,
+        })
+    }
+}
+
+impl Transport for SocketTransport {
+    fn send<'a>(&'a self, buf: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            loop {
+                let mut guard = self.socket.writable().await?;
+
+                if let Ok(result) = guard.try_io(|inner| inner.get_ref().send(buf, 0)) {
+                    return result.map(drop);
+                }
+            }
+        })
+    }
+
+    fn recv<'a>(&'a self, buf: &'a mut [u8]) -> BoxFuture<'a, io::Result<usize>> {
+        Box::pin(async move {
+            loop {
+                let mut guard = self.socket.readable().await?;
+
+                if let Ok(result) = guard.try_io(|inner| inner.get_ref().recv(&mut *buf, 0)) {
+                    return result;
+                }
+            }
+        })
+    }
+
+    fn send_nowait(&self, buf: &[u8]) -> io::Result<()> {
+        self.socket.get_ref().send(buf, 0).map(drop)
+    }
+
+    fn open(&self, mcast_ids: &[u32]) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Self::connect(mcast_ids)?))
+    }
+}
@@ -0,0 +1,94 @@
+//! Physical wireless devices, each of which can host several interfaces.
+
+use anyhow::{Context, Result};
+
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::Buffer;
+
+use crate::client::Nl80211;
+use crate::consts::NL80211_FEATURE_SCAN_RANDOM_MAC_ADDR;
+use crate::enums::{Nl80211Attr, Nl80211Cmd};
+use crate::message::{parse_string, Nl80211Payload};
+
+/// Capabilities of a wiphy relevant to scanning and configuration.
+#[derive(Debug, Clone)]
+pub struct Wiphy {
+    /// Wiphy index, as referenced by [`crate::interface::Interface::wiphy`]
+    pub index: u32,
+    /// Wiphy name, e.g. `phy0`
+    pub name: String,
+    /// `NL80211_FEATURE_*` flags
+    pub feature_flags: u32,
+    /// Maximum number of SSIDs in a single scan request
+    pub max_scan_ssids: Option<u8>,
+    /// Maximum number of match sets of a scheduled scan, zero if unsupported
+    pub max_match_sets: Option<u8>,
+}
+
+impl Wiphy {
+    /// Whether scans can be sent from a random MAC address while not connected.
+    #[must_use]
+    pub const fn supports_scan_random_addr(&self) -> bool {
+        self.feature_flags & NL80211_FEATURE_SCAN_RANDOM_MAC_ADDR != 0
+    }
+}
+
+/// Describes the wiphy with the given index.
+pub async fn get_wiphy(nl80211: &Nl80211, wiphy: u32) -> Result<Wiphy> {
+    let payloads = nl80211
+        .dump(create_get_wiphy_message(wiphy)?)
+        .await
+        .context("Failed to receive get wiphy response")?;
+
+    // Split dumps spread the wiphy attributes over several messages
+    let attr = |attr| {
+        payloads.iter().find_map(|payload| {
+            payload
+                .get_attr_handle()
+                .get_attr_payload_as::<u8>(attr)
+                .ok()
+        })
+    };
+
+    let name = payloads
+        .iter()
+        .find_map(|payload| {
+            payload
+                .get_attr_handle()
+                .get_attr_payload_as_with_len(Nl80211Attr::WiphyName)
+                .ok()
+                .and_then(|name| parse_string(name).ok())
+        })
+        .context("Missing wiphy name")?;
+
+    let feature_flags = payloads
+        .iter()
+        .find_map(|payload| {
+            payload
+                .get_attr_handle()
+                .get_attr_payload_as::<u32>(Nl80211Attr::FeatureFlags)
+                .ok()
+        })
+        .context("Missing wiphy feature flags")?;
+
+    Ok(Wiphy {
+        index: wiphy,
+        name,
+        feature_flags,
+        max_scan_ssids: attr(Nl80211Attr::MaxNumScanSsids),
+        max_match_sets: attr(Nl80211Attr::MaxMatchSets),
+    })
+}
+
+fn create_get_wiphy_message(wiphy: u32) -> Result<Nl80211Payload> {
+    let wiphy_attr = Nlattr::new(false, false, Nl80211Attr::Wiphy, wiphy)
+        .context("Failed to create wiphy attribute")?;
+    // Large wiphy descriptions do not fit into a single message unless split
+    let split_attr = Nlattr::new(false, false, Nl80211Attr::SplitWiphyDump, Buffer::new())
+        .context("Failed to create split wiphy dump attribute")?;
+    Ok(Genlmsghdr::new(
+        Nl80211Cmd::GetWiphy,
+        1,
+        [wiphy_attr, split_attr].into_iter().collect(),
+    ))
+}
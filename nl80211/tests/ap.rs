@@ -0,0 +1,102 @@
+//! Beacon templates of the native access point, checked the way scanning clients decode them.
+
+use macaddr::MacAddr6;
+
+use nl80211::ap::{channel_frequency, Beacon, DEFAULT_CHANNEL};
+use nl80211::scan::information_elements;
+use nl80211::Ssid;
+
+const BSSID: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x01, 0x00];
+
+// Header and the timestamp, beacon interval and capability fields
+const BEACON_FIXED_LEN: usize = 36;
+
+fn beacon(ssid: &str) -> Beacon {
+    Beacon::new(
+        &Ssid::from(ssid),
+        MacAddr6::from(BSSID),
+        DEFAULT_CHANNEL,
+        false,
+    )
+    .unwrap()
+}
+
+fn elements(bytes: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    information_elements(bytes)
+        .map(|(eid, data)| (eid, data.to_vec()))
+        .collect()
+}
+
+#[test]
+fn builds_beacon_header() {
+    let head = beacon("WiFi Connect").head;
+
+    assert_eq!(head[..2], [0x80, 0x00]);
+    assert_eq!(head[4..10], [0xff; 6]);
+    assert_eq!(head[10..16], BSSID);
+    assert_eq!(head[16..22], BSSID);
+    // 100 TU interval of an infrastructure network without privacy
+    assert_eq!(head[32..34], 100_u16.to_le_bytes());
+    assert_eq!(head[34..36], 0x0001_u16.to_le_bytes());
+}
+
+#[test]
+fn splits_elements_around_traffic_indication_map() {
+    let beacon = beacon("WiFi Connect");
+
+    let head = elements(&beacon.head[BEACON_FIXED_LEN..]);
+    let tail = elements(&beacon.tail);
+
+    assert_eq!(
+        head.iter().map(|(eid, _)| *eid).collect::<Vec<_>>(),
+        [0, 1, 3]
+    );
+    assert_eq!(head[0].1, b"WiFi Connect");
+    assert_eq!(head[1].1, [0x82, 0x84, 0x8b, 0x96, 0x0c, 0x12, 0x18, 0x24]);
+    assert_eq!(head[2].1, [DEFAULT_CHANNEL]);
+    assert_eq!(tail, [(50, vec![0x30, 0x48, 0x60, 0x6c])]);
+}
+
+#[test]
+fn rejects_oversized_ssid() {
+    let ssid = Ssid::from(vec![b'a'; 256]);
+
+    assert!(Beacon::new(&ssid, MacAddr6::from(BSSID), DEFAULT_CHANNEL, false).is_err());
+}
+
+#[test]
+fn advertises_wpa2_for_protected_networks() {
+    let beacon = Beacon::new(
+        &Ssid::from("WiFi Connect"),
+        MacAddr6::from(BSSID),
+        DEFAULT_CHANNEL,
+        true,
+    )
+    .unwrap();
+
+    // Privacy capability
+    assert_eq!(beacon.head[34..36], 0x0011_u16.to_le_bytes());
+    assert_eq!(
+        elements(&beacon.tail),
+        [
+            (
+                48,
+                vec![
+                    0x01, 0x00, 0x00, 0x0f, 0xac, 0x04, 0x01, 0x00, 0x00, 0x0f, 0xac, 0x04, 0x01,
+                    0x00, 0x00, 0x0f, 0xac, 0x02, 0x00, 0x00
+                ]
+            ),
+            (50, vec![0x30, 0x48, 0x60, 0x6c])
+        ]
+    );
+}
+
+#[test]
+fn maps_channels_to_frequencies() {
+    assert_eq!(channel_frequency(1), Some(2412));
+    assert_eq!(channel_frequency(DEFAULT_CHANNEL), Some(2437));
+    assert_eq!(channel_frequency(13), Some(2472));
+    assert_eq!(channel_frequency(0), None);
+    assert_eq!(channel_frequency(14), None);
+    assert_eq!(channel_frequency(36), None);
+}
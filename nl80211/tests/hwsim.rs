@@ -0,0 +1,235 @@
+//! Tests against the virtual radios of `mac80211_hwsim`. They need the module loaded with two
+//! radios, e.g. `modprobe mac80211_hwsim radios=2`, and `CAP_NET_ADMIN`, and are ignored by
+//! default. Run them with `cargo test -p nl80211 --test hwsim -- --ignored`.
+
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use nl80211::ap::{AccessPoint, AccessPointConfig, DEFAULT_CHANNEL};
+use nl80211::interface::{find_interface, get_interfaces, Iftype};
+use nl80211::link::set_link_up;
+use nl80211::power::get_power_save;
+use nl80211::regulatory::get_regulatory;
+use nl80211::scan::scan;
+use nl80211::station::get_link_status;
+use nl80211::survey::get_survey;
+use nl80211::wiphy::get_wiphy;
+use nl80211::{Nl80211, Ssid};
+
+/// Names of the network interfaces backed by hwsim radios. Panics if there are none, rather
+/// than passing without testing anything.
+fn hwsim_interfaces() -> Vec<String> {
+    assert!(
+        Path::new("/sys/module/mac80211_hwsim").exists(),
+        "mac80211_hwsim is not loaded"
+    );
+
+    let mut names = fs::read_dir("/sys/class/net")
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| sysfs_phy(name).is_some())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    assert!(!names.is_empty(), "No hwsim interfaces");
+
+    names
+}
+
+/// Wiphy name of an hwsim backed interface.
+fn sysfs_phy(name: &str) -> Option<String> {
+    let phy = fs::canonicalize(format!("/sys/class/net/{name}/phy80211")).ok()?;
+
+    if !phy.to_string_lossy().contains("hwsim") {
+        return None;
+    }
+
+    Some(phy.file_name()?.to_string_lossy().into_owned())
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn lists_hwsim_interfaces() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    let ifaces = get_interfaces(&nl80211).await.unwrap();
+
+    for name in &names {
+        let iface = ifaces.iter().find(|iface| iface.name == *name).unwrap();
+        let found = find_interface(&nl80211, name).await.unwrap();
+        assert_eq!(found.index, iface.index);
+        assert_eq!(found.mac_address, iface.mac_address);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn fails_to_find_missing_interface() {
+    hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    assert!(find_interface(&nl80211, "hwsim-missing").await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn describes_wiphy() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    for name in &names {
+        let iface = find_interface(&nl80211, name).await.unwrap();
+        let wiphy = get_wiphy(&nl80211, iface.wiphy).await.unwrap();
+
+        assert_eq!(wiphy.index, iface.wiphy);
+        assert_eq!(Some(wiphy.name), sysfs_phy(name));
+        assert!(wiphy.max_scan_ssids.is_some());
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn scans() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    let results = scan(&nl80211, &names[0]).await.unwrap();
+
+    assert!(results.iter().all(|bss| bss.frequency > 0));
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn concurrent_scans_share_interface() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    let (first, second) = tokio::join!(scan(&nl80211, &names[0]), scan(&nl80211, &names[0]));
+
+    first.unwrap();
+    second.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn reads_link_status() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    // Unassociated radios have no link, associated ones report the access point
+    if let Some(status) = get_link_status(&nl80211, &names[0]).await.unwrap() {
+        assert!(status.frequency > 0);
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn surveys_channels() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    let survey = get_survey(&nl80211, &names[0]).await.unwrap();
+
+    assert!(survey.iter().all(|channel| channel.frequency > 0));
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn reads_regulatory_domain() {
+    hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+
+    let domain = get_regulatory(&nl80211, None).await.unwrap();
+
+    assert_eq!(domain.alpha2.len(), 2);
+    assert!(domain
+        .rules
+        .iter()
+        .all(|rule| rule.start_khz < rule.end_khz));
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn reads_power_save() {
+    let names = hwsim_interfaces();
+    let nl80211 = Nl80211::new().unwrap();
+    let iface = find_interface(&nl80211, &names[0]).await.unwrap();
+
+    get_power_save(&nl80211, iface.index).await.unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn hosts_access_point() {
+    let names = hwsim_interfaces();
+    let [ap_name, client_name, ..] = names.as_slice() else {
+        panic!("Two hwsim radios needed");
+    };
+    let nl80211 = Nl80211::new().unwrap();
+    let iface = find_interface(&nl80211, ap_name).await.unwrap();
+
+    set_link_up(iface.index, true).unwrap();
+
+    let ssid = Ssid::from("hwsim-portal");
+    let config = AccessPointConfig {
+        ssid: ssid.clone(),
+        channel: DEFAULT_CHANNEL,
+        address: Ipv4Addr::new(192, 168, 42, 1),
+        prefix_len: 24,
+        passphrase: None,
+    };
+    let access_point = AccessPoint::start(&nl80211, ap_name, &config)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        find_interface(&nl80211, ap_name).await.unwrap().iftype,
+        Iftype::AP
+    );
+
+    let results = scan(&nl80211, client_name).await.unwrap();
+    let bss = results.iter().find(|bss| bss.ssid == ssid).unwrap();
+    assert_eq!(bss.bssid, iface.mac_address);
+    assert_eq!(bss.frequency, 2437);
+
+    access_point.stop().await.unwrap();
+
+    assert_eq!(
+        find_interface(&nl80211, ap_name).await.unwrap().iftype,
+        Iftype::Station
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs mac80211_hwsim and CAP_NET_ADMIN"]
+async fn hosts_protected_access_point() {
+    let names = hwsim_interfaces();
+    let [ap_name, client_name, ..] = names.as_slice() else {
+        panic!("Two hwsim radios needed");
+    };
+    let nl80211 = Nl80211::new().unwrap();
+
+    let ssid = Ssid::from("hwsim-protected-portal");
+    let mut config = AccessPointConfig {
+        ssid: ssid.clone(),
+        channel: DEFAULT_CHANNEL,
+        address: Ipv4Addr::new(192, 168, 42, 1),
+        prefix_len: 24,
+        passphrase: Some("short".to_owned()),
+    };
+    assert!(AccessPoint::start(&nl80211, ap_name, &config)
+        .await
+        .is_err());
+
+    config.passphrase = Some("hwsim-passphrase".to_owned());
+    let access_point = AccessPoint::start(&nl80211, ap_name, &config)
+        .await
+        .unwrap();
+
+    let results = scan(&nl80211, client_name).await.unwrap();
+    assert!(results.iter().any(|bss| bss.ssid == ssid));
+
+    access_point.stop().await.unwrap();
+}
@@ -0,0 +1,288 @@
+//! Property tests for the decoding of untrusted data, which arrives over the air in beacons and
+//! probe responses before the kernel relays it. The fuzz targets in `fuzz` cover the same
+//! parsers with coverage guidance.
+
+use core::convert::TryFrom;
+use std::io::Cursor;
+
+use macaddr::MacAddr6;
+
+use neli::consts::genl::Index;
+use neli::genl::{Genlmsghdr, Nlattr};
+use neli::types::{Buffer, GenlBuffer};
+use neli::{FromBytesWithInput, Size, ToBytes};
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use nl80211::consts::NL80211_BSS_STATUS_ASSOCIATED;
+use nl80211::enums::{Nl80211Attr, Nl80211Bss, Nl80211Cmd};
+use nl80211::interface::{Iftype, Interface};
+use nl80211::scan::{information_elements, parse_bss};
+use nl80211::{Nl80211Payload, Ssid};
+
+const WLAN_EID_SSID: u8 = 0;
+const GENL_HDRLEN: usize = 4;
+const NLA_HDRLEN: usize = 4;
+
+fn encode(payload: &Nl80211Payload) -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    payload.to_bytes(&mut cursor).unwrap();
+    cursor.into_inner()
+}
+
+fn decode(bytes: &[u8]) -> Option<Nl80211Payload> {
+    Nl80211Payload::from_bytes_with_input(&mut Cursor::new(bytes), bytes.len()).ok()
+}
+
+/// neli panics on attributes shorter than their header, which the kernel never sends, so such
+/// inputs are left out.
+fn is_well_framed(mut attrs: &[u8]) -> bool {
+    while let Some(&[low, high]) = attrs.get(..2) {
+        let len = usize::from(u16::from_ne_bytes([low, high]));
+        if len < NLA_HDRLEN {
+            return false;
+        }
+        attrs = attrs.get(len.next_multiple_of(4)..).unwrap_or_default();
+    }
+
+    true
+}
+
+fn attr<P: Size + ToBytes>(attr_type: Nl80211Attr, payload: P) -> Nlattr<Nl80211Attr, Buffer> {
+    Nlattr::new(false, false, attr_type, payload).unwrap()
+}
+
+fn bss_attr<P: Size + ToBytes>(attr_type: Nl80211Bss, payload: P) -> Nlattr<Nl80211Bss, Buffer> {
+    Nlattr::new(false, false, attr_type, payload).unwrap()
+}
+
+fn payload(cmd: Nl80211Cmd, attrs: Vec<Nlattr<Nl80211Attr, Buffer>>) -> Nl80211Payload {
+    Genlmsghdr::new(cmd, 1, attrs.into_iter().collect::<GenlBuffer<_, _>>())
+}
+
+/// Information elements serialized back to back.
+fn elements_bytes(elements: &[(u8, Vec<u8>)]) -> Vec<u8> {
+    elements
+        .iter()
+        .flat_map(|(eid, data)| {
+            let len = u8::try_from(data.len()).unwrap();
+            [*eid, len].into_iter().chain(data.iter().copied())
+        })
+        .collect()
+}
+
+fn bss_payload(
+    bssid: [u8; 6],
+    frequency: u32,
+    ies: Vec<u8>,
+    signal_mbm: i32,
+    chain_signals: &[i8],
+    associated: bool,
+) -> Nl80211Payload {
+    let mut bss = Nlattr::new(true, false, Nl80211Attr::Bss, Buffer::new()).unwrap();
+    bss.add_nested_attribute(&bss_attr(Nl80211Bss::Bssid, Buffer::from(bssid.to_vec())))
+        .unwrap();
+    bss.add_nested_attribute(&bss_attr(Nl80211Bss::Frequency, frequency))
+        .unwrap();
+    bss.add_nested_attribute(&bss_attr(
+        Nl80211Bss::InformationElements,
+        Buffer::from(ies),
+    ))
+    .unwrap();
+    bss.add_nested_attribute(&bss_attr(Nl80211Bss::SignalMbm, signal_mbm))
+        .unwrap();
+
+    if !chain_signals.is_empty() {
+        let mut chains = Nlattr::new(true, false, Nl80211Bss::ChainSignal, Buffer::new()).unwrap();
+        for (chain, signal) in (0_u16..).zip(chain_signals) {
+            chains
+                .add_nested_attribute(
+                    &Nlattr::new(false, false, Index::from(chain), *signal).unwrap(),
+                )
+                .unwrap();
+        }
+        bss.add_nested_attribute(&chains).unwrap();
+    }
+
+    if associated {
+        bss.add_nested_attribute(&bss_attr(Nl80211Bss::Status, NL80211_BSS_STATUS_ASSOCIATED))
+            .unwrap();
+    }
+
+    payload(Nl80211Cmd::NewScanResults, vec![bss])
+}
+
+proptest! {
+    #[test]
+    fn walks_well_formed_elements(
+        elements in vec((any::<u8>(), vec(any::<u8>(), 0..=255)), 0..16),
+    ) {
+        let bytes = elements_bytes(&elements);
+
+        let walked = information_elements(&bytes)
+            .map(|(eid, data)| (eid, data.to_vec()))
+            .collect::<Vec<_>>();
+
+        prop_assert_eq!(walked, elements);
+    }
+
+    #[test]
+    fn walks_arbitrary_elements_within_bounds(bytes in vec(any::<u8>(), 0..1024)) {
+        let walked = information_elements(&bytes)
+            .map(|(_, data)| data.len() + 2)
+            .sum::<usize>();
+
+        prop_assert!(walked <= bytes.len());
+    }
+
+    #[test]
+    fn decodes_bss(
+        bssid in any::<[u8; 6]>(),
+        frequency in any::<u32>(),
+        ssid in vec(any::<u8>(), 0..=32),
+        other_elements in vec((1_u8.., vec(any::<u8>(), 0..=255)), 0..4),
+        signal_mbm in -10_000_i32..0,
+        chain_signals in vec(any::<i8>(), 0..4),
+        associated in any::<bool>(),
+    ) {
+        let mut elements = other_elements;
+        elements.insert(0, (WLAN_EID_SSID, ssid.clone()));
+        let payload = bss_payload(
+            bssid,
+            frequency,
+            elements_bytes(&elements),
+            signal_mbm,
+            &chain_signals,
+            associated,
+        );
+
+        let bss = parse_bss(&decode(&encode(&payload)).unwrap()).unwrap();
+
+        let signal_dbm = chain_signals
+            .iter()
+            .max()
+            .map_or(signal_mbm / 100, |&signal| i32::from(signal));
+        prop_assert_eq!(bss.ssid.as_bytes(), ssid.as_slice());
+        prop_assert_eq!(bss.bssid, MacAddr6::from(bssid));
+        prop_assert_eq!(bss.frequency, frequency);
+        prop_assert_eq!(bss.signal_dbm, signal_dbm);
+        prop_assert_eq!(bss.associated, associated);
+    }
+
+    #[test]
+    fn survives_arbitrary_bss_attributes(bytes in vec(any::<u8>(), 0..512)) {
+        prop_assume!(is_well_framed(&bytes));
+
+        let payload = payload(
+            Nl80211Cmd::NewScanResults,
+            vec![Nlattr::new(true, false, Nl80211Attr::Bss, Buffer::from(bytes)).unwrap()],
+        );
+
+        if let Some(payload) = decode(&encode(&payload)) {
+            let _bss = parse_bss(&payload);
+        }
+    }
+
+    #[test]
+    fn survives_arbitrary_messages(bytes in vec(any::<u8>(), 0..512)) {
+        prop_assume!(is_well_framed(bytes.get(GENL_HDRLEN..).unwrap_or_default()));
+
+        if let Some(payload) = decode(&bytes) {
+            let _bss = parse_bss(&payload);
+            Interface::try_from(&payload).ok();
+        }
+    }
+
+    #[test]
+    fn round_trips_hex_ssid(bytes in vec(any::<u8>(), 0..=32)) {
+        let ssid = Ssid::from(bytes);
+
+        prop_assert_eq!(Ssid::from_hex(&ssid.to_hex()).unwrap(), ssid.clone());
+        prop_assert_eq!(Ssid::from_hex(&ssid.to_hex().to_uppercase()).unwrap(), ssid);
+    }
+
+    #[test]
+    fn survives_arbitrary_hex_ssid(hex in ".{0,64}") {
+        if let Ok(ssid) = Ssid::from_hex(&hex) {
+            prop_assert_eq!(ssid.to_hex(), hex.to_lowercase());
+        }
+    }
+
+    #[test]
+    fn decodes_interface(
+        name in "[a-z][a-z0-9]{0,14}",
+        index in any::<u32>(),
+        wiphy in any::<u32>(),
+        wdev in any::<u64>(),
+        mac in any::<[u8; 6]>(),
+    ) {
+        let mut ifname = name.clone().into_bytes();
+        ifname.push(0);
+        let payload = payload(
+            Nl80211Cmd::NewInterface,
+            vec![
+                attr(Nl80211Attr::Ifindex, index),
+                attr(Nl80211Attr::Ifname, Buffer::from(ifname)),
+                attr(Nl80211Attr::Iftype, 2_u32),
+                attr(Nl80211Attr::Wiphy, wiphy),
+                attr(Nl80211Attr::Wdev, wdev),
+                attr(Nl80211Attr::Mac, Buffer::from(mac.to_vec())),
+            ],
+        );
+
+        let iface = Interface::try_from(&decode(&encode(&payload)).unwrap()).unwrap();
+
+        prop_assert_eq!(iface.name, name);
+        prop_assert_eq!(iface.index, index);
+        prop_assert_eq!(iface.iftype, Iftype::Station);
+        prop_assert_eq!(iface.wiphy, wiphy);
+        prop_assert_eq!(iface.wdev, wdev);
+        prop_assert_eq!(iface.mac_address, MacAddr6::from(mac));
+    }
+
+    #[test]
+    fn survives_arbitrary_interface_attributes(
+        attrs in vec((0_u16..300, vec(any::<u8>(), 0..32)), 0..8),
+    ) {
+        let attrs = attrs
+            .into_iter()
+            .map(|(attr_type, data)| attr(Nl80211Attr::from(attr_type), Buffer::from(data)))
+            .collect();
+
+        if let Some(payload) = decode(&encode(&payload(Nl80211Cmd::NewInterface, attrs))) {
+            Interface::try_from(&payload).ok();
+        }
+    }
+}
+
+#[test]
+fn survives_empty_interface_name() {
+    let payload = payload(
+        Nl80211Cmd::NewInterface,
+        vec![attr(Nl80211Attr::Ifname, Buffer::new())],
+    );
+
+    assert!(Interface::try_from(&decode(&encode(&payload)).unwrap()).is_err());
+}
+
+#[test]
+fn encodes_hex_ssid() {
+    assert_eq!(Ssid::from("Café").to_hex(), "436166c3a9");
+    assert_eq!(Ssid::from_hex("436166C3A9").unwrap(), Ssid::from("Café"));
+    assert_eq!(Ssid::from_hex("").unwrap(), Ssid::default());
+}
+
+#[test]
+fn rejects_invalid_hex_ssid() {
+    // Odd length
+    assert!(Ssid::from_hex("abc").is_err());
+    // Signs accepted by integer parsing
+    assert!(Ssid::from_hex("+a+b").is_err());
+    assert!(Ssid::from_hex("-1").is_err());
+    // Non-ASCII characters, with an even byte length
+    assert!(Ssid::from_hex("éé").is_err());
+    assert!(Ssid::from_hex("٣٣").is_err());
+    assert!(Ssid::from_hex("0x41").is_err());
+    assert!(Ssid::from_hex("4g").is_err());
+}
@@ -0,0 +1,417 @@
+//! Offline tests replaying recorded netlink traffic from `tests/fixtures`.
+
+use core::time::Duration;
+use std::path::PathBuf;
+
+use futures_util::stream::StreamExt;
+
+use macaddr::MacAddr6;
+
+use tokio::time::timeout;
+
+use nl80211::error::{is_already_active, ErrorKind, Nl80211Error};
+use nl80211::events::Nl80211Event;
+use nl80211::interface::{find_interface, get_interfaces, Iftype};
+use nl80211::power::{apply_power_settings, restore_power_settings, PowerSettings};
+use nl80211::probe::ProbeListener;
+use nl80211::replay::Fixture;
+use nl80211::scan::{cached_scan, scan};
+use nl80211::sched_scan::{wait_for_match, MatchSet, ScanPlan, SchedScanConfig};
+use nl80211::station::get_link_status;
+use nl80211::survey::get_survey;
+use nl80211::wiphy::get_wiphy;
+use nl80211::{Nl80211, Ssid};
+
+fn fixture(name: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures", name]
+        .iter()
+        .collect()
+}
+
+fn replay(name: &str) -> Nl80211 {
+    Nl80211::replay(fixture(name)).unwrap()
+}
+
+fn sched_scan_config() -> SchedScanConfig {
+    SchedScanConfig {
+        match_sets: vec![MatchSet {
+            ssid: Some(Ssid::from("Home")),
+            rssi_threshold: Some(-70),
+        }],
+        plans: vec![
+            ScanPlan {
+                interval: 10,
+                iterations: Some(6),
+            },
+            ScanPlan {
+                interval: 30,
+                iterations: None,
+            },
+        ],
+    }
+}
+
+#[tokio::test]
+async fn enumerates_interfaces() {
+    let nl80211 = replay("interfaces.nlrec");
+
+    let ifaces = get_interfaces(&nl80211).await.unwrap();
+
+    assert_eq!(ifaces.len(), 2);
+    assert_eq!(ifaces[0].name, "wlan0");
+    assert_eq!(ifaces[0].index, 3);
+    assert_eq!(ifaces[0].iftype, Iftype::Station);
+    assert_eq!(ifaces[0].wiphy, 0);
+    assert_eq!(ifaces[0].mac_address, MacAddr6::new(2, 0, 0, 0, 0, 0));
+    assert_eq!(ifaces[1].name, "wlan1");
+    assert_eq!(ifaces[1].iftype, Iftype::AP);
+}
+
+#[tokio::test]
+async fn finds_interface_by_name() {
+    let nl80211 = replay("interfaces.nlrec");
+
+    let iface = find_interface(&nl80211, "wlan1").await.unwrap();
+
+    assert_eq!(iface.index, 4);
+}
+
+#[tokio::test]
+async fn fails_to_find_missing_interface() {
+    let nl80211 = replay("interfaces.nlrec");
+
+    assert!(find_interface(&nl80211, "wlan9").await.is_err());
+}
+
+#[tokio::test]
+async fn rejects_unexpected_request() {
+    let nl80211 = replay("interfaces.nlrec");
+
+    let err = get_wiphy(&nl80211, 0).await.unwrap_err();
+
+    assert!(format!("{err:#}").contains("Unexpected request"));
+}
+
+#[tokio::test]
+async fn maps_notifications_to_events() {
+    let nl80211 = replay("events.nlrec");
+    let mut monitor = nl80211.event_monitor().unwrap();
+
+    let mut events = Vec::new();
+    for _ in 0..8 {
+        events.push(monitor.next_event().await.unwrap());
+    }
+
+    assert!(matches!(
+        events[0],
+        Nl80211Event::ScanStarted { ifindex: 3 }
+    ));
+    assert!(matches!(events[1], Nl80211Event::ScanDone { ifindex: 4 }));
+    assert!(matches!(
+        events[2],
+        Nl80211Event::ScanAborted { ifindex: 3 }
+    ));
+    assert!(matches!(events[3], Nl80211Event::ScanDone { ifindex: 3 }));
+    assert!(matches!(
+        events[4],
+        Nl80211Event::Connect {
+            ifindex: 3,
+            bssid: Some(bssid),
+            status: Some(0),
+        } if bssid == MacAddr6::new(0x0a, 0x11, 0x22, 0x33, 0x44, 0x55)
+    ));
+    assert!(matches!(
+        events[5],
+        Nl80211Event::Disconnect {
+            ifindex: 4,
+            reason: Some(3),
+            by_ap: false,
+        }
+    ));
+    assert!(matches!(
+        events[6],
+        Nl80211Event::RegChange { alpha2: Some(ref alpha2) } if alpha2 == "DE"
+    ));
+    assert!(matches!(
+        events[7],
+        Nl80211Event::Disconnect {
+            ifindex: 3,
+            reason: Some(4),
+            by_ap: true,
+        }
+    ));
+}
+
+#[tokio::test]
+async fn drops_events_for_other_interfaces() {
+    let nl80211 = replay("events.nlrec");
+    let events = nl80211.event_monitor().unwrap().into_stream(Some(3));
+
+    let events = events
+        .take(6)
+        .map(|event| event.unwrap().ifindex())
+        .collect::<Vec<_>>()
+        .await;
+
+    assert_eq!(events, [Some(3), Some(3), Some(3), Some(3), None, Some(3)]);
+}
+
+#[tokio::test]
+async fn scans_and_parses_results() {
+    let nl80211 = replay("scan.nlrec");
+
+    let results = scan(&nl80211, "wlan0").await.unwrap();
+
+    assert_eq!(results.len(), 3);
+
+    // The strongest receive chain is preferred over the combined signal
+    assert_eq!(results[0].ssid, Ssid::from("Home"));
+    assert_eq!(
+        results[0].bssid,
+        MacAddr6::new(0x0a, 0x11, 0x22, 0x33, 0x44, 0x55)
+    );
+    assert_eq!(results[0].frequency, 2437);
+    assert_eq!(results[0].signal_dbm, -41);
+    assert!(results[0].associated);
+
+    assert_eq!(results[1].ssid, Ssid::from("Cafe Wi-Fi"));
+    assert_eq!(results[1].frequency, 5180);
+    assert_eq!(results[1].signal_dbm, -67);
+    assert!(!results[1].associated);
+
+    // Hidden networks are kept with an empty SSID
+    assert!(results[2].ssid.is_empty());
+    assert_eq!(results[2].signal_dbm, -73);
+}
+
+#[tokio::test]
+async fn retries_busy_scan() {
+    let nl80211 = replay("scan_busy.nlrec");
+
+    let results = scan(&nl80211, "wlan0").await.unwrap();
+
+    assert_eq!(results.len(), 3);
+}
+
+#[tokio::test]
+async fn retries_scan_without_random_addr() {
+    let nl80211 = replay("scan_random_addr_rejected.nlrec");
+
+    let results = scan(&nl80211, "wlan0").await.unwrap();
+
+    assert_eq!(results.len(), 3);
+}
+
+#[tokio::test]
+async fn fails_aborted_scan() {
+    let nl80211 = replay("scan_aborted.nlrec");
+
+    let err = scan(&nl80211, "wlan0").await.unwrap_err();
+
+    assert_eq!(err.to_string(), "Scan aborted");
+}
+
+#[tokio::test]
+async fn aborts_scan_dropped_while_triggering() {
+    let nl80211 = replay("scan_dropped.nlrec");
+
+    let scanned = timeout(Duration::from_millis(100), scan(&nl80211, "wlan0")).await;
+    assert!(scanned.is_err());
+
+    // Only served once the abort request recorded before it has been sent
+    let ifaces = get_interfaces(&nl80211).await.unwrap();
+    assert_eq!(ifaces.len(), 2);
+}
+
+#[tokio::test]
+async fn dumps_cached_results_without_scanning() {
+    let nl80211 = replay("cached_scan.nlrec");
+
+    let cached = cached_scan(&nl80211, "wlan0", Duration::MAX).await.unwrap();
+
+    assert_eq!(cached.results.len(), 3);
+    assert_eq!(cached.results[0].ssid, Ssid::from("Home"));
+}
+
+#[tokio::test]
+async fn reports_link_status() {
+    let nl80211 = replay("link_status.nlrec");
+
+    let status = get_link_status(&nl80211, "wlan0").await.unwrap().unwrap();
+
+    assert_eq!(status.ssid, Ssid::from("Home"));
+    assert_eq!(status.bssid, "0A:11:22:33:44:55");
+    assert_eq!(status.frequency, 2437);
+    assert_eq!(status.signal_dbm, Some(-52));
+    // The 32-bit bitrate is preferred over the capped 16-bit one
+    assert_eq!(status.tx_bitrate_kbps, Some(866_700));
+    assert_eq!(status.rx_bitrate_kbps, Some(54_000));
+    assert_eq!(status.connected_time_secs, Some(120));
+}
+
+#[tokio::test]
+async fn reports_partial_link_status() {
+    let nl80211 = replay("link_status_partial.nlrec");
+
+    let status = get_link_status(&nl80211, "wlan0").await.unwrap().unwrap();
+
+    assert_eq!(status.signal_dbm, None);
+    // Rate info with an MCS index but without a bitrate
+    assert_eq!(status.tx_bitrate_kbps, None);
+    assert_eq!(status.rx_bitrate_kbps, None);
+    assert_eq!(status.connected_time_secs, Some(5));
+}
+
+#[tokio::test]
+async fn reports_no_link_status_when_not_associated() {
+    let nl80211 = replay("link_status_not_associated.nlrec");
+
+    let status = get_link_status(&nl80211, "wlan0").await.unwrap();
+
+    assert!(status.is_none());
+}
+
+#[tokio::test]
+async fn surveys_channels() {
+    let nl80211 = replay("survey.nlrec");
+
+    let survey = get_survey(&nl80211, "wlan0").await.unwrap();
+
+    // The entry without a frequency is skipped
+    assert_eq!(survey.len(), 3);
+
+    assert_eq!(survey[0].frequency, 2412);
+    assert_eq!(survey[0].noise_dbm, Some(-92));
+    assert!(survey[0].in_use);
+    assert_eq!(survey[0].time_ms, Some(1000));
+    assert_eq!(survey[0].busy_percent, Some(25));
+    assert_eq!(survey[0].rx_percent, Some(10));
+    assert_eq!(survey[0].tx_percent, Some(5));
+
+    assert_eq!(survey[1].frequency, 2437);
+    assert!(!survey[1].in_use);
+    assert_eq!(survey[1].time_ms, None);
+    assert_eq!(survey[1].busy_percent, None);
+
+    assert_eq!(survey[2].frequency, 5180);
+    assert_eq!(survey[2].noise_dbm, None);
+    assert_eq!(survey[2].time_ms, Some(0));
+    assert_eq!(survey[2].busy_percent, None);
+}
+
+#[tokio::test]
+async fn waits_for_sched_scan_match() {
+    let nl80211 = replay("sched_scan_match.nlrec");
+
+    let matching = wait_for_match(&nl80211, "wlan0", &sched_scan_config())
+        .await
+        .unwrap();
+
+    assert_eq!(matching.len(), 1);
+    assert_eq!(matching[0].ssid, Ssid::from("Home"));
+}
+
+#[tokio::test]
+async fn reports_all_networks_without_match_sets() {
+    let nl80211 = replay("sched_scan_any.nlrec");
+    let config = SchedScanConfig {
+        match_sets: Vec::new(),
+        ..sched_scan_config()
+    };
+
+    let matching = wait_for_match(&nl80211, "wlan0", &config).await.unwrap();
+
+    assert_eq!(matching.len(), 3);
+}
+
+#[tokio::test]
+async fn fails_stopped_sched_scan() {
+    let nl80211 = replay("sched_scan_stopped.nlrec");
+
+    let err = wait_for_match(&nl80211, "wlan0", &sched_scan_config())
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.to_string(), "Scheduled scan stopped");
+}
+
+#[tokio::test]
+async fn keeps_waiting_without_sched_scan_results() {
+    let nl80211 = replay("sched_scan_waiting.nlrec");
+
+    let waited = timeout(
+        Duration::from_millis(100),
+        wait_for_match(&nl80211, "wlan0", &sched_scan_config()),
+    )
+    .await;
+
+    assert!(waited.is_err());
+}
+
+#[tokio::test]
+async fn fails_to_listen_for_probes_registered_elsewhere() {
+    let nl80211 = replay("probe_registered.nlrec");
+
+    let err = ProbeListener::new(&nl80211, "wlan0").await.unwrap_err();
+
+    assert!(is_already_active(&err));
+    assert_eq!(
+        err.chain().nth(1).map(ToString::to_string).as_deref(),
+        Some("Probe requests are received by another process on the interface")
+    );
+}
+
+#[tokio::test]
+async fn rolls_back_partially_applied_power_settings() {
+    let nl80211 = replay("power_rollback.nlrec");
+
+    let settings = PowerSettings {
+        power_save: Some(true),
+        tx_power_dbm: Some(20),
+    };
+    let err = apply_power_settings(&nl80211, "wlan0", settings)
+        .await
+        .unwrap_err();
+
+    assert_eq!(err.to_string(), "Failed to set TX power");
+    assert_eq!(
+        err.downcast_ref::<Nl80211Error>().map(Nl80211Error::kind),
+        Some(ErrorKind::PermissionDenied)
+    );
+}
+
+#[tokio::test]
+async fn restores_previous_tx_power_level() {
+    let nl80211 = replay("power_restore.nlrec");
+
+    let settings = PowerSettings {
+        power_save: None,
+        tx_power_dbm: Some(20),
+    };
+    let previous = apply_power_settings(&nl80211, "wlan0", settings)
+        .await
+        .unwrap();
+
+    restore_power_settings(&nl80211, previous).await.unwrap();
+}
+
+#[tokio::test]
+async fn reports_extended_ack_error() {
+    let nl80211 = replay("scan_interface_down.nlrec");
+
+    let err = scan(&nl80211, "wlan0").await.unwrap_err();
+    let err = err.downcast_ref::<Nl80211Error>().unwrap();
+
+    assert_eq!(err.errno(), libc::ENETDOWN);
+    assert_eq!(err.kind(), ErrorKind::Other);
+    assert_eq!(err.message(), Some("Interface is down"));
+}
+
+#[test]
+fn round_trips_fixture() {
+    let fixture = Fixture::load(fixture("scan.nlrec")).unwrap();
+
+    let reparsed: Fixture = fixture.to_string().parse().unwrap();
+
+    assert_eq!(reparsed, fixture);
+}
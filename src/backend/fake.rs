@@ -0,0 +1,402 @@
+//! In-memory backend for tests, with scriptable devices, scan results, profiles and activation
+//! outcomes.
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{bail, Context, Result};
+
+use nl80211::Ssid;
+
+use crate::backend::{
+    AccessPoint, ActivationState, Backend, ConnectionType, Connectivity, FailureReason, Profile,
+    ProfileMode, ProfileSettings, ProfileUpdate,
+};
+
+#[derive(Debug, Clone)]
+pub struct FakeDevice {
+    pub interface: String,
+    pub wifi: bool,
+    pub managed: bool,
+}
+
+/// Activation of a profile. It is active from the start or never, as scripted.
+#[derive(Debug, Clone)]
+pub struct FakeConnection {
+    uuid: String,
+    failure: Option<FailureReason>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    devices: Vec<FakeDevice>,
+    access_points: Vec<AccessPoint>,
+    profiles: Vec<Profile>,
+    /// Passphrases set by profile updates
+    passphrases: HashMap<String, String>,
+    /// Outcomes of the next activations, which succeed once these run out
+    activations: VecDeque<ActivationState>,
+    active: Vec<String>,
+    scans: usize,
+    next_uuid: usize,
+    connectivity: Connectivity,
+    /// Whether the next wait for the state of an activation fails
+    fail_wait_for_state: bool,
+    /// Whether the backend only hosts the portal, as the native one
+    portal_only: bool,
+    /// Whether adding a client profile activates the saved one of the network, as with iwd
+    reuse_saved_profiles: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FakeBackend {
+    state: Rc<RefCell<State>>,
+}
+
+impl FakeBackend {
+    /// Backend with a single managed wireless device `wlan0`.
+    pub fn new() -> Self {
+        Self::default()
+            .with_device("wlan0", true, true)
+            .with_connectivity(Connectivity::Full)
+    }
+
+    pub fn with_device(self, interface: &str, wifi: bool, managed: bool) -> Self {
+        self.state.borrow_mut().devices.push(FakeDevice {
+            interface: interface.to_owned(),
+            wifi,
+            managed,
+        });
+        self
+    }
+
+    /// Connectivity reported by every check.
+    pub fn with_connectivity(self, connectivity: Connectivity) -> Self {
+        self.state.borrow_mut().connectivity = connectivity;
+        self
+    }
+
+    /// Activates the saved profile of a network when adding a client profile for it, as iwd
+    /// does, instead of adding another one as Network Manager does.
+    pub fn reusing_saved_profiles(self) -> Self {
+        self.state.borrow_mut().reuse_saved_profiles = true;
+        self
+    }
+
+    /// Hosts the portal without joining networks, as the native backend.
+    pub fn portal_only(self) -> Self {
+        self.state.borrow_mut().portal_only = true;
+        self
+    }
+
+    pub fn without_devices(self) -> Self {
+        self.state.borrow_mut().devices.clear();
+        self
+    }
+
+    pub fn with_access_point(self, ssid: Option<&str>, strength: u8) -> Self {
+        self.state.borrow_mut().access_points.push(AccessPoint {
+            ssid: ssid.map(Ssid::from),
+            strength,
+        });
+        self
+    }
+
+    pub fn with_profile(self, uuid: &str, ssid: &str, access_point: bool) -> Self {
+        self.state.borrow_mut().profiles.push(Profile {
+            id: ssid.to_owned(),
+            uuid: uuid.to_owned(),
+            connection_type: ConnectionType::Wifi,
+            access_point,
+            ssid: Some(Ssid::from(ssid)),
+            autoconnect: !access_point,
+            autoconnect_priority: 0,
+            last_used: None,
+            active: false,
+        });
+        self
+    }
+
+    pub fn with_ethernet_profile(self, uuid: &str, id: &str) -> Self {
+        self.state.borrow_mut().profiles.push(Profile {
+            id: id.to_owned(),
+            uuid: uuid.to_owned(),
+            connection_type: ConnectionType::Ethernet,
+            access_point: false,
+            ssid: None,
+            autoconnect: true,
+            autoconnect_priority: 0,
+            last_used: None,
+            active: false,
+        });
+        self
+    }
+
+    /// Makes the next activation end deactivated, as with wrong credentials.
+    pub fn fail_next_activation(&self) {
+        self.state
+            .borrow_mut()
+            .activations
+            .push_back(ActivationState::Deactivated(None));
+    }
+
+    /// Makes the next activation fail with the reason.
+    pub fn fail_next_activation_with(&self, reason: FailureReason) {
+        self.state
+            .borrow_mut()
+            .activations
+            .push_back(ActivationState::Deactivated(Some(reason)));
+    }
+
+    /// Makes the next wait for the state of an activation fail, leaving the activation as it is.
+    pub fn fail_next_wait_for_state(&self) {
+        self.state.borrow_mut().fail_wait_for_state = true;
+    }
+
+    /// UUIDs of the active profiles.
+    pub fn active(&self) -> Vec<String> {
+        self.state.borrow().active.clone()
+    }
+
+    /// Profile an active connection was activated from.
+    pub fn connection_profile(&self, connection: &FakeConnection) -> Option<Profile> {
+        self.profile(&connection.uuid)
+    }
+
+    pub fn scans(&self) -> usize {
+        self.state.borrow().scans
+    }
+
+    pub fn passphrase(&self, uuid: &str) -> Option<String> {
+        self.state.borrow().passphrases.get(uuid).cloned()
+    }
+
+    fn profile(&self, uuid: &str) -> Option<Profile> {
+        self.current_profiles()
+            .into_iter()
+            .find(|profile| profile.uuid == uuid)
+    }
+
+    /// Profiles with their current activation state.
+    fn current_profiles(&self) -> Vec<Profile> {
+        let state = self.state.borrow();
+
+        state
+            .profiles
+            .iter()
+            .map(|profile| Profile {
+                active: state.active.contains(&profile.uuid),
+                ..profile.clone()
+            })
+            .collect()
+    }
+
+    fn start_activation(&self, uuid: String) -> FakeConnection {
+        let mut state = self.state.borrow_mut();
+        let outcome = state
+            .activations
+            .pop_front()
+            .unwrap_or(ActivationState::Activated);
+
+        // The device has one connection at a time, which is replaced even if activating fails
+        state.active.clear();
+
+        let failure = match outcome {
+            ActivationState::Activated => {
+                state.active.push(uuid.clone());
+                None
+            }
+            ActivationState::Deactivated(reason) => reason,
+        };
+
+        FakeConnection { uuid, failure }
+    }
+}
+
+impl Backend for FakeBackend {
+    type Device = String;
+    type Connection = FakeConnection;
+
+    async fn find_device(&self, interface: Option<&str>) -> Result<String> {
+        let state = self.state.borrow();
+
+        let device = match interface {
+            Some(interface) => {
+                let device = state
+                    .devices
+                    .iter()
+                    .find(|device| device.interface == interface)
+                    .with_context(|| format!("Failed to find interface '{interface}'"))?;
+                if !device.wifi {
+                    bail!("Not a WiFi interface '{interface}'");
+                }
+                if !device.managed {
+                    bail!("Interface is not managed by NetworkManager '{interface}'");
+                }
+                device
+            }
+            None => state
+                .devices
+                .iter()
+                .find(|device| device.wifi && device.managed)
+                .context("Failed to find a managed WiFi device")?,
+        };
+
+        Ok(device.interface.clone())
+    }
+
+    fn device_interface(&self, device: &String) -> String {
+        device.clone()
+    }
+
+    fn can_join(&self) -> bool {
+        !self.state.borrow().portal_only
+    }
+
+    async fn scan(&self, _device: &String) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.scans = state.scans.saturating_add(1);
+        Ok(())
+    }
+
+    async fn access_points(&self, _device: &String) -> Result<Vec<AccessPoint>> {
+        Ok(self.state.borrow().access_points.clone())
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>> {
+        Ok(self.current_profiles())
+    }
+
+    async fn active_profile(&self, _device: &String) -> Result<Option<String>> {
+        let state = self.state.borrow();
+
+        Ok(state
+            .profiles
+            .iter()
+            .find(|profile| !profile.access_point && state.active.contains(&profile.uuid))
+            .map(|profile| profile.uuid.clone()))
+    }
+
+    async fn delete_profile(&self, uuid: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let count = state.profiles.len();
+        state.profiles.retain(|profile| profile.uuid != uuid);
+        state.active.retain(|active| active != uuid);
+
+        if state.profiles.len() == count {
+            bail!("Failed to find connection profile {uuid}");
+        }
+
+        Ok(())
+    }
+
+    async fn update_profile(&self, uuid: &str, update: &ProfileUpdate) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+
+        let profile = state
+            .profiles
+            .iter_mut()
+            .find(|profile| profile.uuid == uuid)
+            .with_context(|| format!("Failed to find connection profile {uuid}"))?;
+
+        if let Some(autoconnect) = update.autoconnect {
+            profile.autoconnect = autoconnect;
+        }
+        if let Some(priority) = update.autoconnect_priority {
+            profile.autoconnect_priority = priority;
+        }
+        if let Some(ref passphrase) = update.passphrase {
+            state
+                .passphrases
+                .insert(uuid.to_owned(), passphrase.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn activate_profile(&self, uuid: &str, _device: &String) -> Result<FakeConnection> {
+        self.profile(uuid)
+            .with_context(|| format!("Failed to find connection profile {uuid}"))?;
+
+        Ok(self.start_activation(uuid.to_owned()))
+    }
+
+    async fn add_and_activate(
+        &self,
+        settings: &ProfileSettings,
+        _device: &String,
+    ) -> Result<FakeConnection> {
+        let access_point = matches!(settings.mode, ProfileMode::AccessPoint { .. });
+
+        let saved = {
+            let state = self.state.borrow();
+            state
+                .profiles
+                .iter()
+                .filter(|_| state.reuse_saved_profiles && !access_point)
+                .find(|profile| {
+                    !profile.access_point && profile.ssid.as_ref() == Some(&settings.ssid)
+                })
+                .map(|profile| profile.uuid.clone())
+        };
+
+        if let Some(uuid) = saved {
+            return Ok(self.start_activation(uuid));
+        }
+
+        let uuid = {
+            let mut state = self.state.borrow_mut();
+            state.next_uuid = state.next_uuid.saturating_add(1);
+            let uuid = format!("fake-{}", state.next_uuid);
+
+            state.profiles.push(Profile {
+                id: settings.ssid.to_string(),
+                uuid: uuid.clone(),
+                connection_type: ConnectionType::Wifi,
+                access_point,
+                ssid: Some(settings.ssid.clone()),
+                autoconnect: !access_point,
+                autoconnect_priority: 0,
+                last_used: None,
+                active: false,
+            });
+
+            uuid
+        };
+
+        Ok(self.start_activation(uuid))
+    }
+
+    async fn deactivate(&self, connection: &FakeConnection) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let count = state.active.len();
+        state.active.retain(|active| *active != connection.uuid);
+
+        if state.active.len() == count {
+            bail!("Connection is not active");
+        }
+
+        Ok(())
+    }
+
+    async fn wait_for_state(&self, connection: &FakeConnection) -> Result<ActivationState> {
+        if core::mem::take(&mut self.state.borrow_mut().fail_wait_for_state) {
+            bail!("Lost track of the activation of {}", connection.uuid);
+        }
+
+        if self.active().contains(&connection.uuid) {
+            Ok(ActivationState::Activated)
+        } else {
+            Ok(ActivationState::Deactivated(connection.failure))
+        }
+    }
+
+    async fn delete_connection_profile(&self, connection: &FakeConnection) -> Result<()> {
+        self.delete_profile(&connection.uuid).await
+    }
+
+    async fn check_connectivity(&self) -> Result<Connectivity> {
+        Ok(self.state.borrow().connectivity)
+    }
+}
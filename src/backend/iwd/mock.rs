@@ -0,0 +1,536 @@
+//! Scripted iwd service on a private session bus, exposing one device `wlan0` with the
+//! networks in range.
+
+// D-Bus methods take `self` and owned arguments whether they need them or not, and hold the
+// state lock throughout
+#![allow(
+    clippy::unused_self,
+    clippy::needless_pass_by_value,
+    clippy::significant_drop_tightening
+)]
+
+use alloc::sync::Arc;
+use core::fmt::Write;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use zbus::message::Header;
+use zbus::names::OwnedUniqueName;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue};
+use zbus::{connection, fdo, interface, Connection, DBusError};
+
+use super::private_bus::PrivateBus;
+
+const DEVICE_PATH: &str = "/net/connman/iwd/0/3";
+const AGENT_MANAGER_PATH: &str = "/net/connman/iwd";
+
+/// Client connection to the bus, as the backend under test uses it.
+pub async fn connect(bus: &PrivateBus) -> Connection {
+    connection::Builder::address(bus.address())
+        .expect("Invalid bus address")
+        .build()
+        .await
+        .expect("Failed to connect to private bus")
+}
+
+#[derive(Debug, Clone)]
+pub struct MockNetwork {
+    name: String,
+    signal_dbm: i16,
+    passphrase: Option<String>,
+    hidden: bool,
+    known: bool,
+    auto_connect: bool,
+}
+
+impl MockNetwork {
+    pub fn new(name: &str, signal_dbm: i16, passphrase: Option<&str>) -> Self {
+        Self {
+            name: name.to_owned(),
+            signal_dbm,
+            passphrase: passphrase.map(ToOwned::to_owned),
+            hidden: false,
+            known: false,
+            auto_connect: true,
+        }
+    }
+
+    pub const fn hidden(mut self) -> Self {
+        self.hidden = true;
+        self
+    }
+
+    pub const fn known(mut self) -> Self {
+        self.known = true;
+        self
+    }
+
+    fn path(&self) -> String {
+        format!("{DEVICE_PATH}/{}_psk", hex(&self.name))
+    }
+
+    fn known_path(&self) -> String {
+        format!("{AGENT_MANAGER_PATH}/{}_psk", hex(&self.name))
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    networks: Vec<MockNetwork>,
+    mode: String,
+    connected: Option<usize>,
+    ap_started: Option<String>,
+    ap_profile_dir: PathBuf,
+    scans: usize,
+    agent: Option<(OwnedUniqueName, OwnedObjectPath)>,
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+fn lock(state: &SharedState) -> MutexGuard<'_, State> {
+    state.lock().expect("Mock state poisoned")
+}
+
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "net.connman.iwd")]
+enum MockError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Failed(String),
+    Aborted(String),
+    NotFound(String),
+    NotAvailable(String),
+    InvalidArguments(String),
+}
+
+/// Handle of the service for inspecting its state.
+pub struct MockIwd {
+    state: SharedState,
+    _connection: Connection,
+}
+
+impl MockIwd {
+    pub async fn serve(
+        bus: &PrivateBus,
+        networks: Vec<MockNetwork>,
+        ap_profile_dir: PathBuf,
+    ) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            networks,
+            mode: "station".to_owned(),
+            connected: None,
+            ap_started: None,
+            ap_profile_dir,
+            scans: 0,
+            agent: None,
+        }));
+
+        let mut builder = connection::Builder::address(bus.address())
+            .expect("Invalid bus address")
+            .name("net.connman.iwd")
+            .expect("Invalid bus name")
+            .serve_at("/", ObjectManager(Arc::clone(&state)))
+            .expect("Failed to serve object manager")
+            .serve_at(AGENT_MANAGER_PATH, AgentManager(Arc::clone(&state)))
+            .expect("Failed to serve agent manager")
+            .serve_at(DEVICE_PATH, Device(Arc::clone(&state)))
+            .expect("Failed to serve device")
+            .serve_at(DEVICE_PATH, Station(Arc::clone(&state)))
+            .expect("Failed to serve station")
+            .serve_at(DEVICE_PATH, AccessPoint(Arc::clone(&state)))
+            .expect("Failed to serve access point");
+
+        let paths = lock(&state)
+            .networks
+            .iter()
+            .map(|network| (network.path(), network.known_path()))
+            .collect::<Vec<_>>();
+        for (index, (path, known_path)) in paths.into_iter().enumerate() {
+            builder = builder
+                .serve_at(path, Network(Arc::clone(&state), index))
+                .expect("Failed to serve network")
+                .serve_at(known_path, KnownNetwork(Arc::clone(&state), index))
+                .expect("Failed to serve known network");
+        }
+
+        Self {
+            state,
+            _connection: builder.build().await.expect("Failed to serve mock iwd"),
+        }
+    }
+
+    pub fn mode(&self) -> String {
+        lock(&self.state).mode.clone()
+    }
+
+    pub fn scans(&self) -> usize {
+        lock(&self.state).scans
+    }
+
+    /// Name of the network the station is connected to.
+    pub fn connected(&self) -> Option<String> {
+        let state = lock(&self.state);
+        let index = state.connected?;
+        state
+            .networks
+            .get(index)
+            .map(|network| network.name.clone())
+    }
+
+    /// SSID of the running access point.
+    pub fn ap_started(&self) -> Option<String> {
+        lock(&self.state).ap_started.clone()
+    }
+
+    pub fn known(&self) -> Vec<String> {
+        lock(&self.state)
+            .networks
+            .iter()
+            .filter(|network| network.known)
+            .map(|network| network.name.clone())
+            .collect()
+    }
+
+    /// Names of the known networks iwd connects to automatically.
+    pub fn auto_connect(&self) -> Vec<String> {
+        lock(&self.state)
+            .networks
+            .iter()
+            .filter(|network| network.known && network.auto_connect)
+            .map(|network| network.name.clone())
+            .collect()
+    }
+}
+
+/// Lists the interfaces of each object without their properties, which are read separately.
+struct ObjectManager(SharedState);
+
+type ManagedObjects = HashMap<OwnedObjectPath, HashMap<String, HashMap<String, OwnedValue>>>;
+
+#[interface(name = "org.freedesktop.DBus.ObjectManager")]
+impl ObjectManager {
+    fn get_managed_objects(&self) -> ManagedObjects {
+        let state = lock(&self.0);
+
+        let mut objects = ManagedObjects::new();
+        let mut add = |path: &str, interfaces: &[&str]| {
+            objects.insert(
+                ObjectPath::try_from(path).expect("Invalid path").into(),
+                interfaces
+                    .iter()
+                    .map(|interface| ((*interface).to_owned(), HashMap::new()))
+                    .collect(),
+            );
+        };
+
+        let mode_interface = if state.mode == "ap" {
+            "net.connman.iwd.AccessPoint"
+        } else {
+            "net.connman.iwd.Station"
+        };
+        add(DEVICE_PATH, &["net.connman.iwd.Device", mode_interface]);
+        add(AGENT_MANAGER_PATH, &["net.connman.iwd.AgentManager"]);
+
+        for network in &state.networks {
+            if state.mode == "station" && !network.hidden {
+                add(&network.path(), &["net.connman.iwd.Network"]);
+            }
+            if network.known {
+                add(&network.known_path(), &["net.connman.iwd.KnownNetwork"]);
+            }
+        }
+
+        objects
+    }
+}
+
+struct AgentManager(SharedState);
+
+#[interface(name = "net.connman.iwd.AgentManager")]
+impl AgentManager {
+    fn register_agent(&self, path: ObjectPath<'_>, #[zbus(header)] header: Header<'_>) {
+        let sender = header.sender().expect("No sender").to_owned().into();
+        lock(&self.0).agent = Some((sender, path.into()));
+    }
+}
+
+struct Device(SharedState);
+
+#[interface(name = "net.connman.iwd.Device")]
+impl Device {
+    #[zbus(property)]
+    fn name(&self) -> String {
+        "wlan0".to_owned()
+    }
+
+    #[zbus(property)]
+    fn mode(&self) -> String {
+        lock(&self.0).mode.clone()
+    }
+
+    #[zbus(property)]
+    fn set_mode(&self, mode: String) -> fdo::Result<()> {
+        if mode != "station" && mode != "ap" {
+            return Err(fdo::Error::InvalidArgs(format!("Unsupported mode {mode}")));
+        }
+
+        let mut state = lock(&self.0);
+        state.connected = None;
+        state.ap_started = None;
+        state.mode = mode;
+
+        Ok(())
+    }
+}
+
+struct Station(SharedState);
+
+impl Station {
+    fn check_mode(&self) -> Result<(), MockError> {
+        if lock(&self.0).mode == "station" {
+            Ok(())
+        } else {
+            Err(MockError::NotAvailable("Not in station mode".to_owned()))
+        }
+    }
+}
+
+#[interface(name = "net.connman.iwd.Station")]
+impl Station {
+    fn scan(&self) -> Result<(), MockError> {
+        self.check_mode()?;
+        let mut state = lock(&self.0);
+        state.scans = state.scans.saturating_add(1);
+        Ok(())
+    }
+
+    fn disconnect(&self) -> Result<(), MockError> {
+        self.check_mode()?;
+        lock(&self.0).connected = None;
+        Ok(())
+    }
+
+    fn get_ordered_networks(&self) -> Result<Vec<(OwnedObjectPath, i16)>, MockError> {
+        self.check_mode()?;
+
+        let mut networks = lock(&self.0)
+            .networks
+            .iter()
+            .filter(|network| !network.hidden)
+            .map(|network| {
+                (
+                    ObjectPath::try_from(network.path())
+                        .expect("Invalid path")
+                        .into(),
+                    network.signal_dbm.saturating_mul(100),
+                )
+            })
+            .collect::<Vec<(OwnedObjectPath, i16)>>();
+        networks.sort_by_key(|&(_, signal)| core::cmp::Reverse(signal));
+
+        Ok(networks)
+    }
+
+    async fn connect_hidden_network(
+        &self,
+        name: String,
+        #[zbus(connection)] connection: &Connection,
+    ) -> Result<(), MockError> {
+        self.check_mode()?;
+
+        let index = lock(&self.0)
+            .networks
+            .iter()
+            .position(|network| network.hidden && network.name == name)
+            .ok_or_else(|| MockError::NotFound(format!("No hidden network {name}")))?;
+
+        connect(&self.0, connection, index).await
+    }
+
+    #[zbus(property)]
+    fn scanning(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn state(&self) -> String {
+        if lock(&self.0).connected.is_some() {
+            "connected".to_owned()
+        } else {
+            "disconnected".to_owned()
+        }
+    }
+
+    #[zbus(property)]
+    fn connected_network(&self) -> fdo::Result<OwnedObjectPath> {
+        let state = lock(&self.0);
+        let network = state
+            .connected
+            .and_then(|index| state.networks.get(index))
+            .ok_or_else(|| fdo::Error::Failed("Not connected".to_owned()))?;
+
+        ObjectPath::try_from(network.path())
+            .map(Into::into)
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+/// Connects to the network, asking the registered agent for the passphrase of unknown ones.
+async fn connect(
+    state: &SharedState,
+    connection: &Connection,
+    index: usize,
+) -> Result<(), MockError> {
+    let (network, agent) = {
+        let state = lock(state);
+        let network = state.networks.get(index).cloned().expect("No network");
+        (network, state.agent.clone())
+    };
+
+    if let (Some(expected), false) = (&network.passphrase, network.known) {
+        let (sender, path) =
+            agent.ok_or_else(|| MockError::Failed("No agent registered".to_owned()))?;
+        let network_path = ObjectPath::try_from(network.path()).expect("Invalid path");
+
+        let passphrase = connection
+            .call_method(
+                Some(sender),
+                path,
+                Some("net.connman.iwd.Agent"),
+                "RequestPassphrase",
+                &(network_path,),
+            )
+            .await
+            .map_err(|err| MockError::Aborted(format!("Agent failed: {err}")))?
+            .body()
+            .deserialize::<String>()?;
+
+        if passphrase != *expected {
+            lock(state).connected = None;
+            return Err(MockError::Failed("Operation failed".to_owned()));
+        }
+    }
+
+    let mut state = lock(state);
+    state.connected = Some(index);
+    if let Some(network) = state.networks.get_mut(index) {
+        network.known = true;
+    }
+
+    Ok(())
+}
+
+struct Network(SharedState, usize);
+
+#[interface(name = "net.connman.iwd.Network")]
+impl Network {
+    async fn connect(&self, #[zbus(connection)] connection: &Connection) -> Result<(), MockError> {
+        connect(&self.0, connection, self.1).await
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        lock(&self.0)
+            .networks
+            .get(self.1)
+            .map(|network| network.name.clone())
+            .unwrap_or_default()
+    }
+}
+
+struct KnownNetwork(SharedState, usize);
+
+#[interface(name = "net.connman.iwd.KnownNetwork")]
+impl KnownNetwork {
+    fn forget(&self) -> Result<(), MockError> {
+        let mut state = lock(&self.0);
+
+        let network = state
+            .networks
+            .get_mut(self.1)
+            .filter(|network| network.known)
+            .ok_or_else(|| MockError::NotFound("Not a known network".to_owned()))?;
+        network.known = false;
+
+        if state.connected == Some(self.1) {
+            state.connected = None;
+        }
+
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        lock(&self.0)
+            .networks
+            .get(self.1)
+            .map(|network| network.name.clone())
+            .unwrap_or_default()
+    }
+
+    #[zbus(property)]
+    fn auto_connect(&self) -> bool {
+        lock(&self.0)
+            .networks
+            .get(self.1)
+            .is_some_and(|network| network.auto_connect)
+    }
+
+    #[zbus(property)]
+    fn set_auto_connect(&self, auto_connect: bool) {
+        if let Some(network) = lock(&self.0).networks.get_mut(self.1) {
+            network.auto_connect = auto_connect;
+        }
+    }
+}
+
+struct AccessPoint(SharedState);
+
+#[interface(name = "net.connman.iwd.AccessPoint")]
+impl AccessPoint {
+    /// Starts from the profile named like iwd does in the profile directory, which must set a
+    /// passphrase.
+    fn start_profile(&self, ssid: String) -> Result<(), MockError> {
+        let mut state = lock(&self.0);
+        if state.mode != "ap" {
+            return Err(MockError::NotAvailable(
+                "Not in access point mode".to_owned(),
+            ));
+        }
+
+        let path = state
+            .ap_profile_dir
+            .join(format!("{}.ap", super::ap_profile_name(&ssid)));
+        let profile = std::fs::read_to_string(path)
+            .map_err(|err| MockError::NotFound(format!("No profile for {ssid}: {err}")))?;
+        if !profile.contains("Passphrase=") {
+            return Err(MockError::InvalidArguments("No passphrase".to_owned()));
+        }
+
+        state.ap_started = Some(ssid);
+
+        Ok(())
+    }
+
+    fn stop(&self) {
+        lock(&self.0).ap_started = None;
+    }
+
+    #[zbus(property)]
+    fn started(&self) -> bool {
+        lock(&self.0).ap_started.is_some()
+    }
+
+    #[zbus(property)]
+    fn name(&self) -> String {
+        lock(&self.0).ap_started.clone().unwrap_or_default()
+    }
+}
+
+fn hex(name: &str) -> String {
+    name.bytes().fold(String::new(), |mut hex, byte| {
+        write!(hex, "{byte:02x}").ok();
+        hex
+    })
+}
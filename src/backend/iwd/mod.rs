@@ -0,0 +1,1282 @@
+//! iwd backend over D-Bus, for systems without Network Manager.
+//!
+//! iwd hosts WPA2 access points only. The captive portal is started from a profile in the iwd
+//! state directory, which also sets the gateway address. iwd only assigns it, and serves DHCP,
+//! with `EnableNetworkConfiguration` set in its main configuration.
+
+// The zbus macros generate inherent methods named after the trait methods
+#![allow(clippy::same_name_method)]
+
+#[cfg(test)]
+mod mock;
+#[cfg(test)]
+#[path = "../../../tests/support/private_bus.rs"]
+mod private_bus;
+
+use alloc::sync::Arc;
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+
+use zbus::fdo::ObjectManagerProxy;
+use zbus::proxy::CacheProperties;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath};
+use zbus::{interface, proxy, Connection, DBusError};
+
+use nl80211::Ssid;
+
+use crate::backend::{
+    AccessPoint, ActivationState, Backend, ConnectionType, Connectivity, FailureReason, Profile,
+    ProfileMode, ProfileSettings, ProfileUpdate,
+};
+use crate::quality::QualityModel;
+
+const IWD_SERVICE: &str = "net.connman.iwd";
+const DEVICE_INTERFACE: &str = "net.connman.iwd.Device";
+const KNOWN_NETWORK_INTERFACE: &str = "net.connman.iwd.KnownNetwork";
+const AGENT_PATH: &str = "/io/balena/WiFiConnect/Agent";
+
+const AP_PROFILE_DIR: &str = "/var/lib/iwd/ap";
+const AP_PROFILE_EXTENSION: &str = "ap";
+
+const MODE_STATION: &str = "station";
+const MODE_AP: &str = "ap";
+const STATE_CONNECTED: &str = "connected";
+const ERROR_BUSY: &str = "net.connman.iwd.Busy";
+const ERROR_FAILED: &str = "net.connman.iwd.Failed";
+const ERROR_ABORTED: &str = "net.connman.iwd.Aborted";
+const ERROR_NO_AGENT: &str = "net.connman.iwd.NoAgent";
+const ERROR_NOT_FOUND: &str = "net.connman.iwd.NotFound";
+
+const WIFI_SCAN_TIMEOUT_SECONDS: usize = 45;
+
+#[proxy(
+    interface = "net.connman.iwd.Device",
+    default_service = "net.connman.iwd"
+)]
+trait Device {
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn mode(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn set_mode(&self, mode: &str) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.Station",
+    default_service = "net.connman.iwd"
+)]
+trait Station {
+    fn scan(&self) -> zbus::Result<()>;
+
+    fn disconnect(&self) -> zbus::Result<()>;
+
+    /// Networks in range with their signal strength in 100 * dBm, strongest first
+    fn get_ordered_networks(&self) -> zbus::Result<Vec<(OwnedObjectPath, i16)>>;
+
+    fn connect_hidden_network(&self, name: &str) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn scanning(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn state(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn connected_network(&self) -> zbus::Result<OwnedObjectPath>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.Network",
+    default_service = "net.connman.iwd"
+)]
+trait Network {
+    /// Returns once connected, asking the registered agent for the passphrase if needed
+    fn connect(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.KnownNetwork",
+    default_service = "net.connman.iwd"
+)]
+trait KnownNetwork {
+    fn forget(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
+    #[zbus(property)]
+    fn auto_connect(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn set_auto_connect(&self, auto_connect: bool) -> zbus::Result<()>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.AccessPoint",
+    default_service = "net.connman.iwd"
+)]
+trait IwdAccessPoint {
+    /// Starts the access point described by the profile named after the SSID
+    fn start_profile(&self, ssid: &str) -> zbus::Result<()>;
+
+    fn stop(&self) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn started(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+}
+
+#[proxy(
+    interface = "net.connman.iwd.AgentManager",
+    default_service = "net.connman.iwd",
+    default_path = "/net/connman/iwd"
+)]
+trait AgentManager {
+    fn register_agent(&self, path: &ObjectPath<'_>) -> zbus::Result<()>;
+}
+
+#[derive(Debug, DBusError)]
+#[zbus(prefix = "net.connman.iwd.Agent.Error")]
+enum AgentError {
+    #[zbus(error)]
+    ZBus(zbus::Error),
+    Canceled(String),
+}
+
+/// Hands iwd the passphrase of the network being connected to.
+struct Agent {
+    passphrase: Arc<Mutex<Option<String>>>,
+}
+
+// D-Bus methods take `self` and owned arguments whether they need them or not
+#[allow(
+    clippy::unused_self,
+    clippy::missing_const_for_fn,
+    clippy::needless_pass_by_value
+)]
+#[interface(name = "net.connman.iwd.Agent")]
+impl Agent {
+    fn release(&self) {}
+
+    fn request_passphrase(&self, network: ObjectPath<'_>) -> Result<String, AgentError> {
+        self.passphrase
+            .lock()
+            .ok()
+            .and_then(|passphrase| passphrase.clone())
+            .ok_or_else(|| AgentError::Canceled(format!("No passphrase for {}", network.as_str())))
+    }
+
+    fn cancel(&self, reason: &str) {
+        println!("Passphrase request canceled: {reason}");
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IwdDevice {
+    path: OwnedObjectPath,
+    interface: String,
+}
+
+#[derive(Debug, Clone)]
+pub enum IwdConnection {
+    AccessPoint {
+        device: OwnedObjectPath,
+        ssid: Ssid,
+    },
+    Station {
+        device: OwnedObjectPath,
+        ssid: String,
+        /// Why iwd rejected the connection attempt, if it did for a known reason
+        failure: Option<FailureReason>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct IwdBackend {
+    connection: Connection,
+    /// Passphrase the agent answers with while connecting
+    passphrase: Arc<Mutex<Option<String>>>,
+    ap_profile_dir: PathBuf,
+}
+
+impl IwdBackend {
+    pub async fn new() -> Result<Self> {
+        let connection = Connection::system()
+            .await
+            .context("Failed to connect to the system D-Bus")?;
+
+        Self::with_connection(connection, PathBuf::from(AP_PROFILE_DIR)).await
+    }
+
+    /// Backend on an existing bus connection, storing access point profiles in `ap_profile_dir`.
+    pub async fn with_connection(connection: Connection, ap_profile_dir: PathBuf) -> Result<Self> {
+        let passphrase = Arc::default();
+
+        connection
+            .object_server()
+            .at(
+                AGENT_PATH,
+                Agent {
+                    passphrase: Arc::clone(&passphrase),
+                },
+            )
+            .await
+            .context("Failed to serve passphrase agent")?;
+
+        AgentManagerProxy::new(&connection)
+            .await
+            .context("Failed to create iwd agent manager proxy")?
+            .register_agent(&ObjectPath::from_static_str_unchecked(AGENT_PATH))
+            .await
+            .context("Failed to register passphrase agent, iwd daemon may not be running")?;
+
+        Ok(Self {
+            connection,
+            passphrase,
+            ap_profile_dir,
+        })
+    }
+
+    /// Objects implementing the interface, sorted by path.
+    async fn object_paths(&self, interface: &str) -> Result<Vec<OwnedObjectPath>> {
+        let objects = ObjectManagerProxy::builder(&self.connection)
+            .destination(IWD_SERVICE)?
+            .path("/")?
+            .build()
+            .await
+            .context("Failed to create iwd object manager proxy")?
+            .get_managed_objects()
+            .await
+            .context("Failed to get iwd objects")?;
+
+        let mut paths = objects
+            .into_iter()
+            .filter(|(_, interfaces)| interfaces.keys().any(|name| name.as_str() == interface))
+            .map(|(path, _)| path)
+            .collect::<Vec<_>>();
+        paths.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+
+        Ok(paths)
+    }
+
+    async fn device(&self, path: &OwnedObjectPath) -> Result<DeviceProxy<'static>> {
+        Ok(DeviceProxy::builder(&self.connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn station(&self, path: &OwnedObjectPath) -> Result<StationProxy<'static>> {
+        Ok(StationProxy::builder(&self.connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn network(&self, path: &OwnedObjectPath) -> Result<NetworkProxy<'static>> {
+        Ok(NetworkProxy::builder(&self.connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn known_network(&self, path: &str) -> Result<KnownNetworkProxy<'static>> {
+        Ok(KnownNetworkProxy::builder(&self.connection)
+            .path(OwnedObjectPath::try_from(path)?)?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    async fn access_point(&self, path: &OwnedObjectPath) -> Result<IwdAccessPointProxy<'static>> {
+        Ok(IwdAccessPointProxy::builder(&self.connection)
+            .path(path.clone())?
+            .cache_properties(CacheProperties::No)
+            .build()
+            .await?)
+    }
+
+    /// Switches between station and access point mode, which swaps the interfaces iwd exposes
+    /// on the device object.
+    async fn set_mode(&self, device: &OwnedObjectPath, mode: &str) -> Result<()> {
+        let proxy = self.device(device).await?;
+
+        if proxy.mode().await.context("Failed to get device mode")? != mode {
+            proxy
+                .set_mode(mode)
+                .await
+                .with_context(|| format!("Failed to switch device to {mode} mode"))?;
+        }
+
+        Ok(())
+    }
+
+    async fn find_network(
+        &self,
+        device: &OwnedObjectPath,
+        ssid: &str,
+    ) -> Result<Option<OwnedObjectPath>> {
+        let networks = self
+            .station(device)
+            .await?
+            .get_ordered_networks()
+            .await
+            .context("Failed to get networks")?;
+
+        for (path, _) in networks {
+            if self.network(&path).await?.name().await? == ssid {
+                return Ok(Some(path));
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn find_known_network(&self, ssid: &str) -> Result<Option<OwnedObjectPath>> {
+        for path in self.object_paths(KNOWN_NETWORK_INTERFACE).await? {
+            if self.known_network(path.as_str()).await?.name().await? == ssid {
+                return Ok(Some(path));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Connects in station mode. Rejections by iwd, such as a wrong passphrase, leave the
+    /// station disconnected and are reported by `wait_for_state`.
+    async fn connect_station(
+        &self,
+        device: &OwnedObjectPath,
+        ssid: &str,
+        passphrase: Option<&str>,
+    ) -> Result<IwdConnection> {
+        self.set_mode(device, MODE_STATION).await?;
+
+        let network = self.find_network(device, ssid).await?;
+
+        self.set_passphrase(passphrase.map(ToOwned::to_owned));
+
+        let result = match network {
+            Some(path) => self.network(&path).await?.connect().await,
+            None => {
+                self.station(device)
+                    .await?
+                    .connect_hidden_network(ssid)
+                    .await
+            }
+        };
+
+        self.set_passphrase(None);
+
+        let failure = match result {
+            Ok(()) => None,
+            Err(ref err @ zbus::Error::MethodError(ref name, _, _)) => {
+                println!("Failed to connect to {ssid}: {err}");
+                failure_reason(name.as_str(), passphrase.is_some())
+            }
+            Err(err) => return Err(err).context("Failed to connect"),
+        };
+
+        Ok(IwdConnection::Station {
+            device: device.clone(),
+            ssid: ssid.to_owned(),
+            failure,
+        })
+    }
+
+    async fn start_access_point(
+        &self,
+        device: &OwnedObjectPath,
+        ssid: &Ssid,
+        gateway: &str,
+        passphrase: Option<&str>,
+    ) -> Result<IwdConnection> {
+        let Some(passphrase) = passphrase else {
+            bail!("iwd only hosts WPA2 access points, a portal passphrase is required");
+        };
+        check_ap_profile_values(passphrase, gateway)?;
+
+        // iwd takes the SSID to start as a D-Bus string
+        let Ok(name) = core::str::from_utf8(ssid.as_bytes()) else {
+            bail!("iwd only hosts access points with UTF-8 SSIDs");
+        };
+
+        let profile = format!(
+            "[Security]\nPassphrase={passphrase}\n\n[IPv4]\nAddress={gateway}\nNetmask=255.255.255.0\n"
+        );
+        fs::create_dir_all(&self.ap_profile_dir)
+            .context("Failed to create access point profile directory")?;
+        fs::write(self.ap_profile_path(ssid), profile)
+            .context("Failed to write access point profile")?;
+
+        self.set_mode(device, MODE_AP).await?;
+
+        match self.access_point(device).await?.start_profile(name).await {
+            Ok(()) => {}
+            Err(err @ zbus::Error::MethodError(..)) => {
+                println!("Failed to start access point: {err}");
+            }
+            Err(err) => return Err(err).context("Failed to start access point"),
+        }
+
+        Ok(IwdConnection::AccessPoint {
+            device: device.clone(),
+            ssid: ssid.clone(),
+        })
+    }
+
+    fn set_passphrase(&self, passphrase: Option<String>) {
+        if let Ok(mut current) = self.passphrase.lock() {
+            *current = passphrase;
+        }
+    }
+
+    fn ap_profile_path(&self, ssid: &Ssid) -> PathBuf {
+        self.ap_profile_dir
+            .join(format!("{}.{AP_PROFILE_EXTENSION}", ap_profile_name(ssid)))
+    }
+
+    fn ap_profiles(&self) -> Result<Vec<Profile>> {
+        let entries = match fs::read_dir(&self.ap_profile_dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err).context("Failed to list access point profiles"),
+        };
+
+        let mut profiles = Vec::new();
+
+        for entry in entries {
+            let path = entry
+                .context("Failed to list access point profiles")?
+                .path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(AP_PROFILE_EXTENSION) {
+                continue;
+            }
+            let Some(ssid) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(ap_profile_ssid)
+            else {
+                continue;
+            };
+
+            profiles.push(Profile {
+                id: ssid.to_string(),
+                uuid: path.display().to_string(),
+                connection_type: ConnectionType::Wifi,
+                access_point: true,
+                ssid: Some(ssid),
+                autoconnect: false,
+                autoconnect_priority: 0,
+                last_used: None,
+                active: false,
+            });
+        }
+
+        profiles.sort_by(|a, b| a.uuid.cmp(&b.uuid));
+
+        Ok(profiles)
+    }
+
+    /// SSIDs of the networks the devices are connected to and of the access points they host,
+    /// as `(access point, SSID)`.
+    async fn active_networks(&self) -> Result<Vec<(bool, String)>> {
+        let mut active = Vec::new();
+
+        for path in self.object_paths(DEVICE_INTERFACE).await? {
+            if self.device(&path).await?.mode().await? == MODE_AP {
+                let access_point = self.access_point(&path).await?;
+                if access_point.started().await.unwrap_or(false) {
+                    active.push((true, access_point.name().await?));
+                }
+                continue;
+            }
+
+            let station = self.station(&path).await?;
+            if station.state().await.ok().as_deref() == Some(STATE_CONNECTED) {
+                let network = station.connected_network().await?;
+                active.push((false, self.network(&network).await?.name().await?));
+            }
+        }
+
+        Ok(active)
+    }
+
+    /// Access point profiles are identified by their path, known networks by their object path.
+    fn is_ap_profile(&self, uuid: &str) -> bool {
+        Path::new(uuid).starts_with(&self.ap_profile_dir)
+    }
+}
+
+impl Backend for IwdBackend {
+    type Device = IwdDevice;
+    type Connection = IwdConnection;
+
+    async fn find_device(&self, interface: Option<&str>) -> Result<IwdDevice> {
+        for path in self.object_paths(DEVICE_INTERFACE).await? {
+            let name = self
+                .device(&path)
+                .await?
+                .name()
+                .await
+                .context("Failed to get device name")?;
+
+            if interface.is_none_or(|interface| interface == name) {
+                return Ok(IwdDevice {
+                    path,
+                    interface: name,
+                });
+            }
+        }
+
+        match interface {
+            Some(interface) => bail!("Failed to find interface '{interface}'"),
+            None => bail!("Failed to find a WiFi device"),
+        }
+    }
+
+    fn device_interface(&self, device: &IwdDevice) -> String {
+        device.interface.clone()
+    }
+
+    async fn scan(&self, device: &IwdDevice) -> Result<()> {
+        self.set_mode(&device.path, MODE_STATION).await?;
+
+        let station = self.station(&device.path).await?;
+
+        match station.scan().await {
+            Ok(()) => {}
+            // A periodic scan is running already, its results are as good
+            Err(zbus::Error::MethodError(ref name, _, _)) if name.as_str() == ERROR_BUSY => {}
+            Err(err) => return Err(err).context("Failed to request WiFi scan"),
+        }
+
+        for _ in 0..WIFI_SCAN_TIMEOUT_SECONDS {
+            if !station
+                .scanning()
+                .await
+                .context("Failed to get scan state")?
+            {
+                break;
+            }
+
+            glib::timeout_future_seconds(1).await;
+        }
+
+        Ok(())
+    }
+
+    async fn access_points(&self, device: &IwdDevice) -> Result<Vec<AccessPoint>> {
+        let networks = self
+            .station(&device.path)
+            .await?
+            .get_ordered_networks()
+            .await
+            .context("Failed to get networks")?;
+
+        let mut access_points = Vec::new();
+
+        for (path, signal) in networks {
+            let name = self.network(&path).await?.name().await?;
+            let signal_dbm = i32::from(signal).saturating_div(100);
+
+            access_points.push(AccessPoint {
+                ssid: Some(Ssid::from(name.as_str())),
+                strength: QualityModel::NetworkManager.quality(signal_dbm),
+            });
+        }
+
+        Ok(access_points)
+    }
+
+    /// iwd has no connection priorities, and reports the last connection time as text, which
+    /// is left out.
+    async fn profiles(&self) -> Result<Vec<Profile>> {
+        let active = self.active_networks().await?;
+        let mut profiles = Vec::new();
+
+        for path in self.object_paths(KNOWN_NETWORK_INTERFACE).await? {
+            let known_network = self.known_network(path.as_str()).await?;
+            let name = known_network.name().await?;
+
+            profiles.push(Profile {
+                id: name.clone(),
+                uuid: path.to_string(),
+                connection_type: ConnectionType::Wifi,
+                access_point: false,
+                ssid: Some(Ssid::from(name.as_str())),
+                autoconnect: known_network.auto_connect().await?,
+                autoconnect_priority: 0,
+                last_used: None,
+                active: active.contains(&(false, name)),
+            });
+        }
+
+        for mut profile in self.ap_profiles()? {
+            profile.active = active.contains(&(true, profile.id.clone()));
+            profiles.push(profile);
+        }
+
+        Ok(profiles)
+    }
+
+    async fn active_profile(&self, device: &IwdDevice) -> Result<Option<String>> {
+        let station = self.station(&device.path).await?;
+
+        // The station interface is gone in access point mode
+        if station.state().await.ok().as_deref() != Some(STATE_CONNECTED) {
+            return Ok(None);
+        }
+
+        let network = station
+            .connected_network()
+            .await
+            .context("Failed to get connected network")?;
+        let ssid = self.network(&network).await?.name().await?;
+
+        Ok(self
+            .find_known_network(&ssid)
+            .await?
+            .map(|path| path.to_string()))
+    }
+
+    async fn delete_profile(&self, uuid: &str) -> Result<()> {
+        if self.is_ap_profile(uuid) {
+            fs::remove_file(uuid).context("Failed to delete access point profile")
+        } else {
+            self.known_network(uuid)
+                .await?
+                .forget()
+                .await
+                .context("Failed to forget known network")
+        }
+    }
+
+    /// Only automatic connecting to known networks can be changed. iwd keeps passphrases in
+    /// its state directory without a D-Bus interface for changing them.
+    async fn update_profile(&self, uuid: &str, update: &ProfileUpdate) -> Result<()> {
+        if self.is_ap_profile(uuid) {
+            bail!("Access point profiles cannot be changed with iwd");
+        }
+        if update.passphrase.is_some() {
+            bail!("Passphrases cannot be changed with iwd, forget the network and connect again");
+        }
+        if update.autoconnect_priority.is_some() {
+            bail!("Autoconnect priorities are not supported with iwd");
+        }
+
+        if let Some(autoconnect) = update.autoconnect {
+            self.known_network(uuid)
+                .await?
+                .set_auto_connect(autoconnect)
+                .await
+                .context("Failed to set automatic connecting")?;
+        }
+
+        Ok(())
+    }
+
+    async fn activate_profile(&self, uuid: &str, device: &IwdDevice) -> Result<IwdConnection> {
+        let ssid = self
+            .known_network(uuid)
+            .await?
+            .name()
+            .await
+            .context("Failed to get known network name")?;
+
+        self.connect_station(&device.path, &ssid, None).await
+    }
+
+    async fn add_and_activate(
+        &self,
+        settings: &ProfileSettings,
+        device: &IwdDevice,
+    ) -> Result<IwdConnection> {
+        if settings.cloned_mac.is_some() {
+            bail!("Cloned MAC addresses are not supported with iwd");
+        }
+
+        let passphrase = settings.passphrase.as_deref();
+
+        match settings.mode {
+            // iwd names networks with their SSID decoded as UTF-8
+            ProfileMode::Client => {
+                self.connect_station(&device.path, &settings.ssid.to_string(), passphrase)
+                    .await
+            }
+            ProfileMode::AccessPoint { ref gateway, .. } => {
+                self.start_access_point(&device.path, &settings.ssid, gateway, passphrase)
+                    .await
+            }
+        }
+    }
+
+    async fn deactivate(&self, connection: &IwdConnection) -> Result<()> {
+        match *connection {
+            IwdConnection::AccessPoint { ref device, .. } => {
+                self.access_point(device)
+                    .await?
+                    .stop()
+                    .await
+                    .context("Failed to stop access point")?;
+                self.set_mode(device, MODE_STATION).await
+            }
+            IwdConnection::Station { ref device, .. } => self
+                .station(device)
+                .await?
+                .disconnect()
+                .await
+                .context("Failed to disconnect"),
+        }
+    }
+
+    /// iwd completes connecting and starting access points before replying, so the state is
+    /// settled already.
+    async fn wait_for_state(&self, connection: &IwdConnection) -> Result<ActivationState> {
+        let activated = match *connection {
+            // The access point interface is gone once the device left access point mode
+            IwdConnection::AccessPoint { ref device, .. } => self
+                .access_point(device)
+                .await?
+                .started()
+                .await
+                .unwrap_or(false),
+            IwdConnection::Station {
+                ref device,
+                ref ssid,
+                ..
+            } => {
+                let station = self.station(device).await?;
+                if station
+                    .state()
+                    .await
+                    .context("Failed to get station state")?
+                    == STATE_CONNECTED
+                {
+                    let network = station.connected_network().await?;
+                    self.network(&network).await?.name().await? == *ssid
+                } else {
+                    false
+                }
+            }
+        };
+
+        if activated {
+            return Ok(ActivationState::Activated);
+        }
+
+        match *connection {
+            IwdConnection::AccessPoint { .. } => Ok(ActivationState::Deactivated(None)),
+            IwdConnection::Station { failure, .. } => Ok(ActivationState::Deactivated(failure)),
+        }
+    }
+
+    async fn delete_connection_profile(&self, connection: &IwdConnection) -> Result<()> {
+        match *connection {
+            IwdConnection::AccessPoint { ref ssid, .. } => {
+                match fs::remove_file(self.ap_profile_path(ssid)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                        Err(err).context("Failed to delete access point profile")
+                    }
+                    _ => Ok(()),
+                }
+            }
+            // iwd only remembers networks it connected to successfully
+            IwdConnection::Station { ref ssid, .. } => {
+                if let Some(path) = self.find_known_network(ssid).await? {
+                    self.known_network(path.as_str())
+                        .await?
+                        .forget()
+                        .await
+                        .context("Failed to forget known network")?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// iwd does not check internet access, so a connected station is reported with unknown
+    /// connectivity.
+    async fn check_connectivity(&self) -> Result<Connectivity> {
+        for path in self.object_paths(DEVICE_INTERFACE).await? {
+            let connected = match self.station(&path).await?.state().await {
+                Ok(state) => state == STATE_CONNECTED,
+                Err(_) => false,
+            };
+            if connected {
+                return Ok(Connectivity::Unknown);
+            }
+        }
+
+        Ok(Connectivity::None)
+    }
+}
+
+/// iwd names profiles after the SSID if it consists of alphanumerics, `-`, `_` and spaces,
+/// and after `=` followed by the hex encoded SSID otherwise, which also keeps SSIDs such as
+/// `..` or ones containing `/` from naming files outside the profile directory.
+fn ap_profile_name(ssid: &Ssid) -> String {
+    let bytes = ssid.as_bytes();
+    let safe = !bytes.is_empty()
+        && bytes
+            .iter()
+            .all(|&b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b' '));
+
+    if safe {
+        ssid.to_string()
+    } else {
+        format!("={}", ssid.to_hex())
+    }
+}
+
+/// Values are written to the INI profile verbatim, so line breaks would add keys and sections
+/// of their own. WPA2 passphrases are 8 to 63 printable ASCII characters.
+fn check_ap_profile_values(passphrase: &str, gateway: &str) -> Result<()> {
+    if !(8..=63).contains(&passphrase.len())
+        || !passphrase
+            .bytes()
+            .all(|b| b == b' ' || b.is_ascii_graphic())
+    {
+        bail!("The portal passphrase must be 8 to 63 printable ASCII characters");
+    }
+
+    if gateway.parse::<Ipv4Addr>().is_err() {
+        bail!("The portal gateway must be an IPv4 address: {gateway:?}");
+    }
+
+    Ok(())
+}
+
+/// SSID of the profile named by [`ap_profile_name`].
+fn ap_profile_ssid(name: &str) -> Option<Ssid> {
+    match name.strip_prefix('=') {
+        Some(hex) => Ssid::from_hex(hex).ok(),
+        None => Some(Ssid::from(name)),
+    }
+}
+
+/// Category of the error iwd rejected a connection attempt with. iwd reports handshake failures
+/// as generic failures, which are most likely a wrong passphrase if one was given.
+fn failure_reason(error: &str, passphrase: bool) -> Option<FailureReason> {
+    match error {
+        ERROR_NOT_FOUND => Some(FailureReason::SsidNotFound),
+        // The agent cancels passphrase requests if no passphrase was given
+        ERROR_ABORTED | ERROR_NO_AGENT => Some(FailureReason::NoSecrets),
+        ERROR_FAILED if passphrase => Some(FailureReason::WrongPassword),
+        ERROR_FAILED => Some(FailureReason::SupplicantFailure),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::indexing_slicing,
+    clippy::assertions_on_result_states
+)]
+mod tests {
+    use core::future::Future;
+    use std::process;
+
+    use glib::MainContext;
+
+    use super::mock::{connect, MockIwd, MockNetwork};
+    use super::private_bus::PrivateBus;
+    use super::*;
+
+    const PORTAL_GATEWAY: &str = "192.168.42.1";
+
+    fn run<F: Future>(future: F) -> F::Output {
+        MainContext::new().block_on(future)
+    }
+
+    fn profile_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("wifi-connect-iwd-{}-{name}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    /// Mock iwd with the networks in range and a backend connected to it. Tests using it need
+    /// `dbus-daemon` and are ignored by default.
+    fn setup(name: &str, networks: Vec<MockNetwork>) -> (PrivateBus, MockIwd, IwdBackend) {
+        let bus = PrivateBus::start();
+        let dir = profile_dir(name);
+
+        let (iwd, backend) = run(async {
+            let iwd = MockIwd::serve(&bus, networks, dir.clone()).await;
+            let backend = IwdBackend::with_connection(connect(&bus).await, dir)
+                .await
+                .unwrap();
+            (iwd, backend)
+        });
+
+        (bus, iwd, backend)
+    }
+
+    fn networks() -> Vec<MockNetwork> {
+        vec![
+            MockNetwork::new("Cafe", -70, None),
+            MockNetwork::new("Home", -50, Some("secret123")),
+            MockNetwork::new("Office", -60, Some("office123")).known(),
+            MockNetwork::new("Hidden", -40, Some("hidden123")).hidden(),
+        ]
+    }
+
+    fn client_settings(ssid: &str, passphrase: Option<&str>) -> ProfileSettings {
+        ProfileSettings {
+            ssid: Ssid::from(ssid),
+            passphrase: passphrase.map(ToOwned::to_owned),
+            cloned_mac: None,
+            mode: ProfileMode::Client,
+        }
+    }
+
+    fn portal_settings(passphrase: Option<&str>) -> ProfileSettings {
+        ProfileSettings {
+            ssid: Ssid::from("WiFiConnect"),
+            passphrase: passphrase.map(ToOwned::to_owned),
+            cloned_mac: None,
+            mode: ProfileMode::AccessPoint {
+                interface: "wlan0".to_owned(),
+                gateway: PORTAL_GATEWAY.to_owned(),
+            },
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn finds_device() {
+        let (_bus, _iwd, backend) = setup("finds_device", networks());
+
+        let device = run(backend.find_device(None)).unwrap();
+        assert_eq!(backend.device_interface(&device), "wlan0");
+
+        assert!(run(backend.find_device(Some("wlan0"))).is_ok());
+        assert!(run(backend.find_device(Some("wlan1"))).is_err());
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn scans_networks() {
+        let (_bus, iwd, backend) = setup("scans_networks", networks());
+
+        let access_points = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            backend.scan(&device).await.unwrap();
+            backend.access_points(&device).await.unwrap()
+        });
+
+        assert_eq!(iwd.scans(), 1);
+        assert_eq!(
+            access_points,
+            [
+                AccessPoint {
+                    ssid: Some(Ssid::from("Home")),
+                    strength: QualityModel::NetworkManager.quality(-50),
+                },
+                AccessPoint {
+                    ssid: Some(Ssid::from("Office")),
+                    strength: QualityModel::NetworkManager.quality(-60),
+                },
+                AccessPoint {
+                    ssid: Some(Ssid::from("Cafe")),
+                    strength: QualityModel::NetworkManager.quality(-70),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn connects_with_passphrase() {
+        let (_bus, iwd, backend) = setup("connects_with_passphrase", networks());
+
+        let state = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            let settings = client_settings("Home", Some("secret123"));
+            let connection = backend.add_and_activate(&settings, &device).await.unwrap();
+            backend.wait_for_state(&connection).await.unwrap()
+        });
+
+        assert_eq!(state, ActivationState::Activated);
+        assert_eq!(iwd.connected().as_deref(), Some("Home"));
+        assert_eq!(iwd.known(), ["Home", "Office"]);
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn connects_hidden_network() {
+        let (_bus, iwd, backend) = setup("connects_hidden_network", networks());
+
+        let state = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            let settings = client_settings("Hidden", Some("hidden123"));
+            let connection = backend.add_and_activate(&settings, &device).await.unwrap();
+            backend.wait_for_state(&connection).await.unwrap()
+        });
+
+        assert_eq!(state, ActivationState::Activated);
+        assert_eq!(iwd.connected().as_deref(), Some("Hidden"));
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn fails_wrong_passphrase() {
+        let (_bus, iwd, backend) = setup("fails_wrong_passphrase", networks());
+
+        let state = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            let settings = client_settings("Home", Some("wrong"));
+            let connection = backend.add_and_activate(&settings, &device).await.unwrap();
+            let state = backend.wait_for_state(&connection).await.unwrap();
+            backend
+                .delete_connection_profile(&connection)
+                .await
+                .unwrap();
+            state
+        });
+
+        assert_eq!(
+            state,
+            ActivationState::Deactivated(Some(FailureReason::WrongPassword))
+        );
+        assert_eq!(iwd.connected(), None);
+        assert_eq!(iwd.known(), ["Office"]);
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn reports_failure_reasons() {
+        let (_bus, iwd, backend) = setup("reports_failure_reasons", networks());
+
+        let states = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            let mut states = Vec::new();
+            for settings in [
+                client_settings("Home", None),
+                client_settings("Airport", Some("airport123")),
+            ] {
+                let connection = backend.add_and_activate(&settings, &device).await.unwrap();
+                states.push(backend.wait_for_state(&connection).await.unwrap());
+            }
+            states
+        });
+
+        assert_eq!(
+            states,
+            [
+                ActivationState::Deactivated(Some(FailureReason::NoSecrets)),
+                ActivationState::Deactivated(Some(FailureReason::SsidNotFound)),
+            ]
+        );
+        assert_eq!(iwd.connected(), None);
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn activates_and_forgets_known_network() {
+        let (_bus, iwd, backend) = setup("activates_known_network", networks());
+
+        let (state, profiles) = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            let profiles = backend.profiles().await.unwrap();
+            let connection = backend
+                .activate_profile(&profiles[0].uuid, &device)
+                .await
+                .unwrap();
+            let state = backend.wait_for_state(&connection).await.unwrap();
+            assert_eq!(
+                backend.active_profile(&device).await.unwrap(),
+                Some(profiles[0].uuid.clone())
+            );
+            backend.delete_profile(&profiles[0].uuid).await.unwrap();
+            (state, profiles)
+        });
+
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].ssid, Some(Ssid::from("Office")));
+        assert!(!profiles[0].access_point);
+        assert_eq!(state, ActivationState::Activated);
+        assert!(iwd.known().is_empty());
+        assert_eq!(iwd.connected(), None);
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn lists_and_updates_known_networks() {
+        let (_bus, iwd, backend) = setup("updates_known_networks", networks());
+
+        let profiles = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            let uuid = backend.profiles().await.unwrap()[0].uuid.clone();
+
+            let connection = backend.activate_profile(&uuid, &device).await.unwrap();
+            backend.wait_for_state(&connection).await.unwrap();
+
+            let update = ProfileUpdate {
+                autoconnect: Some(false),
+                ..ProfileUpdate::default()
+            };
+            backend.update_profile(&uuid, &update).await.unwrap();
+
+            let update = ProfileUpdate {
+                passphrase: Some("changed".to_owned()),
+                ..ProfileUpdate::default()
+            };
+            assert!(backend.update_profile(&uuid, &update).await.is_err());
+
+            backend.profiles().await.unwrap()
+        });
+
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].connection_type, ConnectionType::Wifi);
+        assert!(profiles[0].active);
+        assert!(!profiles[0].autoconnect);
+        assert!(iwd.auto_connect().is_empty());
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn starts_and_stops_portal() {
+        let (_bus, iwd, backend) = setup("starts_and_stops_portal", networks());
+
+        let connection = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            backend
+                .add_and_activate(&portal_settings(Some("portal123")), &device)
+                .await
+                .unwrap()
+        });
+
+        assert_eq!(
+            run(backend.wait_for_state(&connection)).unwrap(),
+            ActivationState::Activated
+        );
+        assert_eq!(iwd.mode(), "ap");
+        assert_eq!(iwd.ap_started().as_deref(), Some("WiFiConnect"));
+
+        let profile =
+            fs::read_to_string(backend.ap_profile_path(&Ssid::from("WiFiConnect"))).unwrap();
+        assert!(profile.contains("Passphrase=portal123\n"));
+        assert!(profile.contains(&format!("Address={PORTAL_GATEWAY}\n")));
+
+        let profiles = run(backend.profiles()).unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert!(profiles[1].access_point);
+        assert!(profiles[1].active);
+        assert_eq!(profiles[1].ssid, Some(Ssid::from("WiFiConnect")));
+
+        run(async {
+            backend.deactivate(&connection).await.unwrap();
+            backend
+                .delete_connection_profile(&connection)
+                .await
+                .unwrap();
+        });
+
+        assert_eq!(iwd.mode(), "station");
+        assert_eq!(iwd.ap_started(), None);
+        assert!(!backend.ap_profile_path(&Ssid::from("WiFiConnect")).exists());
+    }
+
+    #[test]
+    fn names_ap_profiles_like_iwd() {
+        let name = |ssid: &str| ap_profile_name(&Ssid::from(ssid));
+
+        assert_eq!(name("WiFiConnect"), "WiFiConnect");
+        assert_eq!(name("My Net-2_4"), "My Net-2_4");
+        assert_eq!(name("My.Net"), "=4d792e4e6574");
+        assert_eq!(name("../etc/x"), "=2e2e2f6574632f78");
+        assert_eq!(name("Café"), "=436166c3a9");
+
+        // Latin-1 encoded, which is not valid UTF-8
+        let latin1 = Ssid::from(&b"Caf\xe9"[..]);
+        assert_eq!(ap_profile_name(&latin1), "=436166e9");
+
+        for ssid in [
+            Ssid::from("WiFiConnect"),
+            Ssid::from("My.Net"),
+            Ssid::from("../etc/x"),
+            Ssid::from("Café"),
+            latin1,
+        ] {
+            assert_eq!(ap_profile_ssid(&ap_profile_name(&ssid)), Some(ssid));
+        }
+        assert_eq!(ap_profile_ssid("=4"), None);
+    }
+
+    #[test]
+    fn rejects_ap_profile_injection() {
+        assert!(check_ap_profile_values("portal123", PORTAL_GATEWAY).is_ok());
+        assert!(check_ap_profile_values("with spaces ~!", PORTAL_GATEWAY).is_ok());
+        assert!(check_ap_profile_values(&"x".repeat(63), PORTAL_GATEWAY).is_ok());
+
+        let too_long = "x".repeat(64);
+        for passphrase in [
+            "short",
+            too_long.as_str(),
+            "portal123\n[General]\nHidden=true",
+            "portal\r123",
+            "portal\t123",
+            "pörtal123",
+        ] {
+            assert!(
+                check_ap_profile_values(passphrase, PORTAL_GATEWAY).is_err(),
+                "{passphrase:?}"
+            );
+        }
+
+        for gateway in ["", "192.168.42.1\n[General]", "192.168.42", "example.com"] {
+            assert!(
+                check_ap_profile_values("portal123", gateway).is_err(),
+                "{gateway:?}"
+            );
+        }
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn keeps_ap_profiles_in_profile_directory() {
+        let (_bus, _iwd, backend) = setup("keeps_ap_profiles_in_profile_directory", networks());
+
+        let path = backend.ap_profile_path(&Ssid::from("../My.Net"));
+
+        assert_eq!(path.parent(), Some(backend.ap_profile_dir.as_path()));
+        assert_eq!(
+            path.file_name().and_then(|name| name.to_str()),
+            Some("=2e2e2f4d792e4e6574.ap")
+        );
+    }
+
+    #[test]
+    #[ignore = "needs dbus-daemon"]
+    fn rejects_open_portal() {
+        let (_bus, iwd, backend) = setup("rejects_open_portal", networks());
+
+        let result = run(async {
+            let device = backend.find_device(None).await.unwrap();
+            backend
+                .add_and_activate(&portal_settings(None), &device)
+                .await
+        });
+
+        assert!(result.is_err());
+        assert_eq!(iwd.mode(), "station");
+    }
+}
@@ -0,0 +1,238 @@
+//! Connection manager operations of the network thread. The futures are driven by the glib main
+//! loop of that thread and need not be `Send`.
+
+#[cfg(test)]
+pub mod fake;
+pub mod iwd;
+pub mod native;
+pub mod nm;
+pub mod simulated;
+
+use core::fmt;
+
+use anyhow::{bail, Context, Result};
+
+use serde::{Deserialize, Serialize};
+
+use zbus::fdo::DBusProxy;
+use zbus::names::BusName;
+use zbus::Connection;
+
+use nl80211::Ssid;
+
+use crate::opts::{ClonedMac, ConnectionManager};
+
+const NETWORK_MANAGER_BUS_NAME: &str = "org.freedesktop.NetworkManager";
+const IWD_BUS_NAME: &str = "net.connman.iwd";
+
+/// Access point found by the last scan.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessPoint {
+    /// Absent for hidden networks
+    pub ssid: Option<Ssid>,
+    /// Signal strength percentage as reported by the connection manager
+    pub strength: u8,
+}
+
+/// Saved connection profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub id: String,
+    pub uuid: String,
+    pub connection_type: ConnectionType,
+    /// Whether the wireless profile hosts an access point rather than joining one
+    pub access_point: bool,
+    pub ssid: Option<Ssid>,
+    pub autoconnect: bool,
+    /// Profiles with higher priority are preferred when autoconnecting
+    pub autoconnect_priority: i32,
+    /// Seconds since the Unix epoch the profile was last activated at, if known
+    pub last_used: Option<u64>,
+    pub active: bool,
+}
+
+impl Profile {
+    pub const fn is_wifi(&self) -> bool {
+        matches!(self.connection_type, ConnectionType::Wifi)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConnectionType {
+    Wifi,
+    Ethernet,
+    Loopback,
+    Other,
+}
+
+/// Changes to a saved profile. Unset fields are left as they are.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProfileUpdate {
+    pub passphrase: Option<String>,
+    pub autoconnect: Option<bool>,
+    pub autoconnect_priority: Option<i32>,
+}
+
+/// Settings of a wireless profile to create.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileSettings {
+    pub ssid: Ssid,
+    pub passphrase: Option<String>,
+    pub cloned_mac: Option<ClonedMac>,
+    pub mode: ProfileMode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProfileMode {
+    Client,
+    /// Access point bound to the interface, serving the gateway address
+    AccessPoint {
+        interface: String,
+        gateway: String,
+    },
+}
+
+/// State an activation or deactivation settles in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActivationState {
+    Activated,
+    /// With the cause of the failure, if the activation failed for a known reason
+    Deactivated(Option<FailureReason>),
+}
+
+/// Why an activation failed, in categories meaningful to users. Attached to the errors of
+/// failed activations, where the web API reports it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureReason {
+    WrongPassword,
+    /// The network is protected, but no passphrase was given
+    NoSecrets,
+    SsidNotFound,
+    DhcpTimeout,
+    SupplicantFailure,
+    CarrierLost,
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::WrongPassword => write!(f, "Wrong password"),
+            Self::NoSecrets => write!(f, "Password required"),
+            Self::SsidNotFound => write!(f, "Network not found"),
+            Self::DhcpTimeout => write!(f, "No IP address received from the network"),
+            Self::SupplicantFailure => write!(f, "Authentication with the network failed"),
+            Self::CarrierLost => write!(f, "Connection to the network lost"),
+        }
+    }
+}
+
+impl std::error::Error for FailureReason {}
+
+/// Internet access through the active connection, as checked by the connection manager.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    None,
+    /// Behind a captive portal of the network
+    Portal,
+    /// Connected to the network, but without internet access
+    Limited,
+    Full,
+    /// Not checked, as with iwd or with checks disabled in Network Manager
+    Unknown,
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::None => write!(f, "none"),
+            Self::Portal => write!(f, "portal"),
+            Self::Limited => write!(f, "limited"),
+            Self::Full => write!(f, "full"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+pub trait Backend: Clone + 'static {
+    type Device: Clone + 'static;
+    type Connection: Clone + 'static;
+
+    /// Wireless device by interface name, or the first managed one.
+    async fn find_device(&self, interface: Option<&str>) -> Result<Self::Device>;
+
+    fn device_interface(&self, device: &Self::Device) -> String;
+
+    /// Whether the backend joins networks as a client, besides hosting the portal.
+    fn can_join(&self) -> bool {
+        true
+    }
+
+    /// Scans and waits for the results to be available.
+    async fn scan(&self, device: &Self::Device) -> Result<()>;
+
+    async fn access_points(&self, device: &Self::Device) -> Result<Vec<AccessPoint>>;
+
+    async fn profiles(&self) -> Result<Vec<Profile>>;
+
+    /// UUID of the profile the device is connected to a network with, if any.
+    async fn active_profile(&self, device: &Self::Device) -> Result<Option<String>>;
+
+    async fn delete_profile(&self, uuid: &str) -> Result<()>;
+
+    async fn update_profile(&self, uuid: &str, update: &ProfileUpdate) -> Result<()>;
+
+    async fn activate_profile(&self, uuid: &str, device: &Self::Device)
+        -> Result<Self::Connection>;
+
+    async fn add_and_activate(
+        &self,
+        settings: &ProfileSettings,
+        device: &Self::Device,
+    ) -> Result<Self::Connection>;
+
+    async fn deactivate(&self, connection: &Self::Connection) -> Result<()>;
+
+    /// Follows the state changes of the connection until it is activated or deactivated.
+    async fn wait_for_state(&self, connection: &Self::Connection) -> Result<ActivationState>;
+
+    /// Deletes the profile the connection was activated from.
+    async fn delete_connection_profile(&self, connection: &Self::Connection) -> Result<()>;
+
+    async fn check_connectivity(&self) -> Result<Connectivity>;
+}
+
+/// Picks the running connection manager on the system bus if not set explicitly, preferring
+/// Network Manager as it may use iwd itself for wireless devices.
+pub async fn resolve_connection_manager(
+    connection_manager: ConnectionManager,
+) -> Result<ConnectionManager> {
+    if connection_manager != ConnectionManager::Auto {
+        return Ok(connection_manager);
+    }
+
+    let connection = Connection::system()
+        .await
+        .context("Failed to connect to the system D-Bus")?;
+    let dbus = DBusProxy::new(&connection)
+        .await
+        .context("Failed to create D-Bus proxy")?;
+
+    for (bus_name, resolved) in [
+        (NETWORK_MANAGER_BUS_NAME, ConnectionManager::NetworkManager),
+        (IWD_BUS_NAME, ConnectionManager::Iwd),
+    ] {
+        let name = BusName::try_from(bus_name).context("Invalid bus name")?;
+        if dbus
+            .name_has_owner(name)
+            .await
+            .context("Failed to query D-Bus name owner")?
+        {
+            return Ok(resolved);
+        }
+    }
+
+    bail!("Neither NetworkManager nor iwd is running")
+}
@@ -0,0 +1,231 @@
+//! Native backend for minimal systems without a connection manager. The captive portal is
+//! hosted over nl80211 directly, see [`nl80211::ap`].
+//!
+//! The portal is open, or protected with WPA2 when it has a passphrase.
+//! Joining networks needs a supplicant and a DHCP client, so saved networks and connecting are
+//! left to Network Manager or iwd. As with the other backends, no DHCP server is started for
+//! the portal.
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Context, Result};
+
+use nl80211::ap::{AccessPointConfig, DEFAULT_CHANNEL};
+use nl80211::interface::{find_interface, get_interfaces};
+use nl80211::scan::scan;
+use nl80211::{Nl80211, Ssid};
+
+use crate::backend::{
+    AccessPoint, ActivationState, Backend, ConnectionType, Connectivity, Profile, ProfileMode,
+    ProfileSettings, ProfileUpdate,
+};
+use crate::quality::QualityModel;
+
+const PORTAL_UUID: &str = "native-portal";
+const PORTAL_PREFIX_LEN: u8 = 24;
+
+/// Activation of the portal. There is a single portal at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NativeConnection;
+
+#[derive(Debug)]
+struct Portal {
+    ssid: Ssid,
+    access_point: nl80211::ap::AccessPoint,
+}
+
+#[derive(Debug, Clone)]
+pub struct NativeBackend {
+    nl80211: Nl80211,
+    access_points: Rc<RefCell<Vec<AccessPoint>>>,
+    portal: Rc<RefCell<Option<Portal>>>,
+}
+
+impl NativeBackend {
+    /// Must be called from within a tokio runtime, which then drives the nl80211 client.
+    pub fn new() -> Result<Self> {
+        let nl80211 = Nl80211::new().context("Failed to connect to nl80211")?;
+
+        Ok(Self {
+            nl80211,
+            access_points: Rc::default(),
+            portal: Rc::default(),
+        })
+    }
+
+    async fn start_portal(&self, interface: &str, settings: &ProfileSettings) -> Result<()> {
+        let ProfileMode::AccessPoint { ref gateway, .. } = settings.mode else {
+            bail!("The native backend only hosts the captive portal, use Network Manager or iwd");
+        };
+
+        if settings.cloned_mac.is_some() {
+            bail!("Cloned MAC addresses are not supported with the native backend");
+        }
+
+        let config = AccessPointConfig {
+            ssid: settings.ssid.clone(),
+            channel: DEFAULT_CHANNEL,
+            address: gateway
+                .parse::<Ipv4Addr>()
+                .context("Failed to parse gateway address")?,
+            prefix_len: PORTAL_PREFIX_LEN,
+            passphrase: settings.passphrase.clone(),
+        };
+
+        let access_point = nl80211::ap::AccessPoint::start(&self.nl80211, interface, &config)
+            .await
+            .context("Failed to start access point")?;
+
+        *self.portal.borrow_mut() = Some(Portal {
+            ssid: settings.ssid.clone(),
+            access_point,
+        });
+
+        Ok(())
+    }
+
+    async fn stop_portal(&self) -> Result<bool> {
+        let portal = self.portal.borrow_mut().take();
+
+        let Some(portal) = portal else {
+            return Ok(false);
+        };
+
+        portal
+            .access_point
+            .stop()
+            .await
+            .context("Failed to stop access point")?;
+
+        Ok(true)
+    }
+}
+
+impl Backend for NativeBackend {
+    type Device = String;
+    type Connection = NativeConnection;
+
+    async fn find_device(&self, interface: Option<&str>) -> Result<String> {
+        let iface = match interface {
+            Some(interface) => find_interface(&self.nl80211, interface)
+                .await
+                .with_context(|| format!("Failed to find interface '{interface}'"))?,
+            None => get_interfaces(&self.nl80211)
+                .await?
+                .into_iter()
+                .next()
+                .context("Failed to find a WiFi device")?,
+        };
+
+        Ok(iface.name)
+    }
+
+    fn device_interface(&self, device: &String) -> String {
+        device.clone()
+    }
+
+    fn can_join(&self) -> bool {
+        false
+    }
+
+    async fn scan(&self, device: &String) -> Result<()> {
+        let results = scan(&self.nl80211, device)
+            .await
+            .context("Failed to scan")?;
+
+        *self.access_points.borrow_mut() = results
+            .into_iter()
+            .map(|bss| AccessPoint {
+                ssid: Some(bss.ssid).filter(|ssid| !ssid.is_empty()),
+                strength: QualityModel::NetworkManager.quality(bss.signal_dbm),
+            })
+            .collect();
+
+        Ok(())
+    }
+
+    async fn access_points(&self, _device: &String) -> Result<Vec<AccessPoint>> {
+        Ok(self.access_points.borrow().clone())
+    }
+
+    /// The running portal is the only profile.
+    async fn profiles(&self) -> Result<Vec<Profile>> {
+        Ok(self
+            .portal
+            .borrow()
+            .iter()
+            .map(|portal| Profile {
+                id: portal.ssid.to_string(),
+                uuid: PORTAL_UUID.to_owned(),
+                connection_type: ConnectionType::Wifi,
+                access_point: true,
+                ssid: Some(portal.ssid.clone()),
+                autoconnect: false,
+                autoconnect_priority: 0,
+                last_used: None,
+                active: true,
+            })
+            .collect())
+    }
+
+    /// Networks are never joined.
+    async fn active_profile(&self, _device: &String) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    async fn delete_profile(&self, uuid: &str) -> Result<()> {
+        if uuid != PORTAL_UUID || !self.stop_portal().await? {
+            bail!("Failed to find connection profile {uuid}");
+        }
+
+        Ok(())
+    }
+
+    async fn update_profile(&self, _uuid: &str, _update: &ProfileUpdate) -> Result<()> {
+        bail!("The portal profile cannot be changed with the native backend")
+    }
+
+    async fn activate_profile(&self, uuid: &str, _device: &String) -> Result<NativeConnection> {
+        bail!("Failed to find connection profile {uuid}")
+    }
+
+    async fn add_and_activate(
+        &self,
+        settings: &ProfileSettings,
+        device: &String,
+    ) -> Result<NativeConnection> {
+        self.start_portal(device, settings).await?;
+
+        Ok(NativeConnection)
+    }
+
+    async fn deactivate(&self, _connection: &NativeConnection) -> Result<()> {
+        if !self.stop_portal().await? {
+            bail!("Connection is not active");
+        }
+
+        Ok(())
+    }
+
+    async fn wait_for_state(&self, _connection: &NativeConnection) -> Result<ActivationState> {
+        if self.portal.borrow().is_some() {
+            Ok(ActivationState::Activated)
+        } else {
+            Ok(ActivationState::Deactivated(None))
+        }
+    }
+
+    /// The portal profile exists only while the access point runs.
+    async fn delete_connection_profile(&self, _connection: &NativeConnection) -> Result<()> {
+        self.stop_portal().await?;
+
+        Ok(())
+    }
+
+    /// No networks are joined, so there is no connectivity to check.
+    async fn check_connectivity(&self) -> Result<Connectivity> {
+        Ok(Connectivity::None)
+    }
+}
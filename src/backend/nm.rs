@@ -0,0 +1,488 @@
+//! NetworkManager backend over libnm.
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use tokio::sync::oneshot;
+
+use glib::translate::FromGlib;
+
+use alloc::rc::Rc;
+use core::cell::{Cell, RefCell};
+
+use nl80211::Ssid;
+
+use nm::{
+    utils_get_timestamp_msec, ActiveConnection, ActiveConnectionExt, ActiveConnectionState, Cast,
+    Client, Connection, ConnectionExt, ConnectivityState, Device, DeviceExt, DeviceState,
+    DeviceType, DeviceWifi, IPAddress, RemoteConnection, SettingConnection, SettingIP4Config,
+    SettingIPConfigExt, SettingWireless, SettingWirelessSecurity, SimpleConnection,
+    SETTING_IP4_CONFIG_METHOD_MANUAL, SETTING_WIRED_SETTING_NAME, SETTING_WIRELESS_MODE_AP,
+    SETTING_WIRELESS_SETTING_NAME,
+};
+
+use crate::backend::{
+    AccessPoint, ActivationState, Backend, ConnectionType, FailureReason, Profile, ProfileMode,
+    ProfileSettings, ProfileUpdate,
+};
+use crate::opts::ClonedMac;
+
+const WIFI_SCAN_TIMEOUT_SECONDS: usize = 45;
+
+const SETTING_LOOPBACK_SETTING_NAME: &str = "loopback";
+
+const DEVICE_STATE_FAILED: u32 = 120;
+
+// Reasons of active connection state changes, see `NMActiveConnectionStateReason`
+const ACTIVE_REASON_IP_CONFIG_INVALID: u32 = 5;
+const ACTIVE_REASON_NO_SECRETS: u32 = 9;
+const ACTIVE_REASON_LOGIN_FAILED: u32 = 10;
+
+// Reasons of device state changes, see `NMDeviceStateReason`
+const DEVICE_REASON_IP_CONFIG_UNAVAILABLE: u32 = 5;
+const DEVICE_REASON_NO_SECRETS: u32 = 7;
+const DEVICE_REASON_SUPPLICANT_DISCONNECT: u32 = 8;
+const DEVICE_REASON_SUPPLICANT_CONFIG_FAILED: u32 = 9;
+const DEVICE_REASON_SUPPLICANT_FAILED: u32 = 10;
+const DEVICE_REASON_SUPPLICANT_TIMEOUT: u32 = 11;
+const DEVICE_REASON_DHCP_START_FAILED: u32 = 15;
+const DEVICE_REASON_DHCP_ERROR: u32 = 16;
+const DEVICE_REASON_DHCP_FAILED: u32 = 17;
+const DEVICE_REASON_CARRIER: u32 = 40;
+const DEVICE_REASON_SSID_NOT_FOUND: u32 = 53;
+
+#[derive(Clone)]
+pub struct NmBackend {
+    client: Client,
+}
+
+impl NmBackend {
+    pub async fn new() -> Result<Self> {
+        let client = Client::new_future()
+            .await
+            .context("Failed to create NetworkManager client")?;
+
+        if !client.is_nm_running() {
+            return Err(anyhow!("NetworkManager daemon is not running"));
+        }
+
+        Ok(Self { client })
+    }
+
+    fn find_connection(&self, uuid: &str) -> Result<RemoteConnection> {
+        self.client
+            .connections()
+            .into_iter()
+            .find(|connection| connection.uuid().as_deref() == Some(uuid))
+            .with_context(|| format!("Failed to find connection profile {uuid}"))
+    }
+}
+
+impl Backend for NmBackend {
+    type Device = DeviceWifi;
+    type Connection = ActiveConnection;
+
+    async fn find_device(&self, interface: Option<&str>) -> Result<DeviceWifi> {
+        if let Some(iface) = interface {
+            get_exact_device(&self.client, iface)
+        } else {
+            find_any_wifi_device(&self.client)
+        }
+    }
+
+    fn device_interface(&self, device: &DeviceWifi) -> String {
+        device
+            .clone()
+            .upcast::<Device>()
+            .iface()
+            .expect("No interface associated with device")
+            .to_string()
+    }
+
+    async fn scan(&self, device: &DeviceWifi) -> Result<()> {
+        let prescan = utils_get_timestamp_msec();
+
+        device
+            .request_scan_future()
+            .await
+            .context("Failed to request WiFi scan")?;
+
+        for _ in 0..WIFI_SCAN_TIMEOUT_SECONDS {
+            if prescan < device.last_scan() {
+                break;
+            }
+
+            glib::timeout_future_seconds(1).await;
+        }
+
+        Ok(())
+    }
+
+    async fn access_points(&self, device: &DeviceWifi) -> Result<Vec<AccessPoint>> {
+        Ok(device
+            .access_points()
+            .iter()
+            .map(|ap| AccessPoint {
+                ssid: ap.ssid().map(|ssid| Ssid::from(ssid.as_ref())),
+                strength: ap.strength(),
+            })
+            .collect())
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>> {
+        let active = self
+            .client
+            .active_connections()
+            .into_iter()
+            .filter_map(|connection| connection.uuid())
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .client
+            .connections()
+            .into_iter()
+            .map(glib::Cast::upcast::<Connection>)
+            .filter_map(|connection| {
+                let setting_connection = connection.setting_connection()?;
+                let uuid = setting_connection.uuid()?;
+                Some(Profile {
+                    id: setting_connection.id()?.to_string(),
+                    uuid: uuid.to_string(),
+                    connection_type: connection_type(&setting_connection),
+                    access_point: is_access_point_mode(&connection),
+                    ssid: connection_ssid(&connection),
+                    autoconnect: setting_connection.autoconnect(),
+                    autoconnect_priority: setting_connection.autoconnect_priority(),
+                    last_used: Some(setting_connection.timestamp()).filter(|&time| time != 0),
+                    active: active.contains(&uuid),
+                })
+            })
+            .collect())
+    }
+
+    async fn active_profile(&self, device: &DeviceWifi) -> Result<Option<String>> {
+        Ok(device
+            .active_connection()
+            .filter(|connection| connection.state() == ActiveConnectionState::Activated)
+            .and_then(|connection| connection.uuid())
+            .map(|uuid| uuid.to_string()))
+    }
+
+    async fn delete_profile(&self, uuid: &str) -> Result<()> {
+        self.find_connection(uuid)?
+            .delete_future()
+            .await
+            .context("Failed to delete connection profile")
+    }
+
+    async fn update_profile(&self, uuid: &str, update: &ProfileUpdate) -> Result<()> {
+        let connection = self.find_connection(uuid)?;
+        let setting_connection = connection
+            .setting_connection()
+            .context("Connection profile has no connection setting")?;
+
+        if let Some(passphrase) = update.passphrase.as_deref() {
+            if let Some(setting) = connection.setting_wireless_security() {
+                setting.set_psk(Some(passphrase));
+            } else {
+                let setting = SettingWirelessSecurity::new();
+                setting.set_key_mgmt(Some("wpa-psk"));
+                setting.set_psk(Some(passphrase));
+                connection.add_setting(setting);
+            }
+        }
+
+        if let Some(autoconnect) = update.autoconnect {
+            setting_connection.set_autoconnect(autoconnect);
+        }
+
+        if let Some(priority) = update.autoconnect_priority {
+            setting_connection.set_autoconnect_priority(priority);
+        }
+
+        connection
+            .commit_changes_future(true)
+            .await
+            .context("Failed to save connection profile")
+    }
+
+    async fn activate_profile(&self, uuid: &str, device: &DeviceWifi) -> Result<ActiveConnection> {
+        let connection = self.find_connection(uuid)?;
+
+        self.client
+            .activate_connection_future(Some(&connection), Some(device), None)
+            .await
+            .context("Failed to activate connection")
+    }
+
+    async fn add_and_activate(
+        &self,
+        settings: &ProfileSettings,
+        device: &DeviceWifi,
+    ) -> Result<ActiveConnection> {
+        let connection = match settings.mode {
+            ProfileMode::Client => create_client_connection(
+                &settings.ssid,
+                settings.passphrase.as_deref(),
+                settings.cloned_mac,
+            ),
+            ProfileMode::AccessPoint {
+                ref interface,
+                ref gateway,
+            } => create_ap_connection(
+                interface,
+                &settings.ssid,
+                gateway,
+                settings.passphrase.as_deref(),
+                settings.cloned_mac,
+            )?,
+        };
+
+        self.client
+            .add_and_activate_connection_future(Some(&connection), Some(device), None)
+            .await
+            .context("Failed to add and activate connection")
+    }
+
+    async fn deactivate(&self, connection: &ActiveConnection) -> Result<()> {
+        self.client
+            .deactivate_connection_future(connection)
+            .await
+            .context("Failed to deactivate connection")?;
+
+        Ok(())
+    }
+
+    async fn wait_for_state(&self, connection: &ActiveConnection) -> Result<ActivationState> {
+        println!("Monitoring connection state...");
+
+        // Devices fail before the connection is deactivated, with the more specific reason
+        let device_reason = Rc::new(Cell::new(None));
+        let devices = connection.devices();
+        let device_handler_ids = devices
+            .iter()
+            .map(|device| {
+                let device_reason = Rc::clone(&device_reason);
+                device.connect_state_changed(move |_, new_state, _, reason| {
+                    if new_state == DEVICE_STATE_FAILED {
+                        println!("Device failed with reason {reason}");
+                        device_reason.set(Some(reason));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let (sender, receiver) = oneshot::channel::<ActivationState>();
+        let sender_cell = Rc::new(RefCell::new(Some(sender)));
+
+        let handler_id = connection.connect_state_changed(move |_, state_u32, reason| {
+            // SAFETY: conversion from u32 is guaranteed
+            let state = unsafe {
+                ActiveConnectionState::from_glib(
+                    state_u32.try_into().expect("Unknown connection state"),
+                )
+            };
+            println!("Connection: {state:?}");
+
+            let exit = match state {
+                ActiveConnectionState::Activated => Some(ActivationState::Activated),
+                ActiveConnectionState::Deactivated => Some(ActivationState::Deactivated(
+                    failure_reason(reason, device_reason.get()),
+                )),
+                _ => None,
+            };
+            if let Some(result) = exit {
+                if let Some(inner_sender) = sender_cell.borrow_mut().take() {
+                    inner_sender.send(result).ok();
+                }
+            }
+        });
+
+        let state = receiver
+            .await
+            .context("Failed to receive active connection state change")?;
+
+        glib::signal_handler_disconnect(connection, handler_id);
+        for (device, device_handler_id) in devices.iter().zip(device_handler_ids) {
+            glib::signal_handler_disconnect(device, device_handler_id);
+        }
+
+        Ok(state)
+    }
+
+    async fn delete_connection_profile(&self, connection: &ActiveConnection) -> Result<()> {
+        if let Some(remote_connection) = connection.connection() {
+            remote_connection
+                .delete_future()
+                .await
+                .context("Failed to delete connection profile")?;
+        }
+
+        Ok(())
+    }
+
+    async fn check_connectivity(&self) -> Result<Connectivity> {
+        let connectivity = self
+            .client
+            .check_connectivity_future()
+            .await
+            .context("Failed to execute check connectivity")?;
+
+        Ok(match connectivity {
+            ConnectivityState::None => Connectivity::None,
+            ConnectivityState::Portal => Connectivity::Portal,
+            ConnectivityState::Limited => Connectivity::Limited,
+            ConnectivityState::Full => Connectivity::Full,
+            _ => Connectivity::Unknown,
+        })
+    }
+}
+
+/// Category of an activation failure, preferring the reason of the device failing over that of
+/// the connection deactivating. Supplicant disconnects happen when the handshake fails, which is
+/// most likely due to a wrong passphrase.
+const fn failure_reason(reason: u32, device_reason: Option<u32>) -> Option<FailureReason> {
+    match device_reason {
+        Some(DEVICE_REASON_SUPPLICANT_DISCONNECT) => Some(FailureReason::WrongPassword),
+        Some(DEVICE_REASON_NO_SECRETS) => Some(FailureReason::NoSecrets),
+        Some(DEVICE_REASON_SSID_NOT_FOUND) => Some(FailureReason::SsidNotFound),
+        Some(
+            DEVICE_REASON_IP_CONFIG_UNAVAILABLE
+            | DEVICE_REASON_DHCP_START_FAILED
+            | DEVICE_REASON_DHCP_ERROR
+            | DEVICE_REASON_DHCP_FAILED,
+        ) => Some(FailureReason::DhcpTimeout),
+        Some(
+            DEVICE_REASON_SUPPLICANT_CONFIG_FAILED
+            | DEVICE_REASON_SUPPLICANT_FAILED
+            | DEVICE_REASON_SUPPLICANT_TIMEOUT,
+        ) => Some(FailureReason::SupplicantFailure),
+        Some(DEVICE_REASON_CARRIER) => Some(FailureReason::CarrierLost),
+        _ => match reason {
+            ACTIVE_REASON_LOGIN_FAILED => Some(FailureReason::WrongPassword),
+            ACTIVE_REASON_NO_SECRETS => Some(FailureReason::NoSecrets),
+            ACTIVE_REASON_IP_CONFIG_INVALID => Some(FailureReason::DhcpTimeout),
+            _ => None,
+        },
+    }
+}
+
+fn connection_ssid(connection: &Connection) -> Option<Ssid> {
+    Some(Ssid::from(connection.setting_wireless()?.ssid()?.as_ref()))
+}
+
+fn is_access_point_mode(connection: &Connection) -> bool {
+    if let Some(setting) = connection.setting_wireless() {
+        if let Some(mode) = setting.mode() {
+            return mode == *SETTING_WIRELESS_MODE_AP;
+        }
+    }
+
+    false
+}
+
+fn connection_type(setting: &SettingConnection) -> ConnectionType {
+    match setting.connection_type() {
+        Some(name) if name == *SETTING_WIRELESS_SETTING_NAME => ConnectionType::Wifi,
+        Some(name) if name == *SETTING_WIRED_SETTING_NAME => ConnectionType::Ethernet,
+        Some(name) if name == SETTING_LOOPBACK_SETTING_NAME => ConnectionType::Loopback,
+        _ => ConnectionType::Other,
+    }
+}
+
+fn get_exact_device(client: &Client, interface: &str) -> Result<DeviceWifi> {
+    let device = client
+        .device_by_iface(interface)
+        .context(format!("Failed to find interface '{interface}'"))?;
+
+    if device.device_type() != DeviceType::Wifi {
+        bail!("Not a WiFi interface '{}'", interface);
+    }
+
+    if device.state() == DeviceState::Unmanaged {
+        bail!("Interface is not managed by NetworkManager '{}'", interface);
+    }
+
+    Ok(device.downcast().expect("Cannot downcast to DeviceWifi"))
+}
+
+fn find_any_wifi_device(client: &Client) -> Result<DeviceWifi> {
+    for device in client.devices() {
+        if device.device_type() == DeviceType::Wifi && device.state() != DeviceState::Unmanaged {
+            return Ok(device.downcast().expect("Cannot downcast to DeviceWifi"));
+        }
+    }
+
+    bail!("Failed to find a managed WiFi device")
+}
+
+fn create_ap_connection(
+    interface: &str,
+    ssid: &Ssid,
+    address: &str,
+    passphrase: Option<&str>,
+    cloned_mac: Option<ClonedMac>,
+) -> Result<SimpleConnection> {
+    let connection = SimpleConnection::new();
+
+    let s_connection = SettingConnection::new();
+    s_connection.set_type(Some(SETTING_WIRELESS_SETTING_NAME));
+    s_connection.set_id(Some(&ssid.to_string()));
+    s_connection.set_autoconnect(false);
+    s_connection.set_interface_name(Some(interface));
+    connection.add_setting(s_connection);
+
+    let s_wireless = SettingWireless::new();
+    s_wireless.set_ssid(Some(&(ssid.as_bytes().into())));
+    s_wireless.set_band(Some("bg"));
+    s_wireless.set_hidden(false);
+    s_wireless.set_mode(Some(SETTING_WIRELESS_MODE_AP));
+    if let Some(cloned_mac) = cloned_mac {
+        s_wireless.set_cloned_mac_address(Some(&cloned_mac.to_string()));
+    }
+    connection.add_setting(s_wireless);
+
+    if let Some(password) = passphrase {
+        let s_wireless_security = SettingWirelessSecurity::new();
+        s_wireless_security.set_key_mgmt(Some("wpa-psk"));
+        s_wireless_security.set_psk(Some(password));
+        connection.add_setting(s_wireless_security);
+    }
+
+    let s_ip4 = SettingIP4Config::new();
+    let ip_address =
+        IPAddress::new(libc::AF_INET, address, 24).context("Failed to parse gateway address")?;
+    s_ip4.add_address(&ip_address);
+    s_ip4.set_method(Some(SETTING_IP4_CONFIG_METHOD_MANUAL));
+    connection.add_setting(s_ip4);
+
+    Ok(connection)
+}
+
+fn create_client_connection(
+    ssid: &Ssid,
+    passphrase: Option<&str>,
+    cloned_mac: Option<ClonedMac>,
+) -> SimpleConnection {
+    let connection = SimpleConnection::new();
+
+    let s_connection = SettingConnection::new();
+    s_connection.set_type(Some(SETTING_WIRELESS_SETTING_NAME));
+    s_connection.set_id(Some(&ssid.to_string()));
+    connection.add_setting(s_connection);
+
+    let s_wireless = SettingWireless::new();
+    s_wireless.set_ssid(Some(&(ssid.as_bytes().into())));
+    if let Some(cloned_mac) = cloned_mac {
+        s_wireless.set_cloned_mac_address(Some(&cloned_mac.to_string()));
+    }
+    connection.add_setting(s_wireless);
+
+    if let Some(password) = passphrase {
+        let s_wireless_security = SettingWirelessSecurity::new();
+        s_wireless_security.set_key_mgmt(Some("wpa-psk"));
+        s_wireless_security.set_psk(Some(password));
+        connection.add_setting(s_wireless_security);
+    }
+
+    connection
+}
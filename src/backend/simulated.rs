@@ -0,0 +1,537 @@
+//! Simulated backend following a scenario file, for developing the web UI on machines without
+//! wireless hardware. Operations take the scripted delays and nothing on the system changes.
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::time::Duration;
+use std::time::{Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+
+use nl80211::Ssid;
+
+use crate::backend::{
+    AccessPoint, ActivationState, Backend, ConnectionType, Connectivity, FailureReason, Profile,
+    ProfileMode, ProfileSettings, ProfileUpdate,
+};
+use crate::opts::DEFAULT_INTERFACE;
+use crate::scenario::{Outcome, Scenario};
+
+/// Activation of a profile, settling at a scripted time.
+#[derive(Debug, Clone)]
+pub struct SimulatedConnection {
+    id: usize,
+    uuid: String,
+}
+
+#[derive(Debug)]
+struct SimulatedProfile {
+    profile: Profile,
+    passphrase: Option<String>,
+}
+
+#[derive(Debug)]
+struct Activation {
+    id: usize,
+    uuid: String,
+    access_point: bool,
+    settles: Instant,
+    outcome: ActivationState,
+}
+
+#[derive(Debug)]
+struct State {
+    profiles: Vec<SimulatedProfile>,
+    /// The device activates one profile at a time
+    active: Option<Activation>,
+    next_id: usize,
+}
+
+impl State {
+    const fn next_id(&mut self) -> usize {
+        self.next_id = self.next_id.saturating_add(1);
+        self.next_id
+    }
+
+    fn profile(&self, uuid: &str) -> Result<&SimulatedProfile> {
+        self.profiles
+            .iter()
+            .find(|profile| profile.profile.uuid == uuid)
+            .with_context(|| format!("Failed to find connection profile {uuid}"))
+    }
+
+    fn profile_mut(&mut self, uuid: &str) -> Result<&mut SimulatedProfile> {
+        self.profiles
+            .iter_mut()
+            .find(|profile| profile.profile.uuid == uuid)
+            .with_context(|| format!("Failed to find connection profile {uuid}"))
+    }
+
+    fn add_profile(&mut self, settings: &ProfileSettings) -> String {
+        let uuid = format!("simulated-{}", self.next_id());
+
+        let access_point = matches!(settings.mode, ProfileMode::AccessPoint { .. });
+
+        self.profiles.push(SimulatedProfile {
+            profile: Profile {
+                id: settings.ssid.to_string(),
+                uuid: uuid.clone(),
+                connection_type: ConnectionType::Wifi,
+                access_point,
+                ssid: Some(settings.ssid.clone()),
+                autoconnect: !access_point,
+                autoconnect_priority: 0,
+                last_used: None,
+                active: false,
+            },
+            passphrase: settings.passphrase.clone(),
+        });
+
+        uuid
+    }
+
+    /// The connection is settled once activated or deactivated.
+    fn settled(&self) -> Option<&Activation> {
+        self.active
+            .as_ref()
+            .filter(|activation| activation.settles <= Instant::now())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedBackend {
+    scenario: Rc<Scenario>,
+    state: Rc<RefCell<State>>,
+}
+
+impl SimulatedBackend {
+    pub fn new(scenario: Scenario) -> Self {
+        let mut state = State {
+            profiles: Vec::new(),
+            active: None,
+            next_id: 0,
+        };
+
+        for station in scenario.stations.iter().filter(|station| station.saved) {
+            state.add_profile(&ProfileSettings {
+                ssid: Ssid::from(station.ssid.as_str()),
+                passphrase: station.passphrase.clone(),
+                cloned_mac: None,
+                mode: ProfileMode::Client,
+            });
+        }
+
+        Self {
+            scenario: Rc::new(scenario),
+            state: Rc::new(RefCell::new(state)),
+        }
+    }
+
+    /// Replaces the current activation, as the device can only join or host one network.
+    fn start_activation(&self, uuid: &str) -> Result<SimulatedConnection> {
+        let mut state = self.state.borrow_mut();
+
+        let profile = state.profile(uuid)?;
+        let access_point = profile.profile.access_point;
+        let (delay, outcome) = if access_point {
+            (self.scenario.delays.portal, ActivationState::Activated)
+        } else {
+            self.client_outcome(profile)
+        };
+
+        if outcome == ActivationState::Activated {
+            state.profile_mut(uuid)?.profile.last_used = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs());
+        }
+
+        let id = state.next_id();
+        state.active = Some(Activation {
+            id,
+            uuid: uuid.to_owned(),
+            access_point,
+            settles: Instant::now()
+                .checked_add(delay)
+                .context("Simulated delay out of range")?,
+            outcome,
+        });
+
+        Ok(SimulatedConnection {
+            id,
+            uuid: uuid.to_owned(),
+        })
+    }
+
+    /// Networks out of range fail after the connect delay as well.
+    fn client_outcome(&self, profile: &SimulatedProfile) -> (Duration, ActivationState) {
+        let delays = &self.scenario.delays;
+        let failed = |reason| ActivationState::Deactivated(Some(reason));
+
+        let Some(station) = profile
+            .profile
+            .ssid
+            .as_ref()
+            .and_then(|ssid| self.scenario.station(ssid))
+        else {
+            return (delays.connect, failed(FailureReason::SsidNotFound));
+        };
+
+        match station.outcome {
+            Outcome::Success if station.passphrase == profile.passphrase => {
+                (delays.connect, ActivationState::Activated)
+            }
+            Outcome::Success if profile.passphrase.is_none() => {
+                (delays.connect, failed(FailureReason::NoSecrets))
+            }
+            Outcome::Success | Outcome::WrongPassword => {
+                (delays.connect, failed(FailureReason::WrongPassword))
+            }
+            Outcome::Timeout => (delays.timeout, failed(FailureReason::DhcpTimeout)),
+        }
+    }
+}
+
+impl Backend for SimulatedBackend {
+    type Device = String;
+    type Connection = SimulatedConnection;
+
+    async fn find_device(&self, interface: Option<&str>) -> Result<String> {
+        Ok(interface.unwrap_or(DEFAULT_INTERFACE).to_owned())
+    }
+
+    fn device_interface(&self, device: &String) -> String {
+        device.clone()
+    }
+
+    async fn scan(&self, _device: &String) -> Result<()> {
+        glib::timeout_future(self.scenario.delays.scan).await;
+
+        Ok(())
+    }
+
+    async fn access_points(&self, _device: &String) -> Result<Vec<AccessPoint>> {
+        Ok(self.scenario.access_points())
+    }
+
+    async fn profiles(&self) -> Result<Vec<Profile>> {
+        let state = self.state.borrow();
+        let active = state
+            .settled()
+            .filter(|activation| activation.outcome == ActivationState::Activated)
+            .map(|activation| activation.uuid.as_str());
+
+        Ok(state
+            .profiles
+            .iter()
+            .map(|profile| Profile {
+                active: active == Some(profile.profile.uuid.as_str()),
+                ..profile.profile.clone()
+            })
+            .collect())
+    }
+
+    async fn active_profile(&self, _device: &String) -> Result<Option<String>> {
+        Ok(self
+            .state
+            .borrow()
+            .settled()
+            .filter(|activation| {
+                !activation.access_point && activation.outcome == ActivationState::Activated
+            })
+            .map(|activation| activation.uuid.clone()))
+    }
+
+    async fn delete_profile(&self, uuid: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+
+        state.profile(uuid)?;
+        state
+            .profiles
+            .retain(|profile| profile.profile.uuid != uuid);
+
+        if state
+            .active
+            .as_ref()
+            .is_some_and(|activation| activation.uuid == uuid)
+        {
+            state.active = None;
+        }
+
+        Ok(())
+    }
+
+    async fn update_profile(&self, uuid: &str, update: &ProfileUpdate) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        let profile = state.profile_mut(uuid)?;
+
+        if let Some(ref passphrase) = update.passphrase {
+            profile.passphrase = Some(passphrase.clone());
+        }
+        if let Some(autoconnect) = update.autoconnect {
+            profile.profile.autoconnect = autoconnect;
+        }
+        if let Some(priority) = update.autoconnect_priority {
+            profile.profile.autoconnect_priority = priority;
+        }
+
+        Ok(())
+    }
+
+    async fn activate_profile(&self, uuid: &str, _device: &String) -> Result<SimulatedConnection> {
+        self.start_activation(uuid)
+    }
+
+    async fn add_and_activate(
+        &self,
+        settings: &ProfileSettings,
+        _device: &String,
+    ) -> Result<SimulatedConnection> {
+        let uuid = self.state.borrow_mut().add_profile(settings);
+
+        self.start_activation(&uuid)
+    }
+
+    async fn deactivate(&self, connection: &SimulatedConnection) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+
+        if state
+            .active
+            .as_ref()
+            .is_none_or(|activation| activation.id != connection.id)
+        {
+            bail!("Connection is not active");
+        }
+        state.active = None;
+
+        Ok(())
+    }
+
+    async fn wait_for_state(&self, connection: &SimulatedConnection) -> Result<ActivationState> {
+        let pending = self
+            .state
+            .borrow()
+            .active
+            .as_ref()
+            .filter(|activation| activation.id == connection.id)
+            .map(|activation| (activation.settles, activation.outcome));
+
+        let Some((settles, outcome)) = pending else {
+            return Ok(ActivationState::Deactivated(None));
+        };
+
+        glib::timeout_future(settles.saturating_duration_since(Instant::now())).await;
+
+        if let ActivationState::Deactivated(_) = outcome {
+            let mut state = self.state.borrow_mut();
+            if state
+                .active
+                .as_ref()
+                .is_some_and(|activation| activation.id == connection.id)
+            {
+                state.active = None;
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    async fn delete_connection_profile(&self, connection: &SimulatedConnection) -> Result<()> {
+        self.delete_profile(&connection.uuid).await
+    }
+
+    /// Scripted connectivity while joined to a network, none while hosting the portal.
+    async fn check_connectivity(&self) -> Result<Connectivity> {
+        let connected = self.state.borrow().settled().is_some_and(|activation| {
+            !activation.access_point && activation.outcome == ActivationState::Activated
+        });
+
+        if connected {
+            Ok(self.scenario.connectivity.into())
+        } else {
+            Ok(Connectivity::None)
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::indexing_slicing,
+    clippy::assertions_on_result_states
+)]
+mod tests {
+    use core::future::Future;
+
+    use glib::MainContext;
+
+    use super::*;
+    use crate::scenario::{Connectivity, Delays, SimulatedStation};
+
+    fn run<F: Future>(future: F) -> F::Output {
+        MainContext::new().block_on(future)
+    }
+
+    fn station(ssid: &str, passphrase: Option<&str>, outcome: Outcome) -> SimulatedStation {
+        SimulatedStation {
+            ssid: ssid.to_owned(),
+            signal_dbm: -50,
+            passphrase: passphrase.map(ToOwned::to_owned),
+            saved: false,
+            outcome,
+        }
+    }
+
+    fn backend() -> SimulatedBackend {
+        let mut saved = station("Office", Some("office123"), Outcome::Success);
+        saved.saved = true;
+
+        SimulatedBackend::new(Scenario {
+            connectivity: Connectivity::Portal,
+            delays: Delays {
+                scan: Duration::ZERO,
+                connect: Duration::ZERO,
+                timeout: Duration::ZERO,
+                portal: Duration::ZERO,
+            },
+            stations: vec![
+                station("Home", Some("secret123"), Outcome::Success),
+                station("Cafe", None, Outcome::Timeout),
+                station("Library", Some("library123"), Outcome::WrongPassword),
+                station("", None, Outcome::Success),
+                saved,
+            ],
+        })
+    }
+
+    fn connect(
+        backend: &SimulatedBackend,
+        ssid: &str,
+        passphrase: Option<&str>,
+    ) -> ActivationState {
+        let settings = ProfileSettings {
+            ssid: Ssid::from(ssid),
+            passphrase: passphrase.map(ToOwned::to_owned),
+            cloned_mac: None,
+            mode: ProfileMode::Client,
+        };
+
+        run(async {
+            let connection = backend
+                .add_and_activate(&settings, &"wlan0".to_owned())
+                .await
+                .unwrap();
+            backend.wait_for_state(&connection).await.unwrap()
+        })
+    }
+
+    #[test]
+    fn connects_with_matching_passphrase() {
+        let backend = backend();
+
+        assert_eq!(
+            connect(&backend, "Home", Some("secret123")),
+            ActivationState::Activated
+        );
+        assert_eq!(
+            run(backend.check_connectivity()).unwrap(),
+            crate::backend::Connectivity::Portal
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_passphrase() {
+        let backend = backend();
+
+        assert_eq!(
+            connect(&backend, "Home", Some("wrong")),
+            ActivationState::Deactivated(Some(FailureReason::WrongPassword))
+        );
+        assert_eq!(
+            connect(&backend, "Home", None),
+            ActivationState::Deactivated(Some(FailureReason::NoSecrets))
+        );
+        assert_eq!(
+            connect(&backend, "Library", Some("library123")),
+            ActivationState::Deactivated(Some(FailureReason::WrongPassword))
+        );
+        assert_eq!(
+            run(backend.check_connectivity()).unwrap(),
+            crate::backend::Connectivity::None
+        );
+    }
+
+    #[test]
+    fn fails_on_timeout_and_out_of_range() {
+        let backend = backend();
+
+        assert_eq!(
+            connect(&backend, "Cafe", None),
+            ActivationState::Deactivated(Some(FailureReason::DhcpTimeout))
+        );
+        assert_eq!(
+            connect(&backend, "Airport", None),
+            ActivationState::Deactivated(Some(FailureReason::SsidNotFound))
+        );
+    }
+
+    #[test]
+    fn lists_saved_stations_as_profiles() {
+        let backend = backend();
+
+        let profiles = run(backend.profiles()).unwrap();
+
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].ssid, Some(Ssid::from("Office")));
+        assert!(!profiles[0].access_point);
+
+        let connection =
+            run(backend.activate_profile(&profiles[0].uuid, &"wlan0".to_owned())).unwrap();
+        assert_eq!(
+            run(backend.wait_for_state(&connection)).unwrap(),
+            ActivationState::Activated
+        );
+
+        let profiles = run(backend.profiles()).unwrap();
+        assert!(profiles[0].active);
+        assert!(profiles[0].last_used.is_some());
+    }
+
+    #[test]
+    fn updates_profiles() {
+        let backend = backend();
+        let uuid = run(backend.profiles()).unwrap()[0].uuid.clone();
+
+        run(backend.update_profile(
+            &uuid,
+            &ProfileUpdate {
+                passphrase: Some("changed".to_owned()),
+                autoconnect: Some(false),
+                autoconnect_priority: Some(10),
+            },
+        ))
+        .unwrap();
+
+        let connection = run(backend.activate_profile(&uuid, &"wlan0".to_owned())).unwrap();
+        assert_eq!(
+            run(backend.wait_for_state(&connection)).unwrap(),
+            ActivationState::Deactivated(Some(FailureReason::WrongPassword))
+        );
+
+        let profiles = run(backend.profiles()).unwrap();
+        assert!(!profiles[0].autoconnect);
+        assert_eq!(profiles[0].autoconnect_priority, 10);
+        assert!(!profiles[0].active);
+    }
+
+    #[test]
+    fn reports_hidden_stations_without_ssid() {
+        let backend = backend();
+
+        let access_points = run(backend.access_points(&"wlan0".to_owned())).unwrap();
+
+        assert_eq!(access_points.len(), 5);
+        assert_eq!(access_points[3].ssid, None);
+        assert_eq!(access_points[0].strength, 84);
+    }
+}
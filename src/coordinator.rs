@@ -0,0 +1,262 @@
+use core::time::Duration;
+
+use anyhow::Result;
+
+use serde::Serialize;
+
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+
+use nl80211::scan::{cached_scan, scan, Bss};
+use nl80211::Nl80211;
+
+use crate::error::ErrorKind;
+use crate::network::Station;
+use crate::quality::QualityModel;
+use crate::scenario::Scenario;
+
+#[derive(Serialize, Debug)]
+pub struct ScanResults {
+    pub age_ms: u64,
+    pub stations: Vec<Station>,
+}
+
+#[derive(Debug)]
+struct CachedScan {
+    stations: Vec<Station>,
+    completed: Instant,
+}
+
+impl CachedScan {
+    fn to_results(&self) -> ScanResults {
+        let age_ms = self
+            .completed
+            .elapsed()
+            .as_millis()
+            .try_into()
+            .unwrap_or(u64::MAX);
+
+        ScanResults {
+            age_ms,
+            stations: self.stations.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    Nl80211(Nl80211),
+    /// Fixed results of a simulation scenario, available after the scan delay
+    Simulated {
+        stations: Vec<Station>,
+        delay: Duration,
+    },
+    /// nl80211 failed to initialize, so scanning fails
+    Unavailable,
+}
+
+/// Runs at most one scan at a time on the interface and shares its result with every caller
+/// that asked while it was in flight. Results younger than the TTL are served from cache.
+#[derive(Debug)]
+pub struct ScanCoordinator {
+    source: Source,
+    interface: String,
+    ttl: Duration,
+    quality_model: QualityModel,
+    cache: Mutex<Option<CachedScan>>,
+}
+
+impl ScanCoordinator {
+    pub fn new(
+        nl80211: Option<Nl80211>,
+        interface: &str,
+        ttl: Duration,
+        quality_model: QualityModel,
+    ) -> Self {
+        Self {
+            source: nl80211.map_or(Source::Unavailable, Source::Nl80211),
+            interface: interface.to_owned(),
+            ttl,
+            quality_model,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// Serves the stations of a simulation scenario instead of scanning.
+    pub fn simulated(
+        scenario: &Scenario,
+        interface: &str,
+        ttl: Duration,
+        quality_model: QualityModel,
+    ) -> Self {
+        Self {
+            source: Source::Simulated {
+                stations: scenario.scan_results(quality_model),
+                delay: scenario.delays.scan,
+            },
+            interface: interface.to_owned(),
+            ttl,
+            quality_model,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// None when simulating, as there is no device to query.
+    pub fn nl80211(&self) -> Result<Option<&Nl80211>> {
+        match self.source {
+            Source::Nl80211(ref nl80211) => Ok(Some(nl80211)),
+            Source::Simulated { .. } => Ok(None),
+            Source::Unavailable => Err(unavailable()),
+        }
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+
+    pub async fn scan(&self) -> Result<ScanResults> {
+        let requested = Instant::now();
+
+        // The lock is held for the whole scan, so concurrent callers queue up behind it
+        let mut cache = self.cache.lock().await;
+
+        if let Some(cached) = cache.as_ref() {
+            if cached.completed >= requested || cached.completed.elapsed() < self.ttl {
+                return Ok(cached.to_results());
+            }
+        }
+
+        let stations = match self.source {
+            Source::Nl80211(ref nl80211) => self.to_stations(scan(nl80211, &self.interface).await?),
+            Source::Simulated {
+                ref stations,
+                delay,
+            } => {
+                sleep(delay).await;
+                stations.clone()
+            }
+            Source::Unavailable => return Err(unavailable()),
+        };
+
+        let cached = cache.insert(CachedScan {
+            stations,
+            completed: Instant::now(),
+        });
+
+        Ok(cached.to_results())
+    }
+
+    /// Results from the kernel BSS table no older than the TTL, without triggering a scan.
+    pub async fn cached_scan(&self) -> Result<ScanResults> {
+        let nl80211 = match self.source {
+            Source::Nl80211(ref nl80211) => nl80211,
+            Source::Simulated { ref stations, .. } => {
+                return Ok(ScanResults {
+                    age_ms: 0,
+                    stations: stations.clone(),
+                })
+            }
+            Source::Unavailable => return Err(unavailable()),
+        };
+
+        let cached = cached_scan(nl80211, &self.interface, self.ttl).await?;
+
+        Ok(ScanResults {
+            age_ms: cached.age.as_millis().try_into().unwrap_or(u64::MAX),
+            stations: self.to_stations(cached.results),
+        })
+    }
+
+    fn to_stations(&self, results: Vec<Bss>) -> Vec<Station> {
+        results
+            .into_iter()
+            .filter_map(|bss| Station::from_bss(bss, self.quality_model))
+            .collect()
+    }
+}
+
+fn unavailable() -> anyhow::Error {
+    anyhow::Error::new(ErrorKind::Unavailable).context("nl80211 is not available")
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::indexing_slicing,
+    clippy::assertions_on_result_states
+)]
+mod tests {
+    use tokio::join;
+
+    use nl80211::replay::fixture_path;
+
+    use super::*;
+
+    /// Replays recorded traffic, which fails any request beyond the recorded ones.
+    fn coordinator(fixture: &str, ttl: Duration) -> ScanCoordinator {
+        ScanCoordinator::new(
+            Some(Nl80211::replay(fixture_path(fixture)).unwrap()),
+            "wlan0",
+            ttl,
+            QualityModel::default(),
+        )
+    }
+
+    fn is_unexpected_request(err: &anyhow::Error) -> bool {
+        format!("{err:#}").contains("Unexpected request")
+    }
+
+    #[tokio::test]
+    async fn concurrent_scans_share_one_scan() {
+        // Without a TTL, only callers waiting for the scan in flight get its results
+        let coordinator = coordinator("scan.nlrec", Duration::ZERO);
+
+        let (first, second) = join!(coordinator.scan(), coordinator.scan());
+
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert_eq!(first.stations.len(), 2);
+        assert_eq!(first.stations, second.stations);
+    }
+
+    #[tokio::test]
+    async fn serves_results_within_ttl_from_cache() {
+        let coordinator = coordinator("scan.nlrec", Duration::MAX);
+
+        let scanned = coordinator.scan().await.unwrap();
+        let cached = coordinator.scan().await.unwrap();
+
+        assert_eq!(cached.stations, scanned.stations);
+    }
+
+    #[tokio::test]
+    async fn rescans_after_ttl() {
+        let coordinator = coordinator("scan.nlrec", Duration::ZERO);
+
+        coordinator.scan().await.unwrap();
+        let err = coordinator.scan().await.unwrap_err();
+
+        assert!(is_unexpected_request(&err), "{err:#}");
+    }
+
+    #[tokio::test]
+    async fn cached_scan_does_not_trigger_scan() {
+        let coordinator = coordinator("cached_scan.nlrec", Duration::MAX);
+
+        let cached = coordinator.cached_scan().await.unwrap();
+
+        assert_eq!(cached.stations[0].ssid.to_string(), "Home");
+        assert_eq!(cached.stations.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn fails_without_nl80211() {
+        let coordinator =
+            ScanCoordinator::new(None, "wlan0", Duration::MAX, QualityModel::default());
+
+        let err = coordinator.scan().await.unwrap_err();
+
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::Unavailable));
+        assert!(coordinator.cached_scan().await.is_err());
+        assert!(coordinator.nl80211().is_err());
+    }
+}
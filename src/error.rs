@@ -0,0 +1,30 @@
+//! Classification of errors for the web API.
+
+use core::fmt;
+
+/// Errors that are not failures of the device, which the web API reports with a matching
+/// status. Attached to the error chain like [`crate::backend::FailureReason`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The feature depends on a facility the host does not provide
+    Unavailable,
+    /// The requested resource, such as a connection profile, does not exist
+    NotFound,
+    /// The request is malformed or not applicable to the resource
+    InvalidInput,
+    /// The request conflicts with the current state, such as deleting the running portal
+    Conflict,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Unavailable => write!(f, "Not available on this device"),
+            Self::NotFound => write!(f, "Not found"),
+            Self::InvalidInput => write!(f, "Invalid request"),
+            Self::Conflict => write!(f, "Conflicts with the current state"),
+        }
+    }
+}
+
+impl std::error::Error for ErrorKind {}
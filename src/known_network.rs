@@ -0,0 +1,104 @@
+//! Waiting at startup for a saved network to come into range.
+
+use core::time::Duration;
+
+use tokio::time::timeout;
+
+use nl80211::sched_scan::{wait_for_match, MatchSet, ScanPlan, SchedScanConfig};
+use nl80211::{Nl80211, Ssid};
+
+// Scan every 10 seconds for the first minute and every 30 seconds afterwards
+const WAIT_SCAN_PLANS: [ScanPlan; 2] = [
+    ScanPlan {
+        interval: 10,
+        iterations: Some(6),
+    },
+    ScanPlan {
+        interval: 30,
+        iterations: None,
+    },
+];
+
+/// Runs before the network thread starts, so neither the captive portal nor the web API are
+/// served while waiting. Returns the network if it was found, otherwise the portal starts.
+pub async fn wait_for_known_network(
+    nl80211: Option<&Nl80211>,
+    interface: &str,
+    ssid: Ssid,
+    min_signal: i32,
+    wait_timeout: Duration,
+) -> Option<Ssid> {
+    let Some(nl80211) = nl80211 else {
+        println!("Failed to wait for network {ssid}: nl80211 is not available");
+        return None;
+    };
+
+    println!("Waiting for network {ssid}...");
+
+    let config = SchedScanConfig {
+        match_sets: vec![MatchSet {
+            ssid: Some(ssid.clone()),
+            rssi_threshold: Some(min_signal),
+        }],
+        plans: WAIT_SCAN_PLANS.to_vec(),
+    };
+
+    match timeout(wait_timeout, wait_for_match(nl80211, interface, &config)).await {
+        Ok(Ok(_)) => {
+            println!("Network {ssid} found");
+            Some(ssid)
+        }
+        Ok(Err(err)) => {
+            println!("Failed to wait for network {ssid}: {err:#}");
+            None
+        }
+        Err(_) => {
+            println!("Network {ssid} not found");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use nl80211::replay::fixture_path;
+
+    use super::*;
+
+    const WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+    fn replay(fixture: &str) -> Nl80211 {
+        Nl80211::replay(fixture_path(fixture)).unwrap()
+    }
+
+    async fn wait(nl80211: Option<&Nl80211>) -> Option<Ssid> {
+        wait_for_known_network(nl80211, "wlan0", Ssid::from("Home"), -70, WAIT_TIMEOUT).await
+    }
+
+    #[tokio::test]
+    async fn finds_network_in_range() {
+        let nl80211 = replay("sched_scan_match.nlrec");
+
+        assert_eq!(wait(Some(&nl80211)).await, Some(Ssid::from("Home")));
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_timeout() {
+        let nl80211 = replay("sched_scan_waiting.nlrec");
+
+        assert_eq!(wait(Some(&nl80211)).await, None);
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_scheduled_scan_stops() {
+        let nl80211 = replay("sched_scan_stopped.nlrec");
+
+        assert_eq!(wait(Some(&nl80211)).await, None);
+    }
+
+    #[tokio::test]
+    async fn gives_up_without_nl80211() {
+        assert_eq!(wait(None).await, None);
+    }
+}
@@ -30,43 +30,229 @@
 
 extern crate alloc;
 
+mod backend;
+mod coordinator;
+mod error;
+mod known_network;
 mod network;
-mod nl80211;
 mod opts;
+mod probe_log;
+mod quality;
+mod scenario;
 mod web;
 
+use alloc::sync::Arc;
+use core::time::Duration;
 use std::thread;
 
 use anyhow::{Context, Result};
 
 use clap::Parser;
 
+use tokio::runtime::Handle;
 use tokio::sync::oneshot;
 
-use crate::network::{create_channel, run_network_manager_loop};
-use crate::opts::Opts;
+use nl80211::power::{
+    apply_power_settings, restore_power_settings, PowerSettings, PreviousPowerSettings,
+};
+use nl80211::probe::ProbeListener;
+use nl80211::{Nl80211, Ssid};
+
+use crate::coordinator::ScanCoordinator;
+use crate::known_network::wait_for_known_network;
+use crate::network::{create_channel, run_network_manager_loop, run_simulation_loop};
+use crate::opts::{Opts, PowerSave, DEFAULT_INTERFACE};
+use crate::probe_log::{run_probe_listener, ProbeLog};
+use crate::scenario::Scenario;
 use crate::web::run_web_loop;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let opts: Opts = Opts::parse();
 
+    if let Some(ref path) = opts.simulate {
+        let scenario = Scenario::load(path)?;
+        return simulate(opts, scenario).await;
+    }
+
+    opts.validate()?;
+
+    // Connection managers serve the portal without nl80211, which only backs extra endpoints
+    let nl80211 = match Nl80211::new() {
+        Ok(nl80211) => Some(nl80211),
+        Err(err) => {
+            println!("Failed to connect to nl80211, continuing without it: {err:#}");
+            None
+        }
+    };
+
+    // Applied before the captive portal is created on the network thread
+    let previous_power_settings = apply_opts_power_settings(nl80211.as_ref(), &opts).await?;
+
+    let result = serve(opts, nl80211.as_ref()).await;
+
+    // Restored however serving ends, including failures to initialize the network
+    if let (Some(nl80211), Some(previous)) = (nl80211, previous_power_settings) {
+        if let Err(err) = restore_power_settings(&nl80211, previous).await {
+            println!("Failed to restore power settings: {err:#}");
+        }
+    }
+
+    result
+}
+
+/// Waits for the known network, starts the network thread and serves the web API until it
+/// is stopped.
+async fn serve(opts: Opts, nl80211: Option<&Nl80211>) -> Result<()> {
+    let probe_log = Arc::new(ProbeLog::new(opts.probe_log_size));
+    let probe_listener = opts.probe_listener;
+    let port = opts.port;
+    let scan_cache_ttl = Duration::from_secs(opts.scan_cache_ttl);
+    let quality_model = opts.quality_model;
+
+    let known_network = match opts.wait_for_ssid {
+        Some(ref ssid) => {
+            // The connection manager has not picked a device yet, but the portal starts if
+            // the guess is wrong
+            wait_for_known_network(
+                nl80211,
+                opts.interface.as_deref().unwrap_or(DEFAULT_INTERFACE),
+                Ssid::from(ssid.as_str()),
+                opts.wait_min_signal,
+                Duration::from_secs(opts.wait_timeout),
+            )
+            .await
+        }
+        None => None,
+    };
+
+    let (glib_sender, glib_receiver) = create_channel();
+
+    let (initialized_sender, initialized_receiver) = oneshot::channel();
+
+    // The native backend drives its nl80211 client from the network thread
+    let runtime = Handle::current();
+
+    thread::spawn(move || {
+        let _runtime_guard = runtime.enter();
+        run_network_manager_loop(opts, known_network, initialized_sender, glib_receiver);
+    });
+
+    // The interface of the device the connection manager picked, which the nl80211 consumers
+    // need to match
+    let interface = receive_network_initialized(initialized_receiver).await?;
+
+    let scan_coordinator =
+        ScanCoordinator::new(nl80211.cloned(), &interface, scan_cache_ttl, quality_model);
+
+    // Registering before the portal starts would not help, as the kernel drops frame
+    // registrations when the interface becomes an access point
+    if probe_listener {
+        start_probe_listener(nl80211, &interface, Arc::clone(&probe_log)).await;
+    } else {
+        probe_log.set_unavailable("The probe listener is not enabled".to_owned());
+    }
+
+    run_web_loop(glib_sender, scan_coordinator, probe_log, port).await
+}
+
+/// Serves the web API from the scenario, without wireless hardware or a connection manager.
+async fn simulate(opts: Opts, scenario: Scenario) -> Result<()> {
+    let scan_coordinator = ScanCoordinator::simulated(
+        &scenario,
+        opts.interface.as_deref().unwrap_or(DEFAULT_INTERFACE),
+        Duration::from_secs(opts.scan_cache_ttl),
+        opts.quality_model,
+    );
+
+    // Nothing is captured, but the endpoint stays available
+    let probe_log = Arc::new(ProbeLog::new(opts.probe_log_size));
+    let port = opts.port;
+
+    // Waiting is simulated by the network being in range or not
+    let known_network = opts
+        .wait_for_ssid
+        .as_deref()
+        .map(Ssid::from)
+        .filter(|ssid| scenario.station(ssid).is_some());
+
     let (glib_sender, glib_receiver) = create_channel();
 
     let (initialized_sender, initialized_receiver) = oneshot::channel();
 
     thread::spawn(move || {
-        run_network_manager_loop(opts, initialized_sender, glib_receiver);
+        run_simulation_loop(
+            opts,
+            scenario,
+            known_network,
+            initialized_sender,
+            glib_receiver,
+        );
     });
 
     receive_network_initialized(initialized_receiver).await?;
 
-    run_web_loop(glib_sender).await
+    run_web_loop(glib_sender, scan_coordinator, probe_log, port).await
+}
+
+async fn apply_opts_power_settings(
+    nl80211: Option<&Nl80211>,
+    opts: &Opts,
+) -> Result<Option<PreviousPowerSettings>> {
+    let settings = PowerSettings {
+        power_save: opts
+            .power_save
+            .map(|power_save| power_save == PowerSave::On),
+        tx_power_dbm: opts.tx_power,
+    };
+
+    if settings.power_save.is_none() && settings.tx_power_dbm.is_none() {
+        return Ok(None);
+    }
+
+    let nl80211 = nl80211.context("Power settings require nl80211")?;
+    let interface = opts
+        .interface
+        .as_deref()
+        .context("Power settings require the interface to be set with --interface")?;
+
+    apply_power_settings(nl80211, interface, settings)
+        .await
+        .map(Some)
+        .context("Failed to apply power settings")
+}
+
+async fn start_probe_listener(
+    nl80211: Option<&Nl80211>,
+    interface: &str,
+    probe_log: Arc<ProbeLog>,
+) {
+    let Some(nl80211) = nl80211 else {
+        println!("Failed to start probe listener: nl80211 is not available");
+        probe_log.set_unavailable("nl80211 is not available".to_owned());
+        return;
+    };
+
+    match ProbeListener::new(nl80211, interface).await {
+        Ok(listener) => {
+            tokio::spawn(async move {
+                if let Err(err) = run_probe_listener(listener, &probe_log).await {
+                    println!("Probe listener stopped: {err:#}");
+                    probe_log.set_unavailable(format!("Probe listener stopped: {err:#}"));
+                }
+            });
+        }
+        Err(err) => {
+            println!("Failed to start probe listener: {err:#}");
+            probe_log.set_unavailable(format!("{err:#}"));
+        }
+    }
 }
 
+/// Returns the interface of the device the network thread manages.
 async fn receive_network_initialized(
-    initialized_receiver: oneshot::Receiver<Result<()>>,
-) -> Result<()> {
+    initialized_receiver: oneshot::Receiver<Result<String>>,
+) -> Result<String> {
     let received = initialized_receiver
         .await
         .context("Failed to receive network initialization response");
@@ -2,36 +2,56 @@ use anyhow::{anyhow, bail, Context, Result};
 
 use tokio::sync::oneshot;
 
-use glib::translate::FromGlib;
 use glib::{MainContext, MainLoop};
 
 use alloc::rc::Rc;
-use core::cell::RefCell;
-use core::future::Future;
+use core::cell::{Cell, RefCell};
+use core::future::{ready, Future};
+use core::time::Duration;
 use std::collections::HashSet;
+use std::time::Instant;
 
 use serde::Serialize;
 
-use crate::opts::Opts;
+use nl80211::scan::Bss;
+use nl80211::Ssid;
 
-use nm::{
-    utils_get_timestamp_msec, AccessPoint, ActiveConnection, ActiveConnectionExt,
-    ActiveConnectionState, Cast, Client, Connection, ConnectionExt, Device, DeviceExt, DeviceState,
-    DeviceType, DeviceWifi, IPAddress, SettingConnection, SettingIP4Config, SettingIPConfigExt,
-    SettingWireless, SettingWirelessSecurity, SimpleConnection, SETTING_IP4_CONFIG_METHOD_MANUAL,
-    SETTING_WIRELESS_MODE_AP, SETTING_WIRELESS_SETTING_NAME,
+use crate::backend::iwd::IwdBackend;
+use crate::backend::native::NativeBackend;
+use crate::backend::nm::NmBackend;
+use crate::backend::simulated::SimulatedBackend;
+use crate::backend::{
+    resolve_connection_manager, AccessPoint, ActivationState, Backend, ConnectionType,
+    Connectivity, FailureReason, Profile, ProfileMode, ProfileSettings, ProfileUpdate,
 };
-
-const WIFI_SCAN_TIMEOUT_SECONDS: usize = 45;
+use crate::error::ErrorKind;
+use crate::opts::{check_portal_security, ClonedMac, ConnectionManager, Opts};
+use crate::quality::QualityModel;
+use crate::scenario::Scenario;
 
 type TokioResponder = oneshot::Sender<Result<CommandResponse>>;
 
+const CONNECTIVITY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 pub enum Command {
     CheckConnectivity,
-    ListConnections,
+    ListConnections {
+        connection_type: Option<ConnectionType>,
+    },
     ListWiFiNetworks,
     Stop,
+    Connect {
+        ssid: Ssid,
+        passphrase: Option<String>,
+    },
+    DeleteConnection {
+        uuid: String,
+    },
+    UpdateConnection {
+        uuid: String,
+        update: ProfileUpdate,
+    },
 }
 
 pub struct CommandRequest {
@@ -47,18 +67,21 @@ impl CommandRequest {
 
 #[derive(Debug)]
 pub enum CommandResponse {
-    CheckConnectivity(Connectivity),
+    CheckConnectivity(ConnectivityStatus),
     ListConnections(Vec<ConnectionDetails>),
     ListWiFiNetworks(Vec<Station>),
     Stop(Stop),
+    Connect(Connect),
+    DeleteConnection(Delete),
+    UpdateConnection(ConnectionDetails),
 }
 
 #[derive(Serialize, Debug)]
-pub struct Connectivity {
+pub struct ConnectivityStatus {
     pub connectivity: String,
 }
 
-impl Connectivity {
+impl ConnectivityStatus {
     const fn new(connectivity: String) -> Self {
         Self { connectivity }
     }
@@ -68,35 +91,71 @@ impl Connectivity {
 pub struct ConnectionDetails {
     pub id: String,
     pub uuid: String,
+    #[serde(rename = "type")]
+    pub connection_type: ConnectionType,
+    #[serde(flatten)]
+    pub ssid: Option<Ssid>,
+    pub autoconnect: bool,
+    pub autoconnect_priority: i32,
+    /// Seconds since the Unix epoch, when reported by the connection manager
+    pub last_used: Option<u64>,
+    pub active: bool,
 }
 
-impl ConnectionDetails {
-    const fn new(id: String, uuid: String) -> Self {
-        Self { id, uuid }
+impl From<Profile> for ConnectionDetails {
+    fn from(profile: Profile) -> Self {
+        Self {
+            id: profile.id,
+            uuid: profile.uuid,
+            connection_type: profile.connection_type,
+            ssid: profile.ssid,
+            autoconnect: profile.autoconnect,
+            autoconnect_priority: profile.autoconnect_priority,
+            last_used: profile.last_used,
+            active: profile.active,
+        }
     }
 }
 
 #[derive(Serialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Station {
-    pub ssid: String,
+    #[serde(flatten)]
+    pub ssid: Ssid,
     pub quality: u8,
+    pub signal_dbm: Option<i32>,
+    pub associated: bool,
 }
 
 impl Station {
-    const fn new(ssid: String, quality: u8) -> Self {
-        Self { ssid, quality }
+    const fn new(ssid: Ssid, quality: u8) -> Self {
+        Self {
+            ssid,
+            quality,
+            signal_dbm: None,
+            associated: false,
+        }
     }
-}
 
-impl TryFrom<&AccessPoint> for Station {
-    type Error = anyhow::Error;
-
-    fn try_from(ap: &AccessPoint) -> Result<Self, Self::Error> {
-        if let Some(ssid) = ssid_to_str(ap.ssid().as_deref()) {
-            Ok(Self::new(ssid.to_owned(), ap.strength()))
-        } else {
-            bail!("SSID not a string")
+    /// Hidden networks are skipped, as they cannot be connected to by name.
+    pub fn from_bss(bss: Bss, quality_model: QualityModel) -> Option<Self> {
+        if bss.ssid.is_empty() {
+            return None;
         }
+
+        Some(Self {
+            ssid: bss.ssid,
+            quality: quality_model.quality(bss.signal_dbm),
+            signal_dbm: Some(bss.signal_dbm),
+            associated: bss.associated,
+        })
+    }
+
+    /// Connection managers only report a strength percentage, so no raw signal level is
+    /// available.
+    fn from_access_point(ap: &AccessPoint, quality_model: QualityModel) -> Option<Self> {
+        let ssid = ap.ssid.clone()?;
+        let quality = quality_model.quality_from_nm_strength(ap.strength);
+        Some(Self::new(ssid, quality))
     }
 }
 
@@ -113,83 +172,323 @@ impl Stop {
     }
 }
 
-#[allow(dead_code)]
-struct NetworkState {
-    client: Client,
-    device: DeviceWifi,
+#[derive(Serialize, Debug)]
+pub struct Connect {
+    pub connect: String,
+    pub connectivity: VerifiedConnectivity,
+}
+
+impl Connect {
+    fn new(status: &str, connectivity: VerifiedConnectivity) -> Self {
+        Self {
+            connect: status.to_owned(),
+            connectivity,
+        }
+    }
+}
+
+/// Connectivity a new connection was kept with.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum VerifiedConnectivity {
+    Full,
+    /// The backend cannot check internet access, as iwd or Network Manager with checks
+    /// disabled, so the connection is kept without knowing whether it reaches the internet
+    Unverified,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Delete {
+    pub delete: String,
+}
+
+impl Delete {
+    fn new(status: &str) -> Self {
+        Self {
+            delete: status.to_owned(),
+        }
+    }
+}
+
+struct NetworkState<B: Backend> {
+    backend: B,
+    device: B::Device,
     stations: Vec<Station>,
-    portal_connection: Option<ActiveConnection>,
+    /// Shared with connect commands, which restore the portal if connecting fails
+    portal_connection: Rc<RefCell<Option<B::Connection>>>,
+    connect_config: ConnectConfig,
+    /// Set while a connect command runs, which owns the device until it completes
+    connecting: Rc<Cell<bool>>,
 }
 
-impl NetworkState {
+impl<B: Backend> NetworkState<B> {
     fn new(
-        client: Client,
-        device: DeviceWifi,
+        backend: B,
+        device: B::Device,
         stations: Vec<Station>,
-        portal_connection: Option<ActiveConnection>,
+        portal_connection: Option<B::Connection>,
+        connect_config: ConnectConfig,
     ) -> Self {
         Self {
-            client,
+            backend,
             device,
             stations,
-            portal_connection,
+            portal_connection: Rc::new(RefCell::new(portal_connection)),
+            connect_config,
+            connecting: Rc::new(Cell::new(false)),
         }
     }
+
+    /// Future of a connect command. Only one runs at a time, as it takes the portal and
+    /// restores it or the previous connection if connecting fails.
+    fn start_connect(
+        &self,
+        ssid: Ssid,
+        passphrase: Option<String>,
+    ) -> Result<impl Future<Output = Result<CommandResponse>> + 'static> {
+        let guard = ConnectGuard::acquire(&self.connecting)?;
+
+        let future = connect(
+            self.backend.clone(),
+            self.device.clone(),
+            Rc::clone(&self.portal_connection),
+            ssid,
+            passphrase,
+            self.connect_config.clone(),
+        );
+
+        Ok(async move {
+            let _guard = guard;
+            future.await
+        })
+    }
+
+    /// Refuses commands that change the active connection while a connect command runs.
+    fn ensure_not_connecting(&self) -> Result<()> {
+        if self.connecting.get() {
+            return Err(connecting_conflict());
+        }
+
+        Ok(())
+    }
+}
+
+/// Marks a connect command as running until dropped.
+struct ConnectGuard(Rc<Cell<bool>>);
+
+impl ConnectGuard {
+    fn acquire(connecting: &Rc<Cell<bool>>) -> Result<Self> {
+        if connecting.replace(true) {
+            return Err(connecting_conflict());
+        }
+
+        Ok(Self(Rc::clone(connecting)))
+    }
+}
+
+impl Drop for ConnectGuard {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+fn connecting_conflict() -> anyhow::Error {
+    anyhow::Error::new(ErrorKind::Conflict).context("Another connection attempt is in progress")
+}
+
+/// Settings of connect commands, fixed at startup.
+#[derive(Debug, Clone)]
+struct ConnectConfig {
+    cloned_mac: Option<ClonedMac>,
+    /// Captive portal to restore if connecting fails
+    portal: ProfileSettings,
+    /// Time for a new connection to reach full connectivity
+    timeout: Duration,
+}
+
+/// What was active on the device before connecting, to restore if connecting fails.
+#[derive(Debug)]
+enum Snapshot {
+    Portal,
+    /// Client connection by profile UUID
+    Connection(String),
+    Disconnected,
 }
 
 pub fn create_channel() -> (glib::Sender<CommandRequest>, glib::Receiver<CommandRequest>) {
     MainContext::channel(glib::PRIORITY_DEFAULT)
 }
 
+/// Sends the interface of the managed device once the network is initialized, as the connection
+/// manager picks the device if it is not set.
 pub fn run_network_manager_loop(
     opts: Opts,
-    initialized_sender: oneshot::Sender<Result<()>>,
+    known_network: Option<Ssid>,
+    initialized_sender: oneshot::Sender<Result<String>>,
+    glib_receiver: glib::Receiver<CommandRequest>,
+) {
+    let context = MainContext::new();
+
+    context
+        .with_thread_default(|| {
+            let resolved = context
+                .block_on(resolve_connection_manager(opts.connection_manager))
+                .and_then(|connection_manager| {
+                    check_portal_security(connection_manager, opts.password.is_some())
+                        .map(|()| connection_manager)
+                });
+
+            let connection_manager = match resolved {
+                Ok(connection_manager) => connection_manager,
+                Err(err) => {
+                    initialized_sender.send(Err(err)).ok();
+                    return;
+                }
+            };
+
+            println!("Connection manager: {connection_manager:?}");
+
+            match connection_manager {
+                ConnectionManager::Iwd => run_backend_loop(
+                    &context,
+                    IwdBackend::new(),
+                    opts,
+                    known_network,
+                    initialized_sender,
+                    glib_receiver,
+                ),
+                ConnectionManager::Native => run_backend_loop(
+                    &context,
+                    ready(NativeBackend::new()),
+                    opts,
+                    known_network,
+                    initialized_sender,
+                    glib_receiver,
+                ),
+                ConnectionManager::Auto | ConnectionManager::NetworkManager => run_backend_loop(
+                    &context,
+                    NmBackend::new(),
+                    opts,
+                    known_network,
+                    initialized_sender,
+                    glib_receiver,
+                ),
+            }
+        })
+        .expect("Main context is owned already by another thread");
+}
+
+/// Runs the network thread with a simulated backend instead of a connection manager.
+pub fn run_simulation_loop(
+    opts: Opts,
+    scenario: Scenario,
+    known_network: Option<Ssid>,
+    initialized_sender: oneshot::Sender<Result<String>>,
     glib_receiver: glib::Receiver<CommandRequest>,
 ) {
     let context = MainContext::new();
-    let loop_ = MainLoop::new(Some(&context), false);
 
     context
         .with_thread_default(|| {
-            let state = context
-                .block_on(init_network_respond(opts, initialized_sender))
-                .expect("Network not initialized");
-
-            glib_receiver.attach(None, move |command_request| {
-                let CommandRequest { responder, command } = command_request;
-                let _ = &state;
-                match command {
-                    Command::CheckConnectivity => {
-                        spawn(responder, check_connectivity(state.client.clone()));
-                    }
-                    Command::ListConnections => {
-                        respond(responder, Ok(list_connections(&state.client)));
-                    }
-                    Command::ListWiFiNetworks => {
-                        respond(responder, Ok(list_wifi_networks(state.stations.clone())));
-                    }
-                    Command::Stop => {
-                        spawn(
-                            responder,
-                            stop(state.client.clone(), state.portal_connection.clone()),
-                        );
-                    }
-                };
-                glib::Continue(true)
-            });
-
-            loop_.run();
+            println!("Simulating scenario");
+
+            run_backend_loop(
+                &context,
+                ready(Ok(SimulatedBackend::new(scenario))),
+                opts,
+                known_network,
+                initialized_sender,
+                glib_receiver,
+            );
         })
         .expect("Main context is owned already by another thread");
 }
 
-async fn init_network_respond(
+fn run_backend_loop<B: Backend>(
+    context: &MainContext,
+    backend: impl Future<Output = Result<B>>,
     opts: Opts,
-    initialized_sender: oneshot::Sender<Result<()>>,
-) -> Option<NetworkState> {
-    match init_network(opts).await {
+    known_network: Option<Ssid>,
+    initialized_sender: oneshot::Sender<Result<String>>,
+    glib_receiver: glib::Receiver<CommandRequest>,
+) {
+    let loop_ = MainLoop::new(Some(context), false);
+
+    let state = context
+        .block_on(init_network_respond(
+            backend,
+            opts,
+            known_network,
+            initialized_sender,
+        ))
+        .expect("Network not initialized");
+
+    glib_receiver.attach(None, move |command_request| {
+        let CommandRequest { responder, command } = command_request;
+        let _ = &state;
+        match command {
+            Command::CheckConnectivity => {
+                spawn(responder, check_connectivity(state.backend.clone()));
+            }
+            Command::ListConnections { connection_type } => {
+                spawn(
+                    responder,
+                    list_connections(state.backend.clone(), connection_type),
+                );
+            }
+            Command::ListWiFiNetworks => {
+                respond(responder, Ok(list_wifi_networks(state.stations.clone())));
+            }
+            Command::Stop => match state.ensure_not_connecting() {
+                Ok(()) => spawn(
+                    responder,
+                    stop(state.backend.clone(), state.portal_connection.take()),
+                ),
+                Err(err) => respond(responder, Err(err)),
+            },
+            Command::Connect { ssid, passphrase } => match state.start_connect(ssid, passphrase) {
+                Ok(future) => spawn(responder, future),
+                Err(err) => respond(responder, Err(err)),
+            },
+            Command::DeleteConnection { uuid } => match state.ensure_not_connecting() {
+                Ok(()) => spawn(
+                    responder,
+                    delete_connection(
+                        state.backend.clone(),
+                        state.portal_connection.borrow().is_some(),
+                        uuid,
+                    ),
+                ),
+                Err(err) => respond(responder, Err(err)),
+            },
+            Command::UpdateConnection { uuid, update } => {
+                spawn(
+                    responder,
+                    update_connection(state.backend.clone(), uuid, update),
+                );
+            }
+        };
+        glib::Continue(true)
+    });
+
+    loop_.run();
+}
+
+async fn init_network_respond<B: Backend>(
+    backend: impl Future<Output = Result<B>>,
+    opts: Opts,
+    known_network: Option<Ssid>,
+    initialized_sender: oneshot::Sender<Result<String>>,
+) -> Option<NetworkState<B>> {
+    let result = match backend.await {
+        Ok(backend) => init_network(backend, opts, known_network).await,
+        Err(err) => Err(err),
+    };
+
+    match result {
         Ok(state) => {
-            initialized_sender.send(Ok(())).ok();
+            let interface = state.backend.device_interface(&state.device);
+            initialized_sender.send(Ok(interface)).ok();
             Some(state)
         }
         Err(err) => {
@@ -199,23 +498,49 @@ async fn init_network_respond(
     }
 }
 
-async fn init_network(opts: Opts) -> Result<NetworkState> {
-    let client = create_client().await?;
-
-    delete_exising_wifi_connect_ap_profile(&client, &opts.ssid).await?;
+async fn init_network<B: Backend>(
+    backend: B,
+    opts: Opts,
+    known_network: Option<Ssid>,
+) -> Result<NetworkState<B>> {
+    delete_exising_wifi_connect_ap_profile(&backend, &opts.ssid).await?;
 
-    let device = find_device(&client, opts.interface.as_deref())?;
+    let device = backend.find_device(opts.interface.as_deref()).await?;
 
-    let interface = get_wifi_device_interface(&device);
+    let interface = backend.device_interface(&device);
 
     println!("Interface: {interface}");
 
-    scan_wifi(&device).await?;
+    println!("Scanning for networks...");
 
-    let stations = get_nearby_stations(&device);
+    backend.scan(&device).await?;
+
+    let stations = get_nearby_stations(&backend.access_points(&device).await?, opts.quality_model);
+
+    let connect_config = ConnectConfig {
+        cloned_mac: opts.cloned_mac,
+        portal: portal_settings(&backend, &device, &opts),
+        timeout: Duration::from_secs(opts.connect_timeout),
+    };
+
+    if let Some(ref ssid) = known_network {
+        match connect_known_network(&backend, &device, ssid).await {
+            Ok(()) => {
+                println!("Connected to {ssid}");
+                return Ok(NetworkState::new(
+                    backend,
+                    device,
+                    stations,
+                    None,
+                    connect_config,
+                ));
+            }
+            Err(err) => println!("Failed to connect to {ssid}, starting captive portal: {err:#}"),
+        }
+    }
 
     let portal_connection = Some(
-        create_portal(&client, &device, &opts)
+        create_portal(&backend, &device, &connect_config.portal)
             .await
             .context("Failed to create captive portal")?,
     );
@@ -223,10 +548,11 @@ async fn init_network(opts: Opts) -> Result<NetworkState> {
     println!("Network initilized");
 
     Ok(NetworkState::new(
-        client,
+        backend,
         device,
         stations,
         portal_connection,
+        connect_config,
     ))
 }
 
@@ -250,80 +576,281 @@ fn respond(responder: TokioResponder, response: Result<CommandResponse>) {
     let _res = responder.send(response);
 }
 
-async fn check_connectivity(client: Client) -> Result<CommandResponse> {
-    let connectivity = client
-        .check_connectivity_future()
-        .await
-        .context("Failed to execute check connectivity")?;
+async fn check_connectivity<B: Backend>(backend: B) -> Result<CommandResponse> {
+    let connectivity = backend.check_connectivity().await?;
 
-    Ok(CommandResponse::CheckConnectivity(Connectivity::new(
+    Ok(CommandResponse::CheckConnectivity(ConnectivityStatus::new(
         connectivity.to_string(),
     )))
 }
 
-fn list_connections(client: &Client) -> CommandResponse {
-    let all_connections: Vec<_> = client
-        .connections()
+async fn list_connections<B: Backend>(
+    backend: B,
+    connection_type: Option<ConnectionType>,
+) -> Result<CommandResponse> {
+    let connections = backend
+        .profiles()
+        .await?
         .into_iter()
-        .map(glib::Cast::upcast::<Connection>)
+        .filter(|profile| connection_type.is_none_or(|kind| profile.connection_type == kind))
+        .map(ConnectionDetails::from)
         .collect();
 
-    let mut connections = Vec::new();
+    Ok(CommandResponse::ListConnections(connections))
+}
 
-    for connection in all_connections {
-        if let Some(setting_connection) = connection.setting_connection() {
-            if let Some(id) = setting_connection.id() {
-                if let Some(uuid) = setting_connection.uuid() {
-                    connections.push(ConnectionDetails::new(id.to_string(), uuid.to_string()));
-                }
-            }
-        }
+async fn find_profile<B: Backend>(backend: &B, uuid: &str) -> Result<Profile> {
+    backend
+        .profiles()
+        .await?
+        .into_iter()
+        .find(|profile| profile.uuid == uuid)
+        .ok_or_else(|| {
+            anyhow::Error::new(ErrorKind::NotFound)
+                .context(format!("Failed to find connection profile {uuid}"))
+        })
+}
+
+/// The profile of the running portal is refused, as stopping the portal is done with the stop
+/// command.
+async fn delete_connection<B: Backend>(
+    backend: B,
+    portal_running: bool,
+    uuid: String,
+) -> Result<CommandResponse> {
+    let profile = find_profile(&backend, &uuid).await?;
+
+    if portal_running && profile.access_point && profile.active {
+        return Err(anyhow::Error::new(ErrorKind::Conflict)
+            .context("The portal connection profile cannot be deleted"));
     }
 
-    CommandResponse::ListConnections(connections)
+    backend.delete_profile(&uuid).await?;
+
+    Ok(CommandResponse::DeleteConnection(Delete::new("ok")))
+}
+
+async fn update_connection<B: Backend>(
+    backend: B,
+    uuid: String,
+    update: ProfileUpdate,
+) -> Result<CommandResponse> {
+    let profile = find_profile(&backend, &uuid).await?;
+
+    if update.passphrase.is_some() && !profile.is_wifi() {
+        return Err(anyhow::Error::new(ErrorKind::InvalidInput)
+            .context("Passphrases can only be set on WiFi connection profiles"));
+    }
+
+    backend.update_profile(&uuid, &update).await?;
+
+    let profile = find_profile(&backend, &uuid).await?;
+
+    Ok(CommandResponse::UpdateConnection(profile.into()))
 }
 
 const fn list_wifi_networks(stations: Vec<Station>) -> CommandResponse {
     CommandResponse::ListWiFiNetworks(stations)
 }
 
-async fn stop(
-    client: Client,
-    portal_connection: Option<ActiveConnection>,
+async fn stop<B: Backend>(
+    backend: B,
+    portal_connection: Option<B::Connection>,
 ) -> Result<CommandResponse> {
     if let Some(active_connection) = portal_connection {
-        stop_portal(&client, &active_connection).await?;
+        stop_portal(&backend, &active_connection).await?;
     }
 
     Ok(CommandResponse::Stop(Stop::new("ok")))
 }
 
-async fn scan_wifi(device: &DeviceWifi) -> Result<()> {
-    println!("Scanning for networks...");
+/// Connects transactionally: the new profile is kept only if the connection reaches full
+/// connectivity within the timeout. Otherwise it is deleted and the portal or the previous
+/// connection is restored, so that the device stays reachable.
+async fn connect<B: Backend>(
+    backend: B,
+    device: B::Device,
+    portal_connection: Rc<RefCell<Option<B::Connection>>>,
+    ssid: Ssid,
+    passphrase: Option<String>,
+    config: ConnectConfig,
+) -> Result<CommandResponse> {
+    // Checked before stopping the portal, which could not be restored by joining the network
+    if !backend.can_join() {
+        return Err(anyhow::Error::new(ErrorKind::Unavailable)
+            .context("Joining networks is not supported by the connection manager"));
+    }
 
-    let prescan = utils_get_timestamp_msec();
+    let portal = portal_connection.take();
+
+    let snapshot = match portal {
+        Some(_) => Snapshot::Portal,
+        None => match backend.active_profile(&device).await? {
+            Some(uuid) => Snapshot::Connection(uuid),
+            None => Snapshot::Disconnected,
+        },
+    };
+
+    // The device cannot be an access point and a client at the same time
+    if let Some(ref active_connection) = portal {
+        if let Err(err) = stop_portal(&backend, active_connection).await {
+            *portal_connection.borrow_mut() = portal;
+            return Err(err);
+        }
+    }
 
-    device
-        .request_scan_future()
-        .await
-        .context("Failed to request WiFi scan")?;
+    let settings = ProfileSettings {
+        ssid: ssid.clone(),
+        passphrase,
+        cloned_mac: config.cloned_mac,
+        mode: ProfileMode::Client,
+    };
 
-    for _ in 0..WIFI_SCAN_TIMEOUT_SECONDS {
-        if prescan < device.last_scan() {
-            break;
+    let err = match activate_verified(&backend, &device, &settings, config.timeout).await {
+        Ok(connectivity) => {
+            return Ok(CommandResponse::Connect(Connect::new("ok", connectivity)));
         }
+        Err(err) => err,
+    };
+
+    println!("Failed to connect to {ssid}, restoring previous state: {err:#}");
+
+    restore(
+        &backend,
+        &device,
+        &portal_connection,
+        snapshot,
+        &config.portal,
+    )
+    .await
+    .context("Failed to restore previous state after failing to connect")?;
+
+    Err(err)
+}
 
-        glib::timeout_future_seconds(1).await;
+/// Activates a new profile and waits for full connectivity, deleting the profile if either
+/// fails or the activation cannot be followed. Backends such as iwd activate the saved profile
+/// of the network instead of adding one, so only profiles that did not exist before are deleted.
+async fn activate_verified<B: Backend>(
+    backend: &B,
+    device: &B::Device,
+    settings: &ProfileSettings,
+    timeout: Duration,
+) -> Result<VerifiedConnectivity> {
+    let existing = backend
+        .profiles()
+        .await?
+        .into_iter()
+        .map(|profile| profile.uuid)
+        .collect::<HashSet<_>>();
+
+    let active_connection = backend.add_and_activate(settings, device).await?;
+
+    let result = match backend.wait_for_state(&active_connection).await {
+        Ok(ActivationState::Activated) => wait_for_connectivity(backend, timeout).await,
+        Ok(ActivationState::Deactivated(reason)) => Err(activation_error(
+            reason,
+            format!("Failed to activate connection to {}", settings.ssid),
+        )),
+        Err(err) => {
+            // The activation may still be running, so it is stopped before deleting its profile
+            if let Err(deactivate_err) = backend.deactivate(&active_connection).await {
+                println!(
+                    "Failed to deactivate connection to {}: {deactivate_err:#}",
+                    settings.ssid
+                );
+            }
+            Err(err)
+        }
+    };
+
+    if result.is_err() {
+        delete_created_profiles(backend, &settings.ssid, &existing)
+            .await
+            .context("Failed to delete connection profile after failing to connect")?;
+    }
+
+    result
+}
+
+async fn delete_created_profiles<B: Backend>(
+    backend: &B,
+    ssid: &Ssid,
+    existing: &HashSet<String>,
+) -> Result<()> {
+    for profile in backend.profiles().await? {
+        if profile.is_wifi()
+            && !profile.access_point
+            && profile.ssid.as_ref() == Some(ssid)
+            && !existing.contains(&profile.uuid)
+        {
+            backend.delete_profile(&profile.uuid).await?;
+        }
     }
 
     Ok(())
 }
 
-fn get_nearby_stations(device: &DeviceWifi) -> Vec<Station> {
-    let mut stations = device
-        .access_points()
+/// Polls the connectivity until it is full. Connectivity that cannot be checked, as with iwd
+/// or with checks disabled in Network Manager, is reported as unverified instead.
+async fn wait_for_connectivity<B: Backend>(
+    backend: &B,
+    timeout: Duration,
+) -> Result<VerifiedConnectivity> {
+    let deadline = Instant::now()
+        .checked_add(timeout)
+        .context("Connect timeout out of range")?;
+
+    loop {
+        match backend.check_connectivity().await? {
+            Connectivity::Full => return Ok(VerifiedConnectivity::Full),
+            Connectivity::Unknown => return Ok(VerifiedConnectivity::Unverified),
+            connectivity => {
+                if Instant::now() >= deadline {
+                    bail!(
+                        "No full connectivity within {} seconds, connectivity is {connectivity}",
+                        timeout.as_secs()
+                    );
+                }
+            }
+        }
+
+        glib::timeout_future(CONNECTIVITY_POLL_INTERVAL).await;
+    }
+}
+
+/// Brings back what was active before connecting. The portal is started if the previous
+/// connection cannot be restored.
+async fn restore<B: Backend>(
+    backend: &B,
+    device: &B::Device,
+    portal_connection: &RefCell<Option<B::Connection>>,
+    snapshot: Snapshot,
+    portal: &ProfileSettings,
+) -> Result<()> {
+    match snapshot {
+        Snapshot::Disconnected => return Ok(()),
+        Snapshot::Portal => {}
+        Snapshot::Connection(uuid) => match activate_saved(backend, device, &uuid).await {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                println!("Failed to restore previous connection, starting captive portal: {err:#}");
+            }
+        },
+    }
+
+    let connection = create_portal(backend, device, portal)
+        .await
+        .context("Failed to create captive portal")?;
+    *portal_connection.borrow_mut() = Some(connection);
+
+    Ok(())
+}
+
+fn get_nearby_stations(access_points: &[AccessPoint], quality_model: QualityModel) -> Vec<Station> {
+    let mut stations = access_points
         .iter()
-        .filter_map(|ap| Station::try_from(ap).ok())
+        .filter_map(|ap| Station::from_access_point(ap, quality_model))
         .collect::<Vec<_>>();
 
     // Sort access points by signal strength first and then ssid
@@ -340,238 +867,582 @@ fn get_nearby_stations(device: &DeviceWifi) -> Vec<Station> {
     stations
 }
 
-fn ssid_to_str(ssid: Option<&[u8]>) -> Option<&str> {
-    // An access point SSID could be random bytes and not a UTF-8 encoded string
-    core::str::from_utf8(ssid.as_ref()?).ok()
-}
+async fn delete_exising_wifi_connect_ap_profile<B: Backend>(backend: &B, ssid: &str) -> Result<()> {
+    let ssid = Ssid::from(ssid);
 
-async fn create_client() -> Result<Client> {
-    let client = Client::new_future()
-        .await
-        .context("Failed to create NetworkManager client")?;
-
-    if !client.is_nm_running() {
-        return Err(anyhow!("NetworkManager daemon is not running"));
-    }
-
-    Ok(client)
-}
-
-async fn delete_exising_wifi_connect_ap_profile(client: &Client, ssid: &str) -> Result<()> {
-    let connections = client.connections();
-
-    for connection in connections {
-        let c = connection.clone().upcast::<Connection>();
-        if is_access_point_connection(&c) && is_same_ssid(&c, ssid) {
+    for profile in backend.profiles().await? {
+        if profile.is_wifi() && profile.access_point && profile.ssid.as_ref() == Some(&ssid) {
             println!(
                 "Deleting already created by WiFi Connect access point connection profile: {ssid:?}",
             );
-            connection.delete_future().await?;
+            backend.delete_profile(&profile.uuid).await?;
         }
     }
 
     Ok(())
 }
 
-fn is_same_ssid(connection: &Connection, ssid: &str) -> bool {
-    connection_ssid_to_string(connection).as_deref() == Some(ssid)
-}
+async fn connect_known_network<B: Backend>(
+    backend: &B,
+    device: &B::Device,
+    ssid: &Ssid,
+) -> Result<()> {
+    let profile = backend
+        .profiles()
+        .await?
+        .into_iter()
+        .find(|profile| {
+            profile.is_wifi() && !profile.access_point && profile.ssid.as_ref() == Some(ssid)
+        })
+        .context("No saved connection profile")?;
 
-fn connection_ssid_to_string(connection: &Connection) -> Option<String> {
-    ssid_to_str(connection.setting_wireless()?.ssid().as_deref()).map(str::to_owned)
+    activate_saved(backend, device, &profile.uuid).await
 }
 
-fn is_access_point_connection(connection: &Connection) -> bool {
-    is_wifi_connection(connection) && is_access_point_mode(connection)
-}
+async fn activate_saved<B: Backend>(backend: &B, device: &B::Device, uuid: &str) -> Result<()> {
+    let active_connection = backend.activate_profile(uuid, device).await?;
 
-fn is_access_point_mode(connection: &Connection) -> bool {
-    if let Some(setting) = connection.setting_wireless() {
-        if let Some(mode) = setting.mode() {
-            return mode == *SETTING_WIRELESS_MODE_AP;
-        }
+    let state = backend.wait_for_state(&active_connection).await?;
+
+    if let ActivationState::Deactivated(reason) = state {
+        return Err(activation_error(
+            reason,
+            "Connection deactivated".to_owned(),
+        ));
     }
 
-    false
+    Ok(())
 }
 
-fn is_wifi_connection(connection: &Connection) -> bool {
-    if let Some(setting) = connection.setting_connection() {
-        if let Some(connection_type) = setting.connection_type() {
-            return connection_type == *SETTING_WIRELESS_SETTING_NAME;
-        }
+fn portal_settings<B: Backend>(backend: &B, device: &B::Device, opts: &Opts) -> ProfileSettings {
+    ProfileSettings {
+        ssid: Ssid::from(opts.ssid.as_str()),
+        passphrase: opts.password.clone(),
+        cloned_mac: opts.cloned_mac,
+        mode: ProfileMode::AccessPoint {
+            interface: backend.device_interface(device),
+            gateway: opts.gateway.clone(),
+        },
     }
-
-    false
 }
 
-pub fn find_device(client: &Client, interface: Option<&str>) -> Result<DeviceWifi> {
-    if let Some(iface) = interface {
-        get_exact_device(client, iface)
+async fn create_portal<B: Backend>(
+    backend: &B,
+    device: &B::Device,
+    settings: &ProfileSettings,
+) -> Result<B::Connection> {
+    let active_connection = backend.add_and_activate(settings, device).await?;
+
+    let state = backend.wait_for_state(&active_connection).await?;
+
+    if let ActivationState::Deactivated(reason) = state {
+        backend
+            .delete_connection_profile(&active_connection)
+            .await
+            .context("Failed to delete captive portal connection after failing to activate")?;
+        Err(activation_error(
+            reason,
+            "Failed to activate captive portal connection".to_owned(),
+        ))
     } else {
-        find_any_wifi_device(client)
+        Ok(active_connection)
     }
 }
 
-fn get_exact_device(client: &Client, interface: &str) -> Result<DeviceWifi> {
-    let device = client
-        .device_by_iface(interface)
-        .context(format!("Failed to find interface '{interface}'"))?;
-
-    if device.device_type() != DeviceType::Wifi {
-        bail!("Not a WiFi interface '{}'", interface);
+/// Error of an activation that ended deactivated, caused by the failure reason if known.
+fn activation_error(reason: Option<FailureReason>, message: String) -> anyhow::Error {
+    match reason {
+        Some(reason) => anyhow::Error::new(reason).context(message),
+        None => anyhow!(message),
     }
+}
 
-    if device.state() == DeviceState::Unmanaged {
-        bail!("Interface is not managed by NetworkManager '{}'", interface);
-    }
+async fn stop_portal<B: Backend>(backend: &B, active_connection: &B::Connection) -> Result<()> {
+    backend.deactivate(active_connection).await?;
 
-    Ok(device.downcast().expect("Cannot downcast to DeviceWifi"))
+    backend.wait_for_state(active_connection).await?;
+
+    backend
+        .delete_connection_profile(active_connection)
+        .await
+        .context("Failed to delete captive portal connection profile")?;
+
+    Ok(())
 }
 
-fn find_any_wifi_device(client: &Client) -> Result<DeviceWifi> {
-    for device in client.devices() {
-        if device.device_type() == DeviceType::Wifi && device.state() != DeviceState::Unmanaged {
-            return Ok(device.downcast().expect("Cannot downcast to DeviceWifi"));
-        }
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::indexing_slicing,
+    clippy::assertions_on_result_states
+)]
+mod tests {
+    use core::iter::once;
+
+    use clap::Parser;
+
+    use super::*;
+    use crate::backend::fake::FakeBackend;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::parse_from(once("wifi-connect").chain(args.iter().copied()))
     }
 
-    bail!("Failed to find a managed WiFi device")
-}
+    fn run<F: Future>(future: F) -> F::Output {
+        MainContext::new().block_on(future)
+    }
 
-async fn create_portal(
-    client: &Client,
-    device: &DeviceWifi,
-    opts: &Opts,
-) -> Result<ActiveConnection> {
-    let interface = get_wifi_device_interface(device);
+    fn profiles(backend: &FakeBackend) -> Vec<Profile> {
+        run(backend.profiles()).unwrap()
+    }
 
-    let connection = create_ap_connection(
-        interface.as_str(),
-        &opts.ssid,
-        &opts.gateway,
-        opts.password.as_deref(),
-    )?;
+    fn portal(backend: &FakeBackend) -> ProfileSettings {
+        portal_settings(backend, &"wlan0".to_owned(), &opts(&[]))
+    }
 
-    let active_connection = client
-        .add_and_activate_connection_future(Some(&connection), Some(device), None)
-        .await
-        .context("Failed to add and activate connection")?;
+    fn connect_to(
+        state: &NetworkState<FakeBackend>,
+        ssid: &str,
+        passphrase: Option<&str>,
+    ) -> Result<CommandResponse> {
+        run(state.start_connect(Ssid::from(ssid), passphrase.map(ToOwned::to_owned))?)
+    }
 
-    let state = finalize_active_connection_state(&active_connection).await?;
+    fn portal_profile(backend: &FakeBackend, state: &NetworkState<FakeBackend>) -> Profile {
+        let portal = state.portal_connection.borrow();
+        backend
+            .connection_profile(portal.as_ref().expect("No portal"))
+            .expect("No portal profile")
+    }
 
-    if state == ActiveConnectionState::Deactivated {
-        if let Some(remote_connection) = active_connection.connection() {
-            remote_connection
-                .delete_future()
-                .await
-                .context("Failed to delete captive portal connection after failing to activate")?;
+    fn connections(response: Result<CommandResponse>) -> Option<Vec<ConnectionDetails>> {
+        match response.unwrap() {
+            CommandResponse::ListConnections(connections) => Some(connections),
+            _ => None,
         }
-        Err(anyhow!("Failed to activate captive portal connection"))
-    } else {
-        Ok(active_connection)
     }
-}
 
-async fn stop_portal(client: &Client, active_connection: &ActiveConnection) -> Result<()> {
-    client
-        .deactivate_connection_future(active_connection)
-        .await?;
+    #[test]
+    fn lists_connections_by_type() {
+        let backend = FakeBackend::new()
+            .with_profile("home", "Home", false)
+            .with_ethernet_profile("wired", "Wired");
+
+        let all = connections(run(list_connections(backend.clone(), None))).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].ssid, Some(Ssid::from("Home")));
+        assert!(all[0].autoconnect);
+
+        let wired = connections(run(list_connections(
+            backend,
+            Some(ConnectionType::Ethernet),
+        )))
+        .unwrap();
+        assert_eq!(wired.len(), 1);
+        assert_eq!(wired[0].uuid, "wired");
+        assert_eq!(wired[0].connection_type, ConnectionType::Ethernet);
+        assert_eq!(wired[0].ssid, None);
+    }
 
-    finalize_active_connection_state(active_connection).await?;
+    #[test]
+    fn deletes_connection() {
+        let backend = FakeBackend::new()
+            .with_profile("home", "Home", false)
+            .with_profile("cafe", "Cafe", false);
 
-    if let Some(remote_connection) = active_connection.connection() {
-        remote_connection
-            .delete_future()
-            .await
-            .context("Failed to delete captive portal connection profile")?;
+        run(delete_connection(backend.clone(), false, "home".to_owned())).unwrap();
+
+        let uuids = profiles(&backend)
+            .into_iter()
+            .map(|profile| profile.uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(uuids, ["cafe"]);
+
+        let err = run(delete_connection(backend, false, "home".to_owned())).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::NotFound));
     }
 
-    Ok(())
-}
+    #[test]
+    fn refuses_deleting_running_portal() {
+        let backend = FakeBackend::new();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+        let uuid = portal_profile(&backend, &state).uuid;
 
-async fn finalize_active_connection_state(
-    active_connection: &ActiveConnection,
-) -> Result<ActiveConnectionState> {
-    println!("Monitoring connection state...");
+        let err = run(delete_connection(backend.clone(), true, uuid.clone())).unwrap_err();
 
-    let (sender, receiver) = oneshot::channel::<ActiveConnectionState>();
-    let sender_cell = Rc::new(RefCell::new(Some(sender)));
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::Conflict));
+        assert_eq!(backend.active(), [uuid]);
+    }
 
-    let handler_id = active_connection.connect_state_changed(move |_, state_u32, _| {
-        // SAFETY: conversion from u32 is guaranteed
-        let state = unsafe {
-            ActiveConnectionState::from_glib(
-                state_u32.try_into().expect("Unknown connection state"),
-            )
-        };
-        println!("Connection: {state:?}");
+    #[test]
+    fn updates_connection() {
+        let backend = FakeBackend::new()
+            .with_profile("home", "Home", false)
+            .with_ethernet_profile("wired", "Wired");
 
-        let exit = match state {
-            ActiveConnectionState::Activated => Some(ActiveConnectionState::Activated),
-            ActiveConnectionState::Deactivated => Some(ActiveConnectionState::Deactivated),
-            _ => None,
+        let update = ProfileUpdate {
+            passphrase: Some("secret".to_owned()),
+            autoconnect: Some(false),
+            autoconnect_priority: Some(10),
         };
-        if let Some(result) = exit {
-            if let Some(inner_sender) = sender_cell.borrow_mut().take() {
-                inner_sender.send(result).ok();
-            }
-        }
-    });
+        let response = run(update_connection(
+            backend.clone(),
+            "home".to_owned(),
+            update,
+        ))
+        .unwrap();
+
+        assert!(matches!(
+            response,
+            CommandResponse::UpdateConnection(ref details)
+                if !details.autoconnect && details.autoconnect_priority == 10
+        ));
+        assert_eq!(backend.passphrase("home").as_deref(), Some("secret"));
+
+        let update = ProfileUpdate {
+            passphrase: Some("secret".to_owned()),
+            ..ProfileUpdate::default()
+        };
+        let result = run(update_connection(
+            backend.clone(),
+            "wired".to_owned(),
+            update,
+        ));
+        assert_eq!(
+            result.unwrap_err().downcast_ref(),
+            Some(&ErrorKind::InvalidInput)
+        );
+        assert_eq!(backend.passphrase("wired"), None);
+    }
 
-    let state = receiver
-        .await
-        .context("Failed to receive active connection state change")?;
-
-    glib::signal_handler_disconnect(active_connection, handler_id);
-
-    Ok(state)
-}
-
-fn create_ap_connection(
-    interface: &str,
-    ssid: &str,
-    address: &str,
-    passphrase: Option<&str>,
-) -> Result<SimpleConnection> {
-    let connection = SimpleConnection::new();
-
-    let s_connection = SettingConnection::new();
-    s_connection.set_type(Some(SETTING_WIRELESS_SETTING_NAME));
-    s_connection.set_id(Some(ssid));
-    s_connection.set_autoconnect(false);
-    s_connection.set_interface_name(Some(interface));
-    connection.add_setting(s_connection);
-
-    let s_wireless = SettingWireless::new();
-    s_wireless.set_ssid(Some(&(ssid.as_bytes().into())));
-    s_wireless.set_band(Some("bg"));
-    s_wireless.set_hidden(false);
-    s_wireless.set_mode(Some(SETTING_WIRELESS_MODE_AP));
-    connection.add_setting(s_wireless);
-
-    if let Some(password) = passphrase {
-        let s_wireless_security = SettingWirelessSecurity::new();
-        s_wireless_security.set_key_mgmt(Some("wpa-psk"));
-        s_wireless_security.set_psk(Some(password));
-        connection.add_setting(s_wireless_security);
-    }
-
-    let s_ip4 = SettingIP4Config::new();
-    let ip_address =
-        IPAddress::new(libc::AF_INET, address, 24).context("Failed to parse gateway address")?;
-    s_ip4.add_address(&ip_address);
-    s_ip4.set_method(Some(SETTING_IP4_CONFIG_METHOD_MANUAL));
-    connection.add_setting(s_ip4);
-
-    Ok(connection)
-}
-
-fn get_wifi_device_interface(device: &DeviceWifi) -> String {
-    device
-        .clone()
-        .upcast::<Device>()
-        .iface()
-        .expect("No interface associated with device")
-        .to_string()
+    #[test]
+    fn init_network_starts_portal() {
+        let backend = FakeBackend::new()
+            .with_access_point(Some("Home"), 40)
+            .with_access_point(Some("Cafe"), 60)
+            .with_access_point(Some("Home"), 80)
+            .with_access_point(None, 90);
+
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+
+        assert_eq!(backend.scans(), 1);
+        let ssids = state
+            .stations
+            .iter()
+            .map(|station| station.ssid.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(ssids, ["Home", "Cafe"]);
+
+        let profile = portal_profile(&backend, &state);
+        assert!(profile.access_point);
+        assert_eq!(profile.ssid, Some(Ssid::from("WiFiConnect")));
+        assert_eq!(backend.active(), [profile.uuid]);
+    }
+
+    #[test]
+    fn init_network_replaces_stale_portal_profile() {
+        let backend = FakeBackend::new()
+            .with_profile("stale", "WiFiConnect", true)
+            .with_profile("other", "Other", true);
+
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+
+        let uuids = profiles(&backend)
+            .into_iter()
+            .map(|profile| profile.uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(uuids, ["other", &portal_profile(&backend, &state).uuid]);
+    }
+
+    #[test]
+    fn init_network_connects_known_network() {
+        let backend = FakeBackend::new().with_profile("home", "Home", false);
+
+        let state = run(init_network(
+            backend.clone(),
+            opts(&[]),
+            Some(Ssid::from("Home")),
+        ))
+        .unwrap();
+
+        assert!(state.portal_connection.borrow().is_none());
+        assert_eq!(backend.active(), ["home"]);
+    }
+
+    #[test]
+    fn init_network_falls_back_to_portal() {
+        let backend = FakeBackend::new().with_profile("home", "Home", false);
+        backend.fail_next_activation();
+
+        let state = run(init_network(
+            backend.clone(),
+            opts(&[]),
+            Some(Ssid::from("Home")),
+        ))
+        .unwrap();
+
+        assert_eq!(backend.active(), [portal_profile(&backend, &state).uuid]);
+    }
+
+    #[test]
+    fn init_network_uses_requested_interface() {
+        let backend = FakeBackend::new().with_device("wlan1", true, false);
+
+        let err = run(init_network(backend, opts(&["-i", "wlan1"]), None))
+            .err()
+            .unwrap();
+
+        assert!(err.to_string().contains("not managed"));
+    }
+
+    #[test]
+    fn init_network_fails_without_device() {
+        let backend = FakeBackend::new().without_devices();
+
+        assert!(run(init_network(backend, opts(&[]), None)).is_err());
+    }
+
+    #[test]
+    fn create_portal_deletes_failed_profile() {
+        let backend = FakeBackend::new();
+        backend.fail_next_activation();
+
+        let result = run(create_portal(
+            &backend,
+            &"wlan0".to_owned(),
+            &portal(&backend),
+        ));
+
+        assert!(result.is_err());
+        assert!(profiles(&backend).is_empty());
+        assert!(backend.active().is_empty());
+    }
+
+    #[test]
+    fn create_portal_reports_failure_reason() {
+        let backend = FakeBackend::new();
+        backend.fail_next_activation_with(FailureReason::SupplicantFailure);
+
+        let err = run(create_portal(
+            &backend,
+            &"wlan0".to_owned(),
+            &portal(&backend),
+        ))
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<FailureReason>(),
+            Some(&FailureReason::SupplicantFailure)
+        );
+    }
+
+    #[test]
+    fn stop_removes_portal() {
+        let backend = FakeBackend::new().with_profile("home", "Home", false);
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+
+        let response = run(stop(backend.clone(), state.portal_connection.take())).unwrap();
+
+        assert!(matches!(response, CommandResponse::Stop(_)));
+        assert!(backend.active().is_empty());
+        assert_eq!(profiles(&backend).len(), 1);
+    }
+
+    #[test]
+    fn stop_without_portal_succeeds() {
+        let backend = FakeBackend::new();
+
+        assert!(run(stop(backend, None)).is_ok());
+    }
+
+    #[test]
+    fn connect_replaces_portal() {
+        let backend = FakeBackend::new();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+
+        connect_to(&state, "Home", Some("secret")).unwrap();
+
+        let profiles = profiles(&backend);
+        assert_eq!(profiles.len(), 1);
+        assert!(!profiles[0].access_point);
+        assert_eq!(backend.active(), [profiles[0].uuid.clone()]);
+        assert!(state.portal_connection.borrow().is_none());
+    }
+
+    #[test]
+    fn connect_reports_failure_reason_and_restores_portal() {
+        let backend = FakeBackend::new();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+        backend.fail_next_activation_with(FailureReason::WrongPassword);
+
+        let err = connect_to(&state, "Home", Some("wrong")).err().unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<FailureReason>(),
+            Some(&FailureReason::WrongPassword)
+        );
+        assert!(
+            format!("{err:#}").ends_with("Failed to activate connection to Home: Wrong password")
+        );
+
+        let profile = portal_profile(&backend, &state);
+        assert!(profile.access_point);
+        assert_eq!(backend.active(), [profile.uuid.as_str()]);
+        assert_eq!(profiles(&backend), [profile]);
+    }
+
+    #[test]
+    fn refuses_overlapping_connects() {
+        let backend = FakeBackend::new();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+
+        let first = state
+            .start_connect(Ssid::from("Home"), Some("secret".to_owned()))
+            .unwrap();
+
+        let err = connect_to(&state, "Cafe", None).err().unwrap();
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::Conflict));
+        let err = state.ensure_not_connecting().unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::Conflict));
+
+        run(first).unwrap();
+
+        let profiles = profiles(&backend);
+        assert_eq!(profiles.len(), 1);
+        assert_eq!(profiles[0].ssid, Some(Ssid::from("Home")));
+        assert_eq!(backend.active(), [profiles[0].uuid.clone()]);
+        assert!(state.ensure_not_connecting().is_ok());
+    }
+
+    #[test]
+    fn connect_keeps_portal_without_joining_support() {
+        let backend = FakeBackend::new().portal_only();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+        let profile = portal_profile(&backend, &state);
+
+        let err = connect_to(&state, "Home", Some("secret")).err().unwrap();
+
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::Unavailable));
+        assert_eq!(backend.active(), [profile.uuid.as_str()]);
+        assert_eq!(profiles(&backend), [profile]);
+    }
+
+    #[test]
+    fn connect_rolls_back_if_activation_cannot_be_followed() {
+        let backend = FakeBackend::new();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+        backend.fail_next_wait_for_state();
+
+        let err = connect_to(&state, "Home", Some("secret")).err().unwrap();
+
+        assert!(err.to_string().contains("Lost track"));
+        let profile = portal_profile(&backend, &state);
+        assert_eq!(backend.active(), [profile.uuid.as_str()]);
+        assert_eq!(profiles(&backend), [profile]);
+    }
+
+    #[test]
+    fn connect_rolls_back_without_full_connectivity() {
+        let backend = FakeBackend::new().with_connectivity(Connectivity::Limited);
+        let state = run(init_network(
+            backend.clone(),
+            opts(&["--connect-timeout", "0"]),
+            None,
+        ))
+        .unwrap();
+
+        let err = connect_to(&state, "Home", Some("secret")).err().unwrap();
+
+        assert!(err.to_string().contains("No full connectivity"));
+        let profile = portal_profile(&backend, &state);
+        assert_eq!(backend.active(), [profile.uuid.as_str()]);
+        assert_eq!(profiles(&backend), [profile]);
+    }
+
+    #[test]
+    fn connect_restores_previous_connection() {
+        let backend = FakeBackend::new().with_profile("home", "Home", false);
+        let state = run(init_network(
+            backend.clone(),
+            opts(&[]),
+            Some(Ssid::from("Home")),
+        ))
+        .unwrap();
+        backend.fail_next_activation();
+
+        assert!(connect_to(&state, "Cafe", None).is_err());
+
+        assert_eq!(profiles(&backend).len(), 1);
+        assert_eq!(backend.active(), ["home"]);
+        assert!(state.portal_connection.borrow().is_none());
+    }
+
+    #[test]
+    fn connect_falls_back_to_portal_if_previous_connection_fails() {
+        let backend = FakeBackend::new().with_profile("home", "Home", false);
+        let state = run(init_network(
+            backend.clone(),
+            opts(&[]),
+            Some(Ssid::from("Home")),
+        ))
+        .unwrap();
+        backend.fail_next_activation();
+        backend.fail_next_activation();
+
+        assert!(connect_to(&state, "Cafe", None).is_err());
+
+        assert_eq!(backend.active(), [portal_profile(&backend, &state).uuid]);
+    }
+
+    #[test]
+    fn connect_reports_unverified_connectivity() {
+        let backend = FakeBackend::new().with_connectivity(Connectivity::Unknown);
+        let state = run(init_network(backend, opts(&[]), None)).unwrap();
+
+        let response = connect_to(&state, "Home", Some("secret")).unwrap();
+
+        assert!(matches!(
+            response,
+            CommandResponse::Connect(Connect {
+                connectivity: VerifiedConnectivity::Unverified,
+                ..
+            })
+        ));
+        assert!(state.portal_connection.borrow().is_none());
+    }
+
+    #[test]
+    fn connect_keeps_saved_profile_after_failure() {
+        let backend = FakeBackend::new().with_profile("home", "Home", false);
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+        backend.fail_next_activation_with(FailureReason::WrongPassword);
+
+        assert!(connect_to(&state, "Home", Some("wrong")).is_err());
+
+        let uuids = profiles(&backend)
+            .into_iter()
+            .map(|profile| profile.uuid)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            uuids,
+            ["home", portal_profile(&backend, &state).uuid.as_str()]
+        );
+    }
+
+    #[test]
+    fn connect_keeps_reused_saved_profile_after_failure() {
+        let backend = FakeBackend::new()
+            .with_profile("home", "Home", false)
+            .reusing_saved_profiles();
+        let state = run(init_network(backend.clone(), opts(&[]), None)).unwrap();
+        backend.fail_next_activation_with(FailureReason::WrongPassword);
+
+        assert!(connect_to(&state, "Home", Some("wrong")).is_err());
+
+        assert!(profiles(&backend)
+            .iter()
+            .any(|profile| profile.uuid == "home"));
+        assert_eq!(backend.active(), [portal_profile(&backend, &state).uuid]);
+    }
 }
@@ -1,6 +0,0 @@
-mod enums;
-mod interface;
-
-#[allow(dead_code, non_upper_case_globals, non_camel_case_types)]
-mod consts;
-pub mod scan;
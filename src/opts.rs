@@ -1,13 +1,92 @@
-use clap::Parser;
+use core::fmt;
+use core::str::FromStr;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use clap::{Parser, ValueEnum};
+
+use macaddr::MacAddr6;
+
+use crate::quality::QualityModel;
 
 const DEFAULT_GATEWAY: &str = "192.168.42.1";
 const DEFAULT_SSID: &str = "WiFiConnect";
+const DEFAULT_SCAN_CACHE_TTL: u64 = 10;
+const DEFAULT_WAIT_TIMEOUT: u64 = 120;
+const DEFAULT_WAIT_MIN_SIGNAL: i32 = -80;
+const DEFAULT_PROBE_LOG_SIZE: usize = 100;
+const DEFAULT_PORT: u16 = 3000;
+const DEFAULT_CONNECT_TIMEOUT: u64 = 60;
+
+pub const DEFAULT_INTERFACE: &str = "wlan0";
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PowerSave {
+    On,
+    Off,
+}
+
+/// Daemon managing the wireless connections.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionManager {
+    /// Network Manager if running, otherwise iwd
+    Auto,
+    NetworkManager,
+    Iwd,
+    /// Captive portal hosted over nl80211, without joining networks
+    Native,
+}
+
+/// MAC address Network Manager uses on the interface for a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClonedMac {
+    /// Address the interface has when the connection is activated
+    Preserve,
+    /// Hardware address of the device
+    Permanent,
+    /// New random address on every activation
+    Random,
+    /// Random address that stays the same for the connection profile
+    Stable,
+    Address(MacAddr6),
+}
+
+impl FromStr for ClonedMac {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "preserve" => Ok(Self::Preserve),
+            "permanent" => Ok(Self::Permanent),
+            "random" => Ok(Self::Random),
+            "stable" => Ok(Self::Stable),
+            _ => value.parse().map(Self::Address).map_err(|err| {
+                format!("expected preserve, permanent, random, stable or a MAC address: {err}")
+            }),
+        }
+    }
+}
+
+/// Formats as the value of the `cloned-mac-address` wireless setting.
+impl fmt::Display for ClonedMac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Preserve => write!(f, "preserve"),
+            Self::Permanent => write!(f, "permanent"),
+            Self::Random => write!(f, "random"),
+            Self::Stable => write!(f, "stable"),
+            Self::Address(address) => write!(f, "{address}"),
+        }
+    }
+}
 
 #[derive(Parser)]
 pub struct Opts {
     #[clap(short, long, default_value = DEFAULT_SSID)]
     pub ssid: String,
 
+    /// Passphrase of the captive portal, which is open without one. Required with iwd
     #[clap(short, long)]
     pub password: Option<String>,
 
@@ -16,4 +95,193 @@ pub struct Opts {
 
     #[clap(short, long)]
     pub interface: Option<String>,
+
+    /// Port of the web server, which listens on the loopback interface
+    #[clap(long, default_value_t = DEFAULT_PORT)]
+    pub port: u16,
+
+    /// Daemon to configure the access point and client connections with
+    #[clap(long, value_enum, default_value_t = ConnectionManager::Auto)]
+    pub connection_manager: ConnectionManager,
+
+    /// Seconds a new connection has to reach full connectivity before the previous connection
+    /// or the captive portal is restored. Connections the connection manager cannot check are
+    /// kept and reported with unverified connectivity
+    #[clap(long, default_value_t = DEFAULT_CONNECT_TIMEOUT)]
+    pub connect_timeout: u64,
+
+    /// Seconds for which nl80211 scan results are reused before scanning again
+    #[clap(long, default_value_t = DEFAULT_SCAN_CACHE_TTL)]
+    pub scan_cache_ttl: u64,
+
+    /// Wait with a low power scheduled scan for this saved network and connect to it instead
+    /// of starting the captive portal. Neither the portal nor the web API are available while
+    /// waiting
+    #[clap(long)]
+    pub wait_for_ssid: Option<String>,
+
+    /// Seconds to wait for the saved network before falling back to the captive portal
+    #[clap(long, default_value_t = DEFAULT_WAIT_TIMEOUT)]
+    pub wait_timeout: u64,
+
+    /// Minimum signal strength in dBm of the waited for network
+    #[clap(long, default_value_t = DEFAULT_WAIT_MIN_SIGNAL, allow_hyphen_values = true)]
+    pub wait_min_signal: i32,
+
+    /// Curve for converting signal strength to the reported quality percentage
+    #[clap(long, value_enum, default_value_t = QualityModel::default())]
+    pub quality_model: QualityModel,
+
+    /// Record probe requests from nearby devices, available at /probes. Only one process can
+    /// receive them, so this fails while the portal's access point daemon, or the native
+    /// backend's access point, handles them, and /probes then reports the listener as
+    /// unavailable
+    #[clap(long)]
+    pub probe_listener: bool,
+
+    /// Number of most recent probe requests to keep
+    #[clap(long, default_value_t = DEFAULT_PROBE_LOG_SIZE)]
+    pub probe_log_size: usize,
+
+    /// Power save mode of the interface while running. Some USB adapters drop the connection
+    /// with power save on. Requires --interface
+    #[clap(long, value_enum)]
+    pub power_save: Option<PowerSave>,
+
+    /// Fixed TX power in dBm while running. Requires --interface
+    #[clap(long, allow_hyphen_values = true)]
+    pub tx_power: Option<i32>,
+
+    /// MAC address for client connections and the captive portal: preserve, permanent, random,
+    /// stable or an explicit address. Network Manager's default is used if not set
+    #[clap(long)]
+    pub cloned_mac: Option<ClonedMac>,
+
+    /// Serve the web API from a simulated backend following the scenario file, without
+    /// wireless hardware or a connection manager
+    #[clap(long, value_name = "SCENARIO")]
+    pub simulate: Option<PathBuf>,
+}
+
+impl Opts {
+    /// Rejects combinations the connection manager cannot serve. An automatic connection
+    /// manager is checked once resolved, see [`check_portal_security`].
+    pub fn validate(&self) -> Result<()> {
+        // Applied before the connection manager picks the device, so it cannot be guessed
+        if (self.power_save.is_some() || self.tx_power.is_some()) && self.interface.is_none() {
+            bail!("Power settings require the interface to be set with --interface");
+        }
+
+        check_portal_security(self.connection_manager, self.password.is_some())
+    }
+}
+
+/// iwd hosts WPA2 access points only.
+pub fn check_portal_security(
+    connection_manager: ConnectionManager,
+    passphrase: bool,
+) -> Result<()> {
+    match connection_manager {
+        ConnectionManager::Iwd if !passphrase => {
+            bail!("iwd only hosts WPA2 access points, set a portal passphrase with --password")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::assertions_on_result_states)]
+mod tests {
+    use core::iter::once;
+
+    use super::*;
+
+    fn opts(args: &[&str]) -> Opts {
+        Opts::parse_from(once("wifi-connect").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn requires_portal_passphrase_with_iwd() {
+        let err = opts(&["--connection-manager", "iwd"])
+            .validate()
+            .unwrap_err();
+
+        assert!(err.to_string().contains("--password"));
+        assert!(
+            opts(&["--connection-manager", "iwd", "--password", "portal123"])
+                .validate()
+                .is_ok()
+        );
+        assert!(opts(&[]).validate().is_ok());
+    }
+
+    #[test]
+    fn requires_interface_for_power_settings() {
+        let err = opts(&["--tx-power", "10"]).validate().unwrap_err();
+
+        assert!(err.to_string().contains("--interface"));
+        assert!(opts(&["--power-save", "off"]).validate().is_err());
+        assert!(opts(&["--power-save", "off", "-i", "wlan1"])
+            .validate()
+            .is_ok());
+    }
+
+    #[test]
+    fn accepts_open_and_protected_portals_with_native() {
+        assert!(
+            opts(&["--connection-manager", "native", "--password", "portal123"])
+                .validate()
+                .is_ok()
+        );
+        assert!(opts(&["--connection-manager", "native"]).validate().is_ok());
+    }
+
+    #[test]
+    fn round_trips_cloned_mac() {
+        for value in [
+            "preserve",
+            "permanent",
+            "random",
+            "stable",
+            "02:AB:22:33:44:55",
+        ] {
+            let cloned_mac: ClonedMac = value.parse().unwrap();
+
+            assert_eq!(cloned_mac.to_string(), value);
+        }
+    }
+
+    #[test]
+    fn parses_explicit_cloned_mac() {
+        assert_eq!(
+            "02:ab:22:33:44:55".parse::<ClonedMac>(),
+            Ok(ClonedMac::Address(MacAddr6::new(
+                2, 0xab, 0x22, 0x33, 0x44, 0x55
+            )))
+        );
+        assert_eq!(
+            opts(&["--cloned-mac", "stable"]).cloned_mac,
+            Some(ClonedMac::Stable)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_cloned_mac() {
+        for value in [
+            "",
+            "Random",
+            "02:ab:22:33:44",
+            "02:ab:22:33:44:55:66",
+            "02:ab:22:33:44:zz",
+        ] {
+            let err = value.parse::<ClonedMac>().unwrap_err();
+
+            assert!(
+                err.starts_with("expected preserve, permanent"),
+                "{value}: {err}"
+            );
+        }
+
+        assert!(Opts::try_parse_from(["wifi-connect", "--cloned-mac", "02:ab"]).is_err());
+    }
 }
@@ -0,0 +1,98 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::Result;
+
+use crate::error::ErrorKind;
+
+use nl80211::probe::{ProbeListener, ProbeRequest};
+
+/// Most recent probe requests, oldest first. Older entries are dropped when full.
+#[derive(Debug)]
+pub struct ProbeLog {
+    capacity: usize,
+    entries: Mutex<VecDeque<ProbeRequest>>,
+    unavailable: Mutex<Option<String>>,
+}
+
+impl ProbeLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            unavailable: Mutex::new(None),
+        }
+    }
+
+    /// Fails when nothing is being recorded, so that an empty log means no requests were
+    /// received.
+    pub fn entries(&self) -> Result<Vec<ProbeRequest>> {
+        let unavailable = self
+            .unavailable
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+
+        if let Some(reason) = unavailable {
+            return Err(anyhow::Error::new(ErrorKind::Unavailable)
+                .context(reason)
+                .context("Probe requests are not being recorded"));
+        }
+
+        Ok(self.lock().iter().cloned().collect())
+    }
+
+    /// Marks the log as not recording, e.g. as the listener failed to start or stopped.
+    pub fn set_unavailable(&self, reason: String) {
+        *self
+            .unavailable
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(reason);
+    }
+
+    fn push(&self, probe: ProbeRequest) {
+        let mut entries = self.lock();
+
+        while entries.len() >= self.capacity {
+            if entries.pop_front().is_none() {
+                return;
+            }
+        }
+
+        entries.push_back(probe);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<ProbeRequest>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Records probe requests into the log until receiving fails.
+pub async fn run_probe_listener(mut listener: ProbeListener, log: &ProbeLog) -> Result<()> {
+    loop {
+        log.push(listener.next_probe().await?);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_unavailable_listener() {
+        let log = ProbeLog::new(10);
+
+        assert!(log.entries().unwrap().is_empty());
+
+        log.set_unavailable("The probe listener is not enabled".to_owned());
+        let err = log.entries().unwrap_err();
+
+        assert_eq!(err.downcast_ref(), Some(&ErrorKind::Unavailable));
+        assert_eq!(
+            format!("{err:#}"),
+            "Probe requests are not being recorded: The probe listener is not enabled: \
+             Not available on this device"
+        );
+    }
+}
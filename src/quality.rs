@@ -0,0 +1,190 @@
+use clap::ValueEnum;
+
+// Range NetworkManager maps to 0..100 for access points
+const NM_MIN_DBM: i32 = -100;
+const NM_MAX_DBM: i32 = -40;
+
+// Range of the fixed linear mapping
+const LINEAR_MIN_DBM: i32 = -100;
+const LINEAR_MAX_DBM: i32 = -40;
+
+// Minimum signal for one, two, three and four bars
+const BARS_THRESHOLDS_DBM: [i32; 4] = [-88, -77, -66, -55];
+
+/// Curve for converting signal strength in dBm to a 0..100 quality percentage.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QualityModel {
+    /// Linear between -100 and -40 dBm, rounded to the nearest percent
+    Linear,
+    /// Same as the access point strength reported by Network Manager, which is linear between
+    /// -100 and -40 dBm with the percentage lost below -40 dBm rounded down
+    #[default]
+    NetworkManager,
+    /// Zero to four bars in steps of 25
+    Bars,
+}
+
+impl QualityModel {
+    pub fn quality(self, signal_dbm: i32) -> u8 {
+        match self {
+            Self::Linear => scale(signal_dbm, LINEAR_MIN_DBM, LINEAR_MAX_DBM),
+            Self::NetworkManager => {
+                // As in `nm_wifi_utils_level_to_quality`
+                let below_max = NM_MAX_DBM.saturating_sub(signal_dbm.clamp(NM_MIN_DBM, NM_MAX_DBM));
+                let loss = below_max
+                    .saturating_mul(100)
+                    .checked_div(NM_MAX_DBM.saturating_sub(NM_MIN_DBM))
+                    .unwrap_or(0);
+                u8::try_from(100_i32.saturating_sub(loss)).unwrap_or(0)
+            }
+            Self::Bars => {
+                let bars = BARS_THRESHOLDS_DBM
+                    .iter()
+                    .filter(|&&threshold| signal_dbm >= threshold)
+                    .count();
+                u8::try_from(bars.saturating_mul(25)).unwrap_or(100)
+            }
+        }
+    }
+
+    /// Network Manager does not expose the signal level in dBm, so it is recovered by inverting
+    /// its own mapping. Levels outside of -100..-40 dBm are clamped by it and cannot be recovered.
+    pub fn quality_from_nm_strength(self, strength: u8) -> u8 {
+        self.quality(nm_strength_to_dbm(strength))
+    }
+}
+
+pub fn nm_strength_to_dbm(strength: u8) -> i32 {
+    // Smallest distance below the maximum with the percentage loss of the strength
+    let range = NM_MAX_DBM.saturating_sub(NM_MIN_DBM);
+    let loss = 100_i32.saturating_sub(i32::from(strength.min(100)));
+    let below_max = loss
+        .saturating_mul(range)
+        .saturating_add(99)
+        .checked_div(100)
+        .unwrap_or(0);
+
+    NM_MAX_DBM.saturating_sub(below_max)
+}
+
+/// Maps `min_dbm..=max_dbm` linearly onto `0..=100`, rounding to the nearest value.
+fn scale(signal_dbm: i32, min_dbm: i32, max_dbm: i32) -> u8 {
+    let clamped = signal_dbm.clamp(min_dbm, max_dbm);
+
+    let range = max_dbm.saturating_sub(min_dbm);
+    let above_min = clamped.saturating_sub(min_dbm);
+
+    let quality = above_min
+        .saturating_mul(100)
+        .saturating_add(range.saturating_div(2))
+        .checked_div(range)
+        .unwrap_or(0);
+
+    u8::try_from(quality).unwrap_or(100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn linear_clamps_and_scales() {
+        let model = QualityModel::Linear;
+
+        assert_eq!(model.quality(-120), 0);
+        assert_eq!(model.quality(LINEAR_MIN_DBM), 0);
+        assert_eq!(model.quality(-70), 50);
+        assert_eq!(model.quality(LINEAR_MAX_DBM), 100);
+        assert_eq!(model.quality(-10), 100);
+    }
+
+    #[test]
+    fn network_manager_clamps_and_scales() {
+        let model = QualityModel::NetworkManager;
+
+        assert_eq!(model.quality(-120), 0);
+        assert_eq!(model.quality(NM_MIN_DBM), 0);
+        assert_eq!(model.quality(-99), 2);
+        assert_eq!(model.quality(-70), 50);
+        assert_eq!(model.quality(-41), 99);
+        assert_eq!(model.quality(NM_MAX_DBM), 100);
+        assert_eq!(model.quality(-10), 100);
+    }
+
+    #[test]
+    fn bars_step_at_thresholds() {
+        let model = QualityModel::Bars;
+
+        assert_eq!(model.quality(-100), 0);
+        assert_eq!(model.quality(-89), 0);
+        assert_eq!(model.quality(-88), 25);
+        assert_eq!(model.quality(-70), 50);
+        assert_eq!(model.quality(-66), 75);
+        assert_eq!(model.quality(-55), 100);
+        assert_eq!(model.quality(-20), 100);
+    }
+
+    #[test]
+    fn recovers_signal_from_nm_strength() {
+        for signal_dbm in NM_MIN_DBM..=NM_MAX_DBM {
+            let strength = QualityModel::NetworkManager.quality(signal_dbm);
+
+            assert_eq!(nm_strength_to_dbm(strength), signal_dbm);
+        }
+
+        assert_eq!(nm_strength_to_dbm(0), NM_MIN_DBM);
+        assert_eq!(nm_strength_to_dbm(100), NM_MAX_DBM);
+    }
+
+    /// `nm_wifi_utils_level_to_quality` for levels in dBm.
+    fn nm_level_to_quality(signal_dbm: i32) -> u8 {
+        let below_max = signal_dbm.clamp(-100, -40).saturating_add(40).abs();
+        let quality = 100_i32.saturating_sub(below_max.saturating_mul(100) / 60);
+        u8::try_from(quality.clamp(0, 100)).unwrap()
+    }
+
+    #[test]
+    fn network_manager_matches_nm_level_to_quality() {
+        for signal_dbm in -110..=-30 {
+            assert_eq!(
+                QualityModel::NetworkManager.quality(signal_dbm),
+                nm_level_to_quality(signal_dbm),
+                "{signal_dbm} dBm"
+            );
+        }
+    }
+
+    #[test]
+    fn nm_strength_and_nl80211_signal_agree() {
+        let models = [
+            QualityModel::Linear,
+            QualityModel::NetworkManager,
+            QualityModel::Bars,
+        ];
+
+        for model in models {
+            for signal_dbm in NM_MIN_DBM..=NM_MAX_DBM {
+                // Network Manager reports the strength, nl80211 the signal itself
+                let strength = nm_level_to_quality(signal_dbm);
+
+                assert_eq!(
+                    model.quality_from_nm_strength(strength),
+                    model.quality(signal_dbm),
+                    "{model:?} at {signal_dbm} dBm"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn converts_nm_strength_to_other_models() {
+        assert_eq!(
+            QualityModel::NetworkManager.quality_from_nm_strength(42),
+            42
+        );
+        assert_eq!(QualityModel::Linear.quality_from_nm_strength(100), 100);
+        assert_eq!(QualityModel::Linear.quality_from_nm_strength(50), 50);
+        assert_eq!(QualityModel::Linear.quality_from_nm_strength(0), 0);
+        assert_eq!(QualityModel::Bars.quality_from_nm_strength(0), 0);
+    }
+}
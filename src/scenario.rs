@@ -0,0 +1,236 @@
+//! Simulated surroundings for `--simulate`, loaded from a TOML file:
+//!
+//! ```toml
+//! connectivity = "full"
+//!
+//! [delays]
+//! scan_ms = 2000
+//! connect_ms = 3000
+//!
+//! [[stations]]
+//! ssid = "Home"
+//! signal_dbm = -45
+//! passphrase = "correct horse"
+//! saved = true
+//!
+//! [[stations]]
+//! ssid = "Cafe"
+//! outcome = "timeout"
+//! ```
+
+use core::fmt;
+use core::time::Duration;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use serde::{Deserialize, Deserializer};
+
+use nl80211::Ssid;
+
+use crate::backend::{self, AccessPoint};
+use crate::network::Station;
+use crate::quality::QualityModel;
+
+const DEFAULT_SIGNAL_DBM: i32 = -60;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// Connectivity while connected to a network
+    #[serde(default)]
+    pub connectivity: Connectivity,
+    #[serde(default)]
+    pub delays: Delays,
+    #[serde(default)]
+    pub stations: Vec<SimulatedStation>,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario {}", path.display()))?;
+
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse scenario {}", path.display()))
+    }
+
+    pub fn station(&self, ssid: &Ssid) -> Option<&SimulatedStation> {
+        self.stations
+            .iter()
+            .find(|station| Ssid::from(station.ssid.as_str()) == *ssid)
+    }
+
+    /// Stations as reported by connection managers.
+    pub fn access_points(&self) -> Vec<AccessPoint> {
+        self.stations
+            .iter()
+            .map(|station| AccessPoint {
+                ssid: Some(Ssid::from(station.ssid.as_str())).filter(|ssid| !ssid.is_empty()),
+                strength: QualityModel::NetworkManager.quality(station.signal_dbm),
+            })
+            .collect()
+    }
+
+    /// Stations as reported by nl80211 scans, skipping hidden ones.
+    pub fn scan_results(&self, quality_model: QualityModel) -> Vec<Station> {
+        self.stations
+            .iter()
+            .filter(|station| !station.ssid.is_empty())
+            .map(|station| Station {
+                ssid: Ssid::from(station.ssid.as_str()),
+                quality: quality_model.quality(station.signal_dbm),
+                signal_dbm: Some(station.signal_dbm),
+                associated: false,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Connectivity {
+    None,
+    /// Behind a captive portal of the network
+    Portal,
+    Limited,
+    #[default]
+    Full,
+}
+
+impl fmt::Display for Connectivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::None => write!(f, "none"),
+            Self::Portal => write!(f, "portal"),
+            Self::Limited => write!(f, "limited"),
+            Self::Full => write!(f, "full"),
+        }
+    }
+}
+
+impl From<Connectivity> for backend::Connectivity {
+    fn from(connectivity: Connectivity) -> Self {
+        match connectivity {
+            Connectivity::None => Self::None,
+            Connectivity::Portal => Self::Portal,
+            Connectivity::Limited => Self::Limited,
+            Connectivity::Full => Self::Full,
+        }
+    }
+}
+
+/// Durations of the simulated operations, configured in milliseconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Delays {
+    #[serde(rename = "scan_ms", deserialize_with = "millis")]
+    pub scan: Duration,
+    /// Until a connection is established or rejected
+    #[serde(rename = "connect_ms", deserialize_with = "millis")]
+    pub connect: Duration,
+    /// Until a connection with the `timeout` outcome gives up
+    #[serde(rename = "timeout_ms", deserialize_with = "millis")]
+    pub timeout: Duration,
+    /// Until the captive portal is up
+    #[serde(rename = "portal_ms", deserialize_with = "millis")]
+    pub portal: Duration,
+}
+
+impl Default for Delays {
+    fn default() -> Self {
+        Self {
+            scan: Duration::from_secs(2),
+            connect: Duration::from_secs(3),
+            timeout: Duration::from_secs(30),
+            portal: Duration::from_secs(1),
+        }
+    }
+}
+
+fn millis<'de, D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_millis)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimulatedStation {
+    /// Empty for hidden networks
+    pub ssid: String,
+    #[serde(default = "default_signal_dbm")]
+    pub signal_dbm: i32,
+    /// Open network if not set
+    pub passphrase: Option<String>,
+    /// Whether a connection profile for the network exists
+    #[serde(default)]
+    pub saved: bool,
+    #[serde(default)]
+    pub outcome: Outcome,
+}
+
+const fn default_signal_dbm() -> i32 {
+    DEFAULT_SIGNAL_DBM
+}
+
+/// Result of connecting to a station.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// Connects if the passphrase matches
+    #[default]
+    Success,
+    /// Rejects any passphrase
+    WrongPassword,
+    /// Never completes and gives up after the timeout delay, as when no DHCP lease is offered
+    Timeout,
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::unwrap_used,
+    clippy::indexing_slicing,
+    clippy::assertions_on_result_states
+)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_scenario_with_defaults() {
+        let scenario: Scenario = toml::from_str(
+            r#"
+            connectivity = "limited"
+
+            [delays]
+            connect_ms = 500
+
+            [[stations]]
+            ssid = "Home"
+            signal_dbm = -45
+            passphrase = "secret123"
+            saved = true
+
+            [[stations]]
+            ssid = "Cafe"
+            outcome = "wrong-password"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(scenario.connectivity, Connectivity::Limited);
+        assert_eq!(scenario.delays.connect, Duration::from_millis(500));
+        assert_eq!(scenario.delays.scan, Delays::default().scan);
+        assert_eq!(scenario.stations.len(), 2);
+        assert!(scenario.stations[0].saved);
+        assert_eq!(scenario.stations[1].signal_dbm, DEFAULT_SIGNAL_DBM);
+        assert_eq!(scenario.stations[1].passphrase, None);
+        assert_eq!(scenario.stations[1].outcome, Outcome::WrongPassword);
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(
+            toml::from_str::<Scenario>("[[stations]]\nssid = \"Home\"\nsignal = -45\n").is_err()
+        );
+    }
+}
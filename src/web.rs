@@ -1,15 +1,26 @@
-use anyhow::{Context, Result};
+use alloc::sync::Arc;
+
+use anyhow::{bail, Context, Result};
 
 use actix_http::body::BoxBody;
-use actix_web::web::{resource, Data};
-use actix_web::{middleware, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::web::{self, resource, Data, Json, JsonConfig, Path, Query, QueryConfig};
+use actix_web::{middleware, App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError};
 
 use tokio::sync::oneshot;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use nl80211::station::{get_link_status, LinkStatus};
+use nl80211::survey::get_survey;
+use nl80211::Ssid;
 
+use crate::backend::{ConnectionType, FailureReason, ProfileUpdate};
+use crate::coordinator::ScanCoordinator;
+use crate::error::ErrorKind;
 use crate::network::{Command, CommandRequest, CommandResponse};
-use crate::nl80211;
+use crate::probe_log::ProbeLog;
 
 #[derive(Debug)]
 pub enum AppResponse {
@@ -20,31 +31,100 @@ pub enum AppResponse {
 #[derive(Serialize)]
 pub struct AppErrors {
     pub errors: Vec<String>,
+    /// Category of a failed activation, for clients to show their own message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<FailureReason>,
 }
 
 impl AppErrors {
-    fn new(errors: Vec<String>) -> Self {
-        Self { errors }
+    fn new(errors: Vec<String>, reason: Option<FailureReason>) -> Self {
+        Self { errors, reason }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Status {
+    pub link: Option<LinkStatus>,
+}
+
+#[derive(Deserialize)]
+pub struct ListConnectionsParams {
+    #[serde(rename = "type")]
+    pub connection_type: Option<ConnectionType>,
+}
+
+#[derive(Deserialize)]
+pub struct ScanParams {
+    #[serde(default)]
+    pub cached: bool,
+}
+
+/// The SSID can be given as text or, for names that are not valid UTF-8, as hex.
+#[derive(Deserialize)]
+pub struct ConnectParams {
+    pub ssid: Option<String>,
+    pub ssid_hex: Option<String>,
+    pub passphrase: Option<String>,
+}
+
+impl ConnectParams {
+    fn parse_ssid(&self) -> Result<Ssid> {
+        let ssid = match (self.ssid_hex.as_deref(), self.ssid.as_deref()) {
+            (Some(ssid_hex), _) => Ssid::from_hex(ssid_hex)?,
+            (None, Some(ssid)) => Ssid::from(ssid),
+            (None, None) => bail!("Either ssid or ssid_hex is required"),
+        };
+
+        if ssid.is_empty() {
+            bail!("The SSID is empty");
+        }
+
+        if ssid.as_bytes().len() > Ssid::MAX_LEN {
+            bail!("The SSID is longer than {} bytes", Ssid::MAX_LEN);
+        }
+
+        Ok(ssid)
     }
 }
 
 type Sender = glib::Sender<CommandRequest>;
 
-pub async fn run_web_loop(glib_sender: Sender) -> Result<()> {
+pub async fn run_web_loop(
+    glib_sender: Sender,
+    scan_coordinator: ScanCoordinator,
+    probe_log: Arc<ProbeLog>,
+    port: u16,
+) -> Result<()> {
     println!("Web server starting...");
 
+    let scan_coordinator = Data::new(scan_coordinator);
+    let probe_log = Data::from(probe_log);
+
     HttpServer::new(move || {
         App::new()
             .app_data(Data::new(glib_sender.clone()))
+            .app_data(Data::clone(&scan_coordinator))
+            .app_data(Data::clone(&probe_log))
+            .app_data(JsonConfig::default().error_handler(invalid_request))
+            .app_data(QueryConfig::default().error_handler(invalid_request))
             .wrap(middleware::Logger::default())
             .service(resource("/").to(index))
             .service(resource("/check-connectivity").to(check_connectivity))
             .service(resource("/list-connections").to(list_connections))
+            .service(
+                resource("/connections/{uuid}")
+                    .route(web::delete().to(delete_connection))
+                    .route(web::patch().to(update_connection)),
+            )
             .service(resource("/list-wifi-networks").to(list_wifi_networks))
             .service(resource("/stop").to(stop))
+            .service(resource("/connect").to(connect))
             .service(resource("/scan").to(scan))
+            .service(resource("/status").to(status))
+            .service(resource("/survey").to(survey))
+            .service(resource("/probes").to(probes))
     })
-    .bind(("127.0.0.1", 3000))
+    .bind(("127.0.0.1", port))
     .context("Failed to bind listening socket")?
     .run()
     .await
@@ -60,8 +140,31 @@ async fn check_connectivity(sender: Data<Sender>) -> impl Responder {
     send_command(sender.get_ref(), Command::CheckConnectivity).await
 }
 
-async fn list_connections(sender: Data<Sender>) -> impl Responder {
-    send_command(sender.get_ref(), Command::ListConnections).await
+async fn list_connections(
+    sender: Data<Sender>,
+    params: Query<ListConnectionsParams>,
+) -> impl Responder {
+    let connection_type = params.into_inner().connection_type;
+    send_command(
+        sender.get_ref(),
+        Command::ListConnections { connection_type },
+    )
+    .await
+}
+
+async fn delete_connection(sender: Data<Sender>, uuid: Path<String>) -> impl Responder {
+    let uuid = uuid.into_inner();
+    send_command(sender.get_ref(), Command::DeleteConnection { uuid }).await
+}
+
+async fn update_connection(
+    sender: Data<Sender>,
+    uuid: Path<String>,
+    update: Json<ProfileUpdate>,
+) -> impl Responder {
+    let uuid = uuid.into_inner();
+    let update = update.into_inner();
+    send_command(sender.get_ref(), Command::UpdateConnection { uuid, update }).await
 }
 
 async fn list_wifi_networks(sender: Data<Sender>) -> impl Responder {
@@ -72,13 +175,67 @@ async fn stop(sender: Data<Sender>) -> impl Responder {
     send_command(sender.get_ref(), Command::Stop).await
 }
 
-async fn scan() -> HttpResponse {
-    let scan_result = nl80211::scan::scan("wlan0")
-        .await
-        .context("Failed to scan for networks");
+async fn connect(sender: Data<Sender>, params: Json<ConnectParams>) -> AppResponse {
+    let params = params.into_inner();
+
+    match params.parse_ssid() {
+        Ok(ssid) => {
+            let passphrase = params.passphrase;
+            send_command(sender.get_ref(), Command::Connect { ssid, passphrase }).await
+        }
+        Err(err) => AppResponse::Error(
+            err.context(ErrorKind::InvalidInput)
+                .context("Failed to connect"),
+        ),
+    }
+}
+
+async fn scan(scan_coordinator: Data<ScanCoordinator>, params: Query<ScanParams>) -> HttpResponse {
+    let scan_result = if params.cached {
+        scan_coordinator.cached_scan().await
+    } else {
+        scan_coordinator.scan().await
+    }
+    .context("Failed to scan for networks");
 
     match scan_result {
-        Ok(stations) => HttpResponse::Ok().json(stations),
+        Ok(results) => HttpResponse::Ok().json(results),
+        Err(err) => to_http_error_response(&err),
+    }
+}
+
+async fn status(scan_coordinator: Data<ScanCoordinator>) -> HttpResponse {
+    // Simulated links have no status
+    let status_result = match scan_coordinator.nl80211() {
+        Ok(Some(nl80211)) => get_link_status(nl80211, scan_coordinator.interface()).await,
+        Ok(None) => Ok(None),
+        Err(err) => Err(err),
+    }
+    .context("Failed to get link status");
+
+    match status_result {
+        Ok(link) => HttpResponse::Ok().json(Status { link }),
+        Err(err) => to_http_error_response(&err),
+    }
+}
+
+async fn survey(scan_coordinator: Data<ScanCoordinator>) -> HttpResponse {
+    let survey_result = match scan_coordinator.nl80211() {
+        Ok(Some(nl80211)) => get_survey(nl80211, scan_coordinator.interface()).await,
+        Ok(None) => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+    .context("Failed to get channel survey");
+
+    match survey_result {
+        Ok(channels) => HttpResponse::Ok().json(channels),
+        Err(err) => to_http_error_response(&err),
+    }
+}
+
+async fn probes(probe_log: Data<ProbeLog>) -> HttpResponse {
+    match probe_log.entries() {
+        Ok(entries) => HttpResponse::Ok().json(entries),
         Err(err) => to_http_error_response(&err),
     }
 }
@@ -88,9 +245,12 @@ async fn send_command(glib_sender: &glib::Sender<CommandRequest>, command: Comma
 
     let action = match command {
         Command::CheckConnectivity => "check connectivity",
-        Command::ListConnections => "list actions",
+        Command::ListConnections { .. } => "list connections",
         Command::ListWiFiNetworks => "list WiFi networks",
         Command::Stop => "stop",
+        Command::Connect { .. } => "connect",
+        Command::DeleteConnection { .. } => "delete connection",
+        Command::UpdateConnection { .. } => "update connection",
     };
 
     glib_sender
@@ -140,6 +300,11 @@ impl Responder for AppResponse {
                 }
                 CommandResponse::ListWiFiNetworks(networks) => HttpResponse::Ok().json(networks),
                 CommandResponse::Stop(stop) => HttpResponse::Ok().json(stop),
+                CommandResponse::Connect(connect) => HttpResponse::Ok().json(connect),
+                CommandResponse::DeleteConnection(delete) => HttpResponse::Ok().json(delete),
+                CommandResponse::UpdateConnection(connection) => {
+                    HttpResponse::Ok().json(connection)
+                }
             },
         }
     }
@@ -147,6 +312,68 @@ impl Responder for AppResponse {
 
 fn to_http_error_response(err: &anyhow::Error) -> HttpResponse {
     let errors: Vec<String> = err.chain().map(|e| format!("{e}")).collect();
-    let app_errors = AppErrors::new(errors);
-    HttpResponse::InternalServerError().json(app_errors)
+    let reason = err
+        .chain()
+        .find_map(|e| e.downcast_ref::<FailureReason>())
+        .copied();
+    // Kinds are found whether attached as the source or as context
+    let status = match err.downcast_ref::<ErrorKind>() {
+        Some(&ErrorKind::Unavailable) => StatusCode::SERVICE_UNAVAILABLE,
+        Some(&ErrorKind::NotFound) => StatusCode::NOT_FOUND,
+        Some(&ErrorKind::InvalidInput) => StatusCode::BAD_REQUEST,
+        Some(&ErrorKind::Conflict) => StatusCode::CONFLICT,
+        None => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    let app_errors = AppErrors::new(errors, reason);
+    HttpResponse::build(status).json(app_errors)
+}
+
+/// Reports requests the extractors reject, such as unknown fields in a profile update, with
+/// their status in the format of the other errors.
+fn invalid_request<E: ResponseError + 'static>(err: E, _: &HttpRequest) -> actix_web::Error {
+    let app_errors = AppErrors::new(vec![format!("Invalid request: {err}")], None);
+    let response = HttpResponse::build(err.status_code()).json(app_errors);
+    InternalError::from_response(err, response).into()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::assertions_on_result_states)]
+mod tests {
+    use super::*;
+
+    fn params(ssid: Option<&str>, ssid_hex: Option<&str>) -> ConnectParams {
+        ConnectParams {
+            ssid: ssid.map(ToOwned::to_owned),
+            ssid_hex: ssid_hex.map(ToOwned::to_owned),
+            passphrase: None,
+        }
+    }
+
+    #[test]
+    fn parses_text_and_hex_ssids() {
+        assert_eq!(
+            params(Some("Home"), None).parse_ssid().unwrap(),
+            Ssid::from("Home")
+        );
+        assert_eq!(
+            params(Some("Ignored"), Some("ff00")).parse_ssid().unwrap(),
+            Ssid::from(vec![0xff, 0x00])
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_overlong_ssids() {
+        assert!(params(None, None).parse_ssid().is_err());
+        assert!(params(Some(""), None).parse_ssid().is_err());
+        assert!(params(None, Some("")).parse_ssid().is_err());
+        assert!(params(Some("x".repeat(32).as_str()), None)
+            .parse_ssid()
+            .is_ok());
+        assert!(params(Some("x".repeat(33).as_str()), None)
+            .parse_ssid()
+            .is_err());
+        assert!(params(None, Some("00".repeat(33).as_str()))
+            .parse_ssid()
+            .is_err());
+    }
 }
@@ -0,0 +1,103 @@
+//! Runs the binary against the mock and requests its web API.
+
+use std::net::TcpListener;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::time::{sleep, Instant};
+
+use crate::private_bus::PrivateBus;
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Running binary, killed when dropped.
+pub struct WifiConnect {
+    _child: Child,
+    port: u16,
+}
+
+impl WifiConnect {
+    /// Starts against the Network Manager of the bus and waits for the web server. If the
+    /// binary exits instead, its exit status and error output are returned.
+    pub async fn start(bus: &PrivateBus, args: &[&str]) -> Result<Self, String> {
+        let port = free_port();
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_wifi-connect"))
+            .args(["--connection-manager", "network-manager"])
+            .args(["--port", &port.to_string()])
+            .args(args)
+            .env("DBUS_SYSTEM_BUS_ADDRESS", bus.address())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .expect("Failed to start wifi-connect");
+
+        let deadline = Instant::now() + STARTUP_TIMEOUT;
+        loop {
+            if let Some(status) = child.try_wait().expect("Failed to poll wifi-connect") {
+                let mut stderr = String::new();
+                if let Some(mut pipe) = child.stderr.take() {
+                    pipe.read_to_string(&mut stderr).await.ok();
+                }
+                return Err(format!("{status}: {stderr}"));
+            }
+
+            if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                return Ok(Self {
+                    _child: child,
+                    port,
+                });
+            }
+
+            assert!(Instant::now() < deadline, "Web server did not start");
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Returns the status code and JSON body of the response.
+    pub async fn get(&self, path: &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port))
+            .await
+            .expect("Failed to connect to web server");
+
+        let request =
+            format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+        stream
+            .write_all(request.as_bytes())
+            .await
+            .expect("Failed to send request");
+
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .await
+            .expect("Failed to read response");
+
+        let (head, body) = response.split_once("\r\n\r\n").expect("Malformed response");
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .expect("Malformed status line");
+
+        (
+            status,
+            serde_json::from_str(body).expect("Invalid JSON body"),
+        )
+    }
+}
+
+/// Port the OS considers free, so that tests can run in parallel.
+fn free_port() -> u16 {
+    TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port")
+        .port()
+}
@@ -0,0 +1,158 @@
+//! End-to-end tests of the web API over libnm, against a scripted Network Manager on a private
+//! bus. They need `dbus-daemon` and are ignored by default, run them with
+//! `cargo test --test network_manager -- --ignored`. Wireless hardware is not needed, as the
+//! binary continues without nl80211.
+
+mod app;
+mod mock;
+#[path = "../support/private_bus.rs"]
+mod private_bus;
+
+use serde_json::Value;
+
+use app::WifiConnect;
+use mock::{
+    Activation, MockAccessPoint, MockConnection, MockDevice, MockNetworkManager,
+    ACTIVE_STATE_REASON_DEVICE_DISCONNECTED, CONNECTIVITY_FULL,
+    DEVICE_STATE_REASON_SUPPLICANT_FAILED,
+};
+use private_bus::PrivateBus;
+
+const PORTAL: &str = "WiFiConnect";
+
+fn access_points() -> Vec<MockAccessPoint> {
+    vec![
+        MockAccessPoint::new("Office", 60).protected(),
+        MockAccessPoint::new("Home", 80).protected(),
+        MockAccessPoint::new("Office", 40).protected(),
+        MockAccessPoint::new("", 90),
+        MockAccessPoint::new("Cafe", 30),
+    ]
+}
+
+fn connections() -> Vec<MockConnection> {
+    vec![
+        MockConnection::wifi("Home", "Home"),
+        MockConnection::ethernet("Wired connection 1"),
+        MockConnection::access_point(PORTAL, PORTAL),
+    ]
+}
+
+fn strings(value: &Value, key: &str) -> Vec<String> {
+    value
+        .as_array()
+        .expect("Not an array")
+        .iter()
+        .map(|item| item[key].as_str().expect("Not a string").to_owned())
+        .collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn serves_network_state() {
+    let bus = PrivateBus::start();
+    let nm = MockNetworkManager::serve(
+        &bus,
+        MockDevice::new("wlan0"),
+        access_points(),
+        connections(),
+    )
+    .await;
+
+    let app = WifiConnect::start(&bus, &[]).await.unwrap();
+
+    // The stale portal profile is replaced
+    assert_eq!(nm.scans(), 1);
+    assert_eq!(nm.active_ids(), [PORTAL]);
+    assert_eq!(nm.wireless_mode(PORTAL).as_deref(), Some("ap"));
+
+    let (status, networks) = app.get("/list-wifi-networks").await;
+    assert_eq!(status, 200);
+    assert_eq!(strings(&networks, "ssid"), ["Home", "Office", "Cafe"]);
+    assert_eq!(networks[0]["quality"], 80);
+
+    let (status, connections) = app.get("/list-connections").await;
+    assert_eq!(status, 200);
+    let mut ids = strings(&connections, "id");
+    ids.sort();
+    assert_eq!(ids, ["Home", PORTAL, "Wired connection 1"]);
+    assert!(strings(&connections, "uuid")
+        .iter()
+        .all(|uuid| !uuid.is_empty()));
+
+    let (status, wired) = app.get("/list-connections?type=ethernet").await;
+    assert_eq!(status, 200);
+    assert_eq!(strings(&wired, "id"), ["Wired connection 1"]);
+    assert_eq!(wired[0]["type"], "ethernet");
+
+    nm.set_connectivity(CONNECTIVITY_FULL);
+    let (status, connectivity) = app.get("/check-connectivity").await;
+    assert_eq!(status, 200);
+    assert!(connectivity["connectivity"]
+        .as_str()
+        .unwrap()
+        .ends_with("Full"));
+
+    let (status, stop) = app.get("/stop").await;
+    assert_eq!(status, 200);
+    assert_eq!(stop["stop"], "ok");
+    assert!(nm.active_ids().is_empty());
+    assert_eq!(nm.connection_ids(), ["Home", "Wired connection 1"]);
+
+    let (_, connections) = app.get("/list-connections").await;
+    assert_eq!(strings(&connections, "id").len(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn fails_when_portal_does_not_activate() {
+    let bus = PrivateBus::start();
+    let nm = MockNetworkManager::serve(
+        &bus,
+        MockDevice::new("wlan0"),
+        access_points(),
+        connections(),
+    )
+    .await;
+    nm.set_activation(Activation::Fail {
+        reason: ACTIVE_STATE_REASON_DEVICE_DISCONNECTED,
+        device_reason: DEVICE_STATE_REASON_SUPPLICANT_FAILED,
+    });
+
+    let Err(output) = WifiConnect::start(&bus, &[]).await else {
+        panic!("Started without a portal");
+    };
+
+    assert!(
+        output.contains("Failed to create captive portal"),
+        "{output}"
+    );
+    assert!(
+        output.contains("Authentication with the network failed"),
+        "{output}"
+    );
+    assert!(nm.active_ids().is_empty());
+    assert_eq!(nm.connection_ids(), ["Home", "Wired connection 1"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "needs dbus-daemon"]
+async fn fails_without_managed_device() {
+    let bus = PrivateBus::start();
+    let _nm = MockNetworkManager::serve(
+        &bus,
+        MockDevice::new("wlan0").unmanaged(),
+        access_points(),
+        connections(),
+    )
+    .await;
+
+    let Err(output) = WifiConnect::start(&bus, &[]).await else {
+        panic!("Started without a device");
+    };
+
+    assert!(
+        output.contains("Failed to find a managed WiFi device"),
+        "{output}"
+    );
+}
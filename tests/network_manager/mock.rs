@@ -0,0 +1,1353 @@
+//! Scripted NetworkManager service on a private session bus, exposing one wireless device with
+//! the access points in range and the saved connection profiles.
+//!
+//! Activations complete after a delay, as libnm only reports state changes of active
+//! connections it already knows about.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use tokio::runtime::Handle;
+
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+use zbus::{connection, fdo, interface, Connection, ObjectServer};
+
+use crate::private_bus::PrivateBus;
+
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const SETTINGS_PATH: &str = "/org/freedesktop/NetworkManager/Settings";
+const DEVICE_PATH: &str = "/org/freedesktop/NetworkManager/Devices/1";
+
+/// Time from activating or deactivating a connection until the final state is reached.
+const TRANSITION_DELAY: Duration = Duration::from_millis(500);
+const SCAN_DURATION: Duration = Duration::from_millis(100);
+
+const NM_STATE_DISCONNECTED: u32 = 20;
+const NM_STATE_CONNECTED_GLOBAL: u32 = 70;
+
+pub const CONNECTIVITY_NONE: u32 = 1;
+pub const CONNECTIVITY_FULL: u32 = 4;
+
+const DEVICE_TYPE_WIFI: u32 = 2;
+const DEVICE_STATE_UNMANAGED: u32 = 10;
+const DEVICE_STATE_DISCONNECTED: u32 = 30;
+const DEVICE_STATE_PREPARE: u32 = 40;
+const DEVICE_STATE_ACTIVATED: u32 = 100;
+const DEVICE_STATE_DEACTIVATING: u32 = 110;
+const DEVICE_STATE_FAILED: u32 = 120;
+const DEVICE_STATE_REASON_NONE: u32 = 0;
+const DEVICE_STATE_REASON_USER_REQUESTED: u32 = 39;
+
+const ACTIVE_STATE_ACTIVATING: u32 = 1;
+const ACTIVE_STATE_ACTIVATED: u32 = 2;
+const ACTIVE_STATE_DEACTIVATING: u32 = 3;
+const ACTIVE_STATE_DEACTIVATED: u32 = 4;
+const ACTIVE_STATE_REASON_NONE: u32 = 1;
+const ACTIVE_STATE_REASON_USER_DISCONNECTED: u32 = 2;
+
+pub const ACTIVE_STATE_REASON_DEVICE_DISCONNECTED: u32 = 3;
+pub const DEVICE_STATE_REASON_SUPPLICANT_FAILED: u32 = 10;
+
+/// `a{sa{sv}}` settings of a connection profile, keyed by setting name.
+type ConnectionSettings = HashMap<String, HashMap<String, OwnedValue>>;
+
+#[derive(Debug, Clone)]
+pub struct MockDevice {
+    interface: String,
+    managed: bool,
+}
+
+impl MockDevice {
+    pub fn new(interface: &str) -> Self {
+        Self {
+            interface: interface.to_owned(),
+            managed: true,
+        }
+    }
+
+    pub const fn unmanaged(mut self) -> Self {
+        self.managed = false;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MockAccessPoint {
+    ssid: Vec<u8>,
+    strength: u8,
+    protected: bool,
+}
+
+impl MockAccessPoint {
+    /// Access point with an empty SSID for hidden networks.
+    pub fn new(ssid: &str, strength: u8) -> Self {
+        Self {
+            ssid: ssid.as_bytes().to_vec(),
+            strength,
+            protected: false,
+        }
+    }
+
+    pub const fn protected(mut self) -> Self {
+        self.protected = true;
+        self
+    }
+}
+
+/// Saved connection profile.
+#[derive(Debug)]
+pub struct MockConnection(ConnectionSettings);
+
+impl MockConnection {
+    pub fn wifi(id: &str, ssid: &str) -> Self {
+        Self(settings([
+            connection_setting(id, "802-11-wireless"),
+            wireless_setting(ssid, "infrastructure"),
+        ]))
+    }
+
+    /// Access point profile, as left behind by a previous run.
+    pub fn access_point(id: &str, ssid: &str) -> Self {
+        Self(settings([
+            connection_setting(id, "802-11-wireless"),
+            wireless_setting(ssid, "ap"),
+        ]))
+    }
+
+    pub fn ethernet(id: &str) -> Self {
+        Self(settings([connection_setting(id, "802-3-ethernet")]))
+    }
+}
+
+fn connection_setting(
+    id: &str,
+    connection_type: &str,
+) -> (&'static str, Vec<(&'static str, Value<'static>)>) {
+    (
+        "connection",
+        vec![
+            ("id", Value::from(id.to_owned())),
+            ("type", Value::from(connection_type.to_owned())),
+        ],
+    )
+}
+
+fn wireless_setting(ssid: &str, mode: &str) -> (&'static str, Vec<(&'static str, Value<'static>)>) {
+    (
+        "802-11-wireless",
+        vec![
+            ("ssid", Value::from(ssid.as_bytes().to_vec())),
+            ("mode", Value::from(mode.to_owned())),
+        ],
+    )
+}
+
+fn settings<const N: usize>(
+    settings: [(&'static str, Vec<(&'static str, Value<'static>)>); N],
+) -> ConnectionSettings {
+    settings
+        .into_iter()
+        .map(|(name, entries)| {
+            let entries = entries
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = OwnedValue::try_from(value).expect("Invalid setting value");
+                    (key.to_owned(), value)
+                })
+                .collect();
+            (name.to_owned(), entries)
+        })
+        .collect()
+}
+
+/// Outcome of activating a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activation {
+    Succeed,
+    /// Deactivates with the active connection and device state reasons.
+    Fail {
+        reason: u32,
+        device_reason: u32,
+    },
+}
+
+#[derive(Debug)]
+struct Active {
+    connection: u32,
+    state: u32,
+}
+
+#[derive(Debug)]
+struct State {
+    runtime: Handle,
+    device: MockDevice,
+    device_state: u32,
+    /// Reason of the last device state change
+    device_state_reason: u32,
+    access_points: Vec<MockAccessPoint>,
+    connections: BTreeMap<u32, ConnectionSettings>,
+    active: BTreeMap<u32, Active>,
+    next_id: u32,
+    connectivity: u32,
+    activation: Activation,
+    last_scan: i64,
+    scans: usize,
+}
+
+impl State {
+    fn next_id(&mut self) -> u32 {
+        self.next_id = self.next_id.saturating_add(1);
+        self.next_id
+    }
+
+    fn connection(&self, id: u32) -> fdo::Result<&ConnectionSettings> {
+        self.connections
+            .get(&id)
+            .ok_or_else(|| fdo::Error::UnknownObject(format!("No connection {id}")))
+    }
+
+    /// Activations that are not deactivated yet, which NetworkManager lists as active.
+    fn listed_active(&self) -> impl Iterator<Item = (u32, &Active)> {
+        self.active
+            .iter()
+            .filter(|(_, active)| active.state != ACTIVE_STATE_DEACTIVATED)
+            .map(|(id, active)| (*id, active))
+    }
+}
+
+type SharedState = Arc<Mutex<State>>;
+
+fn lock(state: &SharedState) -> MutexGuard<'_, State> {
+    state.lock().expect("Mock state poisoned")
+}
+
+/// Handle of the service for scripting and inspecting its state.
+pub struct MockNetworkManager {
+    state: SharedState,
+    _connection: Connection,
+}
+
+impl MockNetworkManager {
+    /// Must be called from within a tokio runtime, which then runs the state transitions.
+    pub async fn serve(
+        bus: &PrivateBus,
+        device: MockDevice,
+        access_points: Vec<MockAccessPoint>,
+        connections: Vec<MockConnection>,
+    ) -> Self {
+        let device_state = if device.managed {
+            DEVICE_STATE_DISCONNECTED
+        } else {
+            DEVICE_STATE_UNMANAGED
+        };
+
+        let access_point_count = access_points.len();
+
+        let mut state = State {
+            runtime: Handle::current(),
+            device,
+            device_state,
+            device_state_reason: DEVICE_STATE_REASON_NONE,
+            access_points,
+            connections: BTreeMap::new(),
+            active: BTreeMap::new(),
+            next_id: 0,
+            connectivity: CONNECTIVITY_NONE,
+            activation: Activation::Succeed,
+            last_scan: 0,
+            scans: 0,
+        };
+        for MockConnection(mut settings) in connections {
+            let id = state.next_id();
+            assign_uuid(&mut settings, id);
+            state.connections.insert(id, settings);
+        }
+        let connection_ids = state.connections.keys().copied().collect::<Vec<_>>();
+
+        let state = Arc::new(Mutex::new(state));
+
+        let mut builder = connection::Builder::address(bus.address())
+            .expect("Invalid bus address")
+            .name("org.freedesktop.NetworkManager")
+            .expect("Invalid bus name")
+            .serve_at("/org/freedesktop", fdo::ObjectManager)
+            .expect("Failed to serve object manager")
+            .serve_at(NM_PATH, NetworkManager(Arc::clone(&state)))
+            .expect("Failed to serve network manager")
+            .serve_at(SETTINGS_PATH, Settings(Arc::clone(&state)))
+            .expect("Failed to serve settings")
+            .serve_at(DEVICE_PATH, Device(Arc::clone(&state)))
+            .expect("Failed to serve device")
+            .serve_at(DEVICE_PATH, Wireless(Arc::clone(&state)))
+            .expect("Failed to serve wireless device");
+
+        for index in 0..access_point_count {
+            builder = builder
+                .serve_at(
+                    access_point_path(index),
+                    AccessPoint(Arc::clone(&state), index),
+                )
+                .expect("Failed to serve access point");
+        }
+
+        for id in connection_ids {
+            builder = builder
+                .serve_at(
+                    connection_path(id),
+                    SettingsConnection(Arc::clone(&state), id),
+                )
+                .expect("Failed to serve connection");
+        }
+
+        Self {
+            state,
+            _connection: builder
+                .build()
+                .await
+                .expect("Failed to serve mock network manager"),
+        }
+    }
+
+    pub fn set_connectivity(&self, connectivity: u32) {
+        lock(&self.state).connectivity = connectivity;
+    }
+
+    /// Outcome of the following activations.
+    pub fn set_activation(&self, activation: Activation) {
+        lock(&self.state).activation = activation;
+    }
+
+    pub fn scans(&self) -> usize {
+        lock(&self.state).scans
+    }
+
+    /// Names of the saved connection profiles.
+    pub fn connection_ids(&self) -> Vec<String> {
+        lock(&self.state)
+            .connections
+            .values()
+            .filter_map(|settings| string_setting(settings, "connection", "id"))
+            .collect()
+    }
+
+    /// Names of the activating and activated connection profiles.
+    pub fn active_ids(&self) -> Vec<String> {
+        let state = lock(&self.state);
+        state
+            .listed_active()
+            .filter_map(|(_, active)| state.connections.get(&active.connection))
+            .filter_map(|settings| string_setting(settings, "connection", "id"))
+            .collect()
+    }
+
+    /// Wireless mode of the named connection profile.
+    pub fn wireless_mode(&self, id: &str) -> Option<String> {
+        lock(&self.state)
+            .connections
+            .values()
+            .find(|settings| string_setting(settings, "connection", "id").as_deref() == Some(id))
+            .and_then(|settings| string_setting(settings, "802-11-wireless", "mode"))
+    }
+}
+
+fn string_setting(settings: &ConnectionSettings, name: &str, key: &str) -> Option<String> {
+    let value = settings.get(name)?.get(key)?;
+    String::try_from(value.try_clone().ok()?).ok()
+}
+
+/// Profiles added by clients usually leave the UUID to NetworkManager.
+fn assign_uuid(settings: &mut ConnectionSettings, id: u32) {
+    let uuid = OwnedValue::try_from(Value::from(format!("6d6f636b-0000-4000-8000-{id:012x}")))
+        .expect("Invalid UUID value");
+
+    settings
+        .entry("connection".to_owned())
+        .or_default()
+        .entry("uuid".to_owned())
+        .or_insert(uuid);
+}
+
+fn clone_settings(settings: &ConnectionSettings) -> ConnectionSettings {
+    settings
+        .iter()
+        .map(|(name, entries)| {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| {
+                    let value = value.try_clone().expect("Cannot clone setting value");
+                    (key.clone(), value)
+                })
+                .collect();
+            (name.clone(), entries)
+        })
+        .collect()
+}
+
+fn object_path(path: &str) -> OwnedObjectPath {
+    ObjectPath::try_from(path).expect("Invalid path").into()
+}
+
+fn root_path() -> OwnedObjectPath {
+    object_path("/")
+}
+
+fn connection_path(id: u32) -> String {
+    format!("{SETTINGS_PATH}/{id}")
+}
+
+fn active_path(id: u32) -> String {
+    format!("{NM_PATH}/ActiveConnection/{id}")
+}
+
+fn access_point_path(index: usize) -> String {
+    format!("{NM_PATH}/AccessPoint/{index}")
+}
+
+fn parse_id(path: &ObjectPath<'_>, parent: &str) -> fdo::Result<u32> {
+    path.as_str()
+        .strip_prefix(parent)
+        .and_then(|id| id.strip_prefix('/'))
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| fdo::Error::UnknownObject(format!("Unknown object {path}")))
+}
+
+fn check_device(device: &ObjectPath<'_>) -> fdo::Result<()> {
+    if device.as_str() == DEVICE_PATH {
+        Ok(())
+    } else {
+        Err(fdo::Error::UnknownObject(format!(
+            "Unknown device {device}"
+        )))
+    }
+}
+
+/// CLOCK_BOOTTIME in milliseconds, the clock of `LastScan`.
+fn boottime_msec() -> i64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: the timespec is valid for writing
+    unsafe { libc::clock_gettime(libc::CLOCK_BOOTTIME, &mut time) };
+
+    time.tv_sec
+        .saturating_mul(1000)
+        .saturating_add(time.tv_nsec / 1_000_000)
+}
+
+/// Saves the profile and announces it the way NetworkManager does.
+async fn add_connection(
+    state: &SharedState,
+    server: &ObjectServer,
+    mut settings: ConnectionSettings,
+) -> fdo::Result<u32> {
+    let id = {
+        let mut state = lock(state);
+        let id = state.next_id();
+        assign_uuid(&mut settings, id);
+        state.connections.insert(id, settings);
+        id
+    };
+
+    let path = connection_path(id);
+    server
+        .at(path.as_str(), SettingsConnection(Arc::clone(state), id))
+        .await?;
+
+    let settings = server.interface::<_, Settings>(SETTINGS_PATH).await?;
+    settings
+        .get()
+        .await
+        .connections_changed(settings.signal_emitter())
+        .await?;
+    Settings::new_connection(settings.signal_emitter(), object_path(&path)).await?;
+
+    Ok(id)
+}
+
+/// Starts activating the profile, which completes with the scripted outcome after a delay.
+async fn activate(
+    state: &SharedState,
+    server: &ObjectServer,
+    connection: u32,
+) -> fdo::Result<OwnedObjectPath> {
+    let (id, runtime, activation) = {
+        let mut state = lock(state);
+        state.connection(connection)?;
+        if !state.device.managed {
+            return Err(fdo::Error::Failed("Device is not managed".to_owned()));
+        }
+
+        let id = state.next_id();
+        state.active.insert(
+            id,
+            Active {
+                connection,
+                state: ACTIVE_STATE_ACTIVATING,
+            },
+        );
+        (id, state.runtime.clone(), state.activation)
+    };
+
+    let path = active_path(id);
+    server
+        .at(path.as_str(), ActiveConnection(Arc::clone(state), id))
+        .await?;
+    active_connections_changed(server).await?;
+    set_device_state(
+        state,
+        server,
+        DEVICE_STATE_PREPARE,
+        DEVICE_STATE_REASON_NONE,
+    )
+    .await?;
+
+    let state = Arc::clone(state);
+    let server = server.clone();
+    runtime.spawn(async move {
+        tokio::time::sleep(TRANSITION_DELAY).await;
+
+        let result = match activation {
+            Activation::Succeed => {
+                set_device_state(
+                    &state,
+                    &server,
+                    DEVICE_STATE_ACTIVATED,
+                    DEVICE_STATE_REASON_NONE,
+                )
+                .await
+                .ok();
+                set_active_state(
+                    &state,
+                    &server,
+                    id,
+                    ACTIVE_STATE_ACTIVATED,
+                    ACTIVE_STATE_REASON_NONE,
+                )
+                .await
+            }
+            Activation::Fail {
+                reason,
+                device_reason,
+            } => {
+                set_device_state(&state, &server, DEVICE_STATE_FAILED, device_reason)
+                    .await
+                    .ok();
+                set_active_state(&state, &server, id, ACTIVE_STATE_DEACTIVATED, reason).await
+            }
+        };
+
+        if let Err(err) = result {
+            println!("Mock activation failed: {err}");
+        }
+    });
+
+    Ok(object_path(&path))
+}
+
+/// Starts deactivating, which completes after a delay.
+async fn deactivate(state: &SharedState, server: &ObjectServer, id: u32) -> fdo::Result<()> {
+    let runtime = {
+        let state = lock(state);
+        state
+            .listed_active()
+            .find(|(active_id, _)| *active_id == id)
+            .ok_or_else(|| fdo::Error::Failed("Connection is not active".to_owned()))?;
+        state.runtime.clone()
+    };
+
+    set_device_state(
+        state,
+        server,
+        DEVICE_STATE_DEACTIVATING,
+        DEVICE_STATE_REASON_USER_REQUESTED,
+    )
+    .await?;
+    set_active_state(
+        state,
+        server,
+        id,
+        ACTIVE_STATE_DEACTIVATING,
+        ACTIVE_STATE_REASON_USER_DISCONNECTED,
+    )
+    .await?;
+
+    let state = Arc::clone(state);
+    let server = server.clone();
+    runtime.spawn(async move {
+        tokio::time::sleep(TRANSITION_DELAY).await;
+
+        set_device_state(
+            &state,
+            &server,
+            DEVICE_STATE_DISCONNECTED,
+            DEVICE_STATE_REASON_USER_REQUESTED,
+        )
+        .await
+        .ok();
+        let result = set_active_state(
+            &state,
+            &server,
+            id,
+            ACTIVE_STATE_DEACTIVATED,
+            ACTIVE_STATE_REASON_USER_DISCONNECTED,
+        )
+        .await;
+
+        if let Err(err) = result {
+            println!("Mock deactivation failed: {err}");
+        }
+    });
+
+    Ok(())
+}
+
+async fn set_active_state(
+    state: &SharedState,
+    server: &ObjectServer,
+    id: u32,
+    active_state: u32,
+    reason: u32,
+) -> zbus::Result<()> {
+    if let Some(active) = lock(state).active.get_mut(&id) {
+        active.state = active_state;
+    }
+
+    let active = server
+        .interface::<_, ActiveConnection>(active_path(id))
+        .await?;
+    ActiveConnection::state_transition(active.signal_emitter(), active_state, reason).await?;
+    active
+        .get()
+        .await
+        .state_changed(active.signal_emitter())
+        .await?;
+
+    // Deactivated connections are no longer listed, but stay exported for clients holding them
+    if active_state == ACTIVE_STATE_DEACTIVATED {
+        active_connections_changed(server).await?;
+        device_active_connection_changed(server).await?;
+    }
+
+    Ok(())
+}
+
+async fn set_device_state(
+    state: &SharedState,
+    server: &ObjectServer,
+    device_state: u32,
+    reason: u32,
+) -> zbus::Result<()> {
+    let old_state = {
+        let mut state = lock(state);
+        state.device_state_reason = reason;
+        core::mem::replace(&mut state.device_state, device_state)
+    };
+
+    let device = server.interface::<_, Device>(DEVICE_PATH).await?;
+    Device::state_transition(device.signal_emitter(), device_state, old_state, reason).await?;
+    let iface = device.get().await;
+    iface.state_changed(device.signal_emitter()).await?;
+    iface.state_reason_changed(device.signal_emitter()).await?;
+    drop(iface);
+
+    device_active_connection_changed(server).await?;
+
+    let manager = server.interface::<_, NetworkManager>(NM_PATH).await?;
+    manager
+        .get()
+        .await
+        .state_changed(manager.signal_emitter())
+        .await?;
+
+    Ok(())
+}
+
+async fn active_connections_changed(server: &ObjectServer) -> zbus::Result<()> {
+    let manager = server.interface::<_, NetworkManager>(NM_PATH).await?;
+    manager
+        .get()
+        .await
+        .active_connections_changed(manager.signal_emitter())
+        .await?;
+
+    Ok(())
+}
+
+async fn device_active_connection_changed(server: &ObjectServer) -> zbus::Result<()> {
+    let device = server.interface::<_, Device>(DEVICE_PATH).await?;
+    device
+        .get()
+        .await
+        .active_connection_changed(device.signal_emitter())
+        .await?;
+
+    Ok(())
+}
+
+struct NetworkManager(SharedState);
+
+#[interface(name = "org.freedesktop.NetworkManager")]
+impl NetworkManager {
+    fn get_devices(&self) -> Vec<OwnedObjectPath> {
+        vec![object_path(DEVICE_PATH)]
+    }
+
+    fn get_all_devices(&self) -> Vec<OwnedObjectPath> {
+        vec![object_path(DEVICE_PATH)]
+    }
+
+    fn get_permissions(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+
+    fn check_connectivity(&self) -> u32 {
+        lock(&self.0).connectivity
+    }
+
+    async fn activate_connection(
+        &self,
+        connection: OwnedObjectPath,
+        device: OwnedObjectPath,
+        _specific_object: OwnedObjectPath,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<OwnedObjectPath> {
+        check_device(&device)?;
+        let id = parse_id(&connection, SETTINGS_PATH)?;
+
+        activate(&self.0, server, id).await
+    }
+
+    async fn add_and_activate_connection(
+        &self,
+        connection: ConnectionSettings,
+        device: OwnedObjectPath,
+        _specific_object: OwnedObjectPath,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+        check_device(&device)?;
+
+        let id = add_connection(&self.0, server, connection).await?;
+        let active = activate(&self.0, server, id).await?;
+
+        Ok((object_path(&connection_path(id)), active))
+    }
+
+    async fn deactivate_connection(
+        &self,
+        active_connection: OwnedObjectPath,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) -> fdo::Result<()> {
+        let id = parse_id(&active_connection, &format!("{NM_PATH}/ActiveConnection"))?;
+
+        deactivate(&self.0, server, id).await
+    }
+
+    #[zbus(property)]
+    fn devices(&self) -> Vec<OwnedObjectPath> {
+        vec![object_path(DEVICE_PATH)]
+    }
+
+    #[zbus(property)]
+    fn all_devices(&self) -> Vec<OwnedObjectPath> {
+        vec![object_path(DEVICE_PATH)]
+    }
+
+    #[zbus(property)]
+    fn checkpoints(&self) -> Vec<OwnedObjectPath> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn active_connections(&self) -> Vec<OwnedObjectPath> {
+        lock(&self.0)
+            .listed_active()
+            .map(|(id, _)| object_path(&active_path(id)))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn primary_connection(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn activating_connection(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn networking_enabled(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn wireless_enabled(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn wireless_hardware_enabled(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn startup(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn version(&self) -> String {
+        "1.46.0".to_owned()
+    }
+
+    #[zbus(property)]
+    fn capabilities(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        if lock(&self.0).device_state == DEVICE_STATE_ACTIVATED {
+            NM_STATE_CONNECTED_GLOBAL
+        } else {
+            NM_STATE_DISCONNECTED
+        }
+    }
+
+    #[zbus(property)]
+    fn connectivity(&self) -> u32 {
+        lock(&self.0).connectivity
+    }
+
+    #[zbus(property)]
+    fn connectivity_check_available(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn connectivity_check_enabled(&self) -> bool {
+        true
+    }
+}
+
+struct Settings(SharedState);
+
+#[interface(name = "org.freedesktop.NetworkManager.Settings")]
+impl Settings {
+    fn list_connections(&self) -> Vec<OwnedObjectPath> {
+        self.connections()
+    }
+
+    fn get_connection_by_uuid(&self, uuid: String) -> fdo::Result<OwnedObjectPath> {
+        lock(&self.0)
+            .connections
+            .iter()
+            .find(|(_, settings)| {
+                string_setting(settings, "connection", "uuid") == Some(uuid.clone())
+            })
+            .map(|(id, _)| object_path(&connection_path(*id)))
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("No connection with UUID {uuid}")))
+    }
+
+    #[zbus(signal)]
+    async fn new_connection(
+        emitter: &SignalEmitter<'_>,
+        connection: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[zbus(signal)]
+    async fn connection_removed(
+        emitter: &SignalEmitter<'_>,
+        connection: OwnedObjectPath,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn connections(&self) -> Vec<OwnedObjectPath> {
+        lock(&self.0)
+            .connections
+            .keys()
+            .map(|id| object_path(&connection_path(*id)))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn hostname(&self) -> String {
+        "mock".to_owned()
+    }
+
+    #[zbus(property)]
+    fn can_modify(&self) -> bool {
+        true
+    }
+}
+
+struct SettingsConnection(SharedState, u32);
+
+#[interface(name = "org.freedesktop.NetworkManager.Settings.Connection")]
+impl SettingsConnection {
+    fn get_settings(&self) -> fdo::Result<ConnectionSettings> {
+        lock(&self.0).connection(self.1).map(clone_settings)
+    }
+
+    /// Deletes the profile, deactivating it first if active.
+    async fn delete(&self, #[zbus(object_server)] server: &ObjectServer) -> fdo::Result<()> {
+        let active = {
+            let mut state = lock(&self.0);
+            state
+                .connections
+                .remove(&self.1)
+                .ok_or_else(|| fdo::Error::UnknownObject(format!("No connection {}", self.1)))?;
+
+            let active = state
+                .listed_active()
+                .filter(|(_, active)| active.connection == self.1)
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            for id in &active {
+                state.active.remove(id);
+            }
+            active
+        };
+
+        if !active.is_empty() {
+            for id in active {
+                server
+                    .remove::<ActiveConnection, _>(active_path(id).as_str())
+                    .await?;
+            }
+            active_connections_changed(server).await?;
+            set_device_state(
+                &self.0,
+                server,
+                DEVICE_STATE_DISCONNECTED,
+                DEVICE_STATE_REASON_USER_REQUESTED,
+            )
+            .await?;
+        }
+
+        let path = connection_path(self.1);
+        let settings = server.interface::<_, Settings>(SETTINGS_PATH).await?;
+        Settings::connection_removed(settings.signal_emitter(), object_path(&path)).await?;
+        settings
+            .get()
+            .await
+            .connections_changed(settings.signal_emitter())
+            .await?;
+
+        // Removing the object waits for running method calls on it, so it is done last
+        let state = Arc::clone(&self.0);
+        let server = server.clone();
+        let runtime = lock(&state).runtime.clone();
+        runtime.spawn(async move {
+            server
+                .remove::<SettingsConnection, _>(path.as_str())
+                .await
+                .ok();
+        });
+
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn unsaved(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn flags(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn filename(&self) -> String {
+        format!(
+            "/etc/NetworkManager/system-connections/{}.nmconnection",
+            self.1
+        )
+    }
+}
+
+struct Device(SharedState);
+
+#[interface(name = "org.freedesktop.NetworkManager.Device")]
+impl Device {
+    #[zbus(signal, name = "StateChanged")]
+    async fn state_transition(
+        emitter: &SignalEmitter<'_>,
+        new_state: u32,
+        old_state: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn interface(&self) -> String {
+        lock(&self.0).device.interface.clone()
+    }
+
+    #[zbus(property)]
+    fn ip_interface(&self) -> String {
+        lock(&self.0).device.interface.clone()
+    }
+
+    #[zbus(property)]
+    fn udi(&self) -> String {
+        format!(
+            "/sys/devices/virtual/net/{}",
+            lock(&self.0).device.interface
+        )
+    }
+
+    #[zbus(property)]
+    fn path(&self) -> String {
+        String::new()
+    }
+
+    #[zbus(property)]
+    fn driver(&self) -> String {
+        "mock".to_owned()
+    }
+
+    #[zbus(property)]
+    fn device_type(&self) -> u32 {
+        DEVICE_TYPE_WIFI
+    }
+
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        lock(&self.0).device_state
+    }
+
+    /// libnm reports device state changes from this property rather than the signal
+    #[zbus(property)]
+    fn state_reason(&self) -> (u32, u32) {
+        let state = lock(&self.0);
+        (state.device_state, state.device_state_reason)
+    }
+
+    #[zbus(property)]
+    fn managed(&self) -> bool {
+        lock(&self.0).device.managed
+    }
+
+    #[zbus(property)]
+    fn real(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn autoconnect(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn hw_address(&self) -> String {
+        "02:00:00:00:00:01".to_owned()
+    }
+
+    #[zbus(property)]
+    fn active_connection(&self) -> OwnedObjectPath {
+        lock(&self.0)
+            .listed_active()
+            .next()
+            .map_or_else(root_path, |(id, _)| object_path(&active_path(id)))
+    }
+
+    #[zbus(property)]
+    fn available_connections(&self) -> Vec<OwnedObjectPath> {
+        lock(&self.0)
+            .connections
+            .iter()
+            .filter(|(_, settings)| settings.contains_key("802-11-wireless"))
+            .map(|(id, _)| object_path(&connection_path(*id)))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn ip4_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn ip6_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn dhcp4_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn dhcp6_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+}
+
+struct Wireless(SharedState);
+
+#[interface(name = "org.freedesktop.NetworkManager.Device.Wireless")]
+impl Wireless {
+    /// Completes shortly afterwards, announced by a newer `LastScan`.
+    fn request_scan(
+        &self,
+        _options: HashMap<String, OwnedValue>,
+        #[zbus(object_server)] server: &ObjectServer,
+    ) {
+        let runtime = {
+            let mut state = lock(&self.0);
+            state.scans = state.scans.saturating_add(1);
+            state.runtime.clone()
+        };
+
+        let state = Arc::clone(&self.0);
+        let server = server.clone();
+        runtime.spawn(async move {
+            tokio::time::sleep(SCAN_DURATION).await;
+
+            lock(&state).last_scan = boottime_msec();
+
+            if let Ok(wireless) = server.interface::<_, Wireless>(DEVICE_PATH).await {
+                wireless
+                    .get()
+                    .await
+                    .last_scan_changed(wireless.signal_emitter())
+                    .await
+                    .ok();
+            }
+        });
+    }
+
+    fn get_access_points(&self) -> Vec<OwnedObjectPath> {
+        self.access_points()
+    }
+
+    fn get_all_access_points(&self) -> Vec<OwnedObjectPath> {
+        self.access_points()
+    }
+
+    #[zbus(property)]
+    fn access_points(&self) -> Vec<OwnedObjectPath> {
+        (0..lock(&self.0).access_points.len())
+            .map(|index| object_path(&access_point_path(index)))
+            .collect()
+    }
+
+    #[zbus(property)]
+    fn last_scan(&self) -> i64 {
+        lock(&self.0).last_scan
+    }
+
+    #[zbus(property)]
+    fn hw_address(&self) -> String {
+        "02:00:00:00:00:01".to_owned()
+    }
+
+    #[zbus(property)]
+    fn perm_hw_address(&self) -> String {
+        "02:00:00:00:00:01".to_owned()
+    }
+
+    /// Infrastructure mode
+    #[zbus(property)]
+    fn mode(&self) -> u32 {
+        2
+    }
+
+    #[zbus(property)]
+    fn bitrate(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn active_access_point(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn wireless_capabilities(&self) -> u32 {
+        0
+    }
+}
+
+struct AccessPoint(SharedState, usize);
+
+impl AccessPoint {
+    fn with<T: Default>(&self, f: impl FnOnce(&MockAccessPoint) -> T) -> T {
+        lock(&self.0)
+            .access_points
+            .get(self.1)
+            .map(f)
+            .unwrap_or_default()
+    }
+}
+
+#[interface(name = "org.freedesktop.NetworkManager.AccessPoint")]
+impl AccessPoint {
+    #[zbus(property)]
+    fn ssid(&self) -> Vec<u8> {
+        self.with(|access_point| access_point.ssid.clone())
+    }
+
+    #[zbus(property)]
+    fn strength(&self) -> u8 {
+        self.with(|access_point| access_point.strength)
+    }
+
+    /// Privacy flag for protected networks
+    #[zbus(property)]
+    fn flags(&self) -> u32 {
+        self.with(|access_point| u32::from(access_point.protected))
+    }
+
+    #[zbus(property)]
+    fn wpa_flags(&self) -> u32 {
+        0
+    }
+
+    /// PSK key management and CCMP ciphers for protected networks
+    #[zbus(property)]
+    fn rsn_flags(&self) -> u32 {
+        self.with(|access_point| if access_point.protected { 0x188 } else { 0 })
+    }
+
+    #[zbus(property)]
+    fn frequency(&self) -> u32 {
+        2437
+    }
+
+    #[zbus(property)]
+    fn hw_address(&self) -> String {
+        format!("02:00:00:00:01:{:02x}", self.1)
+    }
+
+    /// Infrastructure mode
+    #[zbus(property)]
+    fn mode(&self) -> u32 {
+        2
+    }
+
+    #[zbus(property)]
+    fn max_bitrate(&self) -> u32 {
+        54_000
+    }
+
+    #[zbus(property)]
+    fn last_seen(&self) -> i32 {
+        -1
+    }
+}
+
+struct ActiveConnection(SharedState, u32);
+
+impl ActiveConnection {
+    fn connection_setting(&self, key: &str) -> String {
+        let state = lock(&self.0);
+        state
+            .active
+            .get(&self.1)
+            .and_then(|active| state.connections.get(&active.connection))
+            .and_then(|settings| string_setting(settings, "connection", key))
+            .unwrap_or_default()
+    }
+}
+
+#[interface(name = "org.freedesktop.NetworkManager.Connection.Active")]
+impl ActiveConnection {
+    #[zbus(signal, name = "StateChanged")]
+    async fn state_transition(
+        emitter: &SignalEmitter<'_>,
+        state: u32,
+        reason: u32,
+    ) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn connection(&self) -> OwnedObjectPath {
+        lock(&self.0)
+            .active
+            .get(&self.1)
+            .map_or_else(root_path, |active| {
+                object_path(&connection_path(active.connection))
+            })
+    }
+
+    #[zbus(property)]
+    fn specific_object(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn id(&self) -> String {
+        self.connection_setting("id")
+    }
+
+    #[zbus(property)]
+    fn uuid(&self) -> String {
+        self.connection_setting("uuid")
+    }
+
+    #[zbus(property, name = "Type")]
+    fn connection_type(&self) -> String {
+        self.connection_setting("type")
+    }
+
+    #[zbus(property)]
+    fn devices(&self) -> Vec<OwnedObjectPath> {
+        vec![object_path(DEVICE_PATH)]
+    }
+
+    #[zbus(property)]
+    fn state(&self) -> u32 {
+        lock(&self.0)
+            .active
+            .get(&self.1)
+            .map_or(ACTIVE_STATE_DEACTIVATED, |active| active.state)
+    }
+
+    #[zbus(property)]
+    fn state_flags(&self) -> u32 {
+        0
+    }
+
+    #[zbus(property)]
+    fn default(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn default6(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn vpn(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn master(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn ip4_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn ip6_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn dhcp4_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+
+    #[zbus(property)]
+    fn dhcp6_config(&self) -> OwnedObjectPath {
+        root_path()
+    }
+}
@@ -0,0 +1,45 @@
+//! Private session bus for tests against mock D-Bus services. Shared by the Network Manager
+//! end-to-end tests and the iwd backend unit tests, which include it with `#[path]`.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+
+/// `dbus-daemon` serving a session bus until dropped.
+pub struct PrivateBus {
+    daemon: Child,
+    address: String,
+}
+
+impl PrivateBus {
+    /// Starts a bus. Panics if `dbus-daemon` is not installed, rather than passing without
+    /// testing anything.
+    pub fn start() -> Self {
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to start dbus-daemon, which the D-Bus tests require");
+
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().expect("Missing dbus-daemon output"))
+            .read_line(&mut address)
+            .expect("Failed to read the bus address");
+
+        Self {
+            daemon,
+            address: address.trim().to_owned(),
+        }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+    }
+}